@@ -0,0 +1,189 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::{
+    common::errors::ExchangeError,
+    models::{
+        info::response::market::{CandleModel, FundingRateHistoryModel},
+        ws::responses::{OrderBookResponse, TradeModel, TradesResponse},
+    },
+    ws::recorder::RecordedMessage,
+};
+
+#[derive(Debug, Clone)]
+pub enum MarketEvent {
+    Book(OrderBookResponse),
+    Trade(TradeModel),
+    // Takes effect when the candle closes
+    Candle(CandleModel),
+    Funding {
+        symbol: String,
+        rate: FundingRateHistoryModel,
+    },
+}
+
+impl MarketEvent {
+    /// Milliseconds since the epoch.
+    pub fn timestamp(&self) -> u64 {
+        match self {
+            MarketEvent::Book(book) => book.timestamp,
+            MarketEvent::Trade(trade) => trade.timestamp,
+            MarketEvent::Candle(candle) => candle.end_time,
+            MarketEvent::Funding { rate, .. } => rate.created_at,
+        }
+    }
+
+    pub fn symbol(&self) -> &str {
+        match self {
+            MarketEvent::Book(book) => &book.symbol,
+            MarketEvent::Trade(trade) => &trade.symbol,
+            MarketEvent::Candle(candle) => &candle.symbol,
+            MarketEvent::Funding { symbol, .. } => symbol,
+        }
+    }
+}
+
+/// Candles from `InfoClient::kline` merged with the symbol's funding history,
+/// in time order.
+pub fn candle_events(
+    candles: Vec<CandleModel>,
+    symbol: &str,
+    funding: Vec<FundingRateHistoryModel>,
+) -> Vec<MarketEvent> {
+    let mut events: Vec<MarketEvent> = candles
+        .into_iter()
+        .map(MarketEvent::Candle)
+        .chain(funding.into_iter().map(|rate| MarketEvent::Funding {
+            symbol: symbol.to_string(),
+            rate,
+        }))
+        .collect();
+    events.sort_by_key(MarketEvent::timestamp);
+    events
+}
+
+/// Book, trade and candle events from a `MarketDataRecorder` recording. The
+/// candle channel streams a candle while it is still open, so only its last
+/// update is kept. Other channels are skipped.
+pub fn recorded_events(
+    records: impl IntoIterator<Item = RecordedMessage>,
+) -> Result<Vec<MarketEvent>, ExchangeError> {
+    let mut events = Vec::new();
+    // Symbol and interval to the index of their open candle
+    let mut open_candles: HashMap<String, usize> = HashMap::new();
+    for record in records {
+        let Some(channel) = record.message.get("channel").and_then(Value::as_str) else {
+            continue;
+        };
+        let Some(data) = record.message.get("data") else {
+            continue;
+        };
+        match channel {
+            "book" => events.push(MarketEvent::Book(serde_json::from_value(data.clone())?)),
+            "trades" => {
+                let trades: TradesResponse = serde_json::from_value(data.clone())?;
+                events.extend(trades.into_iter().map(MarketEvent::Trade));
+            }
+            "candle" => {
+                let candle: CandleModel = serde_json::from_value(data.clone())?;
+                let key = format!(
+                    "{}:{}",
+                    candle.symbol,
+                    serde_json::to_string(&candle.interval)?
+                );
+                if let Some(&index) = open_candles.get(&key)
+                    && let MarketEvent::Candle(open) = &mut events[index]
+                    && open.start_time == candle.start_time
+                {
+                    *open = candle;
+                    continue;
+                }
+                open_candles.insert(key, events.len());
+                events.push(MarketEvent::Candle(candle));
+            }
+            _ => {}
+        }
+    }
+    // Stable, so recorded order is kept within a timestamp
+    events.sort_by_key(MarketEvent::timestamp);
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use super::*;
+
+    fn candle(start: u64, close: &str) -> Value {
+        json!({
+            "t": start, "T": start + 60_000, "s": "BTC", "i": "1m", "o": "100",
+            "c": close, "h": "101", "l": "99", "v": "1", "n": 1
+        })
+    }
+
+    fn record(local_ts: u64, channel: &str, data: Value) -> RecordedMessage {
+        RecordedMessage {
+            local_ts,
+            message: json!({ "channel": channel, "data": data }),
+        }
+    }
+
+    #[test]
+    fn test_candle_events_merge_funding() {
+        let candles: Vec<CandleModel> = [0, 60_000]
+            .into_iter()
+            .map(|start| serde_json::from_value(candle(start, "100")).unwrap())
+            .collect();
+        let funding: FundingRateHistoryModel = serde_json::from_value(json!({
+            "oracle_price": "100", "bid_impact_price": "100", "ask_impact_price": "100",
+            "funding_rate": "0.001", "next_funding_rate": "0.001", "created_at": 90_000
+        }))
+        .unwrap();
+
+        let events = candle_events(candles, "BTC", vec![funding]);
+        let timestamps: Vec<u64> = events.iter().map(MarketEvent::timestamp).collect();
+        assert_eq!(timestamps, [60_000, 90_000, 120_000]);
+        assert!(matches!(&events[1], MarketEvent::Funding { symbol, .. } if symbol == "BTC"));
+    }
+
+    #[test]
+    fn test_recorded_events() {
+        let trade = |price: &str, timestamp: u64| {
+            json!({
+                "a": "1", "d": "open_long", "p": price, "s": "BTC", "t": timestamp,
+                "tc": "normal", "u": "11111111111111111111111111111111"
+            })
+        };
+        let records = vec![
+            record(1, "candle", candle(0, "100")),
+            record(2, "trades", json!([trade("100", 10), trade("101", 20)])),
+            record(3, "prices", json!([])),
+            // Same candle again, still open
+            record(4, "candle", candle(0, "102")),
+            record(5, "candle", candle(60_000, "103")),
+            RecordedMessage {
+                local_ts: 6,
+                message: json!({ "channel": "pong" }),
+            },
+        ];
+
+        let events = recorded_events(records).unwrap();
+        assert_eq!(events.len(), 4);
+        let closes: Vec<Decimal> = events
+            .iter()
+            .filter_map(|e| match e {
+                MarketEvent::Candle(c) => Some(c.close),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(closes, [Decimal::from(102), Decimal::from(103)]);
+        assert!(matches!(&events[0], MarketEvent::Trade(t) if t.timestamp == 10));
+        assert_eq!(events[2].timestamp(), 60_000);
+
+        let bad = vec![record(1, "book", json!({ "s": "BTC" }))];
+        assert!(recorded_events(bad).is_err());
+    }
+}
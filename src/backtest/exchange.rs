@@ -0,0 +1,1711 @@
+use std::collections::{HashMap, HashSet};
+
+use rust_decimal::Decimal;
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use uuid::Uuid;
+
+use crate::{
+    backtest::{
+        events::MarketEvent,
+        report::{BacktestReport, StatsTracker},
+    },
+    common::{
+        errors::ExchangeError,
+        types::{OrderEventType, OrderSide, OrderStatus, OrderType, Tif},
+    },
+    models::{
+        exchange::payload::order::{
+            CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+            CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+            SetPositionTpslPayload, TpSlWithOrderPayload,
+        },
+        info::response::market::OrderLevelModel,
+        ws::responses::OrderUpdateModel,
+    },
+};
+
+/// Fee rates as fractions of notional, e.g. `0.0004` for 4 bps.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeTier {
+    pub maker: Decimal,
+    pub taker: Decimal,
+}
+
+/// Rates per fee tier, indexed by the `fee_tier` the exchange reports in
+/// `account_info`. Tiers past the end use the last one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeSchedule {
+    pub tiers: Vec<FeeTier>,
+}
+
+impl FeeSchedule {
+    pub fn tier(&self, fee_tier: u8) -> FeeTier {
+        self.tiers
+            .get(fee_tier as usize)
+            .or(self.tiers.last())
+            .cloned()
+            .unwrap_or(FeeTier {
+                maker: Decimal::ZERO,
+                taker: Decimal::ZERO,
+            })
+    }
+}
+
+impl Default for FeeSchedule {
+    // Base tier at the time of writing, check the current schedule
+    fn default() -> Self {
+        Self {
+            tiers: vec![FeeTier {
+                maker: Decimal::new(15, 5),
+                taker: Decimal::new(4, 4),
+            }],
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulatedExchangeConfig {
+    pub starting_balance: Decimal,
+    pub fees: FeeSchedule,
+    pub fee_tier: u8,
+    // Worst price a triggered stop-market order accepts, in percent
+    pub stop_slippage_percent: Decimal,
+    // Resting orders fill when the market only touches their price
+    pub fill_on_touch: bool,
+    // Keep an `OrderUpdateModel` per order event for `drain_order_updates`
+    pub record_order_updates: bool,
+}
+
+impl Default for SimulatedExchangeConfig {
+    fn default() -> Self {
+        Self {
+            starting_balance: Decimal::from(10_000),
+            fees: FeeSchedule::default(),
+            fee_tier: 0,
+            stop_slippage_percent: Decimal::ONE,
+            fill_on_touch: false,
+            record_order_updates: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Fill {
+    pub timestamp: u64,
+    pub order_id: u64,
+    pub client_order_id: Option<Uuid>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub fee: Decimal,
+    pub liquidity: Liquidity,
+    pub realized_pnl: Decimal,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SimulatedPosition {
+    // Positive long, negative short
+    pub amount: Decimal,
+    pub entry_price: Decimal,
+    // Funding paid since the position was opened, negative when received
+    pub funding: Decimal,
+    pub opened_at: u64,
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RestingOrder {
+    pub order_id: u64,
+    pub client_order_id: Option<Uuid>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub price: Decimal,
+    pub amount: Decimal,
+    pub filled: Decimal,
+    pub reduce_only: bool,
+    pub created_at: u64,
+}
+
+impl RestingOrder {
+    pub fn remaining(&self) -> Decimal {
+        self.amount - self.filled
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StopOrder {
+    pub order_id: u64,
+    pub client_order_id: Option<Uuid>,
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub stop_price: Decimal,
+    pub limit_price: Option<Decimal>,
+    // None closes the whole position when triggered
+    pub amount: Option<Decimal>,
+    pub reduce_only: bool,
+    // Set when the stop was above the market when placed
+    pub trigger_above: bool,
+    // TP/SL pair: triggering one cancels the other
+    pub group: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct MarketState {
+    bids: Vec<OrderLevelModel>,
+    asks: Vec<OrderLevelModel>,
+    last: Option<Decimal>,
+}
+
+impl MarketState {
+    fn mark(&self) -> Option<Decimal> {
+        match (self.bids.first(), self.asks.first()) {
+            (Some(bid), Some(ask)) => Some((bid.price + ask.price) / Decimal::TWO),
+            _ => self.last,
+        }
+    }
+
+    fn opposite(&self, side: &OrderSide) -> &[OrderLevelModel] {
+        match side {
+            OrderSide::Bid => &self.asks,
+            OrderSide::Ask => &self.bids,
+        }
+    }
+}
+
+// What an event tells about where the price went
+struct PriceRange {
+    low: Decimal,
+    high: Decimal,
+    // Liquidity that traded, None when unknown (candles)
+    volume: Option<Decimal>,
+}
+
+// Lifecycle of a live order, for order updates
+#[derive(Clone)]
+struct OrderTrack {
+    client_order_id: Option<Uuid>,
+    symbol: String,
+    side: OrderSide,
+    order_type: OrderType,
+    initial_price: Decimal,
+    stop_price: Option<Decimal>,
+    amount: Decimal,
+    filled: Decimal,
+    notional: Decimal,
+    reduce_only: bool,
+    created_at: u64,
+}
+
+// TP/SL of a resting order, attached on its first fill
+struct PendingTpsl {
+    symbol: String,
+    // Side of the order, the TP/SL take the opposite one
+    side: OrderSide,
+    amount: Decimal,
+    take_profit: Option<TpSlWithOrderPayload>,
+    stop_loss: Option<TpSlWithOrderPayload>,
+}
+
+fn sign(side: &OrderSide) -> Decimal {
+    match side {
+        OrderSide::Bid => Decimal::ONE,
+        OrderSide::Ask => Decimal::NEGATIVE_ONE,
+    }
+}
+
+fn opposite(side: &OrderSide) -> OrderSide {
+    match side {
+        OrderSide::Bid => OrderSide::Ask,
+        OrderSide::Ask => OrderSide::Bid,
+    }
+}
+
+// Whether `price` is at least as good as `limit` for `side`
+fn within(side: &OrderSide, price: Decimal, limit: Decimal) -> bool {
+    match side {
+        OrderSide::Bid => price <= limit,
+        OrderSide::Ask => price >= limit,
+    }
+}
+
+/// Matching engine and account for backtests and paper trading. Orders use the
+/// exchange payloads; resting orders fill against later trades, candles and
+/// book updates passed to `apply`, so nothing is filled from data the strategy
+/// has not seen yet.
+pub struct SimulatedExchange {
+    config: SimulatedExchangeConfig,
+    now: u64,
+    next_id: u64,
+    balance: Decimal,
+    markets: HashMap<String, MarketState>,
+    positions: HashMap<String, SimulatedPosition>,
+    orders: Vec<RestingOrder>,
+    stop_orders: Vec<StopOrder>,
+    pending_tpsl: HashMap<u64, PendingTpsl>,
+    fills: Vec<Fill>,
+    tracks: HashMap<u64, OrderTrack>,
+    order_updates: Vec<OrderUpdateModel>,
+    stats: StatsTracker,
+}
+
+impl SimulatedExchange {
+    pub fn new(config: SimulatedExchangeConfig) -> Self {
+        Self {
+            balance: config.starting_balance,
+            stats: StatsTracker::new(config.starting_balance),
+            config,
+            now: 0,
+            next_id: 1,
+            markets: HashMap::new(),
+            positions: HashMap::new(),
+            orders: Vec::new(),
+            stop_orders: Vec::new(),
+            pending_tpsl: HashMap::new(),
+            fills: Vec::new(),
+            tracks: HashMap::new(),
+            order_updates: Vec::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SimulatedExchangeConfig {
+        &self.config
+    }
+
+    /// Time of the last applied event, in milliseconds.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn balance(&self) -> Decimal {
+        self.balance
+    }
+
+    /// Balance plus unrealized PnL at mark prices.
+    pub fn equity(&self) -> Decimal {
+        self.balance
+            + self
+                .positions
+                .iter()
+                .map(|(symbol, position)| {
+                    self.mark(symbol)
+                        .map(|mark| position.amount * (mark - position.entry_price))
+                        .unwrap_or_default()
+                })
+                .sum::<Decimal>()
+    }
+
+    /// Mid of the last book, or the last trade or candle close.
+    pub fn mark(&self, symbol: &str) -> Option<Decimal> {
+        self.markets.get(symbol).and_then(MarketState::mark)
+    }
+
+    pub fn position(&self, symbol: &str) -> Option<&SimulatedPosition> {
+        self.positions.get(symbol)
+    }
+
+    pub fn positions(&self) -> &HashMap<String, SimulatedPosition> {
+        &self.positions
+    }
+
+    pub fn open_orders(&self) -> &[RestingOrder] {
+        &self.orders
+    }
+
+    pub fn stop_orders(&self) -> &[StopOrder] {
+        &self.stop_orders
+    }
+
+    pub fn fills(&self) -> &[Fill] {
+        &self.fills
+    }
+
+    /// Order updates since the last call, when `record_order_updates` is set.
+    /// The account is left as the default pubkey.
+    pub fn drain_order_updates(&mut self) -> Vec<OrderUpdateModel> {
+        std::mem::take(&mut self.order_updates)
+    }
+
+    pub fn report(&self) -> BacktestReport {
+        BacktestReport::new(self)
+    }
+
+    pub(crate) fn stats(&self) -> &StatsTracker {
+        &self.stats
+    }
+
+    /// Advances the market: updates prices, triggers stops, fills resting orders
+    /// and settles funding.
+    pub fn apply(&mut self, event: &MarketEvent) {
+        self.now = self.now.max(event.timestamp());
+        let symbol = event.symbol().to_string();
+        let range = match event {
+            MarketEvent::Book(book) => {
+                let market = self.markets.entry(symbol.clone()).or_default();
+                market.bids = book.levels.first().cloned().unwrap_or_default();
+                market.asks = book.levels.get(1).cloned().unwrap_or_default();
+                match (market.bids.first(), market.asks.first()) {
+                    (Some(bid), Some(ask)) => Some(PriceRange {
+                        low: bid.price,
+                        high: ask.price,
+                        volume: None,
+                    }),
+                    _ => None,
+                }
+            }
+            MarketEvent::Trade(trade) => {
+                self.markets.entry(symbol.clone()).or_default().last = Some(trade.price);
+                Some(PriceRange {
+                    low: trade.price,
+                    high: trade.price,
+                    volume: Some(trade.amount),
+                })
+            }
+            MarketEvent::Candle(candle) => {
+                self.markets.entry(symbol.clone()).or_default().last = Some(candle.close);
+                Some(PriceRange {
+                    low: candle.low,
+                    high: candle.high,
+                    volume: None,
+                })
+            }
+            MarketEvent::Funding { rate, .. } => {
+                self.settle_funding(&symbol, rate.funding_rate, rate.oracle_price);
+                None
+            }
+        };
+        if let Some(range) = range {
+            self.trigger_stops(&symbol, &range);
+            match event {
+                MarketEvent::Book(_) => self.fill_resting_against_book(&symbol),
+                _ => self.fill_resting(&symbol, &range),
+            }
+        }
+        let equity = self.equity();
+        self.stats.record_equity(self.now, equity);
+    }
+
+    pub fn create_order(&mut self, payload: CreateOrderPayload) -> Result<u64, ExchangeError> {
+        self.stats.orders_placed += 1;
+        let order_id = self.next_order_id();
+        let result = self.place_limit(order_id, payload, OrderType::Limit, None);
+        if result.is_err() {
+            self.stats.orders_rejected += 1;
+        }
+        result
+    }
+
+    pub fn create_market_order(
+        &mut self,
+        payload: CreateMarketOrderPayload,
+    ) -> Result<u64, ExchangeError> {
+        self.stats.orders_placed += 1;
+        let order_id = self.next_order_id();
+        let result = self.place_market(order_id, payload, OrderType::Market, None);
+        if result.is_err() {
+            self.stats.orders_rejected += 1;
+        }
+        result
+    }
+
+    pub fn create_stop_order(
+        &mut self,
+        payload: CreateStopOrderPayload,
+    ) -> Result<u64, ExchangeError> {
+        self.stats.orders_placed += 1;
+        let stop = payload.stop_order;
+        let order_type = match stop.limit_price {
+            Some(_) => OrderType::StopLimit,
+            None => OrderType::StopMarket,
+        };
+        let result = self.add_stop(
+            &payload.symbol,
+            payload.side,
+            order_type,
+            stop.stop_price,
+            stop.limit_price,
+            Some(stop.amount),
+            payload.reduce_only,
+            stop.client_order_id,
+            None,
+        );
+        if result.is_err() {
+            self.stats.orders_rejected += 1;
+        }
+        result
+    }
+
+    /// Replaces the position's TP/SL with a pair closing the whole position.
+    pub fn set_position_tpsl(
+        &mut self,
+        payload: SetPositionTpslPayload,
+    ) -> Result<(), ExchangeError> {
+        if self
+            .positions
+            .get(&payload.symbol)
+            .is_none_or(|p| p.amount.is_zero())
+        {
+            return Err(ExchangeError::Validation(format!(
+                "no open position in {}",
+                payload.symbol
+            )));
+        }
+        let (replaced, kept) = std::mem::take(&mut self.stop_orders)
+            .into_iter()
+            .partition(|s| s.symbol == payload.symbol && s.group.is_some() && s.amount.is_none());
+        self.stop_orders = kept;
+        self.cancelled(replaced.into_iter().map(|s: StopOrder| s.order_id));
+        self.add_tpsl(
+            &payload.symbol,
+            payload.side,
+            None,
+            Some(payload.take_profit),
+            Some(payload.stop_loss),
+        )
+    }
+
+    pub fn cancel_order(&mut self, payload: CancelOrderPayload) -> Result<(), ExchangeError> {
+        let index = self
+            .orders
+            .iter()
+            .position(|o| {
+                o.symbol == payload.symbol
+                    && (Some(o.order_id) == payload.order_id
+                        || (o.client_order_id.is_some()
+                            && o.client_order_id == payload.client_order_id))
+            })
+            .ok_or_else(|| ExchangeError::Validation("order not found".into()))?;
+        let order = self.orders.remove(index);
+        self.cancelled([order.order_id]);
+        Ok(())
+    }
+
+    /// Returns the number of cancelled orders.
+    pub fn cancel_all_orders(&mut self, payload: CancelAllOrdersPayload) -> usize {
+        let (cancelled, kept): (Vec<RestingOrder>, Vec<RestingOrder>) =
+            std::mem::take(&mut self.orders).into_iter().partition(|o| {
+                let matches = payload.all_symbols || payload.symbol.as_deref() == Some(&o.symbol);
+                matches && !(payload.exclude_reduce_only && o.reduce_only)
+            });
+        self.orders = kept;
+        let count = cancelled.len();
+        self.cancelled(cancelled.into_iter().map(|o| o.order_id));
+        count
+    }
+
+    pub fn cancel_stop_order(
+        &mut self,
+        payload: CancelStopOrderPayload,
+    ) -> Result<(), ExchangeError> {
+        let index = self
+            .stop_orders
+            .iter()
+            .position(|o| {
+                o.symbol == payload.symbol
+                    && (Some(o.order_id) == payload.order_id
+                        || (o.client_order_id.is_some()
+                            && o.client_order_id == payload.client_order_id))
+            })
+            .ok_or_else(|| ExchangeError::Validation("stop order not found".into()))?;
+        let stop = self.stop_orders.remove(index);
+        self.cancelled([stop.order_id]);
+        Ok(())
+    }
+
+    fn cancelled(&mut self, order_ids: impl IntoIterator<Item = u64>) {
+        for order_id in order_ids {
+            self.pending_tpsl.remove(&order_id);
+            self.stats.orders_cancelled += 1;
+            self.emit(order_id, OrderEventType::Cancel, OrderStatus::Cancelled);
+        }
+    }
+
+    // Starts tracking, keeping the creation time of a triggered stop
+    #[allow(clippy::too_many_arguments)]
+    fn open_track(
+        &mut self,
+        order_id: u64,
+        client_order_id: Option<Uuid>,
+        symbol: &str,
+        side: &OrderSide,
+        order_type: OrderType,
+        initial_price: Decimal,
+        stop_price: Option<Decimal>,
+        amount: Decimal,
+        reduce_only: bool,
+    ) {
+        let created_at = self
+            .tracks
+            .get(&order_id)
+            .map_or(self.now, |t| t.created_at);
+        self.tracks.insert(
+            order_id,
+            OrderTrack {
+                client_order_id,
+                symbol: symbol.to_string(),
+                side: side.clone(),
+                order_type,
+                initial_price,
+                stop_price,
+                amount,
+                filled: Decimal::ZERO,
+                notional: Decimal::ZERO,
+                reduce_only,
+                created_at,
+            },
+        );
+    }
+
+    // Records an order update; a final status ends tracking
+    fn emit(&mut self, order_id: u64, event_type: OrderEventType, status: OrderStatus) {
+        let done = matches!(
+            status,
+            OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected
+        );
+        let track = if done {
+            self.tracks.remove(&order_id)
+        } else {
+            self.tracks.get(&order_id).cloned()
+        };
+        let Some(track) = track else {
+            return;
+        };
+        if !self.config.record_order_updates {
+            return;
+        }
+        let average_filled_price = if track.filled.is_zero() {
+            Decimal::ZERO
+        } else {
+            track.notional / track.filled
+        };
+        self.order_updates.push(OrderUpdateModel {
+            order_id,
+            client_order_id: track.client_order_id,
+            symbol: track.symbol,
+            side: track.side,
+            initial_price: track.initial_price,
+            average_filled_price,
+            original_amount: track.amount,
+            filled_amount: track.filled,
+            order_status: status,
+            order_type: track.order_type,
+            stop_price: track.stop_price,
+            stop_order_id: None,
+            reduce_only: track.reduce_only,
+            account: Pubkey::default(),
+            event_type,
+            updated_at: self.now,
+            created_at: track.created_at,
+        });
+    }
+
+    fn next_order_id(&mut self) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    // Largest amount a reduce-only order on `side` may trade
+    fn reducible(&self, symbol: &str, side: &OrderSide) -> Decimal {
+        let amount = self
+            .positions
+            .get(symbol)
+            .map(|p| p.amount)
+            .unwrap_or_default();
+        match side {
+            OrderSide::Bid if amount < Decimal::ZERO => -amount,
+            OrderSide::Ask if amount > Decimal::ZERO => amount,
+            _ => Decimal::ZERO,
+        }
+    }
+
+    fn capped_amount(
+        &self,
+        symbol: &str,
+        side: &OrderSide,
+        amount: Decimal,
+        reduce_only: bool,
+    ) -> Result<Decimal, ExchangeError> {
+        if amount <= Decimal::ZERO {
+            return Err(ExchangeError::Validation("amount must be positive".into()));
+        }
+        if !reduce_only {
+            return Ok(amount);
+        }
+        let reducible = self.reducible(symbol, side);
+        if reducible.is_zero() {
+            return Err(ExchangeError::Validation(
+                "reduce-only order would not reduce the position".into(),
+            ));
+        }
+        Ok(amount.min(reducible))
+    }
+
+    fn place_limit(
+        &mut self,
+        order_id: u64,
+        payload: CreateOrderPayload,
+        order_type: OrderType,
+        stop_price: Option<Decimal>,
+    ) -> Result<u64, ExchangeError> {
+        if payload.price <= Decimal::ZERO {
+            return Err(ExchangeError::InvalidPriceValue(format!(
+                "price must be positive, got {}",
+                payload.price
+            )));
+        }
+        let amount = self.capped_amount(
+            &payload.symbol,
+            &payload.side,
+            payload.amount,
+            payload.reduce_only,
+        )?;
+        self.open_track(
+            order_id,
+            payload.client_order_id,
+            &payload.symbol,
+            &payload.side,
+            order_type,
+            payload.price,
+            stop_price,
+            amount,
+            payload.reduce_only,
+        );
+        if payload.tif == Tif::ALO && self.crosses(&payload.symbol, &payload.side, payload.price) {
+            self.emit(
+                order_id,
+                OrderEventType::PostOnlyRejected,
+                OrderStatus::Rejected,
+            );
+            return Err(ExchangeError::Validation(
+                "post-only order would take liquidity".into(),
+            ));
+        }
+        let filled = if payload.tif == Tif::ALO {
+            Decimal::ZERO
+        } else {
+            self.take(
+                order_id,
+                payload.client_order_id,
+                &payload.symbol,
+                &payload.side,
+                amount,
+                payload.price,
+                payload.reduce_only,
+            )
+        };
+        let rests = filled < amount && payload.tif != Tif::IOC;
+        // TP/SL cover the order's capped amount while it can still fill, what
+        // filled otherwise
+        let tpsl_amount = if rests { amount } else { filled };
+        if filled.is_zero() && rests {
+            if payload.take_profit.is_some() || payload.stop_loss.is_some() {
+                self.pending_tpsl.insert(
+                    order_id,
+                    PendingTpsl {
+                        symbol: payload.symbol.clone(),
+                        side: payload.side.clone(),
+                        amount: tpsl_amount,
+                        take_profit: payload.take_profit.clone(),
+                        stop_loss: payload.stop_loss.clone(),
+                    },
+                );
+            }
+        } else if !filled.is_zero() {
+            self.attach_tpsl(
+                &payload.symbol,
+                &payload.side,
+                tpsl_amount,
+                payload.take_profit.clone(),
+                payload.stop_loss.clone(),
+            );
+        }
+        if rests {
+            self.orders.push(RestingOrder {
+                order_id,
+                client_order_id: payload.client_order_id,
+                symbol: payload.symbol,
+                side: payload.side,
+                price: payload.price,
+                amount,
+                filled,
+                reduce_only: payload.reduce_only,
+                created_at: self.now,
+            });
+            let status = if filled.is_zero() {
+                OrderStatus::Open
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+            self.emit(order_id, OrderEventType::Make, status);
+        } else {
+            // IOC remainder
+            self.emit(order_id, OrderEventType::Cancel, OrderStatus::Cancelled);
+        }
+        Ok(order_id)
+    }
+
+    fn place_market(
+        &mut self,
+        order_id: u64,
+        payload: CreateMarketOrderPayload,
+        order_type: OrderType,
+        stop_price: Option<Decimal>,
+    ) -> Result<u64, ExchangeError> {
+        let amount = self.capped_amount(
+            &payload.symbol,
+            &payload.side,
+            payload.amount,
+            payload.reduce_only,
+        )?;
+        let limit =
+            self.slippage_limit(&payload.symbol, &payload.side, payload.slippage_percent)?;
+        self.open_track(
+            order_id,
+            payload.client_order_id,
+            &payload.symbol,
+            &payload.side,
+            order_type,
+            limit,
+            stop_price,
+            amount,
+            payload.reduce_only,
+        );
+        let filled = self.take(
+            order_id,
+            payload.client_order_id,
+            &payload.symbol,
+            &payload.side,
+            amount,
+            limit,
+            payload.reduce_only,
+        );
+        if filled.is_zero() {
+            self.emit(order_id, OrderEventType::Cancel, OrderStatus::Rejected);
+            return Err(ExchangeError::Validation(format!(
+                "no liquidity in {} within {}% slippage",
+                payload.symbol, payload.slippage_percent
+            )));
+        }
+        // The remainder is cancelled below, so TP/SL cover the filled amount
+        self.attach_tpsl(
+            &payload.symbol,
+            &payload.side,
+            filled,
+            payload.take_profit,
+            payload.stop_loss,
+        );
+        // Whatever the slippage limit left unfilled
+        self.emit(order_id, OrderEventType::Cancel, OrderStatus::Cancelled);
+        Ok(order_id)
+    }
+
+    fn slippage_limit(
+        &self,
+        symbol: &str,
+        side: &OrderSide,
+        slippage_percent: Decimal,
+    ) -> Result<Decimal, ExchangeError> {
+        let market = self.markets.get(symbol);
+        let reference = market
+            .and_then(|m| m.opposite(side).first().map(|l| l.price))
+            .or_else(|| market.and_then(|m| m.last))
+            .ok_or_else(|| ExchangeError::Validation(format!("no price for {}", symbol)))?;
+        let slippage = slippage_percent / Decimal::ONE_HUNDRED;
+        Ok(reference * (Decimal::ONE + sign(side) * slippage))
+    }
+
+    fn crosses(&self, symbol: &str, side: &OrderSide, price: Decimal) -> bool {
+        let Some(market) = self.markets.get(symbol) else {
+            return false;
+        };
+        match market.opposite(side).first() {
+            Some(best) => within(side, best.price, price),
+            None => market.last.is_some_and(|last| within(side, last, price)),
+        }
+    }
+
+    // Takes liquidity from the book, or at the last price without one. The book
+    // is not depleted across orders; returns the amount filled
+    #[allow(clippy::too_many_arguments)]
+    fn take(
+        &mut self,
+        order_id: u64,
+        client_order_id: Option<Uuid>,
+        symbol: &str,
+        side: &OrderSide,
+        amount: Decimal,
+        limit: Decimal,
+        reduce_only: bool,
+    ) -> Decimal {
+        let Some(market) = self.markets.get(symbol) else {
+            return Decimal::ZERO;
+        };
+        let levels: Vec<(Decimal, Decimal)> = if market.opposite(side).is_empty() {
+            market
+                .last
+                .map(|last| vec![(last, amount)])
+                .unwrap_or_default()
+        } else {
+            market
+                .opposite(side)
+                .iter()
+                .map(|l| (l.price, l.amount))
+                .collect()
+        };
+        let mut filled = Decimal::ZERO;
+        for (price, available) in levels {
+            let mut remaining = amount - filled;
+            if reduce_only {
+                remaining = remaining.min(self.reducible(symbol, side));
+            }
+            if remaining <= Decimal::ZERO || !within(side, price, limit) {
+                break;
+            }
+            let size = remaining.min(available);
+            self.fill(
+                order_id,
+                client_order_id,
+                symbol,
+                side,
+                price,
+                size,
+                Liquidity::Taker,
+            );
+            filled += size;
+        }
+        filled
+    }
+
+    fn fill_resting(&mut self, symbol: &str, range: &PriceRange) {
+        let fill_on_touch = self.config.fill_on_touch;
+        let mut volume = range.volume;
+        let mut index = 0;
+        while index < self.orders.len() {
+            let order = &self.orders[index];
+            let reached = match order.side {
+                OrderSide::Bid => {
+                    range.low < order.price || (fill_on_touch && range.low == order.price)
+                }
+                OrderSide::Ask => {
+                    range.high > order.price || (fill_on_touch && range.high == order.price)
+                }
+            };
+            if order.symbol != symbol || !reached {
+                index += 1;
+                continue;
+            }
+            let size = match volume {
+                Some(available) => order.remaining().min(available),
+                None => order.remaining(),
+            };
+            if let Some(available) = volume.as_mut() {
+                *available -= size;
+            }
+            if !self.fill_resting_order(index, size) {
+                index += 1;
+            }
+        }
+    }
+
+    fn fill_resting_against_book(&mut self, symbol: &str) {
+        let mut index = 0;
+        while index < self.orders.len() {
+            let order = &self.orders[index];
+            if order.symbol != symbol {
+                index += 1;
+                continue;
+            }
+            let available: Decimal = self
+                .markets
+                .get(symbol)
+                .map(|m| {
+                    m.opposite(&order.side)
+                        .iter()
+                        .take_while(|l| {
+                            // Strictly through our price, otherwise we'd be queued behind
+                            within(&order.side, l.price, order.price) && l.price != order.price
+                        })
+                        .map(|l| l.amount)
+                        .sum()
+                })
+                .unwrap_or_default();
+            let size = order.remaining().min(available);
+            if size.is_zero() {
+                index += 1;
+                continue;
+            }
+            if !self.fill_resting_order(index, size) {
+                index += 1;
+            }
+        }
+    }
+
+    // Fills `size` of the order at `index` at its price; true if it was removed
+    fn fill_resting_order(&mut self, index: usize, size: Decimal) -> bool {
+        let order = self.orders[index].clone();
+        let mut size = size;
+        if order.reduce_only {
+            size = size.min(self.reducible(&order.symbol, &order.side));
+        }
+        if size > Decimal::ZERO {
+            self.fill(
+                order.order_id,
+                order.client_order_id,
+                &order.symbol,
+                &order.side,
+                order.price,
+                size,
+                Liquidity::Maker,
+            );
+        }
+        self.orders[index].filled += size;
+        // A reduce-only order with nothing left to reduce is cancelled
+        let exhausted = order.reduce_only && self.reducible(&order.symbol, &order.side).is_zero();
+        if self.orders[index].remaining() <= Decimal::ZERO || exhausted {
+            self.orders.remove(index);
+            self.pending_tpsl.remove(&order.order_id);
+            self.emit(
+                order.order_id,
+                OrderEventType::Cancel,
+                OrderStatus::Cancelled,
+            );
+            return true;
+        }
+        false
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn fill(
+        &mut self,
+        order_id: u64,
+        client_order_id: Option<Uuid>,
+        symbol: &str,
+        side: &OrderSide,
+        price: Decimal,
+        amount: Decimal,
+        liquidity: Liquidity,
+    ) {
+        let tier = self.config.fees.tier(self.config.fee_tier);
+        let rate = match liquidity {
+            Liquidity::Maker => tier.maker,
+            Liquidity::Taker => tier.taker,
+        };
+        let notional = price * amount;
+        let fee = notional * rate;
+
+        let now = self.now;
+        let position = self.positions.entry(symbol.to_string()).or_default();
+        if position.amount.is_zero() {
+            position.funding = Decimal::ZERO;
+            position.opened_at = now;
+        }
+        position.updated_at = now;
+        let signed = sign(side) * amount;
+        let mut realized_pnl = Decimal::ZERO;
+        if position.amount.is_zero()
+            || position.amount.is_sign_positive() == signed.is_sign_positive()
+        {
+            let total = position.amount.abs() + amount;
+            position.entry_price =
+                (position.amount.abs() * position.entry_price + amount * price) / total;
+            position.amount += signed;
+        } else {
+            let closed = amount.min(position.amount.abs());
+            let direction = if position.amount > Decimal::ZERO {
+                Decimal::ONE
+            } else {
+                Decimal::NEGATIVE_ONE
+            };
+            realized_pnl = closed * (price - position.entry_price) * direction;
+            position.amount += sign(side) * closed;
+            let opened = amount - closed;
+            if opened > Decimal::ZERO {
+                position.amount = sign(side) * opened;
+                position.entry_price = price;
+            } else if position.amount.is_zero() {
+                position.entry_price = Decimal::ZERO;
+            }
+        }
+        self.balance += realized_pnl - fee;
+        self.stats
+            .record_fill(notional, fee, realized_pnl, liquidity);
+        self.fills.push(Fill {
+            timestamp: self.now,
+            order_id,
+            client_order_id,
+            symbol: symbol.to_string(),
+            side: side.clone(),
+            price,
+            amount,
+            fee,
+            liquidity,
+            realized_pnl,
+        });
+
+        if let Some(track) = self.tracks.get_mut(&order_id) {
+            track.filled += amount;
+            track.notional += notional;
+            let event_type = match (liquidity, &track.order_type) {
+                (Liquidity::Maker, _) => OrderEventType::FulfillMaker,
+                (
+                    Liquidity::Taker,
+                    OrderType::Market
+                    | OrderType::StopMarket
+                    | OrderType::TakeProfitMarket
+                    | OrderType::StopLossMarket,
+                ) => OrderEventType::FulfillMarket,
+                (Liquidity::Taker, _) => OrderEventType::FulfillTaker,
+            };
+            let status = if track.filled >= track.amount {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::PartiallyFilled
+            };
+            self.emit(order_id, event_type, status);
+        }
+
+        if let Some(tpsl) = self.pending_tpsl.remove(&order_id) {
+            let PendingTpsl {
+                symbol,
+                side,
+                amount,
+                take_profit,
+                stop_loss,
+            } = tpsl;
+            self.attach_tpsl(&symbol, &side, amount, take_profit, stop_loss);
+        }
+    }
+
+    // TP/SL attached to an order that has filled `amount`, on the opposite side
+    fn attach_tpsl(
+        &mut self,
+        symbol: &str,
+        side: &OrderSide,
+        amount: Decimal,
+        take_profit: Option<TpSlWithOrderPayload>,
+        stop_loss: Option<TpSlWithOrderPayload>,
+    ) {
+        if take_profit.is_none() && stop_loss.is_none() {
+            return;
+        }
+        // Best effort: without a mark the pair can't be oriented
+        self.add_tpsl(symbol, opposite(side), Some(amount), take_profit, stop_loss)
+            .ok();
+    }
+
+    fn add_tpsl(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        amount: Option<Decimal>,
+        take_profit: Option<TpSlWithOrderPayload>,
+        stop_loss: Option<TpSlWithOrderPayload>,
+    ) -> Result<(), ExchangeError> {
+        let group = Some(self.next_order_id());
+        let stops = [
+            (
+                take_profit,
+                OrderType::TakeProfitLimit,
+                OrderType::TakeProfitMarket,
+            ),
+            (
+                stop_loss,
+                OrderType::StopLossLimit,
+                OrderType::StopLossMarket,
+            ),
+        ];
+        for (stop, limit_type, market_type) in stops {
+            let Some(stop) = stop else {
+                continue;
+            };
+            let order_type = match stop.limit_price {
+                Some(_) => limit_type,
+                None => market_type,
+            };
+            self.add_stop(
+                symbol,
+                side.clone(),
+                order_type,
+                stop.stop_price,
+                stop.limit_price,
+                amount,
+                true,
+                stop.client_order_id,
+                group,
+            )?;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_stop(
+        &mut self,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        stop_price: Decimal,
+        limit_price: Option<Decimal>,
+        amount: Option<Decimal>,
+        reduce_only: bool,
+        client_order_id: Option<Uuid>,
+        group: Option<u64>,
+    ) -> Result<u64, ExchangeError> {
+        if stop_price <= Decimal::ZERO {
+            return Err(ExchangeError::InvalidPriceValue(format!(
+                "stop price must be positive, got {}",
+                stop_price
+            )));
+        }
+        let mark = self
+            .mark(symbol)
+            .ok_or_else(|| ExchangeError::Validation(format!("no price for {}", symbol)))?;
+        let order_id = self.next_order_id();
+        let tracked_amount = amount.unwrap_or_else(|| self.reducible(symbol, &side));
+        self.open_track(
+            order_id,
+            client_order_id,
+            symbol,
+            &side,
+            order_type.clone(),
+            limit_price.unwrap_or(stop_price),
+            Some(stop_price),
+            tracked_amount,
+            reduce_only,
+        );
+        self.emit(order_id, OrderEventType::StopCreated, OrderStatus::Open);
+        self.stop_orders.push(StopOrder {
+            order_id,
+            client_order_id,
+            symbol: symbol.to_string(),
+            side,
+            order_type,
+            stop_price,
+            limit_price,
+            amount,
+            reduce_only,
+            trigger_above: stop_price > mark,
+            group,
+        });
+        Ok(order_id)
+    }
+
+    fn trigger_stops(&mut self, symbol: &str, range: &PriceRange) {
+        let (mut triggered, waiting): (Vec<StopOrder>, Vec<StopOrder>) =
+            std::mem::take(&mut self.stop_orders)
+                .into_iter()
+                .partition(|stop| {
+                    stop.symbol == symbol
+                        && if stop.trigger_above {
+                            range.high >= stop.stop_price
+                        } else {
+                            range.low <= stop.stop_price
+                        }
+                });
+        self.stop_orders = waiting;
+        // When one event reaches both legs of a TP/SL pair the path inside it is
+        // unknown, so the stop loss is assumed to have come first
+        triggered.sort_by_key(|stop| {
+            matches!(
+                stop.order_type,
+                OrderType::TakeProfitLimit | OrderType::TakeProfitMarket
+            )
+        });
+        let mut fired_groups = HashSet::new();
+        for stop in triggered {
+            if let Some(group) = stop.group {
+                if !fired_groups.insert(group) {
+                    self.cancelled([stop.order_id]);
+                    continue;
+                }
+                let (siblings, kept) = std::mem::take(&mut self.stop_orders)
+                    .into_iter()
+                    .partition(|s| s.group == Some(group));
+                self.stop_orders = kept;
+                self.cancelled(siblings.into_iter().map(|s: StopOrder| s.order_id));
+            }
+            self.emit(
+                stop.order_id,
+                OrderEventType::StopTriggered,
+                OrderStatus::Open,
+            );
+            let amount = match stop.amount {
+                Some(amount) => amount,
+                None => self.reducible(&stop.symbol, &stop.side),
+            };
+            if amount.is_zero() {
+                self.emit(
+                    stop.order_id,
+                    OrderEventType::Cancel,
+                    OrderStatus::Cancelled,
+                );
+                continue;
+            }
+            let stop_price = Some(stop.stop_price);
+            let result = match stop.limit_price {
+                Some(price) => self.place_limit(
+                    stop.order_id,
+                    CreateOrderPayload {
+                        symbol: stop.symbol.clone(),
+                        price,
+                        amount,
+                        side: stop.side.clone(),
+                        tif: Tif::GTC,
+                        reduce_only: stop.reduce_only,
+                        client_order_id: stop.client_order_id,
+                        take_profit: None,
+                        stop_loss: None,
+                    },
+                    stop.order_type.clone(),
+                    stop_price,
+                ),
+                None => self.place_stop_market(&stop, amount, range),
+            };
+            if let Err(e) = result {
+                self.emit(stop.order_id, OrderEventType::Cancel, OrderStatus::Rejected);
+                tracing::debug!(
+                    "SimulatedExchange: stop {} not executed: {}",
+                    stop.order_id,
+                    e
+                );
+            }
+        }
+    }
+
+    // On candles the path inside the range is unknown, so without a book a
+    // triggered stop fills at its stop price rather than the close
+    fn place_stop_market(
+        &mut self,
+        stop: &StopOrder,
+        amount: Decimal,
+        range: &PriceRange,
+    ) -> Result<u64, ExchangeError> {
+        let market = self.markets.entry(stop.symbol.clone()).or_default();
+        let saved_last = market.last;
+        if range.volume.is_none() && market.opposite(&stop.side).is_empty() {
+            market.last = Some(stop.stop_price);
+        }
+        let payload = CreateMarketOrderPayload {
+            symbol: stop.symbol.clone(),
+            amount,
+            side: stop.side.clone(),
+            slippage_percent: self.config.stop_slippage_percent,
+            reduce_only: stop.reduce_only,
+            client_order_id: stop.client_order_id,
+            take_profit: None,
+            stop_loss: None,
+        };
+        let result = self.place_market(
+            stop.order_id,
+            payload,
+            stop.order_type.clone(),
+            Some(stop.stop_price),
+        );
+        if let Some(market) = self.markets.get_mut(&stop.symbol) {
+            market.last = saved_last;
+        }
+        result
+    }
+
+    // Longs pay shorts when the rate is positive
+    fn settle_funding(&mut self, symbol: &str, rate: Decimal, oracle_price: Decimal) {
+        let Some(position) = self.positions.get_mut(symbol) else {
+            return;
+        };
+        let payment = position.amount * oracle_price * rate;
+        position.funding += payment;
+        self.balance -= payment;
+        self.stats.funding_paid += payment;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        backtest::events::candle_events,
+        models::{
+            exchange::payload::order::TpSlAlonePayload,
+            info::response::market::{CandleModel, FundingRateHistoryModel},
+        },
+        test_util::book_event,
+    };
+
+    use super::*;
+
+    fn trade(timestamp: u64, price: &str, amount: &str) -> MarketEvent {
+        MarketEvent::Trade(
+            serde_json::from_value(json!({
+                "a": amount, "d": "open_long", "p": price, "s": "BTC", "t": timestamp,
+                "tc": "normal", "u": "11111111111111111111111111111111"
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn candle(start: u64, open: u64, high: u64, low: u64, close: u64) -> CandleModel {
+        serde_json::from_value(json!({
+            "t": start, "T": start + 60_000, "s": "BTC", "i": "1m", "o": open.to_string(),
+            "c": close.to_string(), "h": high.to_string(), "l": low.to_string(), "v": "10", "n": 5
+        }))
+        .unwrap()
+    }
+
+    fn limit(side: OrderSide, price: i64, amount: i64, tif: Tif) -> CreateOrderPayload {
+        CreateOrderPayload {
+            symbol: "BTC".into(),
+            price: Decimal::from(price),
+            amount: Decimal::from(amount),
+            side,
+            tif,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        }
+    }
+
+    #[test]
+    fn test_tif_and_fees() {
+        let mut exchange = SimulatedExchange::new(SimulatedExchangeConfig::default());
+        exchange.apply(&trade(1, "100", "1"));
+
+        assert!(
+            exchange
+                .create_order(limit(OrderSide::Bid, 101, 1, Tif::ALO))
+                .is_err()
+        );
+        exchange
+            .create_order(limit(OrderSide::Bid, 99, 1, Tif::ALO))
+            .unwrap();
+        exchange
+            .create_order(limit(OrderSide::Bid, 99, 1, Tif::IOC))
+            .unwrap();
+        assert_eq!(exchange.open_orders().len(), 1);
+
+        // Crossing GTC takes at the last price
+        exchange
+            .create_order(limit(OrderSide::Bid, 101, 1, Tif::GTC))
+            .unwrap();
+        let taker = exchange.fills().last().unwrap();
+        assert_eq!(
+            (taker.price, taker.liquidity),
+            (Decimal::from(100), Liquidity::Taker)
+        );
+        assert_eq!(taker.fee, Decimal::new(4, 2));
+
+        // Resting bid fills as maker, limited by the traded amount
+        exchange.apply(&trade(2, "98", "0.5"));
+        let maker = exchange.fills().last().unwrap();
+        assert_eq!(
+            (maker.price, maker.amount),
+            (Decimal::from(99), Decimal::new(5, 1))
+        );
+        assert_eq!(maker.liquidity, Liquidity::Maker);
+        assert_eq!(exchange.open_orders()[0].remaining(), Decimal::new(5, 1));
+        assert_eq!(
+            exchange.position("BTC").unwrap().amount,
+            Decimal::new(15, 1)
+        );
+
+        let report = exchange.report();
+        assert_eq!((report.orders_placed, report.orders_rejected), (4, 1));
+        assert_eq!(report.fees_paid, Decimal::new(4, 2) + Decimal::new(7425, 6));
+    }
+
+    #[test]
+    fn test_reduce_only_tpsl_and_funding() {
+        let mut exchange = SimulatedExchange::new(SimulatedExchangeConfig::default());
+        let funding: FundingRateHistoryModel = serde_json::from_value(json!({
+            "oracle_price": "100", "bid_impact_price": "100", "ask_impact_price": "100",
+            "funding_rate": "0.001", "next_funding_rate": "0.001", "created_at": 90_000
+        }))
+        .unwrap();
+        let mut events = candle_events(
+            vec![
+                candle(0, 100, 101, 99, 100),
+                candle(60_000, 100, 102, 94, 96),
+            ],
+            "BTC",
+            vec![funding],
+        )
+        .into_iter();
+
+        exchange.apply(&events.next().unwrap());
+        let mut reduce = limit(OrderSide::Bid, 100, 1, Tif::GTC);
+        reduce.reduce_only = true;
+        assert!(exchange.create_order(reduce).is_err());
+        exchange
+            .create_market_order(CreateMarketOrderPayload {
+                symbol: "BTC".into(),
+                amount: Decimal::ONE,
+                side: OrderSide::Bid,
+                slippage_percent: Decimal::ONE,
+                reduce_only: false,
+                client_order_id: None,
+                take_profit: Some(TpSlWithOrderPayload {
+                    stop_price: Decimal::from(110),
+                    limit_price: None,
+                    client_order_id: None,
+                }),
+                stop_loss: Some(TpSlWithOrderPayload {
+                    stop_price: Decimal::from(95),
+                    limit_price: None,
+                    client_order_id: None,
+                }),
+            })
+            .unwrap();
+        assert_eq!(exchange.stop_orders().len(), 2);
+
+        // Funding at 90s: the long pays 1 * 100 * 0.001
+        exchange.apply(&events.next().unwrap());
+        assert_eq!(exchange.report().funding_paid, Decimal::new(1, 1));
+
+        // Stop loss fills at its stop price and cancels the take profit
+        exchange.apply(&events.next().unwrap());
+        assert!(exchange.stop_orders().is_empty());
+        assert!(exchange.position("BTC").unwrap().amount.is_zero());
+        let report = exchange.report();
+        assert_eq!(report.realized_pnl, Decimal::from(-5));
+        assert_eq!(report.turnover, Decimal::from(195));
+        let fees = Decimal::from(195) * Decimal::new(4, 4);
+        assert_eq!(
+            report.total_pnl,
+            Decimal::from(-5) - fees - Decimal::new(1, 1)
+        );
+        assert_eq!(report.max_drawdown, -report.total_pnl);
+    }
+
+    fn market(side: OrderSide, amount: Decimal) -> CreateMarketOrderPayload {
+        CreateMarketOrderPayload {
+            symbol: "BTC".into(),
+            amount,
+            side,
+            slippage_percent: Decimal::ONE,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        }
+    }
+
+    fn recording() -> SimulatedExchange {
+        SimulatedExchange::new(SimulatedExchangeConfig {
+            record_order_updates: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_tpsl_pair_on_ambiguous_candle() {
+        let mut exchange = SimulatedExchange::new(SimulatedExchangeConfig::default());
+        exchange.apply(&MarketEvent::Candle(candle(0, 100, 101, 99, 100)));
+        exchange
+            .create_market_order(market(OrderSide::Bid, Decimal::ONE))
+            .unwrap();
+        let stop = |price: i64| TpSlWithOrderPayload {
+            stop_price: Decimal::from(price),
+            limit_price: None,
+            client_order_id: None,
+        };
+        exchange
+            .set_position_tpsl(SetPositionTpslPayload {
+                symbol: "BTC".into(),
+                side: OrderSide::Ask,
+                take_profit: stop(105),
+                stop_loss: stop(95),
+            })
+            .unwrap();
+
+        // The candle reaches both: only the stop loss executes
+        exchange.apply(&MarketEvent::Candle(candle(60_000, 100, 110, 90, 100)));
+        assert!(exchange.stop_orders().is_empty());
+        assert!(exchange.position("BTC").unwrap().amount.is_zero());
+        assert_eq!(exchange.fills().len(), 2);
+        assert_eq!(exchange.fills()[1].price, Decimal::from(95));
+        let report = exchange.report();
+        assert_eq!(report.realized_pnl, Decimal::from(-5));
+        assert_eq!(report.orders_cancelled, 1);
+    }
+
+    #[test]
+    fn test_stop_limit_rests_after_trigger() {
+        let mut exchange = recording();
+        exchange.apply(&trade(1, "100", "1"));
+        let order_id = exchange
+            .create_stop_order(CreateStopOrderPayload {
+                symbol: "BTC".into(),
+                side: OrderSide::Bid,
+                reduce_only: false,
+                stop_order: TpSlAlonePayload {
+                    stop_price: Decimal::from(105),
+                    limit_price: Some(Decimal::from(104)),
+                    client_order_id: None,
+                    amount: Decimal::ONE,
+                },
+            })
+            .unwrap();
+
+        // Not reached yet
+        exchange.apply(&trade(2, "103", "1"));
+        assert_eq!(exchange.stop_orders().len(), 1);
+
+        // Triggered above the limit: the bid rests at 104
+        exchange.apply(&trade(3, "105", "1"));
+        assert!(exchange.stop_orders().is_empty());
+        assert!(exchange.fills().is_empty());
+        assert_eq!(exchange.open_orders()[0].order_id, order_id);
+        assert_eq!(exchange.open_orders()[0].price, Decimal::from(104));
+
+        exchange.apply(&trade(4, "103.5", "1"));
+        let fill = exchange.fills().last().unwrap();
+        assert_eq!(
+            (fill.order_id, fill.price, fill.liquidity),
+            (order_id, Decimal::from(104), Liquidity::Maker)
+        );
+        let updates = exchange.drain_order_updates();
+        let events: Vec<_> = updates.iter().map(|u| u.event_type.clone()).collect();
+        assert!(matches!(
+            events.as_slice(),
+            [
+                OrderEventType::StopCreated,
+                OrderEventType::StopTriggered,
+                OrderEventType::Make,
+                OrderEventType::FulfillMaker
+            ]
+        ));
+        let last = updates.last().unwrap();
+        assert!(matches!(last.order_type, OrderType::StopLimit));
+        assert!(matches!(last.order_status, OrderStatus::Filled));
+        assert_eq!(last.stop_price, Some(Decimal::from(105)));
+    }
+
+    #[test]
+    fn test_partial_maker_fill_and_reduce_only_exhaustion() {
+        let mut exchange = recording();
+        exchange.apply(&trade(1, "100", "1"));
+        exchange
+            .create_market_order(market(OrderSide::Bid, Decimal::ONE))
+            .unwrap();
+        let mut take_profit = limit(OrderSide::Ask, 101, 1, Tif::GTC);
+        take_profit.reduce_only = true;
+        let order_id = exchange.create_order(take_profit).unwrap();
+        exchange.drain_order_updates();
+
+        // Only 0.4 traded through the ask
+        exchange.apply(&trade(2, "101.5", "0.4"));
+        assert_eq!(exchange.open_orders()[0].remaining(), Decimal::new(6, 1));
+        let update = exchange.drain_order_updates().pop().unwrap();
+        assert!(matches!(update.order_status, OrderStatus::PartiallyFilled));
+        assert_eq!(update.filled_amount, Decimal::new(4, 1));
+
+        // The rest of the position is closed elsewhere
+        exchange
+            .create_market_order(market(OrderSide::Ask, Decimal::new(6, 1)))
+            .unwrap();
+        assert!(exchange.position("BTC").unwrap().amount.is_zero());
+        exchange.drain_order_updates();
+
+        // Nothing left to reduce: cancelled without a fill
+        let fills = exchange.fills().len();
+        exchange.apply(&trade(3, "102", "5"));
+        assert_eq!(exchange.fills().len(), fills);
+        assert!(exchange.open_orders().is_empty());
+        assert!(exchange.position("BTC").unwrap().amount.is_zero());
+        let update = exchange.drain_order_updates().pop().unwrap();
+        assert_eq!(update.order_id, order_id);
+        assert!(matches!(update.order_status, OrderStatus::Cancelled));
+        assert_eq!(update.filled_amount, Decimal::new(4, 1));
+    }
+
+    #[test]
+    fn test_tpsl_sized_to_the_fill() {
+        let mut exchange = SimulatedExchange::new(SimulatedExchangeConfig::default());
+        exchange.apply(&book_event("BTC", 1, ("99", "1"), ("100", "0.5")));
+        let stop = |stop_price: i64| TpSlWithOrderPayload {
+            stop_price: Decimal::from(stop_price),
+            limit_price: None,
+            client_order_id: None,
+        };
+        // Only 0.5 is offered within the slippage limit
+        exchange
+            .create_market_order(CreateMarketOrderPayload {
+                take_profit: Some(stop(110)),
+                stop_loss: Some(stop(90)),
+                ..market(OrderSide::Bid, Decimal::ONE)
+            })
+            .unwrap();
+        let amounts: Vec<_> = exchange.stop_orders().iter().map(|s| s.amount).collect();
+        assert_eq!(amounts, [Some(Decimal::new(5, 1)); 2]);
+    }
+
+    #[test]
+    fn test_ioc_remainder_cancelled() {
+        let mut exchange = recording();
+        exchange.apply(&book_event("BTC", 1, ("99", "1"), ("100", "0.5")));
+        let order_id = exchange
+            .create_order(limit(OrderSide::Bid, 101, 1, Tif::IOC))
+            .unwrap();
+        assert!(exchange.open_orders().is_empty());
+        let fill = exchange.fills().last().unwrap();
+        assert_eq!(
+            (fill.price, fill.amount),
+            (Decimal::from(100), Decimal::new(5, 1))
+        );
+        let update = exchange.drain_order_updates().pop().unwrap();
+        assert_eq!(update.order_id, order_id);
+        assert!(matches!(update.order_status, OrderStatus::Cancelled));
+        assert_eq!(update.filled_amount, Decimal::new(5, 1));
+
+        // Nothing within the limit: cancelled unfilled, not rejected
+        exchange
+            .create_order(limit(OrderSide::Bid, 99, 1, Tif::IOC))
+            .unwrap();
+        let update = exchange.drain_order_updates().pop().unwrap();
+        assert!(matches!(update.order_status, OrderStatus::Cancelled));
+        assert!(update.filled_amount.is_zero());
+        assert_eq!(exchange.fills().len(), 1);
+    }
+
+    #[test]
+    fn test_drawdown() {
+        let mut exchange = SimulatedExchange::new(SimulatedExchangeConfig::default());
+        exchange.apply(&trade(1, "100", "1"));
+        exchange
+            .create_market_order(market(OrderSide::Bid, Decimal::ONE))
+            .unwrap();
+        for (timestamp, price) in [(2, "90"), (3, "110"), (4, "99")] {
+            exchange.apply(&trade(timestamp, price, "1"));
+        }
+
+        let report = exchange.report();
+        let fee = Decimal::new(4, 2);
+        let peak = Decimal::from(10_010) - fee;
+        assert_eq!(
+            report.equity_curve,
+            vec![
+                (1, Decimal::from(10_000)),
+                (2, Decimal::from(9_990) - fee),
+                (3, peak),
+                (4, Decimal::from(9_999) - fee),
+            ]
+        );
+        assert_eq!(report.max_drawdown, Decimal::from(11));
+        assert_eq!(
+            report.max_drawdown_percent,
+            Decimal::from(11) / peak * Decimal::ONE_HUNDRED
+        );
+        assert_eq!(report.unrealized_pnl, Decimal::from(-1));
+        assert_eq!(report.total_pnl, Decimal::from(-1) - fee);
+    }
+}
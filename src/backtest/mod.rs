@@ -0,0 +1,4 @@
+pub mod events;
+pub mod exchange;
+pub mod report;
+pub mod runner;
@@ -0,0 +1,198 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::backtest::exchange::{Fill, Liquidity, SimulatedExchange};
+
+// Running totals kept by `SimulatedExchange`
+#[derive(Debug, Clone)]
+pub(crate) struct StatsTracker {
+    pub(crate) starting_balance: Decimal,
+    pub(crate) fees_paid: Decimal,
+    pub(crate) funding_paid: Decimal,
+    pub(crate) realized_pnl: Decimal,
+    pub(crate) turnover: Decimal,
+    pub(crate) maker_fills: u64,
+    pub(crate) taker_fills: u64,
+    pub(crate) orders_placed: u64,
+    pub(crate) orders_rejected: u64,
+    pub(crate) orders_cancelled: u64,
+    pub(crate) equity_curve: Vec<(u64, Decimal)>,
+    peak: Decimal,
+    pub(crate) max_drawdown: Decimal,
+    pub(crate) max_drawdown_percent: Decimal,
+}
+
+impl StatsTracker {
+    pub(crate) fn new(starting_balance: Decimal) -> Self {
+        Self {
+            starting_balance,
+            fees_paid: Decimal::ZERO,
+            funding_paid: Decimal::ZERO,
+            realized_pnl: Decimal::ZERO,
+            turnover: Decimal::ZERO,
+            maker_fills: 0,
+            taker_fills: 0,
+            orders_placed: 0,
+            orders_rejected: 0,
+            orders_cancelled: 0,
+            equity_curve: Vec::new(),
+            peak: starting_balance,
+            max_drawdown: Decimal::ZERO,
+            max_drawdown_percent: Decimal::ZERO,
+        }
+    }
+
+    pub(crate) fn record_fill(
+        &mut self,
+        notional: Decimal,
+        fee: Decimal,
+        realized_pnl: Decimal,
+        liquidity: Liquidity,
+    ) {
+        self.turnover += notional;
+        self.fees_paid += fee;
+        self.realized_pnl += realized_pnl;
+        match liquidity {
+            Liquidity::Maker => self.maker_fills += 1,
+            Liquidity::Taker => self.taker_fills += 1,
+        }
+    }
+
+    // Only changes are kept, so long flat periods don't grow the curve
+    pub(crate) fn record_equity(&mut self, timestamp: u64, equity: Decimal) {
+        if self
+            .equity_curve
+            .last()
+            .is_none_or(|(_, last)| *last != equity)
+        {
+            self.equity_curve.push((timestamp, equity));
+        }
+        self.peak = self.peak.max(equity);
+        let drawdown = self.peak - equity;
+        if drawdown > self.max_drawdown {
+            self.max_drawdown = drawdown;
+        }
+        if self.peak > Decimal::ZERO {
+            let percent = drawdown / self.peak * Decimal::ONE_HUNDRED;
+            self.max_drawdown_percent = self.max_drawdown_percent.max(percent);
+        }
+    }
+}
+
+/// Outcome of a backtest or paper-trading session. Amounts are in the
+/// settlement asset; `funding_paid` is negative when funding was received.
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestReport {
+    pub starting_balance: Decimal,
+    pub final_equity: Decimal,
+    pub total_pnl: Decimal,
+    pub realized_pnl: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub fees_paid: Decimal,
+    pub funding_paid: Decimal,
+    pub max_drawdown: Decimal,
+    pub max_drawdown_percent: Decimal,
+    // Traded notional
+    pub turnover: Decimal,
+    pub orders_placed: u64,
+    pub orders_rejected: u64,
+    pub orders_cancelled: u64,
+    pub maker_fills: u64,
+    pub taker_fills: u64,
+    pub equity_curve: Vec<(u64, Decimal)>,
+    pub fills: Vec<Fill>,
+}
+
+impl BacktestReport {
+    pub fn new(exchange: &SimulatedExchange) -> Self {
+        let stats = exchange.stats();
+        let final_equity = exchange.equity();
+        Self {
+            starting_balance: stats.starting_balance,
+            final_equity,
+            total_pnl: final_equity - stats.starting_balance,
+            realized_pnl: stats.realized_pnl,
+            unrealized_pnl: final_equity - exchange.balance(),
+            fees_paid: stats.fees_paid,
+            funding_paid: stats.funding_paid,
+            max_drawdown: stats.max_drawdown,
+            max_drawdown_percent: stats.max_drawdown_percent,
+            turnover: stats.turnover,
+            orders_placed: stats.orders_placed,
+            orders_rejected: stats.orders_rejected,
+            orders_cancelled: stats.orders_cancelled,
+            maker_fills: stats.maker_fills,
+            taker_fills: stats.taker_fills,
+            equity_curve: stats.equity_curve.clone(),
+            fills: exchange.fills().to_vec(),
+        }
+    }
+
+    pub fn fill_count(&self) -> u64 {
+        self.maker_fills + self.taker_fills
+    }
+
+    /// Share of fills that added liquidity, 0 to 1.
+    pub fn maker_ratio(&self) -> Decimal {
+        match self.fill_count() {
+            0 => Decimal::ZERO,
+            count => Decimal::from(self.maker_fills) / Decimal::from(count),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtest::exchange::SimulatedExchangeConfig;
+
+    #[test]
+    fn test_equity_curve_and_drawdown() {
+        let mut stats = StatsTracker::new(Decimal::from(100));
+        for (timestamp, equity) in [(1, 100), (2, 100), (3, 80), (4, 120), (5, 90), (6, 110)] {
+            stats.record_equity(timestamp, Decimal::from(equity));
+        }
+        // Unchanged equity is not repeated
+        assert_eq!(
+            stats
+                .equity_curve
+                .iter()
+                .map(|(t, _)| *t)
+                .collect::<Vec<_>>(),
+            [1, 3, 4, 5, 6]
+        );
+        assert_eq!(stats.max_drawdown, Decimal::from(30));
+        // 20% from 100 is smaller than 25% from 120
+        assert_eq!(stats.max_drawdown_percent, Decimal::from(25));
+    }
+
+    #[test]
+    fn test_fill_counts() {
+        let mut stats = StatsTracker::new(Decimal::ZERO);
+        stats.record_fill(
+            Decimal::from(100),
+            Decimal::ONE,
+            Decimal::from(5),
+            Liquidity::Maker,
+        );
+        for _ in 0..3 {
+            stats.record_fill(
+                Decimal::from(100),
+                Decimal::ONE,
+                Decimal::ZERO,
+                Liquidity::Taker,
+            );
+        }
+        assert_eq!(
+            (stats.turnover, stats.fees_paid, stats.realized_pnl),
+            (Decimal::from(400), Decimal::from(4), Decimal::from(5))
+        );
+
+        let mut report = SimulatedExchange::new(SimulatedExchangeConfig::default()).report();
+        assert_eq!(report.maker_ratio(), Decimal::ZERO);
+        report.maker_fills = stats.maker_fills;
+        report.taker_fills = stats.taker_fills;
+        assert_eq!(report.fill_count(), 4);
+        assert_eq!(report.maker_ratio(), Decimal::new(25, 2));
+    }
+}
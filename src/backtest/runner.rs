@@ -0,0 +1,108 @@
+use crate::backtest::{
+    events::MarketEvent,
+    exchange::{SimulatedExchange, SimulatedExchangeConfig},
+    report::BacktestReport,
+};
+
+pub trait Strategy {
+    /// Called after the exchange has applied `event`, so resting orders have
+    /// already filled against it. Orders placed here only see later events.
+    fn on_event(&mut self, event: &MarketEvent, exchange: &mut SimulatedExchange);
+}
+
+pub struct Backtest {
+    exchange: SimulatedExchange,
+}
+
+impl Backtest {
+    pub fn new(config: SimulatedExchangeConfig) -> Self {
+        Self {
+            exchange: SimulatedExchange::new(config),
+        }
+    }
+
+    pub fn exchange(&self) -> &SimulatedExchange {
+        &self.exchange
+    }
+
+    /// Feeds `events`, which must be in time order, through the exchange and
+    /// `strategy`.
+    pub fn run<S: Strategy>(
+        mut self,
+        events: impl IntoIterator<Item = MarketEvent>,
+        strategy: &mut S,
+    ) -> BacktestReport {
+        for event in events {
+            self.exchange.apply(&event);
+            strategy.on_event(&event, &mut self.exchange);
+        }
+        self.exchange.report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        common::types::{OrderSide, Tif},
+        models::exchange::payload::order::CreateOrderPayload,
+    };
+
+    fn trade(timestamp: u64, price: &str) -> MarketEvent {
+        MarketEvent::Trade(
+            serde_json::from_value(json!({
+                "a": "5", "d": "open_long", "p": price, "s": "BTC", "t": timestamp,
+                "tc": "normal", "u": "11111111111111111111111111111111"
+            }))
+            .unwrap(),
+        )
+    }
+
+    // Bids 1 below every trade; records the fills it saw at each event
+    #[derive(Default)]
+    struct BidBelow {
+        seen: Vec<(u64, usize)>,
+    }
+
+    impl Strategy for BidBelow {
+        fn on_event(&mut self, event: &MarketEvent, exchange: &mut SimulatedExchange) {
+            self.seen.push((event.timestamp(), exchange.fills().len()));
+            let MarketEvent::Trade(trade) = event else {
+                return;
+            };
+            exchange
+                .create_order(CreateOrderPayload {
+                    symbol: "BTC".into(),
+                    price: trade.price - Decimal::ONE,
+                    amount: Decimal::ONE,
+                    side: OrderSide::Bid,
+                    tif: Tif::GTC,
+                    reduce_only: false,
+                    client_order_id: None,
+                    take_profit: None,
+                    stop_loss: None,
+                })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_run_orders_only_see_later_events() {
+        let mut strategy = BidBelow::default();
+        let events = vec![trade(1, "100"), trade(2, "99"), trade(3, "97")];
+        let report = Backtest::new(SimulatedExchangeConfig::default()).run(events, &mut strategy);
+
+        // The bid at 99 doesn't fill on the trade at 99 (not through it), the
+        // trade at 97 fills both it and the bid at 98
+        assert_eq!(strategy.seen, [(1, 0), (2, 0), (3, 2)]);
+        assert_eq!(report.orders_placed, 3);
+        assert_eq!(report.maker_fills, 2);
+        assert_eq!(
+            report.fills.iter().map(|f| f.price).collect::<Vec<_>>(),
+            [Decimal::from(99), Decimal::from(98)]
+        );
+    }
+}
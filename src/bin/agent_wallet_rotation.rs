@@ -0,0 +1,55 @@
+use std::{env, sync::Arc};
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    exchange::{
+        agent_wallet::{AgentWalletManager, AgentWalletStore},
+        exchange_client::ExchangeClient,
+    },
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+};
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("debug");
+    let is_mainnet = false;
+    let enable_ws = false;
+    let api_key: Option<String> = None;
+    let main_keypair = Keypair::from_base58_string(env::var("TEST_KEY").unwrap().as_str());
+    let main_pubkey = main_keypair.pubkey();
+    let store_dir = env::temp_dir().join("pacifica_agents");
+
+    // Key host: holds the main key, binds and rotates agents
+    let main_client = ExchangeClient::new(
+        is_mainnet,
+        enable_ws,
+        api_key.clone(),
+        main_keypair,
+        main_pubkey,
+        None,
+    )
+    .await
+    .map_err(|e| format!("failed to init client: {:?}", e))
+    .unwrap();
+    let manager =
+        AgentWalletManager::new(Arc::new(main_client), AgentWalletStore::new(&store_dir)).unwrap();
+    let rotation = manager.rotate().await.unwrap();
+    info!("Agent rotated: {:?}", rotation);
+
+    // Trading host: only sees the store
+    let info_client = InfoClient::new(is_mainnet, enable_ws, api_key)
+        .await
+        .unwrap();
+    let agent_client = AgentWalletStore::new(&store_dir)
+        .exchange_client(info_client, main_pubkey)
+        .unwrap();
+    info!(
+        "Trading as {} with agent {:?}",
+        agent_client.main_pubkey(),
+        agent_client.agent_pubkey()
+    );
+}
@@ -0,0 +1,47 @@
+use std::{env, sync::Arc};
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    exchange::{api_key_manager::ApiKeyManager, exchange_client::ExchangeClient},
+    logging::init_logging_once,
+};
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("debug");
+    let is_mainnet = false;
+    let enable_ws = true;
+    let api_key: Option<String> = env::var("TEST_API_KEY").ok();
+    let main_keypair = Keypair::from_base58_string(env::var("TEST_KEY").unwrap().as_str());
+    let main_pubkey = main_keypair.pubkey();
+
+    let client = Arc::new(
+        ExchangeClient::new(
+            is_mainnet,
+            enable_ws,
+            api_key.clone(),
+            main_keypair,
+            main_pubkey,
+            None,
+        )
+        .await
+        .map_err(|e| format!("failed to init client: {:?}", e))
+        .unwrap(),
+    );
+
+    let ws = client.info_client.web_socket_client.as_ref().unwrap();
+    let mut prices = ws.subscribe_to_prices().await.unwrap();
+
+    let manager = ApiKeyManager::new(client.clone());
+    info!("API keys: {:?}", manager.list().await.unwrap());
+    let rotation = manager.rotate().await.unwrap();
+    info!("Rotated API key: {:?}", rotation);
+
+    // The subscription survives the reconnect
+    if let Some(update) = prices.recv().await {
+        info!("Prices after rotation: {}", update);
+    }
+}
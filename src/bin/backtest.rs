@@ -0,0 +1,115 @@
+use std::collections::VecDeque;
+
+use pacifica_rust_sdk::{
+    backtest::{
+        events::{MarketEvent, candle_events},
+        exchange::{SimulatedExchange, SimulatedExchangeConfig},
+        runner::{Backtest, Strategy},
+    },
+    common::{
+        types::{Interval, OrderSide},
+        utils::get_timestamp_ms,
+    },
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+    models::exchange::payload::order::CreateMarketOrderPayload,
+};
+use rust_decimal::Decimal;
+use tracing::{info, warn};
+
+// Long above the moving average of the last closes, short below it
+struct MovingAverageCross {
+    closes: VecDeque<Decimal>,
+    period: usize,
+    size: Decimal,
+}
+
+impl Strategy for MovingAverageCross {
+    fn on_event(&mut self, event: &MarketEvent, exchange: &mut SimulatedExchange) {
+        let MarketEvent::Candle(candle) = event else {
+            return;
+        };
+        self.closes.push_back(candle.close);
+        if self.closes.len() > self.period {
+            self.closes.pop_front();
+        } else {
+            return;
+        }
+        let average = self.closes.iter().sum::<Decimal>() / Decimal::from(self.period);
+        let target = if candle.close > average {
+            self.size
+        } else {
+            -self.size
+        };
+        let current = exchange
+            .position(&candle.symbol)
+            .map(|p| p.amount)
+            .unwrap_or_default();
+        let delta = target - current;
+        if delta.is_zero() {
+            return;
+        }
+        let side = if delta > Decimal::ZERO {
+            OrderSide::Bid
+        } else {
+            OrderSide::Ask
+        };
+        let result = exchange.create_market_order(CreateMarketOrderPayload {
+            symbol: candle.symbol.clone(),
+            amount: delta.abs(),
+            side,
+            slippage_percent: Decimal::new(5, 1),
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        });
+        if let Err(e) = result {
+            warn!("Order rejected: {:?}", e);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    init_logging_once("info");
+    let client = InfoClient::new(false, false, None).await.unwrap();
+    let symbol = "BTC";
+    let week_ago = get_timestamp_ms() - 7 * 24 * 60 * 60 * 1000;
+
+    let candles = client
+        .kline(symbol.to_string(), Interval::OneHour, week_ago, None)
+        .await
+        .unwrap()
+        .data
+        .unwrap_or_default();
+    let funding = client
+        .funding_rate_history(symbol.to_string())
+        .await
+        .unwrap()
+        .data
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|rate| rate.created_at >= week_ago)
+        .collect();
+    let events = candle_events(candles, symbol, funding);
+    info!("Backtesting over {} events", events.len());
+
+    let mut strategy = MovingAverageCross {
+        closes: VecDeque::new(),
+        period: 24,
+        size: Decimal::new(1, 2),
+    };
+    let report = Backtest::new(SimulatedExchangeConfig::default()).run(events, &mut strategy);
+    info!(
+        "PnL {} (realized {}, fees {}, funding {}), max drawdown {} ({}%), turnover {}, {} fills",
+        report.total_pnl.round_dp(4),
+        report.realized_pnl.round_dp(4),
+        report.fees_paid.round_dp(4),
+        report.funding_paid.round_dp(4),
+        report.max_drawdown.round_dp(4),
+        report.max_drawdown_percent.round_dp(2),
+        report.turnover.round_dp(2),
+        report.fill_count(),
+    );
+}
@@ -122,8 +122,8 @@ async fn main() {
             }
         };
 
-    if results.is_some() {
-        for result in results.unwrap() {
+    if let Some(results) = results {
+        for result in results {
             info!("Operation Response: {:?}", result)
         }
     }
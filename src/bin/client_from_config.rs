@@ -0,0 +1,30 @@
+use std::{env, path::PathBuf};
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::config::ClientConfig;
+use tracing::{error, info};
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+
+    // Optional TOML/JSON file, PACIFICA_* environment variables take precedence
+    let path = env::var("PACIFICA_CONFIG").ok().map(PathBuf::from);
+    let config = ClientConfig::load(path.as_deref())
+        .map_err(|e| format!("invalid config: {:?}", e))
+        .unwrap();
+    config.init_logging();
+    info!("Loaded config: {:?}", config);
+
+    let client = config
+        .exchange_client()
+        .await
+        .map_err(|e| format!("failed to init client: {:?}", e))
+        .unwrap();
+    info!("Using main account: {}", client.main_pubkey());
+
+    match client.info_client.account(client.main_pubkey()).await {
+        Ok(response) => info!("Account: {:?}", response.data),
+        Err(e) => error!("Failed to fetch account: {:?}", e),
+    }
+}
@@ -0,0 +1,53 @@
+use std::{env, sync::Arc, time::Duration};
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    exchange::exchange_client::ExchangeClient,
+    logging::init_logging_once,
+    risk::emergency::{EmergencyCancelConfig, EmergencyCancelRefresher, EmergencyCancelWatchdog},
+};
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::{error, info};
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("debug");
+    let is_mainnet = false;
+    let enable_ws = false;
+    let api_key: Option<String> = None;
+    let main_keypair = Keypair::from_base58_string(env::var("TEST_KEY").unwrap().as_str());
+    let main_pubkey = main_keypair.pubkey();
+
+    let client = ExchangeClient::new(
+        is_mainnet,
+        enable_ws,
+        api_key.clone(),
+        main_keypair,
+        main_pubkey,
+        None,
+    )
+    .await
+    .map_err(|e| format!("failed to init client: {:?}", e))
+    .unwrap();
+
+    info!("Using main account: {}", main_pubkey);
+
+    // Trading process: keep signed cancels on disk, re-signed 2 minutes before expiry
+    let path = env::temp_dir().join("pacifica_emergency_cancel.json");
+    let mut config = EmergencyCancelConfig::new(&path);
+    config.symbols = vec!["BTC".to_string()];
+    config.expiry_window = 600_000;
+    let refresher = EmergencyCancelRefresher::start(Arc::new(client), config);
+    tokio::time::sleep(Duration::from_secs(3)).await;
+
+    // Watchdog process: only needs the file
+    let watchdog = EmergencyCancelWatchdog::new(&path);
+    info!("Packets valid for {:?}", watchdog.remaining());
+    match watchdog.fire(Some("BTC")).await {
+        Ok(response) => info!("Emergency cancel sent: {:?}", response),
+        Err(e) => error!("Emergency cancel failed: {:?}", e),
+    }
+
+    refresher.stop();
+}
@@ -0,0 +1,84 @@
+use std::{str::FromStr, sync::Arc};
+
+use pacifica_rust_sdk::{
+    funding::{
+        analytics::{
+            FundingAnalytics, FundingRanking, HOURLY_PERIODS_PER_YEAR, fetch_payments,
+            fetch_rate_stats, summarize_payments,
+        },
+        monitor::{FundingMonitor, FundingThreshold, track_prices},
+    },
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+    risk::position::PositionSnapshot,
+};
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+use tokio::sync::RwLock;
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    init_logging_once("info");
+
+    let client = InfoClient::new(false, true, None).await.unwrap();
+    // Test Pubkey
+    let account = Pubkey::from_str("94HjQxftTdgKkTLGVEQm11pNr1A8RNSQij2M1fZpdyfn").unwrap();
+
+    // Markets by next funding rate, annualized
+    let analytics = FundingAnalytics::from_info_client(&client).await.unwrap();
+    for market in analytics.ranked(FundingRanking::Extreme).iter().take(5) {
+        info!(
+            "{}: next {} ({}% a year)",
+            market.symbol,
+            market.next_funding,
+            (market.next_annualized * Decimal::ONE_HUNDRED).round_dp(2)
+        );
+    }
+
+    let stats = fetch_rate_stats(&client, "BTC", HOURLY_PERIODS_PER_YEAR)
+        .await
+        .unwrap();
+    info!("BTC funding history: {:?}", stats);
+
+    // Funding paid and received, and the next payment of open positions
+    let payments = fetch_payments(&client, account, 100).await.unwrap();
+    for summary in summarize_payments(&payments) {
+        info!("{:?}", summary);
+    }
+    let positions = client
+        .positions(account)
+        .await
+        .unwrap()
+        .data
+        .unwrap_or_default();
+    let positions: Vec<PositionSnapshot> = positions.iter().map(PositionSnapshot::from).collect();
+    for prediction in analytics.predict(&positions) {
+        info!("{:?}", prediction);
+    }
+
+    // Alerts when a market's next funding goes over 50% or under -50% a year
+    let threshold = FundingThreshold::all()
+        .above(Decimal::new(5, 1))
+        .below(Decimal::new(-5, 1));
+    let monitor = Arc::new(RwLock::new(FundingMonitor::with_analytics(
+        vec![threshold],
+        analytics,
+    )));
+    let mut alerts = monitor.read().await.alerts();
+    let prices = client
+        .web_socket_client
+        .as_ref()
+        .unwrap()
+        .subscribe_to_prices()
+        .await
+        .unwrap();
+    track_prices(monitor.clone(), prices);
+
+    while let Ok(alert) = alerts.recv().await {
+        info!(
+            "{} funding {:?} (was {:?}): {} a year",
+            alert.symbol, alert.zone, alert.previous, alert.annualized
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    common::types::Interval,
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+    ws::recorder::{
+        MarketDataRecorder, MarketStream, RecorderConfig, read_recording, recording_files,
+    },
+};
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("info");
+    let is_mainnet = false;
+    let enable_ws = true;
+    let client = InfoClient::new(is_mainnet, enable_ws, None)
+        .await
+        .map_err(|e| format!("failed to init client: {:?}", e))
+        .unwrap();
+    let ws_client = client.web_socket_client.as_ref().unwrap();
+
+    let dir = std::env::temp_dir().join("pacifica_market_data");
+    let mut streams = vec![MarketStream::Prices];
+    for symbol in ["BTC", "ETH"] {
+        streams.push(MarketStream::Book {
+            symbol: symbol.to_string(),
+            agg_level: None,
+        });
+        streams.push(MarketStream::Trades {
+            symbol: symbol.to_string(),
+        });
+        streams.push(MarketStream::Candle {
+            symbol: symbol.to_string(),
+            interval: Interval::OneMinute,
+        });
+    }
+    let recorder = MarketDataRecorder::start(ws_client, RecorderConfig::new(&dir, streams))
+        .await
+        .unwrap();
+
+    tokio::time::sleep(Duration::from_secs(30)).await;
+    recorder.stop().await.unwrap();
+
+    for file in recording_files(&dir, "market").unwrap() {
+        let records = read_recording(&file).unwrap();
+        info!("{}: {} messages", file.display(), records.len());
+    }
+}
@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use pacifica_rust_sdk::{
+    info::info_client::InfoClient, logging::init_logging_once, metrics::install_prometheus,
+};
+use tracing::info;
+
+// cargo run --features prometheus --bin metrics_exporter
+// curl http://127.0.0.1:9000/metrics
+#[tokio::main]
+async fn main() {
+    init_logging_once("info");
+    install_prometheus("127.0.0.1:9000".parse().unwrap()).unwrap();
+
+    let client = InfoClient::new(false, true, None).await.unwrap();
+    let ws = client.web_socket_client.as_ref().unwrap();
+    let mut prices = ws.subscribe_to_prices().await.unwrap();
+    tokio::spawn(async move { while prices.recv().await.is_some() {} });
+
+    loop {
+        let response = client.prices().await.unwrap();
+        info!("{} prices", response.data.map_or(0, |d| d.len()));
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
@@ -0,0 +1,1142 @@
+mod output;
+
+use std::{path::PathBuf, process::ExitCode, sync::OnceLock};
+
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use pacifica_rust_sdk::{
+    common::{
+        consts,
+        errors::ExchangeError,
+        keystore::{
+            EncryptedKeystore, Kdf, keypair_from_mnemonic, read_keypair_file, write_keypair_file,
+        },
+        types::{AggLevel, DefaultResponse, EquityHistoryInterval, Interval, OrderSide, Tif},
+        utils::{prepare_final_request, prepare_subaccount_create_request, sign_batch_action},
+    },
+    exchange::{
+        exchange_client::ExchangeClient,
+        operations::{Operation, SubaccountCreateAction},
+    },
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+    models::{
+        exchange::payload::{
+            account::WithdrawPayload,
+            agent_wallet::BindAgentWalletPayload,
+            api_key::{CreateApiKeyPayload, ListApiKeysPayload, RevokeApiKeyPayload},
+            batch_order::{BatchOrderActionPayload, BatchOrderFinalRequest},
+            market_settings::{UpdateLeveragePayload, UpdateMarginModePayload},
+            order::{
+                CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+                CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+                SetPositionTpslPayload, TpSlAlonePayload, TpSlWithOrderPayload,
+            },
+            subaccount::SubaccountTransferPayload,
+        },
+        info::params::account::EquityHistoryParams,
+    },
+    rest::{
+        audit::{AuditLog, AuditRecord, SignatureRedaction, diff_json, read_audit_log},
+        rest_client::{RestClient, RestClientOptions},
+    },
+    ws::ws_client::WebSocketOptions,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::json;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use uuid::Uuid;
+use zeroize::Zeroizing;
+
+use crate::output::{OutputFormat, print_response};
+
+/// Command-line access to the Pacifica REST API.
+#[derive(Parser)]
+#[command(name = "pacifica", version)]
+struct Cli {
+    #[command(flatten)]
+    global: GlobalArgs,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Args)]
+#[command(group(ArgGroup::new("key_source").multiple(false)))]
+struct GlobalArgs {
+    #[arg(long, global = true, value_enum, env = "PACIFICA_NETWORK", default_value_t = Network::Testnet)]
+    network: Network,
+
+    /// Overrides the REST URL selected by --network
+    #[arg(long, global = true, env = "PACIFICA_BASE_URL")]
+    base_url: Option<String>,
+
+    /// Solana CLI keypair file (JSON byte array)
+    #[arg(long, global = true, env = "PACIFICA_KEYPAIR", group = "key_source")]
+    keypair: Option<PathBuf>,
+
+    /// Base58 private key
+    #[arg(
+        long,
+        global = true,
+        env = "PACIFICA_PRIVATE_KEY",
+        hide_env_values = true,
+        value_parser = secret,
+        group = "key_source"
+    )]
+    private_key: Option<Zeroizing<String>>,
+
+    /// BIP39 seed phrase
+    #[arg(
+        long,
+        global = true,
+        env = "PACIFICA_MNEMONIC",
+        hide_env_values = true,
+        value_parser = secret,
+        group = "key_source"
+    )]
+    mnemonic: Option<Zeroizing<String>>,
+
+    /// Derivation path for --mnemonic, e.g. "m/44'/501'/0'/0'" as used by wallets
+    #[arg(long, global = true, env = "PACIFICA_DERIVATION_PATH")]
+    derivation_path: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "PACIFICA_MNEMONIC_PASSPHRASE",
+        hide_env_values = true,
+        value_parser = secret
+    )]
+    mnemonic_passphrase: Option<Zeroizing<String>>,
+
+    /// Encrypted keystore file, see `pacifica keystore`
+    #[arg(long, global = true, env = "PACIFICA_KEYSTORE", group = "key_source")]
+    keystore: Option<PathBuf>,
+
+    #[arg(
+        long,
+        global = true,
+        env = "PACIFICA_KEYSTORE_PASSWORD",
+        hide_env_values = true,
+        value_parser = secret
+    )]
+    keystore_password: Option<Zeroizing<String>>,
+
+    /// Main account. When it differs from the keypair, the keypair signs as its agent wallet
+    #[arg(long, global = true, env = "PACIFICA_ACCOUNT")]
+    account: Option<Pubkey>,
+
+    #[arg(long, global = true, env = "PACIFICA_API_KEY", hide_env_values = true)]
+    api_key: Option<String>,
+
+    /// Signature expiry window in milliseconds
+    #[arg(long, global = true)]
+    expiry_window: Option<u32>,
+
+    #[arg(short, long, global = true, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
+    /// Print the signed request instead of sending it
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Log requests and responses to stderr
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Append every request and raw response to this JSONL file
+    #[arg(long, global = true, env = "PACIFICA_AUDIT_LOG")]
+    audit_log: Option<PathBuf>,
+
+    /// How signatures are written to --audit-log: keep, truncate or remove
+    #[arg(long, global = true, value_parser = parse_serde::<SignatureRedaction>, default_value = "truncate")]
+    audit_signatures: SignatureRedaction,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Network {
+    Mainnet,
+    Testnet,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum KdfArg {
+    Scrypt,
+    Argon2id,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum MarginMode {
+    Cross,
+    Isolated,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List markets and their trading rules
+    Markets,
+    /// Mark, oracle and mid prices
+    Prices {
+        #[arg(long)]
+        symbol: Option<String>,
+    },
+    /// Order book snapshot
+    Book {
+        symbol: String,
+        #[arg(long, value_parser = parse_agg_level)]
+        agg_level: Option<AggLevel>,
+    },
+    /// Candles
+    Klines {
+        symbol: String,
+        #[arg(long, value_parser = parse_serde::<Interval>, default_value = "1m")]
+        interval: Interval,
+        /// Start time, ms since epoch
+        #[arg(long)]
+        start: u64,
+        #[arg(long)]
+        end: Option<u64>,
+    },
+    /// Recent public trades
+    Trades { symbol: String },
+    /// Funding rate history of a market
+    FundingHistory { symbol: String },
+    /// Account balances and margin
+    Account { address: Option<Pubkey> },
+    /// Per-market leverage and margin mode
+    Settings { address: Option<Pubkey> },
+    /// Open positions
+    Positions { address: Option<Pubkey> },
+    /// Open orders
+    Orders { address: Option<Pubkey> },
+    /// Past orders
+    OrderHistory {
+        address: Option<Pubkey>,
+        #[arg(long)]
+        limit: Option<u32>,
+        #[arg(long)]
+        offset: Option<u32>,
+    },
+    /// Fills of an account
+    TradeHistory {
+        address: Option<Pubkey>,
+        #[arg(long)]
+        symbol: Option<String>,
+        /// Start time, ms since epoch
+        #[arg(long, requires = "end")]
+        start: Option<u64>,
+        #[arg(long, requires = "start")]
+        end: Option<u64>,
+        #[arg(long)]
+        limit: Option<u32>,
+        #[arg(long)]
+        offset: Option<u32>,
+    },
+    /// Deposits, withdrawals and other balance changes
+    BalanceHistory {
+        address: Option<Pubkey>,
+        #[arg(long)]
+        limit: Option<u32>,
+        #[arg(long)]
+        offset: Option<u32>,
+    },
+    /// Account equity over time
+    EquityHistory {
+        address: Option<Pubkey>,
+        /// 1d, 7d, 30d or all
+        #[arg(long, value_parser = parse_serde::<EquityHistoryInterval>, default_value = "all")]
+        range: EquityHistoryInterval,
+        /// Start time, ms since epoch
+        #[arg(long, requires = "end")]
+        start: Option<u64>,
+        #[arg(long, requires = "start")]
+        end: Option<u64>,
+        #[arg(long)]
+        granularity: Option<u8>,
+        #[arg(long)]
+        limit: Option<u32>,
+        #[arg(long)]
+        offset: Option<u32>,
+    },
+    /// Funding paid and received by an account
+    FundingPayments {
+        address: Option<Pubkey>,
+        #[arg(long)]
+        limit: Option<u32>,
+        #[arg(long)]
+        offset: Option<u32>,
+    },
+    /// State changes of one order
+    OrderById { order_id: u64 },
+    /// Place a limit order
+    Place {
+        symbol: String,
+        #[arg(value_parser = parse_side)]
+        side: OrderSide,
+        amount: Decimal,
+        price: Decimal,
+        #[arg(long, value_parser = parse_tif, default_value = "GTC")]
+        tif: Tif,
+        #[arg(long)]
+        reduce_only: bool,
+        #[arg(long)]
+        client_order_id: Option<Uuid>,
+    },
+    /// Place a market order
+    Market {
+        symbol: String,
+        #[arg(value_parser = parse_side)]
+        side: OrderSide,
+        amount: Decimal,
+        /// Maximum slippage in percent
+        #[arg(long, default_value = "0.5")]
+        slippage: Decimal,
+        #[arg(long)]
+        reduce_only: bool,
+        #[arg(long)]
+        client_order_id: Option<Uuid>,
+    },
+    /// Cancel an order by exchange or client id
+    Cancel {
+        symbol: String,
+        #[arg(long, required_unless_present = "client_order_id")]
+        order_id: Option<u64>,
+        #[arg(long, conflicts_with = "order_id")]
+        client_order_id: Option<Uuid>,
+    },
+    /// Place a stop order, a stop-limit order with --limit-price
+    StopOrder {
+        symbol: String,
+        #[arg(value_parser = parse_side)]
+        side: OrderSide,
+        amount: Decimal,
+        stop_price: Decimal,
+        #[arg(long)]
+        limit_price: Option<Decimal>,
+        #[arg(long)]
+        reduce_only: bool,
+        #[arg(long)]
+        client_order_id: Option<Uuid>,
+    },
+    /// Cancel a stop order by exchange or client id
+    CancelStop {
+        symbol: String,
+        #[arg(long, required_unless_present = "client_order_id")]
+        order_id: Option<u64>,
+        #[arg(long, conflicts_with = "order_id")]
+        client_order_id: Option<Uuid>,
+    },
+    /// Set take profit and stop loss of a position
+    Tpsl {
+        symbol: String,
+        /// Side of the closing orders, ask for a long position
+        #[arg(value_parser = parse_side)]
+        side: OrderSide,
+        take_profit: Decimal,
+        stop_loss: Decimal,
+        #[arg(long)]
+        take_profit_limit: Option<Decimal>,
+        #[arg(long)]
+        stop_loss_limit: Option<Decimal>,
+    },
+    /// Send the orders and cancels of a JSON file as one batch
+    Batch {
+        /// Array of {"type": "create" | "market" | "cancel", ...payload fields}
+        file: PathBuf,
+    },
+    /// Cancel all orders, or only those of one market
+    CancelAll {
+        #[arg(long)]
+        symbol: Option<String>,
+        #[arg(long)]
+        exclude_reduce_only: bool,
+    },
+    /// Set leverage for a market
+    Leverage { symbol: String, leverage: u16 },
+    /// Switch a market between cross and isolated margin
+    MarginMode {
+        symbol: String,
+        #[arg(value_enum)]
+        mode: MarginMode,
+    },
+    /// Withdraw USDC to the main account wallet
+    Withdraw { amount: Decimal },
+    /// Create subaccounts and transfer funds
+    #[command(subcommand)]
+    Subaccount(SubaccountCommand),
+    /// Manage API keys of the main account
+    #[command(subcommand)]
+    ApiKey(ApiKeyCommand),
+    /// Manage agent wallets
+    #[command(subcommand)]
+    Agent(AgentCommand),
+    /// Convert the signer between key formats
+    #[command(subcommand)]
+    Keystore(KeystoreCommand),
+    /// Inspect requests recorded with --audit-log
+    #[command(subcommand)]
+    Audit(AuditCommand),
+}
+
+#[derive(Subcommand)]
+enum SubaccountCommand {
+    /// Register a subaccount, signed by both keys
+    Create {
+        /// Keypair file of the subaccount
+        subaccount_keypair: PathBuf,
+    },
+    /// Move funds between main account and subaccounts
+    Transfer { to: Pubkey, amount: Decimal },
+}
+
+#[derive(Subcommand)]
+enum ApiKeyCommand {
+    Create,
+    List,
+    Revoke { api_key: String },
+}
+
+#[derive(Subcommand)]
+enum AgentCommand {
+    /// Allow an agent wallet to sign for the main account
+    Bind { agent: Pubkey },
+}
+
+#[derive(Subcommand)]
+enum KeystoreCommand {
+    /// Write the signer to a keystore encrypted with --keystore-password
+    Encrypt {
+        out: PathBuf,
+        #[arg(long, value_enum, default_value_t = KdfArg::Scrypt)]
+        kdf: KdfArg,
+    },
+    /// Write the signer to a plain Solana CLI keypair file
+    Export { out: PathBuf },
+}
+
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Send recorded requests again and print or compare the responses
+    Replay {
+        log: PathBuf,
+        /// Only the request with this correlation id
+        #[arg(long)]
+        id: Option<Uuid>,
+        /// Only requests whose endpoint contains this
+        #[arg(long)]
+        endpoint: Option<String>,
+        /// Print the differences from the recorded response instead of the response
+        #[arg(long)]
+        diff: bool,
+        /// Also resend POST requests, which may place or cancel orders if their
+        /// signature is still within its expiry window
+        #[arg(long)]
+        resend_signed: bool,
+    },
+}
+
+/// A batch file entry. Tagged because `BatchOrderActionPayload` is untagged and
+/// would read a market order as a cancel.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum BatchFileAction {
+    Create(CreateOrderPayload),
+    Market(CreateMarketOrderPayload),
+    Cancel(CancelOrderPayload),
+}
+
+impl From<BatchFileAction> for BatchOrderActionPayload {
+    fn from(action: BatchFileAction) -> Self {
+        match action {
+            BatchFileAction::Create(p) => BatchOrderActionPayload::CreateOrder(p),
+            BatchFileAction::Market(p) => BatchOrderActionPayload::CreateMarketOrder(p),
+            BatchFileAction::Cancel(p) => BatchOrderActionPayload::CancelOrder(p),
+        }
+    }
+}
+
+// Signer arguments, decoded on first use so read-only commands neither pay for
+// the keystore KDF nor need its password
+struct KeySource {
+    keypair: Option<PathBuf>,
+    private_key: Option<Zeroizing<String>>,
+    mnemonic: Option<Zeroizing<String>>,
+    mnemonic_passphrase: Option<Zeroizing<String>>,
+    derivation_path: Option<String>,
+    keystore: Option<PathBuf>,
+    keystore_password: Option<Zeroizing<String>>,
+}
+
+impl KeySource {
+    fn load(&self) -> Result<Option<Keypair>, ExchangeError> {
+        if let Some(path) = &self.keypair {
+            Ok(Some(read_keypair_file(path)?))
+        } else if let Some(key) = &self.private_key {
+            Ok(Some(Keypair::try_from_base58_string(key.trim()).map_err(
+                |e| ExchangeError::Validation(format!("invalid private key: {}", e)),
+            )?))
+        } else if let Some(phrase) = &self.mnemonic {
+            Ok(Some(keypair_from_mnemonic(
+                phrase.trim(),
+                self.mnemonic_passphrase.as_ref().map_or("", |p| p.as_str()),
+                self.derivation_path.as_deref(),
+            )?))
+        } else if let Some(path) = &self.keystore {
+            let password = self.keystore_password.as_deref().ok_or_else(|| {
+                ExchangeError::Validation(
+                    "--keystore needs --keystore-password or PACIFICA_KEYSTORE_PASSWORD".into(),
+                )
+            })?;
+            Ok(Some(EncryptedKeystore::load(path)?.decrypt(password)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+struct Context {
+    base_url: String,
+    api_key: Option<String>,
+    key_source: KeySource,
+    keypair: OnceLock<Option<Keypair>>,
+    account: Option<Pubkey>,
+    expiry_window: Option<u32>,
+    keystore_password: Option<Zeroizing<String>>,
+    output: OutputFormat,
+    dry_run: bool,
+    audit_log: Option<AuditLog>,
+}
+
+impl Context {
+    fn from_args(args: GlobalArgs) -> Result<Self, ExchangeError> {
+        let base_url = args.base_url.unwrap_or_else(|| {
+            match args.network {
+                Network::Mainnet => consts::REST_API_MAINNET_URL,
+                Network::Testnet => consts::REST_API_TESTNET_URL,
+            }
+            .to_string()
+        });
+        let audit_log = args
+            .audit_log
+            .map(|path| AuditLog::jsonl(path).map(|log| log.with_signatures(args.audit_signatures)))
+            .transpose()?;
+        Ok(Self {
+            base_url,
+            api_key: args.api_key,
+            key_source: KeySource {
+                keypair: args.keypair,
+                private_key: args.private_key,
+                mnemonic: args.mnemonic,
+                mnemonic_passphrase: args.mnemonic_passphrase,
+                derivation_path: args.derivation_path,
+                keystore: args.keystore,
+                keystore_password: args.keystore_password.clone(),
+            },
+            keypair: OnceLock::new(),
+            account: args.account,
+            expiry_window: args.expiry_window,
+            keystore_password: args.keystore_password,
+            output: args.output,
+            dry_run: args.dry_run,
+            audit_log,
+        })
+    }
+
+    fn signer(&self) -> Result<Option<&Keypair>, ExchangeError> {
+        if self.keypair.get().is_none() {
+            let keypair = self.key_source.load()?;
+            let _ = self.keypair.set(keypair);
+        }
+        Ok(self.keypair.get().and_then(Option::as_ref))
+    }
+
+    fn keypair(&self) -> Result<&Keypair, ExchangeError> {
+        self.signer()?.ok_or_else(|| {
+            ExchangeError::Validation(
+                "this command needs a signer, pass --keypair, --private-key, --mnemonic or --keystore"
+                    .into(),
+            )
+        })
+    }
+
+    fn main_pubkey(&self) -> Result<Pubkey, ExchangeError> {
+        if let Some(account) = self.account {
+            return Ok(account);
+        }
+        match self.signer()? {
+            Some(keypair) => Ok(keypair.pubkey()),
+            None => Err(ExchangeError::Validation(
+                "no account given, pass an address, --account or a keypair".into(),
+            )),
+        }
+    }
+
+    fn agent_pubkey(&self) -> Result<Option<Pubkey>, ExchangeError> {
+        let signer = self.keypair()?.pubkey();
+        Ok((self.main_pubkey()? != signer).then_some(signer))
+    }
+
+    fn rest_options(&self) -> RestClientOptions {
+        RestClientOptions {
+            audit_log: self.audit_log.clone(),
+            ..Default::default()
+        }
+    }
+
+    async fn info_client(&self) -> Result<InfoClient, ExchangeError> {
+        InfoClient::with_options(
+            &self.base_url,
+            None,
+            self.api_key.clone(),
+            self.rest_options(),
+            WebSocketOptions::default(),
+        )
+        .await
+    }
+
+    async fn exchange_client(&self) -> Result<ExchangeClient, ExchangeError> {
+        ExchangeClient::from_info_client(
+            self.info_client().await?,
+            self.keypair()?.insecure_clone(),
+            self.main_pubkey()?,
+            self.agent_pubkey()?,
+        )
+    }
+
+    /// Signs `payload` for `operation`, then either prints the request or posts it.
+    async fn exchange<P>(&self, operation: Operation, payload: P) -> Result<(), ExchangeError>
+    where
+        P: Serialize + std::fmt::Debug,
+    {
+        if self.dry_run {
+            let request = prepare_final_request(
+                &operation.name()?,
+                payload,
+                self.expiry_window,
+                self.keypair()?,
+                &self.main_pubkey()?,
+                &self.agent_pubkey()?,
+            )
+            .await?;
+            return self.print_dry_run(&operation, &request);
+        }
+        let client = self.exchange_client().await?;
+        let response = client
+            .request_exchange_fn::<DefaultResponse<serde_json::Value>, P>(
+                operation,
+                payload,
+                self.expiry_window,
+            )
+            .await?;
+        print_response(self.output, &response)
+    }
+
+    fn print_dry_run<B: Serialize>(
+        &self,
+        operation: &Operation,
+        body: &B,
+    ) -> Result<(), ExchangeError> {
+        let request = json!({
+            "method": "POST",
+            "url": format!("{}{}", self.base_url, operation.endpoint()),
+            "body": body,
+        });
+        println!("{}", serde_json::to_string_pretty(&request)?);
+        Ok(())
+    }
+
+    fn account_or(&self, address: Option<Pubkey>) -> Result<Pubkey, ExchangeError> {
+        address.map_or_else(|| self.main_pubkey(), Ok)
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), ExchangeError> {
+    let ctx = Context::from_args(cli.global)?;
+
+    match cli.command {
+        Command::Markets => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.get_markets_info().await?)
+        }
+        Command::Prices { symbol } => {
+            let info = ctx.info_client().await?;
+            let mut response = info.prices().await?;
+            if let (Some(symbol), Some(data)) = (symbol, response.data.as_mut()) {
+                data.retain(|p| p.symbol.eq_ignore_ascii_case(&symbol));
+            }
+            print_response(ctx.output, &response)
+        }
+        Command::Book { symbol, agg_level } => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.order_book(symbol, agg_level).await?)
+        }
+        Command::Klines {
+            symbol,
+            interval,
+            start,
+            end,
+        } => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.kline(symbol, interval, start, end).await?)
+        }
+        Command::Trades { symbol } => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.recent_trades(symbol).await?)
+        }
+        Command::FundingHistory { symbol } => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.funding_rate_history(symbol).await?)
+        }
+        Command::Account { address } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.account(account).await?)
+        }
+        Command::Settings { address } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.account_settings(account).await?)
+        }
+        Command::Positions { address } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.positions(account).await?)
+        }
+        Command::Orders { address } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.opened_orders(account).await?)
+        }
+        Command::OrderHistory {
+            address,
+            limit,
+            offset,
+        } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(
+                ctx.output,
+                &info.order_history(account, limit, offset).await?,
+            )
+        }
+        Command::TradeHistory {
+            address,
+            symbol,
+            start,
+            end,
+            limit,
+            offset,
+        } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(
+                ctx.output,
+                &info
+                    .trade_history(account, symbol, start, end, limit, offset)
+                    .await?,
+            )
+        }
+        Command::BalanceHistory {
+            address,
+            limit,
+            offset,
+        } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(
+                ctx.output,
+                &info.balance_history(account, limit, offset).await?,
+            )
+        }
+        Command::EquityHistory {
+            address,
+            range,
+            start,
+            end,
+            granularity,
+            limit,
+            offset,
+        } => {
+            let params = EquityHistoryParams {
+                account: ctx.account_or(address)?,
+                time_range: range,
+                start_time: start,
+                end_time: end,
+                granularity_in_minutes: granularity,
+                limit,
+                offset,
+            };
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.equity_history(params).await?)
+        }
+        Command::FundingPayments {
+            address,
+            limit,
+            offset,
+        } => {
+            let account = ctx.account_or(address)?;
+            let info = ctx.info_client().await?;
+            print_response(
+                ctx.output,
+                &info.account_funding_history(account, limit, offset).await?,
+            )
+        }
+        Command::OrderById { order_id } => {
+            let info = ctx.info_client().await?;
+            print_response(ctx.output, &info.order_history_by_id(order_id).await?)
+        }
+        Command::Place {
+            symbol,
+            side,
+            amount,
+            price,
+            tif,
+            reduce_only,
+            client_order_id,
+        } => {
+            let payload = CreateOrderPayload {
+                symbol,
+                price,
+                amount,
+                side,
+                tif,
+                reduce_only,
+                client_order_id,
+                take_profit: None,
+                stop_loss: None,
+            };
+            ctx.exchange(Operation::CreateOrder, payload).await
+        }
+        Command::Market {
+            symbol,
+            side,
+            amount,
+            slippage,
+            reduce_only,
+            client_order_id,
+        } => {
+            let payload = CreateMarketOrderPayload {
+                symbol,
+                amount,
+                side,
+                slippage_percent: slippage,
+                reduce_only,
+                client_order_id,
+                take_profit: None,
+                stop_loss: None,
+            };
+            ctx.exchange(Operation::CreateMarketOrder, payload).await
+        }
+        Command::Cancel {
+            symbol,
+            order_id,
+            client_order_id,
+        } => {
+            let payload = CancelOrderPayload {
+                symbol,
+                order_id,
+                client_order_id,
+            };
+            ctx.exchange(Operation::CancelOrder, payload).await
+        }
+        Command::StopOrder {
+            symbol,
+            side,
+            amount,
+            stop_price,
+            limit_price,
+            reduce_only,
+            client_order_id,
+        } => {
+            let payload = CreateStopOrderPayload {
+                symbol,
+                side,
+                reduce_only,
+                stop_order: TpSlAlonePayload {
+                    stop_price,
+                    limit_price,
+                    client_order_id,
+                    amount,
+                },
+            };
+            ctx.exchange(Operation::CreateStopOrder, payload).await
+        }
+        Command::CancelStop {
+            symbol,
+            order_id,
+            client_order_id,
+        } => {
+            let payload = CancelStopOrderPayload {
+                symbol,
+                order_id,
+                client_order_id,
+            };
+            ctx.exchange(Operation::CancelStopOrder, payload).await
+        }
+        Command::Tpsl {
+            symbol,
+            side,
+            take_profit,
+            stop_loss,
+            take_profit_limit,
+            stop_loss_limit,
+        } => {
+            let payload = SetPositionTpslPayload {
+                symbol,
+                side,
+                take_profit: TpSlWithOrderPayload {
+                    stop_price: take_profit,
+                    limit_price: take_profit_limit,
+                    client_order_id: None,
+                },
+                stop_loss: TpSlWithOrderPayload {
+                    stop_price: stop_loss,
+                    limit_price: stop_loss_limit,
+                    client_order_id: None,
+                },
+            };
+            ctx.exchange(Operation::SetPositionTpsl, payload).await
+        }
+        Command::Batch { file } => {
+            let text = std::fs::read_to_string(&file)?;
+            let actions: Vec<BatchFileAction> = serde_json::from_str(&text)?;
+            let actions: Vec<BatchOrderActionPayload> =
+                actions.into_iter().map(Into::into).collect();
+            if ctx.dry_run {
+                let request = BatchOrderFinalRequest {
+                    actions: actions
+                        .into_iter()
+                        .map(|action| {
+                            sign_batch_action(
+                                action,
+                                ctx.expiry_window,
+                                ctx.keypair()?,
+                                &ctx.main_pubkey()?,
+                                &ctx.agent_pubkey()?,
+                            )
+                        })
+                        .collect::<Result<_, ExchangeError>>()?,
+                };
+                return ctx.print_dry_run(&Operation::BatchOrder, &request);
+            }
+            let client = ctx.exchange_client().await?;
+            print_response(
+                ctx.output,
+                &client.batch_order(actions, ctx.expiry_window).await?,
+            )
+        }
+        Command::CancelAll {
+            symbol,
+            exclude_reduce_only,
+        } => {
+            let payload = CancelAllOrdersPayload {
+                all_symbols: symbol.is_none(),
+                exclude_reduce_only,
+                symbol,
+            };
+            ctx.exchange(Operation::CancelAllOrders, payload).await
+        }
+        Command::Leverage { symbol, leverage } => {
+            let payload = UpdateLeveragePayload { symbol, leverage };
+            ctx.exchange(Operation::UpdateLeverage, payload).await
+        }
+        Command::MarginMode { symbol, mode } => {
+            let payload = UpdateMarginModePayload {
+                symbol,
+                is_isolated: matches!(mode, MarginMode::Isolated),
+            };
+            ctx.exchange(Operation::UpdateMarginMode, payload).await
+        }
+        Command::Withdraw { amount } => {
+            ctx.exchange(Operation::Withdraw, WithdrawPayload { amount })
+                .await
+        }
+        Command::Subaccount(SubaccountCommand::Create { subaccount_keypair }) => {
+            let subaccount = read_keypair_file(&subaccount_keypair)?;
+            if ctx.dry_run {
+                let request = prepare_subaccount_create_request(
+                    ctx.keypair()?,
+                    &ctx.main_pubkey()?,
+                    &subaccount,
+                    ctx.expiry_window,
+                )?;
+                let operation = Operation::SubaccountCreate(SubaccountCreateAction::Confirm);
+                return ctx.print_dry_run(&operation, &request);
+            }
+            let client = ctx.exchange_client().await?;
+            print_response(
+                ctx.output,
+                &client
+                    .subaccount_create(&subaccount, ctx.expiry_window)
+                    .await?,
+            )
+        }
+        Command::Subaccount(SubaccountCommand::Transfer { to, amount }) => {
+            let payload = SubaccountTransferPayload {
+                to_account: to,
+                amount,
+            };
+            ctx.exchange(Operation::SubaccountTransfer, payload).await
+        }
+        Command::ApiKey(ApiKeyCommand::Create) => {
+            ctx.exchange(Operation::CreateApiKey, CreateApiKeyPayload {})
+                .await
+        }
+        Command::ApiKey(ApiKeyCommand::List) => {
+            ctx.exchange(Operation::ListApiKeys, ListApiKeysPayload {})
+                .await
+        }
+        Command::ApiKey(ApiKeyCommand::Revoke { api_key }) => {
+            ctx.exchange(Operation::RevokeApiKey, RevokeApiKeyPayload { api_key })
+                .await
+        }
+        Command::Agent(AgentCommand::Bind { agent }) => {
+            if ctx.agent_pubkey()?.is_some() {
+                return Err(ExchangeError::Validation(
+                    "agent wallets must be bound with the main account keypair".into(),
+                ));
+            }
+            let payload = BindAgentWalletPayload {
+                agent_wallet: agent,
+            };
+            ctx.exchange(Operation::BindAgentWallet, payload).await
+        }
+        Command::Keystore(KeystoreCommand::Encrypt { out, kdf }) => {
+            let password = ctx.keystore_password.as_deref().ok_or_else(|| {
+                ExchangeError::Validation(
+                    "pass the new password with --keystore-password or PACIFICA_KEYSTORE_PASSWORD"
+                        .into(),
+                )
+            })?;
+            let kdf = match kdf {
+                KdfArg::Scrypt => Kdf::scrypt(),
+                KdfArg::Argon2id => Kdf::argon2id(),
+            };
+            let keystore = EncryptedKeystore::encrypt(ctx.keypair()?, password, kdf)?;
+            keystore.save(&out)?;
+            println!("{} -> {}", keystore.pubkey, out.display());
+            Ok(())
+        }
+        Command::Keystore(KeystoreCommand::Export { out }) => {
+            write_keypair_file(ctx.keypair()?, &out)?;
+            println!("{} -> {}", ctx.keypair()?.pubkey(), out.display());
+            Ok(())
+        }
+        Command::Audit(AuditCommand::Replay {
+            log,
+            id,
+            endpoint,
+            diff,
+            resend_signed,
+        }) => {
+            let client = RestClient::with_options(&ctx.base_url, ctx.rest_options());
+            client.set_api_key(ctx.api_key.as_deref())?;
+            let records = read_audit_log(&log)?;
+            let selected = records.iter().filter(|r| {
+                id.is_none_or(|id| r.correlation_id == id)
+                    && endpoint.as_ref().is_none_or(|e| r.endpoint.contains(e))
+            });
+            for record in selected {
+                let label = format!(
+                    "{} {} {}",
+                    record.correlation_id, record.method, record.endpoint
+                );
+                if record.method != "GET" && !resend_signed {
+                    println!("{}: skipped, pass --resend-signed to send it", label);
+                    continue;
+                }
+                match client.replay(record).await {
+                    Ok(replayed) if diff => print_replay_diff(&label, record, &replayed),
+                    Ok(replayed) => println!(
+                        "{}: {}\n{}",
+                        label,
+                        status_text(replayed.status),
+                        replayed.response.unwrap_or_default()
+                    ),
+                    Err(e) => println!("{}: {}", label, e),
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn status_text(status: Option<u16>) -> String {
+    status.map_or_else(|| "no response".to_string(), |s| s.to_string())
+}
+
+fn print_replay_diff(label: &str, recorded: &AuditRecord, replayed: &AuditRecord) {
+    println!(
+        "{}: status {} -> {}",
+        label,
+        status_text(recorded.status),
+        status_text(replayed.status)
+    );
+    match (recorded.response_json(), replayed.response_json()) {
+        (Some(before), Some(after)) => {
+            let diffs = diff_json(&before, &after);
+            if diffs.is_empty() {
+                println!("  no differences");
+            }
+            for d in diffs {
+                let show =
+                    |v: Option<serde_json::Value>| v.map_or("-".to_string(), |v| v.to_string());
+                println!("  {}: {} -> {}", d.path, show(d.before), show(d.after));
+            }
+        }
+        // Non-JSON bodies, e.g. proxy error pages, are compared as text
+        _ if recorded.response == replayed.response => println!("  no differences"),
+        _ => println!(
+            "  body {:?} -> {:?}",
+            recorded.response.as_deref().unwrap_or_default(),
+            replayed.response.as_deref().unwrap_or_default()
+        ),
+    }
+}
+
+fn parse_serde<T: DeserializeOwned>(s: &str) -> Result<T, String> {
+    serde_json::from_value(serde_json::Value::String(s.to_string())).map_err(|e| e.to_string())
+}
+
+// Keeps private keys, seed phrases and passwords zeroized once parsed
+fn secret(s: &str) -> Result<Zeroizing<String>, String> {
+    Ok(Zeroizing::new(s.to_string()))
+}
+
+fn parse_side(s: &str) -> Result<OrderSide, String> {
+    match s.to_lowercase().as_str() {
+        "bid" | "buy" | "long" => Ok(OrderSide::Bid),
+        "ask" | "sell" | "short" => Ok(OrderSide::Ask),
+        other => Err(format!("unknown side `{}`, expected bid or ask", other)),
+    }
+}
+
+fn parse_tif(s: &str) -> Result<Tif, String> {
+    parse_serde(&s.to_uppercase())
+}
+
+fn parse_agg_level(s: &str) -> Result<AggLevel, String> {
+    let level: u32 = s.parse().map_err(|e| format!("{}", e))?;
+    AggLevel::try_from(level).map_err(|e| e.to_string())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    dotenvy::dotenv().ok();
+    let cli = Cli::parse();
+    if cli.global.verbose {
+        init_logging_once("debug");
+    }
+
+    match run(cli).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
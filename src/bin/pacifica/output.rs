@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+use pacifica_rust_sdk::common::{errors::ExchangeError, types::DefaultResponse};
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Table,
+}
+
+/// Prints `data` of a successful response, turns API errors into `Err`.
+pub fn print_response<T: Serialize>(
+    format: OutputFormat,
+    response: &DefaultResponse<T>,
+) -> Result<(), ExchangeError> {
+    if response.success == Some(false) || response.error.is_some() {
+        let message = response.error.as_deref().unwrap_or("request failed");
+        return Err(match response.code {
+            Some(code) => ExchangeError::Custom(format!("{} (code {})", message, code)),
+            None => ExchangeError::Custom(message.to_string()),
+        });
+    }
+    let data = serde_json::to_value(&response.data)?;
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&data)?),
+        OutputFormat::Table => print!("{}", render_table(&data)),
+    }
+    Ok(())
+}
+
+/// Arrays of objects become one row per item, objects become key/value rows.
+fn render_table(value: &Value) -> String {
+    let rows: Vec<Vec<String>> = match value {
+        Value::Null => return String::new(),
+        Value::Array(items) if items.iter().all(Value::is_object) && !items.is_empty() => {
+            let mut columns: Vec<&str> = Vec::new();
+            for item in items.iter().filter_map(Value::as_object) {
+                for key in item.keys() {
+                    if !columns.contains(&key.as_str()) {
+                        columns.push(key);
+                    }
+                }
+            }
+            let mut rows = vec![columns.iter().map(|c| c.to_string()).collect()];
+            rows.extend(
+                items
+                    .iter()
+                    .map(|item| columns.iter().map(|c| cell(&item[*c])).collect()),
+            );
+            rows
+        }
+        Value::Array(items) => items.iter().map(|item| vec![cell(item)]).collect(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(key, value)| vec![key.clone(), cell(value)])
+            .collect(),
+        scalar => vec![vec![cell(scalar)]],
+    };
+
+    let widths: Vec<usize> = (0..rows.first().map_or(0, Vec::len))
+        .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in rows {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
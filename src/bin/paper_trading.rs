@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use pacifica_rust_sdk::{
+    common::types::{OrderSide, Tif},
+    exchange::paper_exchange::{PaperExchange, PaperExchangeConfig},
+    info::info_client::InfoClient,
+    logging::init_logging_once,
+    models::exchange::payload::order::{CreateMarketOrderPayload, CreateOrderPayload},
+};
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    init_logging_once("info");
+    let client = InfoClient::new(false, true, None).await.unwrap();
+    let ws = client.web_socket_client.as_ref().unwrap();
+
+    // Strategy code written against `ExchangeClient` methods runs unchanged
+    let paper = PaperExchange::new(Pubkey::new_unique(), PaperExchangeConfig::default());
+    paper.track(ws, "BTC").await.unwrap();
+    let mut updates = paper.order_updates();
+    tokio::spawn(async move {
+        while let Ok(update) = updates.recv().await {
+            info!(
+                "Order {} {:?} {:?}: filled {} of {}",
+                update.order_id,
+                update.event_type,
+                update.order_status,
+                update.filled_amount,
+                update.original_amount
+            );
+        }
+    });
+
+    // Give the book a moment to arrive
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    let response = paper
+        .market_order(
+            CreateMarketOrderPayload {
+                symbol: "BTC".to_string(),
+                amount: Decimal::new(1, 3),
+                side: OrderSide::Bid,
+                slippage_percent: Decimal::new(5, 1),
+                reduce_only: false,
+                client_order_id: None,
+                take_profit: None,
+                stop_loss: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    info!("Market order: {:?}", response);
+
+    let entry = paper.positions()[0].entry_price;
+    let response = paper
+        .order(
+            CreateOrderPayload {
+                symbol: "BTC".to_string(),
+                price: (entry * Decimal::new(1001, 3)).round_dp(0),
+                amount: Decimal::new(1, 3),
+                side: OrderSide::Ask,
+                tif: Tif::ALO,
+                reduce_only: true,
+                client_order_id: None,
+                take_profit: None,
+                stop_loss: None,
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    info!("Take-profit order: {:?}", response);
+
+    tokio::time::sleep(Duration::from_secs(60)).await;
+    info!("Account: {:?}", paper.account());
+    info!("Positions: {:?}", paper.positions());
+    let report = paper.report();
+    info!(
+        "PnL {} (fees {}), {} fills",
+        report.total_pnl,
+        report.fees_paid,
+        report.fill_count()
+    );
+    paper.stop();
+}
@@ -0,0 +1,50 @@
+use std::env;
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    exchange::session_pool::SessionPool, logging::init_logging_once,
+    models::ws::subscriptions::SubscriptionMethod,
+};
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("debug");
+    let is_mainnet = false;
+    let enable_ws = true;
+    let api_key: Option<String> = None;
+
+    // Markets are fetched once, every account reuses them
+    let pool = SessionPool::new(is_mainnet, enable_ws, api_key)
+        .await
+        .map_err(|e| format!("failed to init pool: {:?}", e))
+        .unwrap();
+
+    let main_keypair = Keypair::from_base58_string(env::var("TEST_KEY").unwrap().as_str());
+    let main_pubkey = main_keypair.pubkey();
+    let client = pool
+        .add_account(main_keypair, main_pubkey, None, None)
+        .await
+        .unwrap();
+    // Further accounts: pool.add_account(subaccount_keypair, subaccount_pubkey, None, None)
+
+    let mut positions = pool
+        .subscribe_account(SubscriptionMethod::Positions, client.main_pubkey())
+        .await
+        .unwrap();
+    let mut order_updates = pool
+        .subscribe_account(SubscriptionMethod::AccountOrderUpdates, main_pubkey)
+        .await
+        .unwrap();
+    info!("Connections in use: {:?}", pool.ws_load().await);
+
+    loop {
+        tokio::select! {
+            Some(msg) = positions.recv() => info!("positions: {}", msg),
+            Some(msg) = order_updates.recv() => info!("order updates: {}", msg),
+            else => break,
+        }
+    }
+}
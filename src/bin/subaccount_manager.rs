@@ -0,0 +1,53 @@
+use std::{collections::HashMap, env, sync::Arc};
+
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    exchange::{exchange_client::ExchangeClient, subaccount_manager::SubaccountManager},
+    logging::init_logging_once,
+};
+use rust_decimal::Decimal;
+use solana_sdk::signature::{Keypair, Signer};
+use tracing::info;
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("debug");
+    let is_mainnet = false;
+    let enable_ws = false;
+    let api_key: Option<String> = None;
+    let main_keypair = Keypair::from_base58_string(env::var("TEST_KEY").unwrap().as_str());
+    let main_pubkey = main_keypair.pubkey();
+
+    let client = ExchangeClient::new(
+        is_mainnet,
+        enable_ws,
+        api_key.clone(),
+        main_keypair,
+        main_pubkey,
+        None,
+    )
+    .await
+    .map_err(|e| format!("failed to init client: {:?}", e))
+    .unwrap();
+
+    let registry = env::temp_dir().join("pacifica_subaccounts.json");
+    let mut manager = SubaccountManager::open(Arc::new(client), &registry).unwrap();
+
+    let subaccount = Keypair::new();
+    let subaccount_pubkey = manager
+        .create(subaccount, Some("hedge".to_string()))
+        .await
+        .unwrap();
+
+    let report = manager.equity_report().await.unwrap();
+    info!("{}", serde_json::to_string_pretty(&report).unwrap());
+
+    // 80% of the equity on the main account, 20% on the new subaccount
+    let targets = HashMap::from([
+        (main_pubkey, Decimal::from(80)),
+        (subaccount_pubkey, Decimal::from(20)),
+    ]);
+    let transfers = manager.rebalance(&targets, Decimal::ONE).await.unwrap();
+    info!("Rebalance transfers: {:?}", transfers);
+}
@@ -30,6 +30,21 @@ async fn main() {
 
     info!("InfoClient initialized");
 
+    // Connection lifecycle: connecting, connected, disconnected, resubscribed
+    if let Some(ws_client) = client.web_socket_client.clone() {
+        let mut events = ws_client.events();
+        tokio::spawn(async move {
+            while let Ok(event) = events.recv().await {
+                info!(
+                    "WebSocket {:?}, state {:?}, stats {:?}",
+                    event,
+                    ws_client.state(),
+                    ws_client.stats()
+                );
+            }
+        });
+    }
+
     // Test Pubkey
     let account: Pubkey = Pubkey::from_str("94HjQxftTdgKkTLGVEQm11pNr1A8RNSQij2M1fZpdyfn").unwrap();
     let symbol = "BTC";
@@ -0,0 +1,45 @@
+use dotenvy::from_filename;
+use pacifica_rust_sdk::{
+    logging::init_logging_once,
+    models::ws::responses::{PricesResponse, TradesResponse},
+    ws::{
+        replay::{ReplaySpeed, ReplayWebSocketClient},
+        ws_client::Subscription,
+    },
+};
+use tracing::{error, info};
+
+// Strategy code only sees `Subscription`s, live or replayed
+async fn run_strategy(mut prices: Subscription, mut trades: Subscription) {
+    loop {
+        tokio::select! {
+            Some(msg) = prices.recv_typed::<PricesResponse>() => match msg {
+                Ok(msg) => info!("{} prices", msg.data.len()),
+                Err(e) => error!("bad prices message: {:?}", e),
+            },
+            Some(msg) = trades.recv_typed::<TradesResponse>() => match msg {
+                Ok(msg) => info!("{} BTC trades", msg.data.len()),
+                Err(e) => error!("bad trades message: {:?}", e),
+            },
+            else => break,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    from_filename("src/bin/EXAMPLE.env").ok();
+    init_logging_once("info");
+
+    // Written by the market_recorder example
+    let dir = std::env::temp_dir().join("pacifica_market_data");
+    let client =
+        ReplayWebSocketClient::from_dir(&dir, "market", ReplaySpeed::Accelerated(10.0)).unwrap();
+    let prices = client.subscribe_to_prices().await.unwrap();
+    let trades = client.subscribe_to_trades("BTC").await.unwrap();
+    let clock = client.clock();
+
+    client.start().unwrap();
+    run_strategy(prices, trades).await;
+    info!("Replay finished at {}", clock.now_ms());
+}
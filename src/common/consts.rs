@@ -3,3 +3,8 @@ pub static REST_API_TESTNET_URL: &str = "https://test-api.pacifica.fi/api";
 pub static API_VERSION_ENDPOINT: &str = "/v1";
 pub static WS_MAINNET_URL: &str = "wss://ws.pacifica.fi/ws";
 pub static WS_TESTNET_URL: &str = "wss://test-ws.pacifica.fi/ws";
+pub static MAX_REQUEST_BYTES: usize = 4096;
+// "~10 operations" fit the request size limit according to the rate limit docs
+pub static MAX_BATCH_ACTIONS: usize = 10;
+pub static MAX_WS_CONNECTIONS_PER_IP: usize = 100;
+pub static MAX_WS_SUBSCRIPTIONS_PER_CHANNEL: usize = 20;
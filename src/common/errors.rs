@@ -1,5 +1,8 @@
 use reqwest::header::InvalidHeaderValue;
+use rust_decimal::Decimal;
 use thiserror::Error;
+
+use crate::rest::response::RateLimit;
 use tokio_tungstenite::tungstenite::{
     Error as TungsteniteError, http::Error as TungsteniteHttpError,
 };
@@ -12,16 +15,153 @@ pub enum SignError {
     Unknown,
 }
 
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+    #[error("Kill switch is active")]
+    KillSwitchActive,
+    #[error("Symbol {0} is not allowed")]
+    SymbolNotAllowed(String),
+    #[error("Order notional {notional} exceeds limit {limit} for {symbol}")]
+    MaxNotionalExceeded {
+        symbol: String,
+        notional: Decimal,
+        limit: Decimal,
+    },
+    #[error("Resulting position {position} exceeds limit {limit} for {symbol}")]
+    MaxPositionExceeded {
+        symbol: String,
+        position: Decimal,
+        limit: Decimal,
+    },
+    #[error("Open orders {open} would exceed limit {limit}")]
+    MaxOpenOrdersExceeded { open: u32, limit: u32 },
+    #[error("Order rate exceeds {limit} orders per second")]
+    RateLimitExceeded { limit: u32 },
+    #[error("Price {price} deviates {deviation} from reference {reference} for {symbol}")]
+    PriceOutOfBand {
+        symbol: String,
+        price: Decimal,
+        reference: Decimal,
+        deviation: Decimal,
+    },
+    #[error("No reference price for {0}")]
+    MissingReferencePrice(String),
+}
+
+// Classification of exchange error messages, see docs/error-codes.md
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderRejectReason {
+    PostOnlyRejected,
+    InsufficientBalance,
+    OrderNotFound,
+    AmountTooLow,
+    AmountTooHigh,
+    OpenOrderLimit,
+    InvalidOrderType,
+    DuplicateClientOrderId,
+    UnusedClientOrderId,
+    BookNotFound,
+    InvalidTick,
+    ImmediateLiquidation,
+    PriceTooFarFromMark,
+    InvalidReduceOnly,
+    NoPositionForReduceOnly,
+    InvalidSignature,
+    RateLimited,
+    Timeout,
+    // The request carrying the action failed before it reached the exchange
+    RequestFailed(String),
+    // The request may have reached the exchange but no per-action answer came
+    // back, e.g. a timeout after sending, a 5xx or an undecodable body. The
+    // action may have been applied, check before resubmitting
+    OutcomeUnknown(String),
+    Unknown(String),
+}
+
+impl OrderRejectReason {
+    pub fn from_message(message: &str) -> Self {
+        let m = message.to_lowercase();
+        let patterns: [(&str, OrderRejectReason); 18] = [
+            ("post only", OrderRejectReason::PostOnlyRejected),
+            ("post_only", OrderRejectReason::PostOnlyRejected),
+            (
+                "insufficient balance",
+                OrderRejectReason::InsufficientBalance,
+            ),
+            ("order not found", OrderRejectReason::OrderNotFound),
+            ("order amount too low", OrderRejectReason::AmountTooLow),
+            ("order amount too high", OrderRejectReason::AmountTooHigh),
+            ("open order limit", OrderRejectReason::OpenOrderLimit),
+            ("invalid order type", OrderRejectReason::InvalidOrderType),
+            (
+                "duplicate client order id",
+                OrderRejectReason::DuplicateClientOrderId,
+            ),
+            (
+                "unused client order id",
+                OrderRejectReason::UnusedClientOrderId,
+            ),
+            ("book not found", OrderRejectReason::BookNotFound),
+            ("invalid tick", OrderRejectReason::InvalidTick),
+            (
+                "immediate liquidation",
+                OrderRejectReason::ImmediateLiquidation,
+            ),
+            (
+                "price too far from mark",
+                OrderRejectReason::PriceTooFarFromMark,
+            ),
+            ("invalid reduce-only", OrderRejectReason::InvalidReduceOnly),
+            (
+                "no position found for reduce-only",
+                OrderRejectReason::NoPositionForReduceOnly,
+            ),
+            ("rate limit", OrderRejectReason::RateLimited),
+            ("timed out", OrderRejectReason::Timeout),
+        ];
+        if m.contains("invalid signature") || m.contains("verification failed") {
+            return OrderRejectReason::InvalidSignature;
+        }
+        patterns
+            .into_iter()
+            .find(|(pattern, _)| m.contains(pattern))
+            .map(|(_, reason)| reason)
+            .unwrap_or_else(|| OrderRejectReason::Unknown(message.to_string()))
+    }
+
+    /// Transient failures that may succeed when re-signed and resubmitted.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            OrderRejectReason::RateLimited
+                | OrderRejectReason::Timeout
+                | OrderRejectReason::RequestFailed(_)
+        )
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum ExchangeError {
     #[error("Custom error: {0}")]
     Custom(String),
     #[error("HTTP error: {0}")]
     Http(#[from] reqwest::Error),
+    #[error("HTTP status {status}: {}", excerpt(body))]
+    HttpStatus {
+        status: u16,
+        body: String,
+        rate_limit: Option<RateLimit>,
+    },
     #[error("Header not valid: {0}")]
     InvalidHeaderValue(#[from] InvalidHeaderValue),
     #[error("Serialization error: {0}")]
     Serde(#[from] serde_json::Error),
+    // The exchange answered, so whatever was sent may have been applied
+    #[error("Response decode error (status {status}): {source}")]
+    ResponseDecode {
+        status: u16,
+        source: serde_json::Error,
+    },
     #[error("Signing error: {0}")]
     Sign(#[from] SignError),
     #[error("IO error: {0}")]
@@ -52,6 +192,59 @@ pub enum ExchangeError {
     InvalidPriceValue(String),
     #[error("Symbol not found in hashmap: {0}")]
     SymbolNotFound(String),
+    #[error("Mark price not found for symbol: {0}")]
+    MarkPriceNotFound(String),
+    #[error("Risk check failed: {0}")]
+    RiskViolation(#[from] RiskViolation),
+    #[error("Keystore error: {0}")]
+    Keystore(String),
+}
+
+impl ExchangeError {
+    /// HTTP status of a failed REST call, when one was received.
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            ExchangeError::HttpStatus { status, .. }
+            | ExchangeError::ResponseDecode { status, .. } => Some(*status),
+            ExchangeError::Http(e) => e.status().map(|s| s.as_u16()),
+            _ => None,
+        }
+    }
+
+    pub fn rate_limit(&self) -> Option<&RateLimit> {
+        match self {
+            ExchangeError::HttpStatus { rate_limit, .. } => rate_limit.as_ref(),
+            _ => None,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, ExchangeError::Http(e) if e.is_timeout())
+    }
+
+    /// `true` when the request certainly did not reach the exchange: it failed
+    /// while being built, serialized or signed, or no connection could be opened.
+    /// A response that doesn't decode (`ResponseDecode`) is not unsent.
+    pub fn is_unsent(&self) -> bool {
+        match self {
+            ExchangeError::Http(e) => e.is_builder() || e.is_connect(),
+            ExchangeError::Serde(_)
+            | ExchangeError::Sign(_)
+            | ExchangeError::InvalidHeaderValue(_)
+            | ExchangeError::Validation(_)
+            | ExchangeError::NotInitialized(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// Error pages can be long HTML documents
+fn excerpt(body: &str) -> String {
+    const MAX_CHARS: usize = 200;
+    match body.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body.to_string(),
+    }
 }
 
 impl From<TungsteniteError> for ExchangeError {
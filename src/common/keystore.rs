@@ -0,0 +1,439 @@
+use std::{io::Write, path::Path};
+
+use chacha20poly1305::{
+    ChaCha20Poly1305, Key, Nonce,
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload, rand_core::RngCore},
+};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_derivation_path::DerivationPath;
+use solana_keypair::seed_derivable::keypair_from_seed_and_derivation_path;
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use zeroize::Zeroizing;
+
+use crate::common::errors::ExchangeError;
+
+/// Path used by Phantom, Solflare and `solana-keygen ... 'prompt://?key=0/0'`.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/501'/0'/0'";
+
+const KEYSTORE_VERSION: u32 = 1;
+const SALT_LEN: usize = 32;
+// Ceilings on the KDF cost read from a keystore file, so a crafted file can't
+// make decryption allocate or run without bound. scrypt uses 128 * r * 2^log_n
+// bytes, p times over; Argon2id uses m_cost KiB, t_cost passes over it.
+const MAX_SCRYPT_MEMORY: u64 = 1 << 30;
+const MAX_SCRYPT_P: u32 = 16;
+const MAX_ARGON2_M_COST: u32 = 1 << 20;
+// t_cost * m_cost, e.g. four passes over the largest memory
+const MAX_ARGON2_WORK: u64 = 4 << 20;
+const MAX_ARGON2_P_COST: u32 = 16;
+
+/// Reads a Solana CLI keypair file, a JSON array of the 64 secret key bytes.
+pub fn read_keypair_file(path: impl AsRef<Path>) -> Result<Keypair, ExchangeError> {
+    let path = path.as_ref();
+    let content = Zeroizing::new(std::fs::read_to_string(path)?);
+    let bytes: Zeroizing<Vec<u8>> = Zeroizing::new(serde_json::from_str(&content)?);
+    Keypair::try_from(bytes.as_slice()).map_err(|e| {
+        ExchangeError::Keystore(format!("invalid keypair in {}: {}", path.display(), e))
+    })
+}
+
+/// Writes `keypair` in the Solana CLI format, readable by the owner only on unix.
+pub fn write_keypair_file(keypair: &Keypair, path: impl AsRef<Path>) -> Result<(), ExchangeError> {
+    let bytes = Zeroizing::new(keypair.to_bytes());
+    let content = Zeroizing::new(serde_json::to_vec(bytes.as_slice())?);
+    write_private(path.as_ref(), &content)
+}
+
+/// Derives a keypair from a BIP39 mnemonic.
+///
+/// With a derivation path (`m/44'/501'/0'/0'` or the `solana-keygen` short form `0/0`)
+/// the key is derived as wallets do. Without one it matches the legacy
+/// `solana-keygen recover` output, the first 32 bytes of the seed.
+pub fn keypair_from_mnemonic(
+    phrase: &str,
+    passphrase: &str,
+    derivation_path: Option<&str>,
+) -> Result<Keypair, ExchangeError> {
+    let mnemonic = bip39::Mnemonic::parse(phrase)
+        .map_err(|e| ExchangeError::Keystore(format!("invalid mnemonic: {}", e)))?;
+    let seed = Zeroizing::new(mnemonic.to_seed(passphrase));
+    match derivation_path {
+        Some(path) => {
+            let path = parse_derivation_path(path)?;
+            keypair_from_seed_and_derivation_path(seed.as_slice(), Some(path))
+                .map_err(|e| ExchangeError::Keystore(format!("key derivation failed: {}", e)))
+        }
+        None => {
+            let secret: Zeroizing<[u8; 32]> =
+                Zeroizing::new(seed[..32].try_into().expect("seed is 64 bytes"));
+            Ok(Keypair::new_from_array(*secret))
+        }
+    }
+}
+
+fn parse_derivation_path(path: &str) -> Result<DerivationPath, ExchangeError> {
+    let parsed = if path.starts_with('m') {
+        DerivationPath::from_absolute_path_str(path)
+    } else {
+        DerivationPath::from_key_str(path)
+    };
+    parsed.map_err(|e| ExchangeError::Keystore(format!("invalid derivation path {}: {}", path, e)))
+}
+
+/// Password based key derivation used by `EncryptedKeystore`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "name", rename_all = "lowercase")]
+pub enum Kdf {
+    Scrypt {
+        log_n: u8,
+        r: u32,
+        p: u32,
+    },
+    Argon2id {
+        m_cost: u32,
+        t_cost: u32,
+        p_cost: u32,
+    },
+}
+
+impl Kdf {
+    /// scrypt with the parameters recommended by the `scrypt` crate (N = 2^17).
+    pub fn scrypt() -> Self {
+        Kdf::Scrypt {
+            log_n: scrypt::Params::RECOMMENDED_LOG_N,
+            r: scrypt::Params::RECOMMENDED_R,
+            p: scrypt::Params::RECOMMENDED_P,
+        }
+    }
+
+    /// Argon2id with the OWASP parameters used as `argon2` crate defaults.
+    pub fn argon2id() -> Self {
+        Kdf::Argon2id {
+            m_cost: argon2::Params::DEFAULT_M_COST,
+            t_cost: argon2::Params::DEFAULT_T_COST,
+            p_cost: argon2::Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive_key(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+    ) -> Result<Zeroizing<[u8; 32]>, ExchangeError> {
+        let mut key = Zeroizing::new([0u8; 32]);
+        match *self {
+            Kdf::Scrypt { log_n, r, p } => {
+                // Saturates rather than overflow for a huge log_n
+                let memory = 1u64
+                    .checked_shl(log_n.into())
+                    .map_or(u64::MAX, |n| n.saturating_mul(128 * u64::from(r)));
+                check_cost("scrypt memory (bytes)", memory, MAX_SCRYPT_MEMORY)?;
+                check_cost("scrypt p", p.into(), MAX_SCRYPT_P.into())?;
+                let params = scrypt::Params::new(log_n, r, p, key.len()).map_err(|e| {
+                    ExchangeError::Keystore(format!("invalid scrypt params: {}", e))
+                })?;
+                scrypt::scrypt(password, salt, &params, key.as_mut_slice())
+                    .map_err(|e| ExchangeError::Keystore(format!("scrypt failed: {}", e)))?;
+            }
+            Kdf::Argon2id {
+                m_cost,
+                t_cost,
+                p_cost,
+            } => {
+                check_cost(
+                    "argon2 m_cost (KiB)",
+                    m_cost.into(),
+                    MAX_ARGON2_M_COST.into(),
+                )?;
+                let work = u64::from(t_cost) * u64::from(m_cost);
+                check_cost("argon2 t_cost * m_cost (KiB)", work, MAX_ARGON2_WORK)?;
+                check_cost("argon2 p_cost", p_cost.into(), MAX_ARGON2_P_COST.into())?;
+                let params =
+                    argon2::Params::new(m_cost, t_cost, p_cost, Some(key.len())).map_err(|e| {
+                        ExchangeError::Keystore(format!("invalid argon2 params: {}", e))
+                    })?;
+                argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+                    .hash_password_into(password, salt, key.as_mut_slice())
+                    .map_err(|e| ExchangeError::Keystore(format!("argon2 failed: {}", e)))?;
+            }
+        }
+        Ok(key)
+    }
+}
+
+fn check_cost(name: &str, value: u64, limit: u64) -> Result<(), ExchangeError> {
+    if value > limit {
+        return Err(ExchangeError::Keystore(format!(
+            "{} {} is above the limit of {}",
+            name, value, limit
+        )));
+    }
+    Ok(())
+}
+
+/// A keypair encrypted with ChaCha20-Poly1305 under a password derived key.
+///
+/// Binary fields are base58 encoded. The public key is stored in clear so the
+/// account can be identified without the password, and is authenticated as
+/// associated data.
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncryptedKeystore {
+    pub version: u32,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pubkey: Pubkey,
+    pub kdf: Kdf,
+    pub salt: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl EncryptedKeystore {
+    pub fn encrypt(keypair: &Keypair, password: &str, kdf: Kdf) -> Result<Self, ExchangeError> {
+        let pubkey = keypair.pubkey();
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = kdf.derive_key(password.as_bytes(), &salt)?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let secret = Zeroizing::new(keypair.to_bytes());
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_slice()))
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: secret.as_slice(),
+                    aad: pubkey.as_ref(),
+                },
+            )
+            .map_err(|e| ExchangeError::Keystore(format!("encryption failed: {}", e)))?;
+        Ok(Self {
+            version: KEYSTORE_VERSION,
+            pubkey,
+            kdf,
+            salt: bs58::encode(salt).into_string(),
+            nonce: bs58::encode(nonce).into_string(),
+            ciphertext: bs58::encode(ciphertext).into_string(),
+        })
+    }
+
+    /// Fails on a wrong password or a modified file.
+    pub fn decrypt(&self, password: &str) -> Result<Keypair, ExchangeError> {
+        if self.version != KEYSTORE_VERSION {
+            return Err(ExchangeError::Keystore(format!(
+                "unsupported keystore version {}",
+                self.version
+            )));
+        }
+        let salt = decode_field("salt", &self.salt)?;
+        let nonce = decode_field("nonce", &self.nonce)?;
+        let ciphertext = decode_field("ciphertext", &self.ciphertext)?;
+        if nonce.len() != 12 {
+            return Err(ExchangeError::Keystore("invalid keystore nonce".into()));
+        }
+        let key = self.kdf.derive_key(password.as_bytes(), &salt)?;
+        let secret = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(key.as_slice()))
+                .decrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &ciphertext,
+                        aad: self.pubkey.as_ref(),
+                    },
+                )
+                .map_err(|_| {
+                    ExchangeError::Keystore("wrong password or corrupted keystore".into())
+                })?,
+        );
+        let keypair = Keypair::try_from(secret.as_slice())
+            .map_err(|e| ExchangeError::Keystore(format!("invalid decrypted key: {}", e)))?;
+        if keypair.pubkey() != self.pubkey {
+            return Err(ExchangeError::Keystore(
+                "decrypted key does not match the keystore pubkey".into(),
+            ));
+        }
+        Ok(keypair)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError> {
+        write_private(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+}
+
+fn decode_field(name: &str, value: &str) -> Result<Vec<u8>, ExchangeError> {
+    bs58::decode(value)
+        .into_vec()
+        .map_err(|e| ExchangeError::Keystore(format!("invalid keystore {}: {}", name, e)))
+}
+
+fn write_private(path: &Path, content: &[u8]) -> Result<(), ExchangeError> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // `mode` only applies to a new file
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.json", name, uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_keypair_file_roundtrip() {
+        let keypair = Keypair::new();
+        let path = temp_path("keypair");
+        write_keypair_file(&keypair, &path).unwrap();
+        let loaded = read_keypair_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_bytes(), keypair.to_bytes());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_overwrite_restricts_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("keypair");
+        std::fs::write(&path, b"[]").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_keypair_file(&Keypair::new(), &path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn test_mnemonic_derivation() {
+        let derived = keypair_from_mnemonic(PHRASE, "", Some(DEFAULT_DERIVATION_PATH)).unwrap();
+        assert_eq!(
+            derived.pubkey().to_string(),
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+        let short = keypair_from_mnemonic(PHRASE, "", Some("0/0")).unwrap();
+        assert_eq!(short.pubkey(), derived.pubkey());
+
+        let legacy = keypair_from_mnemonic(PHRASE, "", None).unwrap();
+        let with_passphrase = keypair_from_mnemonic(PHRASE, "secret", None).unwrap();
+        assert_ne!(legacy.pubkey(), derived.pubkey());
+        assert_ne!(legacy.pubkey(), with_passphrase.pubkey());
+
+        assert!(keypair_from_mnemonic("abandon abandon", "", None).is_err());
+        assert!(keypair_from_mnemonic(PHRASE, "", Some("m/44'/x")).is_err());
+    }
+
+    #[test]
+    fn test_encrypted_keystore_roundtrip() {
+        let keypair = Keypair::new();
+        let kdfs = [
+            Kdf::Scrypt {
+                log_n: 10,
+                r: 8,
+                p: 1,
+            },
+            Kdf::Argon2id {
+                m_cost: 1024,
+                t_cost: 1,
+                p_cost: 1,
+            },
+        ];
+        for kdf in kdfs {
+            let keystore = EncryptedKeystore::encrypt(&keypair, "hunter2", kdf.clone()).unwrap();
+            let path = temp_path("keystore");
+            keystore.save(&path).unwrap();
+            let loaded = EncryptedKeystore::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(loaded.kdf, kdf);
+            assert_eq!(loaded.pubkey, keypair.pubkey());
+            assert_eq!(
+                loaded.decrypt("hunter2").unwrap().to_bytes(),
+                keypair.to_bytes()
+            );
+            assert!(loaded.decrypt("hunter3").is_err());
+        }
+    }
+
+    #[test]
+    fn test_kdf_cost_is_capped() {
+        let keystore = EncryptedKeystore::encrypt(
+            &Keypair::new(),
+            "hunter2",
+            Kdf::Scrypt {
+                log_n: 10,
+                r: 8,
+                p: 1,
+            },
+        )
+        .unwrap();
+        // Each is rejected before any derivation work, which would take
+        // minutes or exhaust memory otherwise
+        for kdf in [
+            Kdf::Scrypt {
+                log_n: 40,
+                r: 8,
+                p: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 255,
+                r: 1,
+                p: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 20,
+                r: u32::MAX,
+                p: 1,
+            },
+            Kdf::Scrypt {
+                log_n: 10,
+                r: 8,
+                p: u32::MAX,
+            },
+            Kdf::Argon2id {
+                m_cost: u32::MAX,
+                t_cost: 1,
+                p_cost: 1,
+            },
+            Kdf::Argon2id {
+                m_cost: 1 << 20,
+                t_cost: u32::MAX,
+                p_cost: 1,
+            },
+            Kdf::Argon2id {
+                m_cost: 1 << 10,
+                t_cost: 1,
+                p_cost: u32::MAX,
+            },
+        ] {
+            let crafted = EncryptedKeystore {
+                kdf,
+                ..keystore.clone()
+            };
+            let err = crafted.decrypt("hunter2").unwrap_err().to_string();
+            assert!(err.contains("above the limit"), "{}", err);
+        }
+    }
+}
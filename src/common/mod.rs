@@ -1,5 +1,6 @@
 pub mod consts;
 pub mod errors;
+pub mod keystore;
 pub mod signing;
 pub mod tick_lot;
 pub mod types;
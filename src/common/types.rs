@@ -289,6 +289,13 @@ pub struct FinalRequest<P> {
     pub payload: P,
 }
 
+// Signed body and the endpoint it has to be posted to, see `ExchangeClient::submit_prebuilt`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PrebuiltRequest<B> {
+    pub endpoint: String,
+    pub body: B,
+}
+
 // WebSocket structs don't implement validation fn,
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct WebSocketParams<P> {
@@ -1,22 +1,36 @@
 use std::{
     fmt::Debug,
     str::FromStr,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Instant, SystemTime, UNIX_EPOCH},
 };
 
 use rust_decimal::prelude::ToPrimitive;
 use serde::{Serialize, Serializer};
 use serde_json::{Map, Value};
-use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
 use tracing::debug;
 use uuid::Uuid;
 
-use crate::common::{
-    errors::ExchangeError,
-    signing::sign_message,
-    types::{
-        DefaultFinalHeaders, DefaultSignatureHeaders, FinalRequest, OperationFinalHeaders,
-        PacificSignature,
+use crate::{
+    common::{
+        errors::ExchangeError,
+        signing::sign_message,
+        types::{
+            DefaultFinalHeaders, DefaultResponse, DefaultSignatureHeaders, FinalRequest,
+            OperationFinalHeaders, PacificSignature, SubAccountFinalHeaders,
+        },
+    },
+    exchange::operations::{Operation, SubaccountCreateAction},
+    logging::redacted_json,
+    metrics,
+    models::exchange::payload::{
+        batch_order::{
+            BatchOrderActionPayload, BatchOrderActionType, BatchOrderActionsFinalHeaders,
+        },
+        subaccount::{SubaccountConfirmPayload, SubaccountInitiatePayload},
     },
 };
 
@@ -112,6 +126,22 @@ pub fn match_both_some<T, P>(one: &Option<T>, second: &Option<P>) -> Result<(),
     }
 }
 
+/// Turns an API level failure (`success: false` or an `error`) into `Err`.
+pub fn ensure_success<T>(
+    operation: &str,
+    response: &DefaultResponse<T>,
+) -> Result<(), ExchangeError> {
+    if response.success == Some(false) || response.error.is_some() {
+        return Err(ExchangeError::Custom(format!(
+            "{} rejected: {} (code {:?})",
+            operation,
+            response.error.as_deref().unwrap_or("unknown error"),
+            response.code
+        )));
+    }
+    Ok(())
+}
+
 pub trait Validatable {
     fn validate(&self) -> Result<(), String> {
         Ok(())
@@ -140,12 +170,14 @@ pub async fn prepare_final_request<P>(
 where
     P: Serialize + Debug,
 {
+    let started = Instant::now();
     let sign_headers = DefaultSignatureHeaders {
         timestamp: get_timestamp_ms(),
         expiry_window,
         type_field: request_method.to_string(),
     };
     let (_message, signature) = sign_message(&sign_headers, &sign_payload, keypair)?;
+    metrics::record_sign(request_method, started.elapsed());
     let final_headers = OperationFinalHeaders::Default(DefaultFinalHeaders {
         account: *main_pubkey,
         agent_wallet: *agent_pubkey,
@@ -158,7 +190,102 @@ where
         payload: sign_payload,
     };
 
-    debug!("create_final_request: final_request={:?}", &final_request);
+    debug!(
+        "create_final_request: final_request={}",
+        redacted_json(&final_request)
+    );
 
     Ok(final_request)
 }
+
+// Batch actions are signed one by one with the operation name of the single request
+pub fn sign_batch_action(
+    action: BatchOrderActionPayload,
+    expiry_window: Option<u32>,
+    keypair: &Keypair,
+    main_pubkey: &Pubkey,
+    agent_pubkey: &Option<Pubkey>,
+) -> Result<BatchOrderActionsFinalHeaders, ExchangeError> {
+    let (action_type, operation) = match action {
+        BatchOrderActionPayload::CreateOrder(_) => {
+            (BatchOrderActionType::Create, Operation::CreateOrder)
+        }
+        BatchOrderActionPayload::CancelOrder(_) => {
+            (BatchOrderActionType::Cancel, Operation::CancelOrder)
+        }
+        BatchOrderActionPayload::CreateMarketOrder(_) => (
+            BatchOrderActionType::CreateMarket,
+            Operation::CreateMarketOrder,
+        ),
+    };
+    let started = Instant::now();
+    let sign_headers = DefaultSignatureHeaders {
+        timestamp: get_timestamp_ms(),
+        expiry_window,
+        type_field: operation.name()?,
+    };
+    let (_message, signature) = sign_message(&sign_headers, &action, keypair)?;
+    metrics::record_sign(&sign_headers.type_field, started.elapsed());
+    let final_headers = OperationFinalHeaders::Default(DefaultFinalHeaders {
+        account: *main_pubkey,
+        agent_wallet: *agent_pubkey,
+        signature: PacificSignature::Simple(signature),
+        expiry_window: sign_headers.expiry_window,
+        timestamp: sign_headers.timestamp,
+    });
+
+    Ok(BatchOrderActionsFinalHeaders {
+        type_field: action_type,
+        data: FinalRequest {
+            headers: final_headers,
+            payload: action,
+        },
+    })
+}
+
+// Subaccount creation carries two signatures: the subaccount signs the main
+// account, then the main account signs the subaccount's signature.
+pub fn prepare_subaccount_create_request(
+    main_keypair: &Keypair,
+    main_pubkey: &Pubkey,
+    subaccount: &Keypair,
+    expiry_window: Option<u32>,
+) -> Result<FinalRequest<()>, ExchangeError> {
+    let started = Instant::now();
+    let timestamp = get_timestamp_ms();
+
+    let sub_headers = DefaultSignatureHeaders {
+        timestamp,
+        expiry_window,
+        type_field: Operation::SubaccountCreate(SubaccountCreateAction::Initiate).name()?,
+    };
+    let sub_payload = SubaccountInitiatePayload {
+        account: *main_pubkey,
+    };
+    let (_sub_msg, sub_signature) = sign_message(&sub_headers, &sub_payload, subaccount)?;
+
+    let main_headers = DefaultSignatureHeaders {
+        timestamp,
+        expiry_window,
+        type_field: Operation::SubaccountCreate(SubaccountCreateAction::Confirm).name()?,
+    };
+    let main_payload = SubaccountConfirmPayload {
+        signature: sub_signature.clone(),
+    };
+    let (_main_msg, main_signature) = sign_message(&main_headers, &main_payload, main_keypair)?;
+    metrics::record_sign(&main_headers.type_field, started.elapsed());
+
+    let final_headers = OperationFinalHeaders::SubAccountCreate(SubAccountFinalHeaders {
+        main_account: *main_pubkey,
+        subaccount: subaccount.pubkey(),
+        main_signature: PacificSignature::Simple(main_signature),
+        sub_signature: PacificSignature::Simple(sub_signature),
+        expiry_window,
+        timestamp,
+    });
+
+    Ok(FinalRequest {
+        headers: final_headers,
+        payload: (),
+    })
+}
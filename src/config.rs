@@ -0,0 +1,620 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use zeroize::Zeroizing;
+
+use crate::{
+    common::{
+        consts,
+        errors::ExchangeError,
+        keystore::{EncryptedKeystore, keypair_from_mnemonic, read_keypair_file},
+    },
+    exchange::exchange_client::ExchangeClient,
+    info::info_client::InfoClient,
+    logging::{LogFormat, try_init_logging},
+    rest::rest_client::RestClientOptions,
+    ws::ws_client::WebSocketOptions,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Network {
+    Mainnet,
+    #[default]
+    Testnet,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WsConfig {
+    pub ping_interval_ms: u64,
+    pub reconnect_delay_ms: u64,
+    pub max_reconnect_delay_ms: u64,
+    pub max_reconnect_attempts: Option<u32>,
+    pub send_timeout_ms: u64,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        let defaults = WebSocketOptions::default();
+        Self {
+            ping_interval_ms: defaults.ping_interval.as_millis() as u64,
+            reconnect_delay_ms: defaults.reconnect_delay.as_millis() as u64,
+            max_reconnect_delay_ms: defaults.max_reconnect_delay.as_millis() as u64,
+            max_reconnect_attempts: defaults.max_reconnect_attempts,
+            send_timeout_ms: defaults.send_timeout.as_millis() as u64,
+        }
+    }
+}
+
+/// Everything needed to build `InfoClient` and `ExchangeClient`, loaded from a
+/// TOML or JSON file and/or `PACIFICA_*` environment variables.
+///
+/// ```toml
+/// network = "mainnet"
+/// keypair_path = "/home/trader/.config/solana/trader.json"
+/// # or: keystore_path = "trader.keystore.json" with PACIFICA_KEYSTORE_PASSWORD set
+/// account = "7xKX..."          # set when the keypair is an agent wallet
+/// enable_ws = true
+/// max_requests_per_second = 10
+///
+/// [ws]
+/// ping_interval_ms = 30000
+/// ```
+#[serde_as]
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    pub network: Network,
+    // Override the URLs selected by `network`
+    pub base_url: Option<String>,
+    pub ws_url: Option<String>,
+    pub enable_ws: bool,
+    // Secrets are read but never written back when the config is serialized
+    #[serde(skip_serializing)]
+    pub api_key: Option<String>,
+    // Key sources below are mutually exclusive
+    // Solana CLI keypair file
+    pub keypair_path: Option<PathBuf>,
+    // Base58 encoded
+    #[serde(skip_serializing)]
+    pub private_key: Option<Zeroizing<String>>,
+    // BIP39 phrase, derived with `derivation_path` when set
+    #[serde(skip_serializing)]
+    pub mnemonic: Option<Zeroizing<String>>,
+    pub derivation_path: Option<String>,
+    // `EncryptedKeystore` file
+    pub keystore_path: Option<PathBuf>,
+    // Secrets unlocking `mnemonic` / `keystore_path`, only read from the environment
+    #[serde(skip)]
+    pub mnemonic_passphrase: Option<Zeroizing<String>>,
+    #[serde(skip)]
+    pub keystore_password: Option<Zeroizing<String>>,
+    // Main account. When it differs from the signer, the signer is its agent wallet
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub account: Option<Pubkey>,
+    pub request_timeout_ms: Option<u64>,
+    pub connect_timeout_ms: Option<u64>,
+    pub max_requests_per_second: Option<u32>,
+    pub ws: WsConfig,
+    pub log_level: Option<String>,
+    pub log_format: LogFormat,
+    #[serde(skip)]
+    unlocked: UnlockedKeystore,
+}
+
+// Keystore decrypted by `validate`, reused by `keypair` while the path and password
+// stay the same so the KDF runs once
+#[derive(Clone, Default)]
+struct UnlockedKeystore(Arc<Mutex<Option<Unlocked>>>);
+
+// Keystore path, password and the keypair they decrypt to
+type Unlocked = (PathBuf, Zeroizing<String>, Keypair);
+
+// Keeps secrets out of logs
+impl std::fmt::Debug for ClientConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn redacted<T>(v: &Option<T>) -> Option<&'static str> {
+            v.as_ref().map(|_| "<redacted>")
+        }
+        f.debug_struct("ClientConfig")
+            .field("network", &self.network)
+            .field("base_url", &self.base_url)
+            .field("ws_url", &self.ws_url)
+            .field("enable_ws", &self.enable_ws)
+            .field("api_key", &redacted(&self.api_key))
+            .field("keypair_path", &self.keypair_path)
+            .field("private_key", &redacted(&self.private_key))
+            .field("mnemonic", &redacted(&self.mnemonic))
+            .field("derivation_path", &self.derivation_path)
+            .field("keystore_path", &self.keystore_path)
+            .field("mnemonic_passphrase", &redacted(&self.mnemonic_passphrase))
+            .field("keystore_password", &redacted(&self.keystore_password))
+            .field("account", &self.account)
+            .field("request_timeout_ms", &self.request_timeout_ms)
+            .field("connect_timeout_ms", &self.connect_timeout_ms)
+            .field("max_requests_per_second", &self.max_requests_per_second)
+            .field("ws", &self.ws)
+            .field("log_level", &self.log_level)
+            .field("log_format", &self.log_format)
+            .finish()
+    }
+}
+
+impl ClientConfig {
+    /// Reads a `.toml` or `.json` file, without applying the environment.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        let path = path.as_ref();
+        let content = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&content)
+                .map_err(|e| ExchangeError::Validation(format!("{}: {}", path.display(), e))),
+            Some("json") => Ok(serde_json::from_str(&content)?),
+            _ => Err(ExchangeError::Validation(format!(
+                "unsupported config format: {}, expected .toml or .json",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Defaults overridden by the environment.
+    pub fn from_env() -> Result<Self, ExchangeError> {
+        Self::default().apply_env()
+    }
+
+    /// File (if any), then environment overrides, then `validate`.
+    pub fn load(path: Option<&Path>) -> Result<Self, ExchangeError> {
+        let config = match path {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        }
+        .apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn apply_env(self) -> Result<Self, ExchangeError> {
+        self.apply_vars(|name| std::env::var(name).ok())
+    }
+
+    /// Applies `PACIFICA_*` variables returned by `var` on top of `self`.
+    pub fn apply_vars<F>(mut self, var: F) -> Result<Self, ExchangeError>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        fn parse<T: std::str::FromStr>(name: &str, value: String) -> Result<T, ExchangeError>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .trim()
+                .parse()
+                .map_err(|e| ExchangeError::Validation(format!("{}: {}", name, e)))
+        }
+
+        if let Some(v) = var("PACIFICA_NETWORK") {
+            self.network = serde_json::from_value(serde_json::Value::String(v.to_lowercase()))
+                .map_err(|_| {
+                    ExchangeError::Validation(format!(
+                        "PACIFICA_NETWORK: expected mainnet or testnet, got {}",
+                        v
+                    ))
+                })?;
+        }
+        if let Some(v) = var("PACIFICA_BASE_URL") {
+            self.base_url = Some(v);
+        }
+        if let Some(v) = var("PACIFICA_WS_URL") {
+            self.ws_url = Some(v);
+        }
+        if let Some(v) = var("PACIFICA_ENABLE_WS") {
+            self.enable_ws = parse("PACIFICA_ENABLE_WS", v)?;
+        }
+        if let Some(v) = var("PACIFICA_API_KEY") {
+            self.api_key = Some(v);
+        }
+        // A key from the environment replaces whatever the file configured
+        if let Some(v) = var("PACIFICA_KEYPAIR") {
+            self.clear_key_sources();
+            self.keypair_path = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("PACIFICA_PRIVATE_KEY") {
+            self.clear_key_sources();
+            self.private_key = Some(Zeroizing::new(v));
+        }
+        if let Some(v) = var("PACIFICA_MNEMONIC") {
+            self.clear_key_sources();
+            self.mnemonic = Some(Zeroizing::new(v));
+        }
+        if let Some(v) = var("PACIFICA_KEYSTORE") {
+            self.clear_key_sources();
+            self.keystore_path = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("PACIFICA_DERIVATION_PATH") {
+            self.derivation_path = Some(v);
+        }
+        if let Some(v) = var("PACIFICA_MNEMONIC_PASSPHRASE") {
+            self.mnemonic_passphrase = Some(Zeroizing::new(v));
+        }
+        if let Some(v) = var("PACIFICA_KEYSTORE_PASSWORD") {
+            self.keystore_password = Some(Zeroizing::new(v));
+        }
+        if let Some(v) = var("PACIFICA_ACCOUNT") {
+            self.account = Some(parse("PACIFICA_ACCOUNT", v)?);
+        }
+        if let Some(v) = var("PACIFICA_REQUEST_TIMEOUT_MS") {
+            self.request_timeout_ms = Some(parse("PACIFICA_REQUEST_TIMEOUT_MS", v)?);
+        }
+        if let Some(v) = var("PACIFICA_CONNECT_TIMEOUT_MS") {
+            self.connect_timeout_ms = Some(parse("PACIFICA_CONNECT_TIMEOUT_MS", v)?);
+        }
+        if let Some(v) = var("PACIFICA_MAX_REQUESTS_PER_SECOND") {
+            self.max_requests_per_second = Some(parse("PACIFICA_MAX_REQUESTS_PER_SECOND", v)?);
+        }
+        if let Some(v) = var("PACIFICA_WS_PING_INTERVAL_MS") {
+            self.ws.ping_interval_ms = parse("PACIFICA_WS_PING_INTERVAL_MS", v)?;
+        }
+        if let Some(v) = var("PACIFICA_WS_RECONNECT_DELAY_MS") {
+            self.ws.reconnect_delay_ms = parse("PACIFICA_WS_RECONNECT_DELAY_MS", v)?;
+        }
+        if let Some(v) = var("PACIFICA_WS_MAX_RECONNECT_DELAY_MS") {
+            self.ws.max_reconnect_delay_ms = parse("PACIFICA_WS_MAX_RECONNECT_DELAY_MS", v)?;
+        }
+        if let Some(v) = var("PACIFICA_WS_MAX_RECONNECT_ATTEMPTS") {
+            self.ws.max_reconnect_attempts = Some(parse("PACIFICA_WS_MAX_RECONNECT_ATTEMPTS", v)?);
+        }
+        if let Some(v) = var("PACIFICA_WS_SEND_TIMEOUT_MS") {
+            self.ws.send_timeout_ms = parse("PACIFICA_WS_SEND_TIMEOUT_MS", v)?;
+        }
+        if let Some(v) = var("PACIFICA_LOG_LEVEL") {
+            self.log_level = Some(v);
+        }
+        if let Some(v) = var("PACIFICA_LOG_FORMAT") {
+            self.log_format = v.parse()?;
+        }
+        Ok(self)
+    }
+
+    fn clear_key_sources(&mut self) {
+        self.keypair_path = None;
+        self.private_key = None;
+        self.mnemonic = None;
+        self.keystore_path = None;
+    }
+
+    /// Checks URLs, numeric limits, the log filter and that the configured key decodes.
+    pub fn validate(&self) -> Result<(), ExchangeError> {
+        let key_sources = [
+            self.keypair_path.is_some(),
+            self.private_key.is_some(),
+            self.mnemonic.is_some(),
+            self.keystore_path.is_some(),
+        ];
+        if key_sources.into_iter().filter(|set| *set).count() > 1 {
+            return Err(ExchangeError::Validation(
+                "keypair_path, private_key, mnemonic and keystore_path are mutually exclusive"
+                    .into(),
+            ));
+        }
+        if !self.base_url().starts_with("http://") && !self.base_url().starts_with("https://") {
+            return Err(ExchangeError::Validation(format!(
+                "base_url must be an http(s) URL, got {}",
+                self.base_url()
+            )));
+        }
+        // The WebSocket URL is only used with `enable_ws`
+        if self.enable_ws
+            && !self.ws_url().starts_with("ws://")
+            && !self.ws_url().starts_with("wss://")
+        {
+            return Err(ExchangeError::Validation(format!(
+                "ws_url must be a ws(s) URL, got {}",
+                self.ws_url()
+            )));
+        }
+        if self.request_timeout_ms == Some(0) {
+            return Err(ExchangeError::Validation(
+                "request_timeout_ms must be positive".into(),
+            ));
+        }
+        if self.connect_timeout_ms == Some(0) {
+            return Err(ExchangeError::Validation(
+                "connect_timeout_ms must be positive".into(),
+            ));
+        }
+        if self.max_requests_per_second == Some(0) {
+            return Err(ExchangeError::Validation(
+                "max_requests_per_second must be positive".into(),
+            ));
+        }
+        if self.ws.ping_interval_ms == 0 {
+            return Err(ExchangeError::Validation(
+                "ws.ping_interval_ms must be positive".into(),
+            ));
+        }
+        if self.ws.send_timeout_ms == 0 {
+            return Err(ExchangeError::Validation(
+                "ws.send_timeout_ms must be positive".into(),
+            ));
+        }
+        if self.ws.max_reconnect_delay_ms < self.ws.reconnect_delay_ms {
+            return Err(ExchangeError::Validation(
+                "ws.max_reconnect_delay_ms must not be below ws.reconnect_delay_ms".into(),
+            ));
+        }
+        if let Some(level) = &self.log_level {
+            tracing_subscriber::EnvFilter::try_new(level).map_err(|e| {
+                ExchangeError::Validation(format!("invalid log_level {}: {}", level, e))
+            })?;
+        }
+        self.keypair()?;
+        Ok(())
+    }
+
+    pub fn base_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(match self.network {
+            Network::Mainnet => consts::REST_API_MAINNET_URL,
+            Network::Testnet => consts::REST_API_TESTNET_URL,
+        })
+    }
+
+    pub fn ws_url(&self) -> &str {
+        self.ws_url.as_deref().unwrap_or(match self.network {
+            Network::Mainnet => consts::WS_MAINNET_URL,
+            Network::Testnet => consts::WS_TESTNET_URL,
+        })
+    }
+
+    /// The signing key, None when no key source is set.
+    pub fn keypair(&self) -> Result<Option<Keypair>, ExchangeError> {
+        if let Some(path) = &self.keypair_path {
+            return read_keypair_file(path).map(Some);
+        }
+        if let Some(key) = &self.private_key {
+            let keypair = Keypair::try_from_base58_string(key.trim())
+                .map_err(|e| ExchangeError::Validation(format!("invalid private_key: {}", e)))?;
+            return Ok(Some(keypair));
+        }
+        if let Some(phrase) = &self.mnemonic {
+            return keypair_from_mnemonic(
+                phrase.trim(),
+                self.mnemonic_passphrase.as_ref().map_or("", |p| p.as_str()),
+                self.derivation_path.as_deref(),
+            )
+            .map(Some);
+        }
+        if let Some(path) = &self.keystore_path {
+            let password = self.keystore_password.as_ref().ok_or_else(|| {
+                ExchangeError::Validation(
+                    "keystore_path requires PACIFICA_KEYSTORE_PASSWORD".into(),
+                )
+            })?;
+            let mut unlocked = self.unlocked.0.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some((unlocked_path, unlocked_password, keypair)) = unlocked.as_ref()
+                && unlocked_path == path
+                && unlocked_password == password
+            {
+                return Ok(Some(keypair.insecure_clone()));
+            }
+            let keypair = EncryptedKeystore::load(path)?.decrypt(password)?;
+            *unlocked = Some((path.clone(), password.clone(), keypair.insecure_clone()));
+            return Ok(Some(keypair));
+        }
+        Ok(None)
+    }
+
+    pub fn rest_options(&self) -> RestClientOptions {
+        RestClientOptions {
+            timeout: self.request_timeout_ms.map(Duration::from_millis),
+            connect_timeout: self.connect_timeout_ms.map(Duration::from_millis),
+            max_requests_per_second: self.max_requests_per_second,
+            audit_log: None,
+        }
+    }
+
+    pub fn ws_options(&self) -> WebSocketOptions {
+        WebSocketOptions {
+            ping_interval: Duration::from_millis(self.ws.ping_interval_ms),
+            reconnect_delay: Duration::from_millis(self.ws.reconnect_delay_ms),
+            max_reconnect_delay: Duration::from_millis(self.ws.max_reconnect_delay_ms),
+            max_reconnect_attempts: self.ws.max_reconnect_attempts,
+            send_timeout: Duration::from_millis(self.ws.send_timeout_ms),
+        }
+    }
+
+    /// Installs the global `tracing` subscriber when `log_level` is set, in
+    /// `log_format`. A subscriber installed before is kept.
+    pub fn init_logging(&self) {
+        if let Some(level) = &self.log_level {
+            try_init_logging(level, self.log_format).ok();
+        }
+    }
+
+    pub async fn info_client(&self) -> Result<InfoClient, ExchangeError> {
+        InfoClient::with_options(
+            self.base_url(),
+            self.enable_ws.then(|| self.ws_url()),
+            self.api_key.clone(),
+            self.rest_options(),
+            self.ws_options(),
+        )
+        .await
+    }
+
+    pub async fn exchange_client(&self) -> Result<ExchangeClient, ExchangeError> {
+        let signer = self.keypair()?.ok_or_else(|| {
+            ExchangeError::Validation(
+                "one of keypair_path, private_key, mnemonic or keystore_path is required".into(),
+            )
+        })?;
+        let main_pubkey = self.account.unwrap_or_else(|| signer.pubkey());
+        let agent_pubkey = (main_pubkey != signer.pubkey()).then(|| signer.pubkey());
+        let info_client = self.info_client().await?;
+        ExchangeClient::from_info_client(info_client, signer, main_pubkey, agent_pubkey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::common::keystore::Kdf;
+
+    #[test]
+    fn test_toml_and_env_overrides() {
+        let keypair = Keypair::new();
+        let config: ClientConfig = toml::from_str(
+            r#"
+            network = "mainnet"
+            enable_ws = true
+            max_requests_per_second = 5
+
+            [ws]
+            ping_interval_ms = 20000
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.base_url(), consts::REST_API_MAINNET_URL);
+        assert_eq!(config.ws.ping_interval_ms, 20_000);
+        assert_eq!(config.ws.reconnect_delay_ms, 1_000);
+
+        let vars = HashMap::from([
+            ("PACIFICA_BASE_URL", "http://localhost:8080/api".to_string()),
+            ("PACIFICA_PRIVATE_KEY", keypair.to_base58_string()),
+            ("PACIFICA_REQUEST_TIMEOUT_MS", "1500".to_string()),
+        ]);
+        let config = config.apply_vars(|name| vars.get(name).cloned()).unwrap();
+        config.validate().unwrap();
+        assert_eq!(config.base_url(), "http://localhost:8080/api");
+        assert_eq!(config.ws_url(), consts::WS_MAINNET_URL);
+        assert_eq!(
+            config.rest_options().timeout,
+            Some(Duration::from_millis(1500))
+        );
+        assert_eq!(
+            config.keypair().unwrap().unwrap().pubkey(),
+            keypair.pubkey()
+        );
+        assert!(!format!("{:?}", config).contains(&keypair.to_base58_string()));
+        assert!(
+            !serde_json::to_string(&config)
+                .unwrap()
+                .contains(&keypair.to_base58_string())
+        );
+
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let vars = HashMap::from([
+            ("PACIFICA_MNEMONIC", phrase.to_string()),
+            ("PACIFICA_DERIVATION_PATH", "m/44'/501'/0'/0'".to_string()),
+        ]);
+        let config = config.apply_vars(|name| vars.get(name).cloned()).unwrap();
+        assert!(config.private_key.is_none());
+        assert_eq!(
+            config.keypair().unwrap().unwrap().pubkey().to_string(),
+            "HAgk14JpMQLgt6rVgv7cBQFJWFto5Dqxi472uT3DKpqk"
+        );
+        assert!(!format!("{:?}", config).contains(phrase));
+        let serialized = serde_json::to_string(&config).unwrap();
+        assert!(!serialized.contains(phrase));
+    }
+
+    #[test]
+    fn test_keystore_decrypted_once() {
+        let keypair = Keypair::new();
+        let kdf = Kdf::Scrypt {
+            log_n: 10,
+            r: 8,
+            p: 1,
+        };
+        let path = std::env::temp_dir().join(format!("keystore-{}.json", uuid::Uuid::new_v4()));
+        EncryptedKeystore::encrypt(&keypair, "hunter2", kdf)
+            .unwrap()
+            .save(&path)
+            .unwrap();
+        let vars = HashMap::from([
+            ("PACIFICA_KEYSTORE", path.display().to_string()),
+            ("PACIFICA_KEYSTORE_PASSWORD", "hunter2".to_string()),
+            ("PACIFICA_WS_SEND_TIMEOUT_MS", "2500".to_string()),
+            ("PACIFICA_WS_MAX_RECONNECT_DELAY_MS", "90000".to_string()),
+        ]);
+        let config = ClientConfig::default()
+            .apply_vars(|name| vars.get(name).cloned())
+            .unwrap();
+        config.validate().unwrap();
+        assert_eq!(
+            config.ws_options().send_timeout,
+            Duration::from_millis(2500)
+        );
+        assert_eq!(
+            config.ws_options().max_reconnect_delay,
+            Duration::from_secs(90)
+        );
+
+        // Without the file, only the key decrypted by `validate` can be returned
+        std::fs::remove_file(&path).unwrap();
+        let cloned = config.clone();
+        assert_eq!(
+            cloned.keypair().unwrap().unwrap().pubkey(),
+            keypair.pubkey()
+        );
+        let changed = ClientConfig {
+            keystore_password: Some(Zeroizing::new("hunter3".into())),
+            ..config
+        };
+        assert!(changed.keypair().is_err());
+    }
+
+    #[test]
+    fn test_validation_errors() {
+        let config: ClientConfig =
+            serde_json::from_str(r#"{"base_url": "localhost", "enable_ws": false}"#).unwrap();
+        assert!(matches!(
+            config.validate(),
+            Err(ExchangeError::Validation(_))
+        ));
+
+        let config = ClientConfig {
+            private_key: Some(Zeroizing::new("not a key".into())),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = ClientConfig {
+            ws_url: Some("localhost".into()),
+            enable_ws: true,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+        let config = ClientConfig {
+            enable_ws: false,
+            ..config
+        };
+        assert!(config.validate().is_ok());
+
+        let config = ClientConfig {
+            keystore_path: Some("missing.json".into()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(ExchangeError::Validation(_))
+        ));
+
+        assert!(toml::from_str::<ClientConfig>("netwrok = \"mainnet\"").is_err());
+        assert!(
+            ClientConfig::default()
+                .apply_vars(|name| (name == "PACIFICA_NETWORK").then(|| "devnet".into()))
+                .is_err()
+        );
+    }
+}
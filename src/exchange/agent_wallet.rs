@@ -0,0 +1,388 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use tracing::{info, warn};
+use zeroize::Zeroizing;
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        keystore::{EncryptedKeystore, Kdf, read_keypair_file, write_keypair_file},
+        types::DefaultResponse,
+        utils::{ensure_success, prepare_final_request},
+    },
+    exchange::{exchange_client::ExchangeClient, operations::Operation},
+    info::info_client::InfoClient,
+    models::exchange::{
+        payload::{agent_wallet::BindAgentWalletPayload, api_key::ListApiKeysPayload},
+        response::{agent_wallet::BindAgentWalletResponse, api_key::ListApiKeysResponse},
+    },
+};
+
+const ACTIVE_FILE: &str = "active";
+
+/// Agent keys on disk, one directory per main account:
+/// `<dir>/<main_pubkey>/<agent_pubkey>.json` plus an `active` file naming the
+/// agent trading hosts should sign with.
+pub struct AgentWalletStore {
+    dir: PathBuf,
+    // Some: keys are `EncryptedKeystore` files, None: plain Solana CLI keypair files
+    encryption: Option<(Zeroizing<String>, Kdf)>,
+}
+
+impl AgentWalletStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            encryption: None,
+        }
+    }
+
+    /// Stores keys encrypted with `password`, see `EncryptedKeystore`.
+    pub fn encrypted(dir: impl Into<PathBuf>, password: impl Into<String>, kdf: Kdf) -> Self {
+        Self {
+            dir: dir.into(),
+            encryption: Some((Zeroizing::new(password.into()), kdf)),
+        }
+    }
+
+    fn account_dir(&self, main_pubkey: &Pubkey) -> PathBuf {
+        self.dir.join(main_pubkey.to_string())
+    }
+
+    fn key_path(&self, main_pubkey: &Pubkey, agent_pubkey: &Pubkey) -> PathBuf {
+        self.account_dir(main_pubkey)
+            .join(format!("{}.json", agent_pubkey))
+    }
+
+    pub fn save(&self, main_pubkey: &Pubkey, agent: &Keypair) -> Result<(), ExchangeError> {
+        std::fs::create_dir_all(self.account_dir(main_pubkey))?;
+        let path = self.key_path(main_pubkey, &agent.pubkey());
+        match &self.encryption {
+            Some((password, kdf)) => {
+                EncryptedKeystore::encrypt(agent, password, kdf.clone())?.save(path)
+            }
+            None => write_keypair_file(agent, path),
+        }
+    }
+
+    pub fn load(
+        &self,
+        main_pubkey: &Pubkey,
+        agent_pubkey: &Pubkey,
+    ) -> Result<Keypair, ExchangeError> {
+        let path = self.key_path(main_pubkey, agent_pubkey);
+        let keypair = match &self.encryption {
+            Some((password, _)) => EncryptedKeystore::load(&path)?.decrypt(password)?,
+            None => read_keypair_file(&path)?,
+        };
+        if keypair.pubkey() != *agent_pubkey {
+            return Err(ExchangeError::Keystore(format!(
+                "{} holds a key for {}",
+                path.display(),
+                keypair.pubkey()
+            )));
+        }
+        Ok(keypair)
+    }
+
+    /// Agents stored for `main_pubkey`, bound or not.
+    pub fn list(&self, main_pubkey: &Pubkey) -> Result<Vec<Pubkey>, ExchangeError> {
+        let dir = self.account_dir(main_pubkey);
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut agents = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            if let Some(agent) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse().ok())
+            {
+                agents.push(agent);
+            }
+        }
+        agents.sort();
+        Ok(agents)
+    }
+
+    pub fn remove(&self, main_pubkey: &Pubkey, agent_pubkey: &Pubkey) -> Result<(), ExchangeError> {
+        if self.active(main_pubkey)? == Some(*agent_pubkey) {
+            std::fs::remove_file(self.account_dir(main_pubkey).join(ACTIVE_FILE))?;
+        }
+        std::fs::remove_file(self.key_path(main_pubkey, agent_pubkey))?;
+        Ok(())
+    }
+
+    pub fn active(&self, main_pubkey: &Pubkey) -> Result<Option<Pubkey>, ExchangeError> {
+        let path = self.account_dir(main_pubkey).join(ACTIVE_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(&path)?;
+        content.trim().parse().map(Some).map_err(|e| {
+            ExchangeError::Validation(format!("invalid agent in {}: {}", path.display(), e))
+        })
+    }
+
+    pub fn set_active(
+        &self,
+        main_pubkey: &Pubkey,
+        agent_pubkey: &Pubkey,
+    ) -> Result<(), ExchangeError> {
+        if !self.key_path(main_pubkey, agent_pubkey).exists() {
+            return Err(ExchangeError::NotInitialized(format!(
+                "agent {} is not stored for {}",
+                agent_pubkey, main_pubkey
+            )));
+        }
+        let path = self.account_dir(main_pubkey).join(ACTIVE_FILE);
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, agent_pubkey.to_string())?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn load_active(&self, main_pubkey: &Pubkey) -> Result<Keypair, ExchangeError> {
+        let agent_pubkey = self.active(main_pubkey)?.ok_or_else(|| {
+            ExchangeError::NotInitialized(format!("no active agent wallet for {}", main_pubkey))
+        })?;
+        self.load(main_pubkey, &agent_pubkey)
+    }
+
+    /// Client signing with the active agent for `main_pubkey`, for hosts that
+    /// never see the main key.
+    pub fn exchange_client(
+        &self,
+        info_client: InfoClient,
+        main_pubkey: Pubkey,
+    ) -> Result<ExchangeClient, ExchangeError> {
+        let agent = self.load_active(&main_pubkey)?;
+        let agent_pubkey = agent.pubkey();
+        ExchangeClient::from_info_client(info_client, agent, main_pubkey, Some(agent_pubkey))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AgentRotation {
+    pub previous: Option<Pubkey>,
+    pub current: Pubkey,
+}
+
+/// Creates, binds and rotates agent wallets. Runs where the main key lives;
+/// trading hosts only need `AgentWalletStore::exchange_client`.
+pub struct AgentWalletManager {
+    main_client: Arc<ExchangeClient>,
+    store: AgentWalletStore,
+    pub expiry_window: Option<u32>,
+}
+
+impl AgentWalletManager {
+    /// `main_client` must sign with the main account key.
+    pub fn new(
+        main_client: Arc<ExchangeClient>,
+        store: AgentWalletStore,
+    ) -> Result<Self, ExchangeError> {
+        if main_client.agent_pubkey().is_some() {
+            return Err(ExchangeError::Validation(
+                "AgentWalletManager needs a client signing with the main account key".into(),
+            ));
+        }
+        Ok(Self {
+            main_client,
+            store,
+            expiry_window: None,
+        })
+    }
+
+    pub fn store(&self) -> &AgentWalletStore {
+        &self.store
+    }
+
+    pub fn main_pubkey(&self) -> Pubkey {
+        self.main_client.main_pubkey()
+    }
+
+    /// Generates an agent key, stores it and binds it. The first agent of an
+    /// account becomes active.
+    pub async fn create(&self) -> Result<Pubkey, ExchangeError> {
+        let main_pubkey = self.main_pubkey();
+        let agent = Keypair::new();
+        let agent_pubkey = agent.pubkey();
+        // Stored before binding so a bound key can never be lost
+        self.store.save(&main_pubkey, &agent)?;
+        // A failed request may still have bound the agent, keep its key for
+        // `verify` or another `bind`
+        let response = self.send_bind(&agent_pubkey).await.inspect_err(|e| {
+            warn!(
+                "AgentWalletManager: bind of agent {} to {} not confirmed, key kept: {:?}",
+                agent_pubkey, main_pubkey, e
+            )
+        })?;
+        // Only an explicit rejection proves the agent is not bound
+        if let Err(e) = ensure_success("bind_agent_wallet", &response) {
+            self.store.remove(&main_pubkey, &agent_pubkey)?;
+            return Err(e);
+        }
+        if self.store.active(&main_pubkey)?.is_none() {
+            self.store.set_active(&main_pubkey, &agent_pubkey)?;
+        }
+        info!(
+            "AgentWalletManager: bound agent {} to {}",
+            agent_pubkey, main_pubkey
+        );
+        Ok(agent_pubkey)
+    }
+
+    pub async fn bind(&self, agent_pubkey: &Pubkey) -> Result<(), ExchangeError> {
+        let response = self.send_bind(agent_pubkey).await?;
+        ensure_success("bind_agent_wallet", &response)
+    }
+
+    async fn send_bind(
+        &self,
+        agent_pubkey: &Pubkey,
+    ) -> Result<DefaultResponse<BindAgentWalletResponse>, ExchangeError> {
+        self.main_client
+            .bind_agent_wallet(
+                BindAgentWalletPayload {
+                    agent_wallet: *agent_pubkey,
+                },
+                self.expiry_window,
+            )
+            .await
+    }
+
+    /// Sends a read-only signed request (`list_api_keys`) as the agent, which the
+    /// exchange only accepts once the agent is bound.
+    pub async fn verify(&self, agent_pubkey: &Pubkey) -> Result<(), ExchangeError> {
+        let main_pubkey = self.main_pubkey();
+        let agent = self.store.load(&main_pubkey, agent_pubkey)?;
+        let operation = Operation::ListApiKeys;
+        let body = prepare_final_request(
+            &operation.name()?,
+            ListApiKeysPayload {},
+            self.expiry_window,
+            &agent,
+            &main_pubkey,
+            &Some(*agent_pubkey),
+        )
+        .await?;
+        let response: DefaultResponse<ListApiKeysResponse> = self
+            .main_client
+            .submit_prebuilt(&operation.endpoint(), &body)
+            .await?;
+        ensure_success("agent verification", &response)
+    }
+
+    /// Binds a new agent, verifies it and makes it active. The previous agent's
+    /// key is then deleted; the exchange has no unbind call, so it stays bound
+    /// but unusable from this store.
+    pub async fn rotate(&self) -> Result<AgentRotation, ExchangeError> {
+        let main_pubkey = self.main_pubkey();
+        let previous = self.store.active(&main_pubkey)?;
+        let current = self.create().await?;
+        self.verify(&current).await?;
+        self.store.set_active(&main_pubkey, &current)?;
+        if let Some(previous) = previous
+            && previous != current
+            && let Err(e) = self.store.remove(&main_pubkey, &previous)
+        {
+            warn!(
+                "AgentWalletManager: failed to delete retired agent {}: {:?}",
+                previous, e
+            );
+        }
+        info!(
+            "AgentWalletManager: rotated {} from {:?} to {}",
+            main_pubkey, previous, current
+        );
+        Ok(AgentRotation { previous, current })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{HttpResponse, spawn_http_server};
+
+    #[test]
+    fn test_store_lifecycle() {
+        let main_pubkey = Keypair::new().pubkey();
+        let stores = [
+            AgentWalletStore::new(std::env::temp_dir().join(uuid::Uuid::new_v4().to_string())),
+            AgentWalletStore::encrypted(
+                std::env::temp_dir().join(uuid::Uuid::new_v4().to_string()),
+                "hunter2",
+                Kdf::Scrypt {
+                    log_n: 10,
+                    r: 8,
+                    p: 1,
+                },
+            ),
+        ];
+        for store in stores {
+            let (first, second) = (Keypair::new(), Keypair::new());
+            store.save(&main_pubkey, &first).unwrap();
+            store.save(&main_pubkey, &second).unwrap();
+            assert_eq!(store.active(&main_pubkey).unwrap(), None);
+            assert!(
+                store
+                    .set_active(&main_pubkey, &Keypair::new().pubkey())
+                    .is_err()
+            );
+
+            store.set_active(&main_pubkey, &second.pubkey()).unwrap();
+            assert_eq!(
+                store.load_active(&main_pubkey).unwrap().to_bytes(),
+                second.to_bytes()
+            );
+            let mut expected = vec![first.pubkey(), second.pubkey()];
+            expected.sort();
+            assert_eq!(store.list(&main_pubkey).unwrap(), expected);
+
+            store.remove(&main_pubkey, &second.pubkey()).unwrap();
+            assert_eq!(store.active(&main_pubkey).unwrap(), None);
+            assert_eq!(store.list(&main_pubkey).unwrap(), vec![first.pubkey()]);
+            std::fs::remove_dir_all(store.dir()).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_keeps_key_unless_rejected() {
+        for (response, kept) in [
+            (HttpResponse::status(503), true),
+            (HttpResponse::rejected("invalid signature", 400), false),
+        ] {
+            let url = spawn_http_server(move |_| response.clone()).await;
+            let keypair = Keypair::new();
+            let main_pubkey = keypair.pubkey();
+            let client =
+                ExchangeClient::from_markets(&url, Vec::new(), keypair, main_pubkey, None).unwrap();
+            let store =
+                AgentWalletStore::new(std::env::temp_dir().join(uuid::Uuid::new_v4().to_string()));
+            let manager = AgentWalletManager::new(Arc::new(client), store).unwrap();
+
+            assert!(manager.create().await.is_err());
+            assert_eq!(
+                manager.store().list(&main_pubkey).unwrap().len(),
+                kept as usize
+            );
+            let _ = std::fs::remove_dir_all(manager.store().dir());
+        }
+    }
+}
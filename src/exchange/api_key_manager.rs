@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use tracing::{info, warn};
+
+use crate::{
+    common::{errors::ExchangeError, utils::ensure_success},
+    exchange::exchange_client::ExchangeClient,
+    models::exchange::{
+        payload::api_key::{CreateApiKeyPayload, ListApiKeysPayload, RevokeApiKeyPayload},
+        response::api_key::ListApiKeysResponse,
+    },
+};
+
+/// Documented per-account limit, used when the exchange does not report one.
+pub const MAX_API_KEYS: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKeyRotation {
+    pub previous: Option<String>,
+    pub current: String,
+    // `false` when there was no previous key or revoking it failed after the
+    // switch; the previous key then stays active and counts against the limit
+    pub previous_revoked: bool,
+}
+
+/// Creates, lists, revokes and rotates the API keys of the client's account.
+///
+/// The key is applied through `ExchangeClient::set_api_key`, which every client
+/// sharing the same `RestClient` picks up, and which reconnects the WebSocket
+/// with subscriptions restored.
+pub struct ApiKeyManager {
+    client: Arc<ExchangeClient>,
+    pub expiry_window: Option<u32>,
+    // Lets `rotate` revoke the current key before creating its replacement
+    // when the account is at its key limit. Off by default: between the two
+    // calls the client has no valid key
+    pub revoke_first_at_limit: bool,
+}
+
+impl ApiKeyManager {
+    pub fn new(client: Arc<ExchangeClient>) -> Self {
+        Self {
+            client,
+            expiry_window: None,
+            revoke_first_at_limit: false,
+        }
+    }
+
+    pub async fn list(&self) -> Result<ListApiKeysResponse, ExchangeError> {
+        let response = self
+            .client
+            .get_list_api_keys(ListApiKeysPayload {}, self.expiry_window)
+            .await?;
+        ensure_success("list_api_keys", &response)?;
+        response
+            .data
+            .ok_or_else(|| ExchangeError::Custom("list_api_keys returned no data".into()))
+    }
+
+    /// Creates a key, refusing up front when the account is at its limit.
+    pub async fn create(&self) -> Result<String, ExchangeError> {
+        let keys = self.list().await?;
+        let limit = key_limit(&keys);
+        if keys.active_api_keys.len() >= limit {
+            return Err(ExchangeError::Validation(format!(
+                "account already has {} of {} API keys, revoke one first",
+                keys.active_api_keys.len(),
+                limit
+            )));
+        }
+        let response = self
+            .client
+            .create_api_key(CreateApiKeyPayload {}, self.expiry_window)
+            .await?;
+        ensure_success("create_api_key", &response)?;
+        response
+            .data
+            .map(|data| data.api_key)
+            .ok_or_else(|| ExchangeError::Custom("create_api_key returned no key".into()))
+    }
+
+    pub async fn revoke(&self, api_key: &str) -> Result<(), ExchangeError> {
+        let response = self
+            .client
+            .revoke_api_key(
+                RevokeApiKeyPayload {
+                    api_key: api_key.to_string(),
+                },
+                self.expiry_window,
+            )
+            .await?;
+        ensure_success("revoke_api_key", &response)
+    }
+
+    /// Creates a new key, switches REST and WebSocket to it, then revokes the
+    /// previous one. If the switch fails the new key is revoked instead and the
+    /// client keeps using the old one. A failed revoke of the previous key is
+    /// not an error, see `ApiKeyRotation::previous_revoked`.
+    ///
+    /// At the key limit `create` refuses, so the rotation fails unless
+    /// `revoke_first_at_limit` is set.
+    pub async fn rotate(&self) -> Result<ApiKeyRotation, ExchangeError> {
+        let previous = self.client.api_key();
+        let mut previous_revoked = false;
+        if self.revoke_first_at_limit
+            && let Some(previous) = &previous
+        {
+            let keys = self.list().await?;
+            if keys.active_api_keys.len() >= key_limit(&keys) {
+                info!("ApiKeyManager: at the key limit, revoking the current key first");
+                self.revoke(previous).await?;
+                previous_revoked = true;
+            }
+        }
+        let current = self.create().await?;
+        if let Err(e) = self.client.set_api_key(Some(current.clone())).await {
+            warn!("ApiKeyManager: switching to the new key failed: {:?}", e);
+            self.client.set_api_key(previous.clone()).await.ok();
+            if let Err(revoke_err) = self.revoke(&current).await {
+                warn!(
+                    "ApiKeyManager: failed to revoke unused key: {:?}",
+                    revoke_err
+                );
+            }
+            return Err(e);
+        }
+        if let Some(previous) = previous.as_deref().filter(|_| !previous_revoked) {
+            match self.revoke(previous).await {
+                Ok(()) => previous_revoked = true,
+                Err(e) => warn!("ApiKeyManager: failed to revoke previous key: {:?}", e),
+            }
+        }
+        info!("ApiKeyManager: rotated API key");
+        Ok(ApiKeyRotation {
+            previous,
+            current,
+            previous_revoked,
+        })
+    }
+}
+
+fn key_limit(keys: &ListApiKeysResponse) -> usize {
+    match keys.api_key_limit {
+        0 => MAX_API_KEYS,
+        limit => limit as usize,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde_json::json;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::test_util::{HttpResponse, spawn_http_server};
+
+    // Active keys kept by the mock exchange; revokes fail when `revoke_fails`
+    async fn manager(
+        active: &[&str],
+        created: &'static str,
+        revoke_fails: bool,
+    ) -> (ApiKeyManager, Arc<Mutex<Vec<String>>>) {
+        let keys = Arc::new(Mutex::new(
+            active.iter().map(|k| k.to_string()).collect::<Vec<_>>(),
+        ));
+        let state = keys.clone();
+        let url = spawn_http_server(move |req| {
+            let mut keys = state.lock().unwrap();
+            match req.path().rsplit('/').next().unwrap_or_default() {
+                "api_keys" => HttpResponse::ok(json!({
+                    "active_api_keys": *keys,
+                    "api_key_limit": MAX_API_KEYS,
+                })),
+                "create" => {
+                    keys.push(created.to_string());
+                    HttpResponse::ok(json!({ "api_key": created }))
+                }
+                _ if revoke_fails => HttpResponse::status(503),
+                _ => {
+                    let revoked = req.json()["api_key"].as_str().unwrap().to_string();
+                    keys.retain(|k| *k != revoked);
+                    HttpResponse::ok(json!(null))
+                }
+            }
+        })
+        .await;
+        let keypair = Keypair::new();
+        let main_pubkey = keypair.pubkey();
+        let client =
+            ExchangeClient::from_markets(&url, Vec::new(), keypair, main_pubkey, None).unwrap();
+        client.set_api_key(Some("old".into())).await.unwrap();
+        (ApiKeyManager::new(Arc::new(client)), keys)
+    }
+
+    #[tokio::test]
+    async fn test_rotate() {
+        let (manager, keys) = manager(&["old"], "new", false).await;
+        let rotation = manager.rotate().await.unwrap();
+        assert!(rotation.previous_revoked);
+        assert_eq!(manager.client.api_key().as_deref(), Some("new"));
+        assert_eq!(*keys.lock().unwrap(), ["new"]);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_rolls_back_when_the_switch_fails() {
+        // Not a valid header value, so the client can't switch to it
+        let (manager, keys) = manager(&["old"], "bad\nkey", false).await;
+        assert!(manager.rotate().await.is_err());
+        assert_eq!(manager.client.api_key().as_deref(), Some("old"));
+        assert_eq!(*keys.lock().unwrap(), ["old"]);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_keeps_the_new_key_when_revoke_fails() {
+        let (manager, keys) = manager(&["old"], "new", true).await;
+        let rotation = manager.rotate().await.unwrap();
+        assert!(!rotation.previous_revoked);
+        assert_eq!(manager.client.api_key().as_deref(), Some("new"));
+        assert_eq!(*keys.lock().unwrap(), ["old", "new"]);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_at_the_key_limit() {
+        let full = ["old", "k2", "k3", "k4", "k5"];
+        let (mut manager, keys) = manager(&full, "new", false).await;
+        assert!(manager.rotate().await.is_err());
+        assert_eq!(manager.client.api_key().as_deref(), Some("old"));
+
+        manager.revoke_first_at_limit = true;
+        let rotation = manager.rotate().await.unwrap();
+        assert!(rotation.previous_revoked);
+        assert_eq!(manager.client.api_key().as_deref(), Some("new"));
+        assert_eq!(*keys.lock().unwrap(), ["k2", "k3", "k4", "k5", "new"]);
+    }
+}
@@ -0,0 +1,361 @@
+use tracing::{debug, error};
+
+use crate::{
+    common::{
+        consts::{MAX_BATCH_ACTIONS, MAX_REQUEST_BYTES},
+        errors::{ExchangeError, OrderRejectReason},
+    },
+    exchange::exchange_client::ExchangeClient,
+    models::exchange::{
+        payload::{
+            batch_order::{
+                BatchOrderActionPayload, BatchOrderActionsFinalHeaders, BatchOrderFinalRequest,
+            },
+            order::{CancelOrderPayload, CreateMarketOrderPayload, CreateOrderPayload},
+        },
+        response::batch_order::BatchOrderModel,
+    },
+};
+
+// `{"actions":[` + `]}`
+const BATCH_ENVELOPE_BYTES: usize = 14;
+
+/// Result of a single action, `index` is its position in the builder input.
+#[derive(Debug, Clone)]
+pub struct BatchActionResult {
+    pub index: usize,
+    pub chunk: usize,
+    pub result: Result<BatchOrderModel, BatchRequestError>,
+}
+
+/// A request that got no per-action answer, with what that means for its actions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchRequestError {
+    pub message: String,
+    // `RequestFailed` when it never reached the exchange, `RateLimited` on a
+    // 429, `OutcomeUnknown` otherwise
+    pub reason: OrderRejectReason,
+}
+
+impl From<&ExchangeError> for BatchRequestError {
+    fn from(e: &ExchangeError) -> Self {
+        let message = e.to_string();
+        let reason = if e.is_unsent() {
+            OrderRejectReason::RequestFailed(message.clone())
+        } else if e.status() == Some(429) {
+            OrderRejectReason::RateLimited
+        } else {
+            OrderRejectReason::OutcomeUnknown(message.clone())
+        };
+        Self { message, reason }
+    }
+}
+
+/// Collects batch actions and splits them into requests below the exchange size limit
+/// and, by default, of at most `MAX_BATCH_ACTIONS` actions each.
+///
+/// The batch endpoint only accepts `Create`, `CreateMarket` and `Cancel` actions;
+/// stop orders and cancel-all have to be sent through their own endpoints.
+#[derive(Debug, Clone)]
+pub struct BatchOrderBuilder {
+    actions: Vec<BatchOrderActionPayload>,
+    max_bytes: usize,
+    max_actions: Option<usize>,
+    cancels_first: bool,
+}
+
+impl Default for BatchOrderBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatchOrderBuilder {
+    pub fn new() -> Self {
+        Self {
+            actions: Vec::new(),
+            max_bytes: MAX_REQUEST_BYTES,
+            max_actions: Some(MAX_BATCH_ACTIONS),
+            cancels_first: true,
+        }
+    }
+
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn max_actions(mut self, max_actions: usize) -> Self {
+        self.max_actions = Some(max_actions);
+        self
+    }
+
+    /// When enabled (default), cancels are sent before any create, both across
+    /// and within chunks. Relative order inside each group is kept.
+    pub fn cancels_first(mut self, cancels_first: bool) -> Self {
+        self.cancels_first = cancels_first;
+        self
+    }
+
+    pub fn push(mut self, action: BatchOrderActionPayload) -> Self {
+        self.actions.push(action);
+        self
+    }
+
+    pub fn create_order(self, payload: CreateOrderPayload) -> Self {
+        self.push(BatchOrderActionPayload::CreateOrder(payload))
+    }
+
+    pub fn create_market_order(self, payload: CreateMarketOrderPayload) -> Self {
+        self.push(BatchOrderActionPayload::CreateMarketOrder(payload))
+    }
+
+    pub fn cancel_order(self, payload: CancelOrderPayload) -> Self {
+        self.push(BatchOrderActionPayload::CancelOrder(payload))
+    }
+
+    pub fn actions(&self) -> &[BatchOrderActionPayload] {
+        &self.actions
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Input indices in send order.
+    fn send_order(&self) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.actions.len()).collect();
+        if self.cancels_first {
+            indices.sort_by_key(|i| {
+                !matches!(self.actions[*i], BatchOrderActionPayload::CancelOrder(_))
+            });
+        }
+        indices
+    }
+
+    /// Signs every action and packs them into requests of at most `max_bytes`.
+    /// Each chunk carries the input indices of its actions.
+    pub fn build(
+        &self,
+        client: &ExchangeClient,
+        expiry_window: Option<u32>,
+    ) -> Result<Vec<(Vec<usize>, BatchOrderFinalRequest)>, ExchangeError> {
+        let signed = self
+            .send_order()
+            .into_iter()
+            .map(|i| {
+                client
+                    .sign_batch_action(self.actions[i].clone(), expiry_window)
+                    .map(|a| (i, a))
+            })
+            .collect::<Result<Vec<_>, ExchangeError>>()?;
+        chunk_signed_actions(signed, self.max_bytes, self.max_actions)
+    }
+
+    /// Sends chunks sequentially and returns one result per input action, in input order.
+    /// A failed chunk marks all of its actions as failed and does not stop later chunks.
+    pub async fn send(
+        &self,
+        client: &ExchangeClient,
+        expiry_window: Option<u32>,
+    ) -> Result<Vec<BatchActionResult>, ExchangeError> {
+        let chunks = self.build(client, expiry_window)?;
+        let mut results: Vec<Option<BatchActionResult>> = vec![None; self.actions.len()];
+
+        for (chunk_idx, (indices, request)) in chunks.into_iter().enumerate() {
+            debug!(
+                "BatchOrderBuilder: sending chunk {} with {} action(s)",
+                chunk_idx,
+                indices.len()
+            );
+            let chunk_results: Vec<Result<BatchOrderModel, BatchRequestError>> =
+                match client.send_batch_order(&request).await {
+                    Ok(response) => match response.data {
+                        Some(data) if data.results.len() == indices.len() => {
+                            data.results.into_iter().map(Ok).collect()
+                        }
+                        Some(data) => rejected_results(
+                            Some(format!(
+                                "expected {} results, got {}",
+                                indices.len(),
+                                data.results.len()
+                            )),
+                            indices.len(),
+                        ),
+                        None => rejected_results(response.error, indices.len()),
+                    },
+                    Err(e) => {
+                        error!("BatchOrderBuilder: chunk {} failed: {:?}", chunk_idx, e);
+                        vec![Err(BatchRequestError::from(&e)); indices.len()]
+                    }
+                };
+
+            for (index, result) in indices.into_iter().zip(chunk_results) {
+                results[index] = Some(BatchActionResult {
+                    index,
+                    chunk: chunk_idx,
+                    result,
+                });
+            }
+        }
+
+        Ok(results.into_iter().flatten().collect())
+    }
+}
+
+/// Per-action results for a batch the exchange answered without them, e.g.
+/// rejected as a whole. Classified from `error` like any per-action failure;
+/// `Err` is kept for requests that got no answer at all.
+pub(crate) fn rejected_results(
+    error: Option<String>,
+    count: usize,
+) -> Vec<Result<BatchOrderModel, BatchRequestError>> {
+    let model = BatchOrderModel {
+        success: false,
+        order_id: None,
+        error: Some(
+            error.unwrap_or_else(|| "batch response had no per-action results".to_string()),
+        ),
+    };
+    vec![Ok(model); count]
+}
+
+pub fn chunk_signed_actions(
+    signed: Vec<(usize, BatchOrderActionsFinalHeaders)>,
+    max_bytes: usize,
+    max_actions: Option<usize>,
+) -> Result<Vec<(Vec<usize>, BatchOrderFinalRequest)>, ExchangeError> {
+    let mut chunks = Vec::new();
+    let mut indices: Vec<usize> = Vec::new();
+    let mut actions: Vec<BatchOrderActionsFinalHeaders> = Vec::new();
+    let mut size = BATCH_ENVELOPE_BYTES;
+
+    for (index, action) in signed {
+        let action_size = serde_json::to_vec(&action)?.len();
+        if BATCH_ENVELOPE_BYTES + action_size > max_bytes {
+            return Err(ExchangeError::Validation(format!(
+                "batch action {} is {} bytes and cannot fit into a {} byte request",
+                index, action_size, max_bytes
+            )));
+        }
+        let separator = usize::from(!actions.is_empty());
+        let full = size + separator + action_size > max_bytes
+            || max_actions.is_some_and(|max| actions.len() >= max);
+        if full {
+            chunks.push((
+                std::mem::take(&mut indices),
+                BatchOrderFinalRequest {
+                    actions: std::mem::take(&mut actions),
+                },
+            ));
+            size = BATCH_ENVELOPE_BYTES;
+        }
+        size += usize::from(!actions.is_empty()) + action_size;
+        indices.push(index);
+        actions.push(action);
+    }
+
+    if !actions.is_empty() {
+        chunks.push((indices, BatchOrderFinalRequest { actions }));
+    }
+    Ok(chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rust_decimal::Decimal;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::common::{
+        types::{OrderSide, Tif},
+        utils::sign_batch_action,
+    };
+
+    fn create(i: u32) -> BatchOrderActionPayload {
+        BatchOrderActionPayload::CreateOrder(CreateOrderPayload {
+            symbol: "BTC".to_string(),
+            price: Decimal::from(100_000 + i),
+            amount: Decimal::from_str("0.001").unwrap(),
+            side: OrderSide::Bid,
+            tif: Tif::GTC,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        })
+    }
+
+    fn cancel(order_id: u64) -> BatchOrderActionPayload {
+        BatchOrderActionPayload::CancelOrder(CancelOrderPayload {
+            symbol: "BTC".to_string(),
+            order_id: Some(order_id),
+            client_order_id: None,
+        })
+    }
+
+    #[test]
+    fn test_chunks_respect_size_limit_and_keep_indices() {
+        let keypair = Keypair::new();
+        let signed: Vec<_> = (0..25)
+            .map(|i| {
+                let action = if i % 5 == 0 {
+                    cancel(i as u64)
+                } else {
+                    create(i)
+                };
+                let signed =
+                    sign_batch_action(action, None, &keypair, &keypair.pubkey(), &None).unwrap();
+                (i as usize, signed)
+            })
+            .collect();
+
+        // The builder's default also caps the action count
+        let default_max = BatchOrderBuilder::new().max_actions;
+        let chunks = chunk_signed_actions(signed.clone(), MAX_REQUEST_BYTES, default_max).unwrap();
+        assert_eq!(chunks.len(), 3);
+        assert!(
+            chunks
+                .iter()
+                .all(|(indices, _)| indices.len() <= MAX_BATCH_ACTIONS)
+        );
+
+        let chunks = chunk_signed_actions(signed, MAX_REQUEST_BYTES, None).unwrap();
+        assert!(chunks.len() > 1);
+        let mut seen: Vec<usize> = Vec::new();
+        for (indices, request) in &chunks {
+            assert!(serde_json::to_vec(request).unwrap().len() <= MAX_REQUEST_BYTES);
+            assert_eq!(indices.len(), request.actions.len());
+            seen.extend(indices);
+        }
+        assert_eq!(seen, (0..25).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_cancels_first_ordering() {
+        let builder = BatchOrderBuilder::new()
+            .push(create(1))
+            .push(cancel(7))
+            .push(create(2))
+            .push(cancel(8));
+        assert_eq!(builder.send_order(), vec![1, 3, 0, 2]);
+        assert_eq!(builder.cancels_first(false).send_order(), vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_oversized_action_is_rejected() {
+        let keypair = Keypair::new();
+        let signed =
+            sign_batch_action(create(1), None, &keypair, &keypair.pubkey(), &None).unwrap();
+        assert!(matches!(
+            chunk_signed_actions(vec![(0, signed)], 100, None),
+            Err(ExchangeError::Validation(_))
+        ));
+    }
+}
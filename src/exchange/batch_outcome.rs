@@ -0,0 +1,312 @@
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        errors::{ExchangeError, OrderRejectReason},
+        types::DefaultResponse,
+    },
+    exchange::{
+        batch_builder::{BatchActionResult, BatchOrderBuilder, rejected_results},
+        exchange_client::ExchangeClient,
+    },
+    models::exchange::{
+        payload::batch_order::BatchOrderActionPayload, response::batch_order::BatchOrderResponse,
+    },
+};
+
+#[derive(Debug, Clone)]
+pub struct BatchActionOutcome {
+    pub index: usize,
+    pub action: BatchOrderActionPayload,
+    pub client_order_id: Option<Uuid>,
+    pub order_id: Option<u64>,
+    // None when the action succeeded
+    pub reason: Option<OrderRejectReason>,
+    pub error: Option<String>,
+}
+
+impl BatchActionOutcome {
+    pub fn is_success(&self) -> bool {
+        self.reason.is_none()
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.reason.as_ref().is_some_and(|r| r.is_retryable())
+    }
+}
+
+/// Batch results zipped with the actions that produced them, in submission order.
+#[derive(Debug, Clone)]
+pub struct BatchOutcome {
+    pub actions: Vec<BatchActionOutcome>,
+}
+
+impl BatchOutcome {
+    pub fn from_response(
+        actions: Vec<BatchOrderActionPayload>,
+        response: &DefaultResponse<BatchOrderResponse>,
+    ) -> Result<Self, ExchangeError> {
+        let results: Vec<BatchActionResult> = match &response.data {
+            Some(data) if data.results.len() == actions.len() => data
+                .results
+                .iter()
+                .enumerate()
+                .map(|(index, r)| BatchActionResult {
+                    index,
+                    chunk: 0,
+                    result: Ok(r.clone()),
+                })
+                .collect(),
+            _ => rejected_results(response.error.clone(), actions.len())
+                .into_iter()
+                .enumerate()
+                .map(|(index, result)| BatchActionResult {
+                    index,
+                    chunk: 0,
+                    result,
+                })
+                .collect(),
+        };
+        Self::from_results(actions, results)
+    }
+
+    /// `results` must be aligned with `actions`, as returned by `BatchOrderBuilder::send`;
+    /// a different number of results is an error.
+    pub fn from_results(
+        actions: Vec<BatchOrderActionPayload>,
+        results: Vec<BatchActionResult>,
+    ) -> Result<Self, ExchangeError> {
+        if results.len() != actions.len() {
+            return Err(ExchangeError::Validation(format!(
+                "{} batch results for {} actions",
+                results.len(),
+                actions.len()
+            )));
+        }
+        let actions = actions
+            .into_iter()
+            .zip(results)
+            .enumerate()
+            .map(|(index, (action, result))| {
+                let (order_id, reason, error) = match result.result {
+                    Ok(model) if model.success => (model.order_id, None, None),
+                    Ok(model) => {
+                        let msg = model.error.unwrap_or_default();
+                        (
+                            model.order_id,
+                            Some(OrderRejectReason::from_message(&msg)),
+                            Some(msg),
+                        )
+                    }
+                    Err(e) => (None, Some(e.reason), Some(e.message)),
+                };
+                BatchActionOutcome {
+                    index,
+                    client_order_id: action.client_order_id(),
+                    action,
+                    order_id,
+                    reason,
+                    error,
+                }
+            })
+            .collect();
+        Ok(Self { actions })
+    }
+
+    pub fn all_succeeded(&self) -> bool {
+        self.actions.iter().all(|a| a.is_success())
+    }
+
+    pub fn succeeded(&self) -> impl Iterator<Item = &BatchActionOutcome> {
+        self.actions.iter().filter(|a| a.is_success())
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = &BatchActionOutcome> {
+        self.actions.iter().filter(|a| !a.is_success())
+    }
+
+    pub fn by_client_order_id(&self, client_order_id: &Uuid) -> Option<&BatchActionOutcome> {
+        self.actions
+            .iter()
+            .find(|a| a.client_order_id.as_ref() == Some(client_order_id))
+    }
+
+    /// Re-signs and resubmits failed actions with a retryable reason. Retried
+    /// entries are replaced in place; the rest are returned unchanged.
+    ///
+    /// Only requests that never reached the exchange or were rate limited are
+    /// retried; `OutcomeUnknown` actions are left for the caller to check.
+    pub async fn retry_failed(
+        &self,
+        client: &ExchangeClient,
+        expiry_window: Option<u32>,
+    ) -> Result<BatchOutcome, ExchangeError> {
+        let retry: Vec<&BatchActionOutcome> =
+            self.actions.iter().filter(|a| a.is_retryable()).collect();
+        let mut outcome = self.clone();
+        if retry.is_empty() {
+            return Ok(outcome);
+        }
+
+        let mut builder = BatchOrderBuilder::new().cancels_first(false);
+        for a in &retry {
+            builder = builder.push(a.action.clone());
+        }
+        let results = builder.send(client, expiry_window).await?;
+        let retried = BatchOutcome::from_results(builder.actions().to_vec(), results)?;
+
+        for (original, mut new) in retry.iter().zip(retried.actions) {
+            new.index = original.index;
+            outcome.actions[original.index] = new;
+        }
+        Ok(outcome)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use serde_json::json;
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::{
+        exchange::batch_builder::BatchRequestError,
+        models::exchange::{
+            payload::order::CancelOrderPayload, response::batch_order::BatchOrderModel,
+        },
+        test_util::{HttpResponse, spawn_http_server},
+    };
+
+    fn cancel(order_id: u64) -> BatchOrderActionPayload {
+        BatchOrderActionPayload::CancelOrder(CancelOrderPayload {
+            symbol: "BTC".to_string(),
+            order_id: Some(order_id),
+            client_order_id: Some(Uuid::new_v4()),
+        })
+    }
+
+    #[test]
+    fn test_outcome_classifies_errors() {
+        let actions = vec![cancel(1), cancel(2), cancel(3)];
+        let response = DefaultResponse {
+            success: Some(true),
+            data: Some(BatchOrderResponse {
+                results: vec![
+                    BatchOrderModel {
+                        success: true,
+                        order_id: Some(1),
+                        error: None,
+                    },
+                    BatchOrderModel {
+                        success: false,
+                        order_id: None,
+                        error: Some("Order not found for abc: 2".to_string()),
+                    },
+                    BatchOrderModel {
+                        success: false,
+                        order_id: None,
+                        error: Some("Rate limit exceeded".to_string()),
+                    },
+                ],
+            }),
+            error: None,
+            code: None,
+        };
+
+        let outcome = BatchOutcome::from_response(actions.clone(), &response).unwrap();
+        assert!(!outcome.all_succeeded());
+        assert_eq!(outcome.succeeded().count(), 1);
+        assert_eq!(
+            outcome.actions[1].reason,
+            Some(OrderRejectReason::OrderNotFound)
+        );
+        assert!(!outcome.actions[1].is_retryable());
+        assert!(outcome.actions[2].is_retryable());
+        let cloid = actions[2].client_order_id().unwrap();
+        assert_eq!(outcome.by_client_order_id(&cloid).unwrap().index, 2);
+    }
+
+    #[test]
+    fn test_missing_results_mark_all_failed() {
+        let response: DefaultResponse<BatchOrderResponse> = DefaultResponse {
+            success: Some(false),
+            data: None,
+            error: Some("Invalid signature".to_string()),
+            code: Some(400),
+        };
+        let outcome = BatchOutcome::from_response(vec![cancel(1), cancel(2)], &response).unwrap();
+        assert_eq!(outcome.failed().count(), 2);
+        // A rejection of the whole batch is final for every action
+        assert!(
+            outcome
+                .actions
+                .iter()
+                .all(|a| a.reason == Some(OrderRejectReason::InvalidSignature))
+        );
+        assert!(!outcome.actions.iter().any(|a| a.is_retryable()));
+
+        // Only a request that never reached the exchange is retried
+        let failed = |e: ExchangeError| BatchActionResult {
+            index: 0,
+            chunk: 0,
+            result: Err(BatchRequestError::from(&e)),
+        };
+        let unsent = ExchangeError::NotInitialized("no client".to_string());
+        let outcome = BatchOutcome::from_results(vec![cancel(3)], vec![failed(unsent)]).unwrap();
+        assert!(matches!(
+            outcome.actions[0].reason,
+            Some(OrderRejectReason::RequestFailed(_))
+        ));
+        assert!(outcome.actions[0].is_retryable());
+
+        // A 5xx may have been applied
+        let server_error = ExchangeError::HttpStatus {
+            status: 502,
+            body: String::new(),
+            rate_limit: None,
+        };
+        let outcome =
+            BatchOutcome::from_results(vec![cancel(4)], vec![failed(server_error)]).unwrap();
+        assert!(matches!(
+            outcome.actions[0].reason,
+            Some(OrderRejectReason::OutcomeUnknown(_))
+        ));
+        assert!(!outcome.actions[0].is_retryable());
+
+        // Results that don't line up with the actions
+        assert!(BatchOutcome::from_results(vec![cancel(5), cancel(6)], Vec::new()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_success_is_not_retried() {
+        // The exchange accepted the batch but its answer doesn't decode
+        let hits = Arc::new(AtomicUsize::new(0));
+        let seen = hits.clone();
+        let url = spawn_http_server(move |_| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            HttpResponse::ok(json!({ "results": "unexpected" }))
+        })
+        .await;
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client = ExchangeClient::from_markets(&url, Vec::new(), keypair, pubkey, None).unwrap();
+
+        let builder = BatchOrderBuilder::new().push(cancel(1));
+        let results = builder.send(&client, None).await.unwrap();
+        let outcome = BatchOutcome::from_results(builder.actions().to_vec(), results).unwrap();
+        assert!(matches!(
+            outcome.actions[0].reason,
+            Some(OrderRejectReason::OutcomeUnknown(_))
+        ));
+        assert!(!outcome.actions[0].is_retryable());
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        outcome.retry_failed(&client, None).await.unwrap();
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+}
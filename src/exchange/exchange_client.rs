@@ -7,64 +7,67 @@ use solana_sdk::{
     signature::{Keypair, Signer},
 };
 use tokio::sync::mpsc;
-use tracing::debug;
+use tracing::{Instrument, debug};
 use uuid::Uuid;
 
 use crate::{
     common::{
         errors::ExchangeError,
-        signing::sign_message,
-        types::{
-            DefaultFinalHeaders, DefaultResponse, DefaultSignatureHeaders, FinalRequest,
-            OperationFinalHeaders, PacificSignature, SubAccountFinalHeaders,
-        },
-        utils::{get_timestamp_ms, prepare_final_request},
+        types::{DefaultResponse, FinalRequest, PrebuiltRequest},
+        utils::{prepare_final_request, prepare_subaccount_create_request, sign_batch_action},
+    },
+    exchange::{
+        batch_outcome::BatchOutcome,
+        operations::{Operation, SubaccountCreateAction},
     },
-    exchange::operations::{Operation, SubaccountCreateAction},
     info::info_client::InfoClient,
-    models::exchange::{
-        payload::{
-            account::WithdrawPayload,
-            agent_wallet::BindAgentWalletPayload,
-            api_key::{CreateApiKeyPayload, ListApiKeysPayload, RevokeApiKeyPayload},
-            batch_order::{
-                BatchOrderActionPayload, BatchOrderActionType, BatchOrderActionsFinalHeaders,
-                BatchOrderFinalRequest,
-            },
-            market_settings::{UpdateLeveragePayload, UpdateMarginModePayload},
-            order::{
-                CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
-                CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
-                SetPositionTpslPayload,
+    logging::{OperationTimer, operation_span},
+    models::{
+        exchange::{
+            payload::{
+                account::WithdrawPayload,
+                agent_wallet::BindAgentWalletPayload,
+                api_key::{CreateApiKeyPayload, ListApiKeysPayload, RevokeApiKeyPayload},
+                batch_order::{
+                    BatchOrderActionPayload, BatchOrderActionsFinalHeaders, BatchOrderFinalRequest,
+                },
+                market_settings::{UpdateLeveragePayload, UpdateMarginModePayload},
+                order::{
+                    CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+                    CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+                    SetPositionTpslPayload,
+                },
+                subaccount::SubaccountTransferPayload,
             },
-            subaccount::{
-                SubaccountConfirmPayload, SubaccountInitiatePayload, SubaccountTransferPayload,
-            },
-        },
-        response::{
-            account::WithdrawResponse,
-            agent_wallet::BindAgentWalletResponse,
-            api_key::{CreateApiKeyResponse, ListApiKeysResponse, RevokeApiKeyResponse},
-            batch_order::BatchOrderResponse,
-            market_settings::{UpdateLeverageResponse, UpdateMarginModeResponse},
-            order::{
-                CancelAllOrdersResponse, CancelOrderResponse, CancelStopOrderResponse,
-                CreateMarketOrderResponse, CreateOrderResponse, CreateStopOrderResponse,
-                SetPositionTPSLResponse,
+            response::{
+                account::WithdrawResponse,
+                agent_wallet::BindAgentWalletResponse,
+                api_key::{CreateApiKeyResponse, ListApiKeysResponse, RevokeApiKeyResponse},
+                batch_order::BatchOrderResponse,
+                market_settings::{UpdateLeverageResponse, UpdateMarginModeResponse},
+                order::{
+                    CancelAllOrdersResponse, CancelOrderResponse, CancelStopOrderResponse,
+                    CreateMarketOrderResponse, CreateOrderResponse, CreateStopOrderResponse,
+                    SetPositionTPSLResponse,
+                },
+                subaccount::{SubaccountCreateResponse, SubaccountTransferResponse},
             },
-            subaccount::{SubaccountCreateResponse, SubaccountTransferResponse},
         },
+        info::response::market::MarketsInfoResponse,
+    },
+    rest::{
+        audit::AuditLog,
+        response::{RequestOptions, RestResponse},
+        rest_client::{RestClient, RestClientOptions},
     },
-    rest::rest_client::RestClient,
 };
 
 pub struct ExchangeClient {
-    pub base_url: &'static str,
+    pub base_url: String,
     pub info_client: InfoClient,
     signer_keypair: Keypair,
     main_pubkey: Pubkey,
     agent_pubkey: Option<Pubkey>,
-    pub api_key: Option<String>,
     http_client: RestClient,
     default_headers: HeaderMap,
 }
@@ -77,6 +80,63 @@ impl ExchangeClient {
         signer_keypair: Keypair,
         main_pubkey: Pubkey,
         agent_pubkey: Option<Pubkey>,
+    ) -> Result<Self, ExchangeError> {
+        let (base_url, ws_url) = if is_mainnet {
+            (
+                crate::common::consts::REST_API_MAINNET_URL,
+                crate::common::consts::WS_MAINNET_URL,
+            )
+        } else {
+            (
+                crate::common::consts::REST_API_TESTNET_URL,
+                crate::common::consts::WS_TESTNET_URL,
+            )
+        };
+        Self::with_urls(
+            base_url,
+            enable_ws.then_some(ws_url),
+            api_key,
+            signer_keypair,
+            main_pubkey,
+            agent_pubkey,
+        )
+        .await
+    }
+
+    /// Same as `new` but against explicit endpoints, see `InfoClient::with_urls`.
+    pub async fn with_urls(
+        base_url: &str,
+        ws_url: Option<&str>,
+        api_key: Option<String>,
+        signer_keypair: Keypair,
+        main_pubkey: Pubkey,
+        agent_pubkey: Option<Pubkey>,
+    ) -> Result<Self, ExchangeError> {
+        let info_client = InfoClient::with_urls(base_url, ws_url, api_key).await?;
+        Self::from_info_client(info_client, signer_keypair, main_pubkey, agent_pubkey)
+    }
+
+    /// Client for signing offline, see `InfoClient::from_markets`. `build_*`
+    /// methods work without network access; sending needs `base_url` reachable.
+    pub fn from_markets(
+        base_url: &str,
+        markets: MarketsInfoResponse,
+        signer_keypair: Keypair,
+        main_pubkey: Pubkey,
+        agent_pubkey: Option<Pubkey>,
+    ) -> Result<Self, ExchangeError> {
+        let info_client =
+            InfoClient::from_markets(base_url, None, RestClientOptions::default(), markets)?;
+        Self::from_info_client(info_client, signer_keypair, main_pubkey, agent_pubkey)
+    }
+
+    /// Wraps an already connected `InfoClient`, reusing its endpoints, API key and
+    /// HTTP client.
+    pub fn from_info_client(
+        info_client: InfoClient,
+        signer_keypair: Keypair,
+        main_pubkey: Pubkey,
+        agent_pubkey: Option<Pubkey>,
     ) -> Result<Self, ExchangeError> {
         if agent_pubkey.is_some() && agent_pubkey != Some(signer_keypair.pubkey()) {
             return Err(ExchangeError::Custom(
@@ -84,25 +144,13 @@ impl ExchangeClient {
             ));
         }
 
-        let base_url = if is_mainnet {
-            crate::common::consts::REST_API_MAINNET_URL
-        } else {
-            crate::common::consts::REST_API_TESTNET_URL
-        };
-
         let mut default_headers = HeaderMap::new();
         default_headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-        if let Some(ref key) = api_key {
-            default_headers.insert("PF-API-KEY", HeaderValue::from_str(key)?);
-        }
-
-        let info_client = InfoClient::new(is_mainnet, enable_ws, api_key.clone()).await?;
-        let http_client = RestClient::new(base_url);
+        let http_client = info_client.http_client().clone();
 
         Ok(Self {
-            base_url,
+            base_url: info_client.base_url.clone(),
             info_client,
-            api_key,
             signer_keypair,
             main_pubkey,
             agent_pubkey,
@@ -111,38 +159,51 @@ impl ExchangeClient {
         })
     }
 
+    pub fn main_pubkey(&self) -> Pubkey {
+        self.main_pubkey
+    }
+
+    pub fn agent_pubkey(&self) -> Option<Pubkey> {
+        self.agent_pubkey
+    }
+
+    pub fn api_key(&self) -> Option<String> {
+        self.http_client.api_key()
+    }
+
     pub async fn set_default_api_key(&mut self, api_key: String) -> Result<(), ExchangeError> {
-        self.api_key = Some(api_key);
+        self.set_api_key(Some(api_key)).await
+    }
 
-        if let Some(ref key) = self.api_key {
-            let header_value = HeaderValue::from_str(key)?;
-            self.default_headers.insert("PF-API-KEY", header_value);
-        }
-        self.info_client
-            .set_default_api_key(self.api_key.clone().unwrap())
-            .await
+    /// See `InfoClient::set_api_key`, the REST client is shared with `info_client`.
+    pub async fn set_api_key(&self, api_key: Option<String>) -> Result<(), ExchangeError> {
+        self.info_client.set_api_key(api_key).await
+    }
+
+    /// See `RestClient::set_audit_log`, the REST client is shared with `info_client`.
+    pub fn set_audit_log(&self, audit_log: Option<AuditLog>) {
+        self.info_client.set_audit_log(audit_log);
     }
 
     async fn send_request<T, P>(
         &self,
         operation: Operation,
         final_request: FinalRequest<P>,
-    ) -> Result<T, ExchangeError>
+        options: &RequestOptions,
+    ) -> Result<RestResponse<T>, ExchangeError>
     where
         T: DeserializeOwned + Debug,
         P: Serialize + Debug,
     {
         debug!("send_request: operation={:?}", operation);
-        debug!("send_request: final_request={:?}", &final_request);
-
-        let response = self
-            .http_client
-            .post::<T, FinalRequest<P>>(Some(&operation.endpoint()), Some(&final_request), None)
-            .await?;
-
-        debug!("send_request: response={:?}", &response);
-
-        Ok(response)
+        self.http_client
+            .post_with::<T, FinalRequest<P>>(
+                Some(&operation.endpoint()),
+                Some(&final_request),
+                None,
+                options,
+            )
+            .await
     }
 
     pub async fn request_ws_exchange_fn<P>(
@@ -159,6 +220,26 @@ impl ExchangeClient {
                 "WebSocket client not initialized".into(),
             ));
         }
+        let span = operation_span(request_method, Some(&sign_payload));
+        let timer = OperationTimer::start(&span);
+        let result = self
+            .send_ws_exchange_request(request_method, sign_payload, expiry_window)
+            .instrument(span)
+            .await;
+        // Only covers sending, the answer arrives on the returned channel
+        timer.finish(&result, None);
+        result
+    }
+
+    async fn send_ws_exchange_request<P>(
+        &self,
+        request_method: &str,
+        sign_payload: P,
+        expiry_window: Option<u32>,
+    ) -> Result<mpsc::Receiver<serde_json::Value>, ExchangeError>
+    where
+        P: Serialize + Debug,
+    {
         let final_request = prepare_final_request(
             request_method,
             sign_payload,
@@ -193,9 +274,66 @@ impl ExchangeClient {
     where
         T: DeserializeOwned + Debug,
         P: Serialize + Debug,
+    {
+        self.request_exchange_fn_with(
+            operation,
+            sign_payload,
+            expiry_window,
+            &RequestOptions::default(),
+        )
+        .await
+        .map(|response| response.data)
+    }
+
+    /// `request_exchange_fn` with per-call options, returning the HTTP status,
+    /// headers and latency alongside the response.
+    pub async fn request_exchange_fn_with<T, P>(
+        &self,
+        operation: Operation,
+        sign_payload: P,
+        expiry_window: Option<u32>,
+        options: &RequestOptions,
+    ) -> Result<RestResponse<T>, ExchangeError>
+    where
+        T: DeserializeOwned + Debug,
+        P: Serialize + Debug,
+    {
+        let name = operation.name()?;
+        let span = operation_span(&name, Some(&sign_payload));
+        let timer = OperationTimer::start(&span);
+        let result = async {
+            let final_request = prepare_final_request(
+                &name,
+                sign_payload,
+                expiry_window,
+                &self.signer_keypair,
+                &self.main_pubkey,
+                &self.agent_pubkey,
+            )
+            .await?;
+            self.send_request::<T, P>(operation, final_request, options)
+                .await
+        }
+        .instrument(span)
+        .await;
+        timer.finish(&result, result.as_ref().ok().map(|r| r.meta.status));
+        result
+    }
+
+    /// Signs `sign_payload` for `operation` without sending it. The signature is only
+    /// accepted until `timestamp + expiry_window`, so keep the window long enough for
+    /// the time between building and `submit_prebuilt`.
+    pub async fn build_request<P>(
+        &self,
+        operation: Operation,
+        sign_payload: P,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<P>>, ExchangeError>
+    where
+        P: Serialize + Debug,
     {
         let final_request = prepare_final_request(
-            operation.name().as_deref().unwrap(),
+            &operation.name()?,
             sign_payload,
             expiry_window,
             &self.signer_keypair,
@@ -203,8 +341,47 @@ impl ExchangeClient {
             &self.agent_pubkey,
         )
         .await?;
+        Ok(PrebuiltRequest {
+            endpoint: operation.endpoint(),
+            body: final_request,
+        })
+    }
 
-        self.send_request::<T, P>(operation, final_request).await
+    /// Posts a body produced by one of the `build_*` methods, possibly by another
+    /// process or machine. The body is sent as is.
+    pub async fn submit_prebuilt<T, B>(&self, endpoint: &str, body: &B) -> Result<T, ExchangeError>
+    where
+        T: DeserializeOwned + Debug,
+        B: Serialize,
+    {
+        self.submit_prebuilt_with(endpoint, body, &RequestOptions::default())
+            .await
+            .map(|response| response.data)
+    }
+
+    /// `submit_prebuilt` with per-call options, returning the response metadata too.
+    pub async fn submit_prebuilt_with<T, B>(
+        &self,
+        endpoint: &str,
+        body: &B,
+        options: &RequestOptions,
+    ) -> Result<RestResponse<T>, ExchangeError>
+    where
+        T: DeserializeOwned + Debug,
+        B: Serialize,
+    {
+        debug!("submit_prebuilt: endpoint={}", endpoint);
+        let response = self
+            .http_client
+            .post_with::<T, B>(
+                Some(endpoint),
+                Some(body),
+                Some(&self.default_headers),
+                options,
+            )
+            .await?;
+        debug!("submit_prebuilt: response={:?}", &response.data);
+        Ok(response)
     }
 
     // pub async fn get_points(&self, user: Pubkey) -> Result<GetPointsResponse, ExchangeError> {
@@ -238,58 +415,66 @@ impl ExchangeClient {
         orders: Vec<BatchOrderActionPayload>,
         expiry_window: Option<u32>,
     ) -> Result<DefaultResponse<BatchOrderResponse>, ExchangeError> {
-        let mut actions: Vec<BatchOrderActionsFinalHeaders> = Vec::new();
-        for order in orders {
-            let (order_type, operation_name) = match order {
-                BatchOrderActionPayload::CreateOrder(_) => (
-                    BatchOrderActionType::Create,
-                    Operation::CreateOrder.name().unwrap(),
-                ),
-                BatchOrderActionPayload::CancelOrder(_) => (
-                    BatchOrderActionType::Cancel,
-                    Operation::CancelOrder.name().unwrap(),
-                ),
-                BatchOrderActionPayload::CreateMarketOrder(_) => (
-                    BatchOrderActionType::CreateMarket,
-                    Operation::CreateMarketOrder.name().unwrap(),
-                ),
-            };
-            let sign_headers = DefaultSignatureHeaders {
-                timestamp: get_timestamp_ms(),
-                expiry_window,
-                type_field: operation_name,
-            };
-            let (_message, signature) = sign_message(&sign_headers, &order, &self.signer_keypair)?;
-            let final_headers = OperationFinalHeaders::Default(DefaultFinalHeaders {
-                account: self.main_pubkey,
-                agent_wallet: self.agent_pubkey,
-                signature: PacificSignature::Simple(signature),
-                expiry_window: sign_headers.expiry_window,
-                timestamp: sign_headers.timestamp,
-            });
-            let final_request = FinalRequest {
-                headers: final_headers,
-                payload: order.clone(),
-            };
-
-            actions.push(BatchOrderActionsFinalHeaders {
-                type_field: order_type,
-                data: final_request,
-            });
-        }
+        let prebuilt = self.build_batch_order(orders, expiry_window)?;
+        self.send_batch_order(&prebuilt.body).await
+    }
 
-        let final_request = BatchOrderFinalRequest { actions };
+    pub fn build_batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<BatchOrderFinalRequest>, ExchangeError> {
+        let actions = orders
+            .into_iter()
+            .map(|order| self.sign_batch_action(order, expiry_window))
+            .collect::<Result<Vec<BatchOrderActionsFinalHeaders>, ExchangeError>>()?;
+        Ok(PrebuiltRequest {
+            endpoint: Operation::BatchOrder.endpoint(),
+            body: BatchOrderFinalRequest { actions },
+        })
+    }
 
-        let response = self
+    pub async fn batch_order_outcome(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> Result<BatchOutcome, ExchangeError> {
+        let response = self.batch_order(orders.clone(), expiry_window).await?;
+        BatchOutcome::from_response(orders, &response)
+    }
+
+    pub fn sign_batch_action(
+        &self,
+        action: BatchOrderActionPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<BatchOrderActionsFinalHeaders, ExchangeError> {
+        sign_batch_action(
+            action,
+            expiry_window,
+            &self.signer_keypair,
+            &self.main_pubkey,
+            &self.agent_pubkey,
+        )
+    }
+
+    pub async fn send_batch_order(
+        &self,
+        final_request: &BatchOrderFinalRequest,
+    ) -> Result<DefaultResponse<BatchOrderResponse>, ExchangeError> {
+        let span = operation_span::<()>("batch_order", None);
+        let timer = OperationTimer::start(&span);
+        let result = self
             .http_client
-            .post::<DefaultResponse<BatchOrderResponse>, BatchOrderFinalRequest>(
+            .post_with::<DefaultResponse<BatchOrderResponse>, BatchOrderFinalRequest>(
                 Some(&Operation::BatchOrder.endpoint()),
-                Some(&final_request),
+                Some(final_request),
                 Some(&self.default_headers),
+                &RequestOptions::default(),
             )
-            .await?;
-
-        Ok(response)
+            .instrument(span)
+            .await;
+        timer.finish(&result, result.as_ref().ok().map(|r| r.meta.status));
+        result.map(|response| response.data)
     }
 
     pub async fn market_order(
@@ -432,6 +617,31 @@ impl ExchangeClient {
         subaccount: &Keypair,
         expiry_window: Option<u32>,
     ) -> Result<DefaultResponse<SubaccountCreateResponse>, ExchangeError> {
+        let span = operation_span::<()>("subaccount_create", None);
+        let timer = OperationTimer::start(&span);
+        let result = async {
+            let prebuilt = self.build_subaccount_create(subaccount, expiry_window)?;
+            // Posted without `default_headers`, the JSON body sets the content type
+            self.http_client
+                .post_with::<DefaultResponse<SubaccountCreateResponse>, _>(
+                    Some(&prebuilt.endpoint),
+                    Some(&prebuilt.body),
+                    None,
+                    &RequestOptions::default(),
+                )
+                .await
+        }
+        .instrument(span)
+        .await;
+        timer.finish(&result, result.as_ref().ok().map(|r| r.meta.status));
+        result.map(|response| response.data)
+    }
+
+    pub fn build_subaccount_create(
+        &self,
+        subaccount: &Keypair,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<()>>, ExchangeError> {
         if self.signer_keypair.pubkey() == subaccount.pubkey() {
             return Err(ExchangeError::Custom(
                 "Main account and subaccount cannot be the same".to_string(),
@@ -442,57 +652,16 @@ impl ExchangeClient {
                 "Main account and agent pubkey cannot be the same".to_string(),
             ));
         }
-        let timestamp = get_timestamp_ms();
-
-        let sub_headers = DefaultSignatureHeaders {
-            timestamp,
-            expiry_window,
-            type_field: Operation::SubaccountCreate(SubaccountCreateAction::Initiate)
-                .name()
-                .unwrap(),
-        };
-        let sub_payload = SubaccountInitiatePayload {
-            account: self.main_pubkey,
-        };
-        let (_sub_msg, sub_signature) = sign_message(&sub_headers, &sub_payload, subaccount)?;
-
-        let main_headers = DefaultSignatureHeaders {
-            timestamp,
-            expiry_window,
-            type_field: Operation::SubaccountCreate(SubaccountCreateAction::Confirm)
-                .name()
-                .unwrap(),
-        };
-        let main_payload = SubaccountConfirmPayload {
-            signature: sub_signature.clone(),
-        };
-        let (_main_msg, main_signature) =
-            sign_message(&main_headers, &main_payload, &self.signer_keypair)?;
-
-        let final_headers = OperationFinalHeaders::SubAccountCreate(SubAccountFinalHeaders {
-            main_account: self.main_pubkey,
-            subaccount: subaccount.pubkey(),
-            main_signature: PacificSignature::Simple(main_signature),
-            sub_signature: PacificSignature::Simple(sub_signature),
+        let final_request = prepare_subaccount_create_request(
+            &self.signer_keypair,
+            &self.main_pubkey,
+            subaccount,
             expiry_window,
-            timestamp,
-        });
-
-        let final_request = FinalRequest {
-            headers: final_headers,
-            payload: (),
-        };
-
-        let response = self
-            .http_client
-            .post::<DefaultResponse<SubaccountCreateResponse>, _>(
-                Some(&Operation::SubaccountCreate(SubaccountCreateAction::Confirm).endpoint()),
-                Some(&final_request),
-                None,
-            )
-            .await?;
-
-        Ok(response)
+        )?;
+        Ok(PrebuiltRequest {
+            endpoint: Operation::SubaccountCreate(SubaccountCreateAction::Confirm).endpoint(),
+            body: final_request,
+        })
     }
 
     pub async fn subaccount_transfer(
@@ -574,4 +743,221 @@ impl ExchangeClient {
             .await?;
         Ok(response)
     }
+
+    // `build_*` variants return the signed request instead of sending it
+
+    pub async fn build_order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CreateOrderPayload>>, ExchangeError> {
+        self.build_request(Operation::CreateOrder, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CreateMarketOrderPayload>>, ExchangeError> {
+        self.build_request(Operation::CreateMarketOrder, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CreateStopOrderPayload>>, ExchangeError> {
+        self.build_request(Operation::CreateStopOrder, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<SetPositionTpslPayload>>, ExchangeError> {
+        self.build_request(Operation::SetPositionTpsl, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CancelOrderPayload>>, ExchangeError> {
+        self.build_request(Operation::CancelOrder, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CancelAllOrdersPayload>>, ExchangeError> {
+        self.build_request(Operation::CancelAllOrders, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CancelStopOrderPayload>>, ExchangeError> {
+        self.build_request(Operation::CancelStopOrder, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_update_margin_mode(
+        &self,
+        sign_payload: UpdateMarginModePayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<UpdateMarginModePayload>>, ExchangeError> {
+        self.build_request(Operation::UpdateMarginMode, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<UpdateLeveragePayload>>, ExchangeError> {
+        self.build_request(Operation::UpdateLeverage, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_withdraw(
+        &self,
+        sign_payload: WithdrawPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<WithdrawPayload>>, ExchangeError> {
+        self.build_request(Operation::Withdraw, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_subaccount_transfer(
+        &self,
+        sign_payload: SubaccountTransferPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<SubaccountTransferPayload>>, ExchangeError> {
+        self.build_request(Operation::SubaccountTransfer, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_bind_agent_wallet(
+        &self,
+        sign_payload: BindAgentWalletPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<BindAgentWalletPayload>>, ExchangeError> {
+        if Some(self.signer_keypair.pubkey()) == self.agent_pubkey {
+            return Err(ExchangeError::Custom(
+                "Main account and agent pubkey cannot be the same".to_string(),
+            ));
+        }
+        self.build_request(Operation::BindAgentWallet, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_create_api_key(
+        &self,
+        sign_payload: CreateApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<CreateApiKeyPayload>>, ExchangeError> {
+        self.build_request(Operation::CreateApiKey, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_revoke_api_key(
+        &self,
+        sign_payload: RevokeApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<RevokeApiKeyPayload>>, ExchangeError> {
+        self.build_request(Operation::RevokeApiKey, sign_payload, expiry_window)
+            .await
+    }
+
+    pub async fn build_list_api_keys(
+        &self,
+        sign_payload: ListApiKeysPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<PrebuiltRequest<FinalRequest<ListApiKeysPayload>>, ExchangeError> {
+        self.build_request(Operation::ListApiKeys, sign_payload, expiry_window)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::{
+        common::types::{OrderSide, Tif},
+        models::{
+            exchange::response::order::CreateOrderResponse, info::response::market::MarketModel,
+        },
+        test_util::{HttpResponse, spawn_http_server},
+    };
+
+    fn btc() -> MarketModel {
+        MarketModel {
+            symbol: "BTC".to_string(),
+            tick_size: Decimal::ONE,
+            min_tick: Decimal::ZERO,
+            max_tick: Decimal::from(1_000_000),
+            lot_size: Decimal::new(1, 5),
+            max_leverage: Decimal::from(50),
+            isolated_only: false,
+            min_order_size: Decimal::from(10),
+            max_order_size: Decimal::from(1_000_000),
+            funding_rate: Decimal::ZERO,
+            next_funding_rate: Decimal::ZERO,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prebuilt_round_trip() {
+        let (requests_tx, mut requests) = mpsc::unbounded_channel();
+        let url = spawn_http_server(move |request| {
+            requests_tx.send(request.clone()).unwrap();
+            HttpResponse::ok(json!({"order_id": 7}))
+        })
+        .await;
+
+        // Built offline, then stored and loaded again before sending
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client =
+            ExchangeClient::from_markets(&url, vec![btc()], keypair, pubkey, None).unwrap();
+        assert!(client.info_client.market_cache.contains_key("BTC"));
+        let payload = CreateOrderPayload {
+            symbol: "BTC".to_string(),
+            price: Decimal::from(100_000),
+            amount: Decimal::new(1, 2),
+            side: OrderSide::Bid,
+            tif: Tif::GTC,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        };
+        let prebuilt = client.build_order(payload, Some(60_000)).await.unwrap();
+        let stored = serde_json::to_string(&prebuilt).unwrap();
+        let loaded: PrebuiltRequest<Value> = serde_json::from_str(&stored).unwrap();
+        assert_eq!(loaded.body["account"], pubkey.to_string());
+        assert!(loaded.body["signature"].is_string());
+
+        let response: DefaultResponse<CreateOrderResponse> = client
+            .submit_prebuilt(&loaded.endpoint, &loaded.body)
+            .await
+            .unwrap();
+        assert_eq!(response.data.unwrap().order_id, 7);
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, "POST");
+        assert!(request.path().ends_with("/orders/create"), "{request:?}");
+        assert_eq!(request.json(), loaded.body);
+    }
 }
@@ -1,2 +1,10 @@
+pub mod agent_wallet;
+pub mod api_key_manager;
+pub mod batch_builder;
+pub mod batch_outcome;
 pub mod exchange_client;
 pub mod operations;
+pub mod paper_exchange;
+pub mod session_pool;
+pub mod subaccount_manager;
+pub mod trading_api;
@@ -0,0 +1,629 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rust_decimal::Decimal;
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{debug, warn};
+
+use crate::{
+    backtest::{
+        events::MarketEvent,
+        exchange::{SimulatedExchange, SimulatedExchangeConfig},
+        report::BacktestReport,
+    },
+    common::{
+        errors::ExchangeError,
+        types::{DefaultResponse, EmptyResponseData, OrderSide},
+    },
+    models::{
+        exchange::{
+            payload::{
+                batch_order::BatchOrderActionPayload,
+                market_settings::UpdateLeveragePayload,
+                order::{
+                    CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+                    CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+                    SetPositionTpslPayload,
+                },
+            },
+            response::{
+                batch_order::{BatchOrderModel, BatchOrderResponse},
+                market_settings::UpdateLeverageResponse,
+                order::{
+                    CancelAllOrdersResponse, CancelOrderResponse, CancelStopOrderResponse,
+                    CreateMarketOrderResponse, CreateOrderResponse, CreateStopOrderResponse,
+                    SetPositionTPSLResponse,
+                },
+            },
+        },
+        info::response::account::{AccountResponse, PositionModel},
+        ws::responses::{OrderBookResponse, OrderUpdateModel, TradesResponse},
+    },
+    ws::ws_client::WebSocketClient,
+};
+
+#[derive(Debug, Clone)]
+pub struct PaperExchangeConfig {
+    pub exchange: SimulatedExchangeConfig,
+    // Leverage of symbols not set with `update_leverage`
+    pub default_leverage: u16,
+}
+
+impl Default for PaperExchangeConfig {
+    fn default() -> Self {
+        Self {
+            exchange: SimulatedExchangeConfig::default(),
+            default_leverage: 10,
+        }
+    }
+}
+
+struct PaperState {
+    exchange: SimulatedExchange,
+    leverage: HashMap<String, u16>,
+    default_leverage: u16,
+}
+
+impl PaperState {
+    fn leverage(&self, symbol: &str) -> Decimal {
+        Decimal::from(
+            self.leverage
+                .get(symbol)
+                .copied()
+                .unwrap_or(self.default_leverage),
+        )
+    }
+
+    // Initial margin of positions and resting orders
+    fn margin_used(&self) -> Decimal {
+        let positions: Decimal = self
+            .exchange
+            .positions()
+            .iter()
+            .map(|(symbol, position)| {
+                let price = self.exchange.mark(symbol).unwrap_or(position.entry_price);
+                position.amount.abs() * price / self.leverage(symbol)
+            })
+            .sum();
+        let orders: Decimal = self
+            .exchange
+            .open_orders()
+            .iter()
+            .filter(|o| !o.reduce_only)
+            .map(|o| o.remaining() * o.price / self.leverage(&o.symbol))
+            .sum();
+        positions + orders
+    }
+
+    fn check_margin(
+        &self,
+        symbol: &str,
+        amount: Decimal,
+        price: Option<Decimal>,
+        reduce_only: bool,
+    ) -> Result<(), ExchangeError> {
+        if reduce_only {
+            return Ok(());
+        }
+        let Some(price) = price.or_else(|| self.exchange.mark(symbol)) else {
+            return Err(ExchangeError::Validation(format!(
+                "no price for {}",
+                symbol
+            )));
+        };
+        let required = amount * price / self.leverage(symbol);
+        let available = self.exchange.equity() - self.margin_used();
+        if required > available {
+            return Err(ExchangeError::Validation(format!(
+                "insufficient margin: {} required, {} available",
+                required.round_dp(2),
+                available.round_dp(2)
+            )));
+        }
+        Ok(())
+    }
+}
+
+type Updates = broadcast::Sender<OrderUpdateModel>;
+
+fn publish(state: &mut PaperState, updates: &Updates, account: Pubkey) {
+    for mut update in state.exchange.drain_order_updates() {
+        update.account = account;
+        // No receivers is fine
+        updates.send(update).ok();
+    }
+}
+
+fn respond<T>(result: Result<T, ExchangeError>) -> DefaultResponse<T> {
+    match result {
+        Ok(data) => DefaultResponse {
+            success: Some(true),
+            data: Some(data),
+            error: None,
+            code: None,
+        },
+        Err(e) => DefaultResponse {
+            success: Some(false),
+            data: None,
+            error: Some(e.to_string()),
+            code: Some(400),
+        },
+    }
+}
+
+/// Simulated account with the trading methods of `ExchangeClient`, matched
+/// against live `book` and `trades` streams. Nothing is signed or sent; orders
+/// rejected by the simulation come back as `success: false` responses like
+/// exchange rejections. Order updates are published as `OrderUpdateModel`s.
+pub struct PaperExchange {
+    account: Pubkey,
+    state: Arc<Mutex<PaperState>>,
+    updates: Updates,
+    feeds: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl PaperExchange {
+    /// `account` is reported in order updates.
+    pub fn new(account: Pubkey, config: PaperExchangeConfig) -> Self {
+        let mut exchange_config = config.exchange;
+        exchange_config.record_order_updates = true;
+        Self {
+            account,
+            state: Arc::new(Mutex::new(PaperState {
+                exchange: SimulatedExchange::new(exchange_config),
+                leverage: HashMap::new(),
+                default_leverage: config.default_leverage,
+            })),
+            updates: broadcast::channel(1024).0,
+            feeds: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Subscribes to `symbol`'s book and trades on `web_socket_client` and
+    /// matches resting orders against them. Streams of the same symbol the
+    /// caller opened on the client keep receiving alongside.
+    pub async fn track(
+        &self,
+        web_socket_client: &WebSocketClient,
+        symbol: &str,
+    ) -> Result<(), ExchangeError> {
+        let mut book = web_socket_client
+            .subscribe_to_orderbook(symbol, None)
+            .await?;
+        let mut trades = web_socket_client.subscribe_to_trades(symbol).await?;
+        let state = self.state.clone();
+        let updates = self.updates.clone();
+        let account = self.account;
+        let handle = tokio::spawn(async move {
+            let apply = |event: MarketEvent| {
+                let mut state = state.lock().unwrap();
+                state.exchange.apply(&event);
+                publish(&mut state, &updates, account);
+            };
+            loop {
+                tokio::select! {
+                    Some(msg) = book.recv_typed::<OrderBookResponse>() => match msg {
+                        Ok(msg) => apply(MarketEvent::Book(msg.data)),
+                        Err(e) => debug!("PaperExchange: bad book message: {:?}", e),
+                    },
+                    Some(msg) = trades.recv_typed::<TradesResponse>() => match msg {
+                        Ok(msg) => msg.data.into_iter().map(MarketEvent::Trade).for_each(apply),
+                        Err(e) => debug!("PaperExchange: bad trades message: {:?}", e),
+                    },
+                    else => break,
+                }
+            }
+            warn!("PaperExchange: market data feed ended");
+        });
+        self.feeds.lock().unwrap().push(handle);
+        Ok(())
+    }
+
+    /// Feeds an event directly, e.g. `MarketEvent::Funding` or data from
+    /// another source.
+    pub fn apply(&self, event: &MarketEvent) {
+        self.with_exchange(|exchange| exchange.apply(event));
+    }
+
+    pub fn main_pubkey(&self) -> Pubkey {
+        self.account
+    }
+
+    pub fn order_updates(&self) -> broadcast::Receiver<OrderUpdateModel> {
+        self.updates.subscribe()
+    }
+
+    pub async fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateOrderResponse>, ExchangeError> {
+        Ok(respond(self.with_state(|state| {
+            state.check_margin(
+                &sign_payload.symbol,
+                sign_payload.amount,
+                Some(sign_payload.price),
+                sign_payload.reduce_only,
+            )?;
+            let order_id = state.exchange.create_order(sign_payload)?;
+            Ok(CreateOrderResponse { order_id })
+        })))
+    }
+
+    /// Runs the actions in order; each result says whether it was accepted.
+    pub async fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<BatchOrderResponse>, ExchangeError> {
+        let mut results = Vec::with_capacity(orders.len());
+        for action in orders {
+            let (success, order_id, error) = match action {
+                BatchOrderActionPayload::CreateOrder(payload) => {
+                    let r = self.order(payload, expiry_window).await?;
+                    (r.success, r.data.map(|d| d.order_id), r.error)
+                }
+                BatchOrderActionPayload::CreateMarketOrder(payload) => {
+                    let r = self.market_order(payload, expiry_window).await?;
+                    (r.success, r.data.map(|d| d.order_id), r.error)
+                }
+                BatchOrderActionPayload::CancelOrder(payload) => {
+                    let order_id = payload.order_id;
+                    let r = self.cancel_order(payload, expiry_window).await?;
+                    (r.success, order_id.filter(|_| r.error.is_none()), r.error)
+                }
+            };
+            results.push(BatchOrderModel {
+                success: success == Some(true),
+                order_id,
+                error,
+            });
+        }
+        Ok(respond(Ok(BatchOrderResponse { results })))
+    }
+
+    pub async fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateMarketOrderResponse>, ExchangeError> {
+        Ok(respond(self.with_state(|state| {
+            state.check_margin(
+                &sign_payload.symbol,
+                sign_payload.amount,
+                None,
+                sign_payload.reduce_only,
+            )?;
+            let order_id = state.exchange.create_market_order(sign_payload)?;
+            Ok(CreateMarketOrderResponse { order_id })
+        })))
+    }
+
+    pub async fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateStopOrderResponse>, ExchangeError> {
+        Ok(respond(self.with_exchange(|exchange| {
+            let order_id = exchange.create_stop_order(sign_payload)?;
+            Ok(CreateStopOrderResponse { order_id })
+        })))
+    }
+
+    pub async fn set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<SetPositionTPSLResponse>, ExchangeError> {
+        Ok(respond(self.with_exchange(|exchange| {
+            exchange.set_position_tpsl(sign_payload)?;
+            Ok(SetPositionTPSLResponse {})
+        })))
+    }
+
+    pub async fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelOrderResponse>, ExchangeError> {
+        Ok(respond(self.with_exchange(|exchange| {
+            exchange.cancel_order(sign_payload)?;
+            Ok(CancelOrderResponse {})
+        })))
+    }
+
+    pub async fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        Ok(respond(self.with_exchange(|exchange| {
+            let cancelled_count = exchange.cancel_all_orders(sign_payload) as u32;
+            Ok(CancelAllOrdersResponse { cancelled_count })
+        })))
+    }
+
+    pub async fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelStopOrderResponse>, ExchangeError> {
+        Ok(respond(self.with_exchange(|exchange| {
+            exchange.cancel_stop_order(sign_payload)?;
+            Ok(CancelStopOrderResponse {})
+        })))
+    }
+
+    pub async fn update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        _expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<UpdateLeverageResponse>, ExchangeError> {
+        Ok(respond(self.with_state(|state| {
+            if sign_payload.leverage == 0 {
+                return Err(ExchangeError::Validation(
+                    "leverage must be at least 1".into(),
+                ));
+            }
+            let previous = state
+                .leverage
+                .insert(sign_payload.symbol.clone(), sign_payload.leverage);
+            // Lowering leverage must leave enough margin for the open position
+            if state.exchange.equity() < state.margin_used() {
+                match previous {
+                    Some(leverage) => state.leverage.insert(sign_payload.symbol, leverage),
+                    None => state.leverage.remove(&sign_payload.symbol),
+                };
+                return Err(ExchangeError::Validation(
+                    "insufficient margin for leverage".into(),
+                ));
+            }
+            Ok(EmptyResponseData {})
+        })))
+    }
+
+    /// Simulated counterpart of `InfoClient::account`.
+    pub fn account(&self) -> AccountResponse {
+        let state = self.state.lock().unwrap();
+        let exchange = &state.exchange;
+        let equity = exchange.equity();
+        let margin_used = state.margin_used();
+        let available = (equity - margin_used).max(Decimal::ZERO);
+        AccountResponse {
+            balance: exchange.balance(),
+            fee_level: exchange.config().fee_tier,
+            account_equity: equity,
+            available_to_spend: available,
+            available_to_withdraw: available.min(exchange.balance()),
+            pending_balance: Decimal::ZERO,
+            total_margin_used: margin_used,
+            positions_count: exchange
+                .positions()
+                .values()
+                .filter(|p| !p.amount.is_zero())
+                .count() as u32,
+            orders_count: exchange.open_orders().len() as u32,
+            stop_orders_count: exchange.stop_orders().len() as u32,
+            updated_at: exchange.now(),
+        }
+    }
+
+    /// Simulated counterpart of `InfoClient::positions`.
+    pub fn positions(&self) -> Vec<PositionModel> {
+        let state = self.state.lock().unwrap();
+        state
+            .exchange
+            .positions()
+            .iter()
+            .filter(|(_, p)| !p.amount.is_zero())
+            .map(|(symbol, p)| PositionModel {
+                symbol: symbol.clone(),
+                side: if p.amount > Decimal::ZERO {
+                    OrderSide::Bid
+                } else {
+                    OrderSide::Ask
+                },
+                amount: p.amount.abs(),
+                entry_price: p.entry_price,
+                margin: None,
+                funding: p.funding,
+                isolated: false,
+                created_at: p.opened_at,
+                updated_at: p.updated_at,
+            })
+            .collect()
+    }
+
+    /// PnL, fees, funding, drawdown and fills since creation.
+    pub fn report(&self) -> BacktestReport {
+        self.state.lock().unwrap().exchange.report()
+    }
+
+    /// Stops the market data feeds; the account state is kept.
+    pub fn stop(&self) {
+        for handle in self.feeds.lock().unwrap().drain(..) {
+            handle.abort();
+        }
+    }
+
+    fn with_state<T>(&self, f: impl FnOnce(&mut PaperState) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        let result = f(&mut state);
+        publish(&mut state, &self.updates, self.account);
+        result
+    }
+
+    fn with_exchange<T>(&self, f: impl FnOnce(&mut SimulatedExchange) -> T) -> T {
+        self.with_state(|state| f(&mut state.exchange))
+    }
+}
+
+impl Drop for PaperExchange {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        common::types::{OrderEventType, OrderStatus, Tif},
+        test_util::{book_data, book_event, spawn_ws_server},
+    };
+
+    use super::*;
+
+    fn bid(price: i64, amount: i64) -> CreateOrderPayload {
+        bid_on("BTC", price, amount)
+    }
+
+    fn bid_on(symbol: &str, price: i64, amount: i64) -> CreateOrderPayload {
+        CreateOrderPayload {
+            symbol: symbol.into(),
+            price: Decimal::from(price),
+            amount: Decimal::from(amount),
+            side: OrderSide::Bid,
+            tif: Tif::GTC,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_paper_orders_margin_and_updates() {
+        let account = Pubkey::new_unique();
+        let paper = PaperExchange::new(account, PaperExchangeConfig::default());
+        let mut updates = paper.order_updates();
+        paper.apply(&book_event("BTC", 1, ("99", "1"), ("101", "1")));
+
+        // 10x on 10_000 allows 100_000 notional, 1_000 at 100
+        let rejected = paper.order(bid(100, 1_001), None).await.unwrap();
+        assert_eq!(rejected.success, Some(false));
+
+        let resting = paper.order(bid(100, 1), None).await.unwrap();
+        let order_id = resting.data.unwrap().order_id;
+        let update = updates.recv().await.unwrap();
+        assert_eq!((update.order_id, update.account), (order_id, account));
+        assert!(matches!(update.order_status, OrderStatus::Open));
+
+        // Ask drops through the bid: filled as maker
+        paper.apply(&book_event("BTC", 1, ("98", "1"), ("99", "1")));
+        let update = updates.recv().await.unwrap();
+        assert!(matches!(update.event_type, OrderEventType::FulfillMaker));
+        assert!(matches!(update.order_status, OrderStatus::Filled));
+        assert_eq!(update.average_filled_price, Decimal::from(100));
+
+        let positions = paper.positions();
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].amount, Decimal::ONE);
+        let info = paper.account();
+        assert_eq!(info.positions_count, 1);
+        assert_eq!(info.total_margin_used, Decimal::new(985, 2));
+
+        let leverage = paper
+            .update_leverage(
+                UpdateLeveragePayload {
+                    symbol: "BTC".into(),
+                    leverage: 2,
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(leverage.success, Some(true));
+        assert_eq!(paper.account().total_margin_used, Decimal::new(4925, 2));
+    }
+
+    #[tokio::test]
+    async fn test_track_two_symbols() {
+        let (url, push) = spawn_ws_server().await;
+        let client = WebSocketClient::new(&url, None).await.unwrap();
+        let paper = PaperExchange::new(Pubkey::new_unique(), PaperExchangeConfig::default());
+        let mut updates = paper.order_updates();
+        paper.track(&client, "BTC").await.unwrap();
+        paper.track(&client, "ETH").await.unwrap();
+
+        let book = |symbol: &str, bid: &str, ask: &str| {
+            let data = book_data(symbol, 1, (bid, "1"), (ask, "1"));
+            push.send(json!({ "channel": "book", "data": data }).to_string())
+                .unwrap();
+        };
+        book("BTC", "99", "101");
+        book("ETH", "99", "101");
+        for symbol in ["BTC", "ETH"] {
+            // Opening an order needs a mark, so wait until the book is applied
+            tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                loop {
+                    let placed = paper.order(bid_on(symbol, 100, 1), None).await.unwrap();
+                    if placed.success == Some(true) {
+                        break;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+                }
+            })
+            .await
+            .unwrap();
+            assert!(matches!(
+                updates.recv().await.unwrap().order_status,
+                OrderStatus::Open
+            ));
+        }
+
+        // Both feeds stay live: each book crosses its own symbol's bid
+        book("ETH", "98", "99");
+        book("BTC", "98", "99");
+        let mut filled = Vec::new();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while filled.len() < 2 {
+                let update = updates.recv().await.unwrap();
+                if matches!(update.order_status, OrderStatus::Filled) {
+                    filled.push(update.symbol);
+                }
+            }
+        })
+        .await
+        .unwrap();
+        filled.sort();
+        assert_eq!(filled, ["BTC", "ETH"]);
+    }
+
+    #[tokio::test]
+    async fn test_track_shares_the_strategy_client() {
+        let (url, push) = spawn_ws_server().await;
+        let client = WebSocketClient::new(&url, None).await.unwrap();
+        let mut strategy_book = client.subscribe_to_orderbook("BTC", None).await.unwrap();
+        let paper = PaperExchange::new(Pubkey::new_unique(), PaperExchangeConfig::default());
+        paper.track(&client, "BTC").await.unwrap();
+
+        let msg = json!({
+            "channel": "book",
+            "data": book_data("BTC", 1, ("99", "1"), ("101", "1"))
+        });
+        push.send(msg.to_string()).unwrap();
+        let timeout = std::time::Duration::from_secs(5);
+        let received = tokio::time::timeout(timeout, strategy_book.recv()).await;
+        assert_eq!(received.unwrap().unwrap()["data"]["s"], "BTC");
+        // The paper feed got the same message
+        tokio::time::timeout(timeout, async {
+            while paper.order(bid(100, 1), None).await.unwrap().success != Some(true) {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        // Stopping the paper feeds leaves the strategy's subscription alone
+        paper.stop();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        push.send(msg.to_string()).unwrap();
+        let received = tokio::time::timeout(timeout, strategy_book.recv()).await;
+        assert_eq!(received.unwrap().unwrap()["data"]["s"], "BTC");
+    }
+}
@@ -0,0 +1,569 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+use tokio::{
+    sync::{Mutex, mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, info};
+
+use crate::{
+    common::{consts, errors::ExchangeError, types::WebSocketParams},
+    exchange::exchange_client::ExchangeClient,
+    info::info_client::InfoClient,
+    models::ws::subscriptions::SubscriptionMethod,
+    rest::rest_client::RestClientOptions,
+    ws::ws_client::{Subscription, WebSocketClient, WebSocketOptions, subscription_key},
+};
+
+#[derive(Debug, Clone)]
+pub struct SessionPoolOptions {
+    pub rest: RestClientOptions,
+    pub ws: WebSocketOptions,
+    // Subscriptions fail once this many connections are full. Account channels
+    // whose messages don't name the account take one connection per account
+    pub max_ws_connections: usize,
+    pub max_subscriptions_per_channel: usize,
+}
+
+impl Default for SessionPoolOptions {
+    fn default() -> Self {
+        Self {
+            rest: RestClientOptions::default(),
+            ws: WebSocketOptions::default(),
+            max_ws_connections: consts::MAX_WS_CONNECTIONS_PER_IP,
+            max_subscriptions_per_channel: consts::MAX_WS_SUBSCRIPTIONS_PER_CHANNEL,
+        }
+    }
+}
+
+/// Many signing identities in one process over shared resources: one HTTP
+/// connection pool and rate limiter, market metadata fetched once, and a few
+/// WebSocket connections filled up to the per-channel subscription limit.
+///
+/// Each account gets its own `ExchangeClient`, with its own API key for REST.
+/// WebSocket connections carry the pool's API key. Identical subscriptions from
+/// several accounts (e.g. `prices`) are sent once and fanned out.
+pub struct SessionPool {
+    info_client: InfoClient,
+    accounts: RwLock<HashMap<Pubkey, Arc<ExchangeClient>>>,
+    ws: Option<Arc<Mutex<WsPool>>>,
+    next_subscriber: AtomicU64,
+}
+
+impl SessionPool {
+    pub async fn new(
+        is_mainnet: bool,
+        enable_ws: bool,
+        api_key: Option<String>,
+    ) -> Result<Self, ExchangeError> {
+        let (base_url, ws_url) = if is_mainnet {
+            (consts::REST_API_MAINNET_URL, consts::WS_MAINNET_URL)
+        } else {
+            (consts::REST_API_TESTNET_URL, consts::WS_TESTNET_URL)
+        };
+        Self::with_options(
+            base_url,
+            enable_ws.then_some(ws_url),
+            api_key,
+            SessionPoolOptions::default(),
+        )
+        .await
+    }
+
+    pub async fn with_options(
+        base_url: &str,
+        ws_url: Option<&str>,
+        api_key: Option<String>,
+        options: SessionPoolOptions,
+    ) -> Result<Self, ExchangeError> {
+        if options.max_ws_connections > consts::MAX_WS_CONNECTIONS_PER_IP {
+            return Err(ExchangeError::Validation(format!(
+                "max_ws_connections is {}, the exchange allows {} per IP",
+                options.max_ws_connections,
+                consts::MAX_WS_CONNECTIONS_PER_IP
+            )));
+        }
+        // Connections are owned by the pool, not by this client
+        let info_client = InfoClient::with_options(
+            base_url,
+            None,
+            api_key.clone(),
+            options.rest.clone(),
+            options.ws.clone(),
+        )
+        .await?;
+        let ws = ws_url.map(|url| {
+            Arc::new(Mutex::new(WsPool {
+                url: url.to_string(),
+                api_key,
+                options,
+                connections: Vec::new(),
+                opening: 0,
+                subscriptions: HashMap::new(),
+            }))
+        });
+        Ok(Self {
+            info_client,
+            accounts: RwLock::new(HashMap::new()),
+            ws,
+            next_subscriber: AtomicU64::new(0),
+        })
+    }
+
+    /// Market data and REST access shared by all accounts.
+    pub fn info_client(&self) -> &InfoClient {
+        &self.info_client
+    }
+
+    /// Registers an account. `api_key` applies to its REST calls only, `None`
+    /// uses the pool's key. WebSocket trading requests go over the pool's first
+    /// connection.
+    pub async fn add_account(
+        &self,
+        signer_keypair: Keypair,
+        main_pubkey: Pubkey,
+        agent_pubkey: Option<Pubkey>,
+        api_key: Option<String>,
+    ) -> Result<Arc<ExchangeClient>, ExchangeError> {
+        if self.accounts.read().unwrap().contains_key(&main_pubkey) {
+            return Err(ExchangeError::Validation(format!(
+                "account {} is already in the pool",
+                main_pubkey
+            )));
+        }
+        let web_socket_client = match &self.ws {
+            Some(ws) => Some(WsPool::trading_connection(ws).await?),
+            None => None,
+        };
+        let info_client = self.info_client.share(
+            api_key.or_else(|| self.info_client.api_key()),
+            web_socket_client,
+        )?;
+        let client = Arc::new(ExchangeClient::from_info_client(
+            info_client,
+            signer_keypair,
+            main_pubkey,
+            agent_pubkey,
+        )?);
+        self.accounts
+            .write()
+            .unwrap()
+            .insert(main_pubkey, client.clone());
+        info!("SessionPool: added account {}", main_pubkey);
+        Ok(client)
+    }
+
+    pub fn remove_account(&self, main_pubkey: &Pubkey) -> Option<Arc<ExchangeClient>> {
+        self.accounts.write().unwrap().remove(main_pubkey)
+    }
+
+    pub fn client(&self, main_pubkey: &Pubkey) -> Option<Arc<ExchangeClient>> {
+        self.accounts.read().unwrap().get(main_pubkey).cloned()
+    }
+
+    pub fn accounts(&self) -> Vec<Pubkey> {
+        let mut accounts: Vec<Pubkey> = self.accounts.read().unwrap().keys().copied().collect();
+        accounts.sort();
+        accounts
+    }
+
+    /// Subscribes on the first connection with room for it, opening one if needed.
+    /// The subscription is released when the returned value is dropped.
+    pub async fn subscribe<P: Serialize + Clone>(
+        &self,
+        params: WebSocketParams<P>,
+    ) -> Result<PooledSubscription, ExchangeError> {
+        let ws = self.ws.as_ref().ok_or_else(|| {
+            ExchangeError::NotInitialized("SessionPool has no WebSocket URL".into())
+        })?;
+        let id = self.next_subscriber.fetch_add(1, Ordering::Relaxed);
+        let params = serde_json::to_value(&params)?;
+        let (tx, rx) = mpsc::channel(32);
+        let params_id = WsPool::subscribe(ws, params, id, tx).await?;
+        Ok(PooledSubscription {
+            ws: ws.clone(),
+            params_id,
+            id,
+            rx,
+        })
+    }
+
+    /// Subscribes `account` to one of the account channels (positions, orders,
+    /// balance, ...). `positions`, `orders`, `balance`, `account_info` and
+    /// `account_trades` need a connection per account, so at most
+    /// `max_ws_connections` accounts can subscribe to each of them.
+    pub async fn subscribe_account(
+        &self,
+        method: SubscriptionMethod,
+        account: Pubkey,
+    ) -> Result<PooledSubscription, ExchangeError> {
+        self.subscribe(WebSocketParams {
+            source: method.to_string(),
+            params: json!({ "account": account.to_string() }),
+        })
+        .await
+    }
+
+    /// Subscription count per channel for each open connection.
+    pub async fn ws_load(&self) -> Vec<HashMap<String, usize>> {
+        let Some(ws) = &self.ws else {
+            return Vec::new();
+        };
+        ws.lock()
+            .await
+            .connections
+            .iter()
+            .map(|c| {
+                c.keys
+                    .iter()
+                    .map(|(channel, keys)| (channel.clone(), keys.len()))
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+pub struct PooledSubscription {
+    ws: Arc<Mutex<WsPool>>,
+    params_id: String,
+    id: u64,
+    rx: mpsc::Receiver<Value>,
+}
+
+impl PooledSubscription {
+    pub async fn recv(&mut self) -> Option<Value> {
+        self.rx.recv().await
+    }
+}
+
+impl Drop for PooledSubscription {
+    fn drop(&mut self) {
+        let ws = self.ws.clone();
+        let params_id = std::mem::take(&mut self.params_id);
+        let id = self.id;
+        // Without a runtime the connections are gone with it, nothing to release
+        if let Ok(handle) = tokio::runtime::Handle::try_current() {
+            handle.spawn(async move {
+                WsPool::release(&ws, &params_id, id).await;
+            });
+        }
+    }
+}
+
+struct PooledConnection {
+    client: WebSocketClient,
+    // channel -> subscription keys held on this connection
+    keys: HashMap<String, HashSet<String>>,
+}
+
+struct SharedSubscription {
+    connection: usize,
+    channel: String,
+    key: String,
+    subscribers: Arc<Mutex<HashMap<u64, mpsc::Sender<Value>>>>,
+    // `Some(true)` once the connection holds the subscription, `Some(false)`
+    // if subscribing failed
+    ready: watch::Receiver<Option<bool>>,
+    // Owns the connection's `Subscription`; aborting it releases the key
+    forwarder: Option<JoinHandle<()>>,
+}
+
+struct WsPool {
+    url: String,
+    api_key: Option<String>,
+    options: SessionPoolOptions,
+    connections: Vec<PooledConnection>,
+    // Connections being opened, counted against `max_ws_connections`
+    opening: usize,
+    // Keyed by the serialized params, so identical subscriptions are shared
+    subscriptions: HashMap<String, SharedSubscription>,
+}
+
+// Network calls happen with the pool unlocked: the pool is only locked to
+// reserve a connection slot or a subscription key and to record the result
+impl WsPool {
+    async fn open_connection(pool: &Mutex<WsPool>, channel: &str) -> Result<(), ExchangeError> {
+        let (url, api_key, options) = {
+            let mut ws = pool.lock().await;
+            if ws.connections.len() + ws.opening >= ws.options.max_ws_connections {
+                return Err(ExchangeError::Validation(format!(
+                    "all {} WebSocket connections of the pool are in use and `{}` needs \
+                     another, raise max_ws_connections (at most {})",
+                    ws.options.max_ws_connections,
+                    channel,
+                    consts::MAX_WS_CONNECTIONS_PER_IP
+                )));
+            }
+            ws.opening += 1;
+            (ws.url.clone(), ws.api_key.clone(), ws.options.ws.clone())
+        };
+        let client = WebSocketClient::with_options(&url, api_key, options).await;
+        let mut ws = pool.lock().await;
+        ws.opening -= 1;
+        ws.connections.push(PooledConnection {
+            client: client?,
+            keys: HashMap::new(),
+        });
+        debug!("SessionPool: opened connection {}", ws.connections.len());
+        Ok(())
+    }
+
+    async fn trading_connection(pool: &Mutex<WsPool>) -> Result<WebSocketClient, ExchangeError> {
+        if pool.lock().await.connections.is_empty() {
+            Self::open_connection(pool, "trading").await?;
+        }
+        Ok(pool.lock().await.connections[0].client.clone())
+    }
+
+    async fn subscribe(
+        pool: &Mutex<WsPool>,
+        params: Value,
+        id: u64,
+        tx: mpsc::Sender<Value>,
+    ) -> Result<String, ExchangeError> {
+        let params_id = params.to_string();
+        let channel = params
+            .get("source")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let key = subscription_key(&params);
+
+        let (client, ready, subscribers) = loop {
+            let mut ws = pool.lock().await;
+            if let Some(shared) = ws.subscriptions.get(&params_id) {
+                shared.subscribers.lock().await.insert(id, tx);
+                let mut ready = shared.ready.clone();
+                drop(ws);
+                return match ready.wait_for(Option::is_some).await.as_deref() {
+                    Ok(Some(true)) => Ok(params_id),
+                    _ => Err(ExchangeError::WebSocketSendRequest(format!(
+                        "subscribing to {} failed",
+                        params_id
+                    ))),
+                };
+            }
+            let loads: Vec<_> = ws.connections.iter().map(|c| &c.keys).collect();
+            let limit = ws.options.max_subscriptions_per_channel;
+            let Some(connection) = place(&loads, &channel, &key, limit) else {
+                drop(ws);
+                Self::open_connection(pool, &channel).await?;
+                continue;
+            };
+            let conn = &mut ws.connections[connection];
+            conn.keys
+                .entry(channel.clone())
+                .or_default()
+                .insert(key.clone());
+            let client = conn.client.clone();
+            let (ready, ready_rx) = watch::channel(None);
+            let subscribers = Arc::new(Mutex::new(HashMap::from([(id, tx)])));
+            ws.subscriptions.insert(
+                params_id.clone(),
+                SharedSubscription {
+                    connection,
+                    channel: channel.clone(),
+                    key: key.clone(),
+                    subscribers: subscribers.clone(),
+                    ready: ready_rx,
+                    forwarder: None,
+                },
+            );
+            break (client, ready, subscribers);
+        };
+
+        let typed: WebSocketParams<Value> = serde_json::from_value(params)?;
+        let subscription = match client.subscribe(typed, &key).await {
+            Ok(subscription) => subscription,
+            Err(e) => {
+                let mut ws = pool.lock().await;
+                if let Some(shared) = ws.subscriptions.remove(&params_id) {
+                    ws.free_key(&shared);
+                }
+                ready.send_replace(Some(false));
+                return Err(e);
+            }
+        };
+        let forwarder = tokio::spawn(forward(subscription, subscribers));
+        match pool.lock().await.subscriptions.get_mut(&params_id) {
+            Some(shared) => shared.forwarder = Some(forwarder),
+            // Released while subscribing
+            None => forwarder.abort(),
+        }
+        ready.send_replace(Some(true));
+        Ok(params_id)
+    }
+
+    async fn release(pool: &Mutex<WsPool>, params_id: &str, id: u64) {
+        let mut ws = pool.lock().await;
+        let Some(shared) = ws.subscriptions.get(params_id) else {
+            return;
+        };
+        {
+            let mut subscribers = shared.subscribers.lock().await;
+            subscribers.remove(&id);
+            if !subscribers.is_empty() {
+                return;
+            }
+        }
+        let shared = ws.subscriptions.remove(params_id).unwrap();
+        ws.free_key(&shared);
+        // Dropping the connection's `Subscription` unsubscribes in the background
+        if let Some(forwarder) = shared.forwarder {
+            forwarder.abort();
+        }
+    }
+
+    fn free_key(&mut self, shared: &SharedSubscription) {
+        if let Some(keys) = self.connections[shared.connection]
+            .keys
+            .get_mut(&shared.channel)
+        {
+            keys.remove(&shared.key);
+        }
+    }
+}
+
+async fn forward(
+    mut subscription: Subscription,
+    subscribers: Arc<Mutex<HashMap<u64, mpsc::Sender<Value>>>>,
+) {
+    while let Some(msg) = subscription.recv().await {
+        // Cloned out so a slow subscriber doesn't block subscribe and release
+        let senders: Vec<_> = subscribers.lock().await.values().cloned().collect();
+        for tx in senders {
+            tx.send(msg.clone()).await.ok();
+        }
+    }
+}
+
+// First connection where `channel` is below `limit` and `key` is free, so its
+// messages can still be told apart
+fn place(
+    connections: &[&HashMap<String, HashSet<String>>],
+    channel: &str,
+    key: &str,
+    limit: usize,
+) -> Option<usize> {
+    connections.iter().position(|keys| match keys.get(channel) {
+        Some(used) => used.len() < limit && !used.contains(key),
+        None => true,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_util::spawn_ws_server;
+
+    fn fill(count: usize, channel: &str, keys: impl Fn(usize) -> String) -> usize {
+        let mut connections: Vec<HashMap<String, HashSet<String>>> = Vec::new();
+        for i in 0..count {
+            let key = keys(i);
+            let loads: Vec<_> = connections.iter().collect();
+            let index = match place(&loads, channel, &key, 20) {
+                Some(index) => index,
+                None => {
+                    connections.push(HashMap::new());
+                    connections.len() - 1
+                }
+            };
+            connections[index]
+                .entry(channel.to_string())
+                .or_default()
+                .insert(key);
+        }
+        connections.len()
+    }
+
+    #[test]
+    fn test_place() {
+        let accounts: Vec<Pubkey> = (0..30).map(|_| Pubkey::new_unique()).collect();
+        let key = |method: &str, i: usize| {
+            subscription_key(&json!({ "source": method, "account": accounts[i].to_string() }))
+        };
+        // Routed by the account in the message: 20 per connection
+        assert_eq!(fill(30, "margin", |i| key("margin", i)), 2);
+        // Messages don't name the account: one per connection
+        assert_eq!(fill(3, "positions", |i| key("positions", i)), 3);
+        assert_eq!(
+            fill(2, "book", |_| subscription_key(
+                &json!({ "source": "book", "symbol": "BTC" })
+            )),
+            2
+        );
+        // Positions of 30 subaccounts fit the default limit
+        assert!(
+            fill(30, "positions", |i| key("positions", i))
+                <= SessionPoolOptions::default().max_ws_connections
+        );
+    }
+
+    fn ws_pool(url: &str) -> Arc<Mutex<WsPool>> {
+        Arc::new(Mutex::new(WsPool {
+            url: url.to_string(),
+            api_key: None,
+            options: SessionPoolOptions::default(),
+            connections: Vec::new(),
+            opening: 0,
+            subscriptions: HashMap::new(),
+        }))
+    }
+
+    #[tokio::test]
+    async fn test_accounts_share_a_connection_and_release_alone() {
+        let (url, push) = spawn_ws_server().await;
+        let ws = ws_pool(&url);
+        let margin =
+            |account: &Pubkey| json!({ "source": "margin", "account": account.to_string() });
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+
+        let (tx_a, _rx_a) = mpsc::channel(8);
+        let (tx_b, mut rx_b) = mpsc::channel(8);
+        let (tx_b2, mut rx_b2) = mpsc::channel(8);
+        let id_a = WsPool::subscribe(&ws, margin(&a), 0, tx_a).await.unwrap();
+        let (id_b, id_b2) = tokio::join!(
+            WsPool::subscribe(&ws, margin(&b), 1, tx_b),
+            WsPool::subscribe(&ws, margin(&b), 2, tx_b2)
+        );
+        assert_eq!(id_b.unwrap(), id_b2.unwrap());
+        assert_eq!(ws.lock().await.connections.len(), 1);
+        assert_eq!(ws.lock().await.connections[0].keys["margin"].len(), 2);
+
+        WsPool::release(&ws, &id_a, 0).await;
+        assert_eq!(ws.lock().await.connections[0].keys["margin"].len(), 1);
+
+        push.send(json!({ "channel": "margin", "data": { "u": b.to_string() } }).to_string())
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        for rx in [&mut rx_b, &mut rx_b2] {
+            let msg = tokio::time::timeout(timeout, rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(msg["data"]["u"], b.to_string());
+        }
+    }
+
+    #[test]
+    fn test_drop_subscription_outside_runtime() {
+        let subscription = PooledSubscription {
+            ws: ws_pool(""),
+            params_id: "prices".into(),
+            id: 0,
+            rx: mpsc::channel(1).1,
+        };
+        drop(subscription);
+    }
+}
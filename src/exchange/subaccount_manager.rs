@@ -0,0 +1,679 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use futures_util::future::try_join_all;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signer},
+};
+use tracing::{info, warn};
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        keystore::read_keypair_file,
+        types::DefaultResponse,
+        utils::{ensure_success, get_timestamp_ms, prepare_final_request},
+    },
+    exchange::{exchange_client::ExchangeClient, operations::Operation},
+    models::{
+        exchange::{
+            payload::subaccount::SubaccountTransferPayload,
+            response::subaccount::SubaccountTransferResponse,
+        },
+        info::response::account::{AccountResponse, OpenedOrderModel, PositionModel},
+    },
+};
+
+// Transfers are rounded down to whole cents
+const TRANSFER_DECIMALS: u32 = 2;
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubaccountEntry {
+    #[serde_as(as = "DisplayFromStr")]
+    pub pubkey: Pubkey,
+    pub label: Option<String>,
+    // Solana CLI keypair file, needed to move funds out of the subaccount
+    pub keypair_path: Option<PathBuf>,
+    pub created_at: u64,
+}
+
+/// Parent/child accounts as persisted by `SubaccountManager`.
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubaccountRegistry {
+    #[serde_as(as = "DisplayFromStr")]
+    pub main: Pubkey,
+    pub subaccounts: Vec<SubaccountEntry>,
+}
+
+impl SubaccountRegistry {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn get(&self, pubkey: &Pubkey) -> Option<&SubaccountEntry> {
+        self.subaccounts.iter().find(|s| s.pubkey == *pubkey)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccountView {
+    pub account: Pubkey,
+    // None for the main account
+    pub parent: Option<Pubkey>,
+    pub label: Option<String>,
+    pub info: AccountResponse,
+    pub positions: Vec<PositionModel>,
+    pub orders: Vec<OpenedOrderModel>,
+}
+
+/// State of the main account and all registered subaccounts at one point in time.
+#[derive(Debug, Clone)]
+pub struct HierarchySnapshot {
+    pub main: AccountView,
+    pub subaccounts: Vec<AccountView>,
+}
+
+impl HierarchySnapshot {
+    pub fn accounts(&self) -> impl Iterator<Item = &AccountView> {
+        std::iter::once(&self.main).chain(&self.subaccounts)
+    }
+
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&AccountView> {
+        self.accounts().find(|a| a.account == *pubkey)
+    }
+
+    /// Positions of every account, tagged with the account holding them.
+    pub fn positions(&self) -> impl Iterator<Item = (&Pubkey, &PositionModel)> {
+        self.accounts()
+            .flat_map(|a| a.positions.iter().map(move |p| (&a.account, p)))
+    }
+
+    pub fn orders(&self) -> impl Iterator<Item = (&Pubkey, &OpenedOrderModel)> {
+        self.accounts()
+            .flat_map(|a| a.orders.iter().map(move |o| (&a.account, o)))
+    }
+
+    pub fn equity_report(&self) -> EquityReport {
+        let total_equity: Decimal = self.accounts().map(|a| a.info.account_equity).sum();
+        let accounts = self
+            .accounts()
+            .map(|a| EquityLine {
+                account: a.account,
+                label: a.label.clone(),
+                equity: a.info.account_equity,
+                balance: a.info.balance,
+                margin_used: a.info.total_margin_used,
+                available_to_withdraw: a.info.available_to_withdraw,
+                share: if total_equity.is_zero() {
+                    Decimal::ZERO
+                } else {
+                    a.info.account_equity / total_equity
+                },
+                positions: a.positions.len(),
+                orders: a.orders.len(),
+            })
+            .collect::<Vec<_>>();
+        EquityReport {
+            total_equity,
+            total_balance: accounts.iter().map(|a| a.balance).sum(),
+            total_margin_used: accounts.iter().map(|a| a.margin_used).sum(),
+            total_available_to_withdraw: accounts.iter().map(|a| a.available_to_withdraw).sum(),
+            accounts,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EquityLine {
+    #[serde_as(as = "DisplayFromStr")]
+    pub account: Pubkey,
+    pub label: Option<String>,
+    pub equity: Decimal,
+    pub balance: Decimal,
+    pub margin_used: Decimal,
+    pub available_to_withdraw: Decimal,
+    // Fraction of the total equity, 0..=1
+    pub share: Decimal,
+    pub positions: usize,
+    pub orders: usize,
+}
+
+/// Consolidated equity across the main account and its subaccounts.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EquityReport {
+    pub total_equity: Decimal,
+    pub total_balance: Decimal,
+    pub total_margin_used: Decimal,
+    pub total_available_to_withdraw: Decimal,
+    pub accounts: Vec<EquityLine>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedTransfer {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub amount: Decimal,
+}
+
+/// Transfers moving equity towards `targets` (account -> weight, normalized over
+/// the targeted accounts). Accounts without a target are left alone, and an
+/// account never sends more than its `available_to_withdraw`.
+pub fn plan_rebalance(
+    snapshot: &HierarchySnapshot,
+    targets: &HashMap<Pubkey, Decimal>,
+    min_transfer: Decimal,
+) -> Result<Vec<PlannedTransfer>, ExchangeError> {
+    let mut accounts = Vec::new();
+    for (pubkey, weight) in targets {
+        if weight.is_sign_negative() {
+            return Err(ExchangeError::Validation(format!(
+                "negative target weight for {}",
+                pubkey
+            )));
+        }
+        let view = snapshot.account(pubkey).ok_or_else(|| {
+            ExchangeError::Validation(format!("{} is not part of the account hierarchy", pubkey))
+        })?;
+        accounts.push((view, *weight));
+    }
+    let total_weight: Decimal = accounts.iter().map(|(_, w)| *w).sum();
+    if total_weight.is_zero() {
+        return Err(ExchangeError::Validation(
+            "target weights must not all be zero".into(),
+        ));
+    }
+    let total_equity: Decimal = accounts.iter().map(|(v, _)| v.info.account_equity).sum();
+
+    let mut donors = Vec::new();
+    let mut receivers = Vec::new();
+    for (view, weight) in accounts {
+        let delta = total_equity * weight / total_weight - view.info.account_equity;
+        if delta.is_sign_negative() {
+            let amount = (-delta).min(view.info.available_to_withdraw.max(Decimal::ZERO));
+            donors.push((view.account, amount));
+        } else if delta >= min_transfer {
+            receivers.push((view.account, delta));
+        }
+    }
+    // Deterministic plans: largest imbalances first
+    donors.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    receivers.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+    let mut transfers = Vec::new();
+    let mut receivers = receivers.into_iter().peekable();
+    for (from, mut available) in donors {
+        while let Some((to, needed)) = receivers.peek_mut() {
+            let amount = available
+                .min(*needed)
+                .round_dp_with_strategy(TRANSFER_DECIMALS, RoundingStrategy::ToZero);
+            if amount < min_transfer || amount.is_zero() {
+                break;
+            }
+            transfers.push(PlannedTransfer {
+                from,
+                to: *to,
+                amount,
+            });
+            available -= amount;
+            *needed -= amount;
+            if *needed < min_transfer {
+                receivers.next();
+            }
+        }
+    }
+    Ok(transfers)
+}
+
+/// Tracks the subaccounts of one main account, aggregates their state and moves
+/// collateral between them.
+pub struct SubaccountManager {
+    main_client: Arc<ExchangeClient>,
+    registry_path: PathBuf,
+    registry: SubaccountRegistry,
+    // Keys of subaccounts created in this process or loaded from `keypair_path`
+    signers: HashMap<Pubkey, Keypair>,
+    pub expiry_window: Option<u32>,
+}
+
+impl SubaccountManager {
+    /// Loads the registry at `registry_path`, or starts an empty one.
+    pub fn open(
+        main_client: Arc<ExchangeClient>,
+        registry_path: impl Into<PathBuf>,
+    ) -> Result<Self, ExchangeError> {
+        let registry_path = registry_path.into();
+        let main = main_client.main_pubkey();
+        let registry = if registry_path.exists() {
+            let registry = SubaccountRegistry::load(&registry_path)?;
+            if registry.main != main {
+                return Err(ExchangeError::Validation(format!(
+                    "{} belongs to {}, not {}",
+                    registry_path.display(),
+                    registry.main,
+                    main
+                )));
+            }
+            registry
+        } else {
+            SubaccountRegistry {
+                main,
+                subaccounts: Vec::new(),
+            }
+        };
+        Ok(Self {
+            main_client,
+            registry_path,
+            registry,
+            signers: HashMap::new(),
+            expiry_window: None,
+        })
+    }
+
+    pub fn registry(&self) -> &SubaccountRegistry {
+        &self.registry
+    }
+
+    /// Registers `subaccount` with the exchange, signed by the main key and the
+    /// subaccount key, then records it.
+    pub async fn create(
+        &mut self,
+        subaccount: Keypair,
+        label: Option<String>,
+    ) -> Result<Pubkey, ExchangeError> {
+        self.create_entry(subaccount, label, None).await
+    }
+
+    /// Same as `create` with the key read from a Solana CLI keypair file, which
+    /// is remembered so later sessions can move funds out of the subaccount.
+    pub async fn create_from_file(
+        &mut self,
+        keypair_path: impl Into<PathBuf>,
+        label: Option<String>,
+    ) -> Result<Pubkey, ExchangeError> {
+        let keypair_path = keypair_path.into();
+        let subaccount = read_keypair_file(&keypair_path)?;
+        self.create_entry(subaccount, label, Some(keypair_path))
+            .await
+    }
+
+    async fn create_entry(
+        &mut self,
+        subaccount: Keypair,
+        label: Option<String>,
+        keypair_path: Option<PathBuf>,
+    ) -> Result<Pubkey, ExchangeError> {
+        let pubkey = subaccount.pubkey();
+        if self.registry.get(&pubkey).is_some() {
+            return Err(ExchangeError::Validation(format!(
+                "subaccount {} is already registered",
+                pubkey
+            )));
+        }
+        let response = self
+            .main_client
+            .subaccount_create(&subaccount, self.expiry_window)
+            .await?;
+        ensure_success("subaccount_create", &response)?;
+        self.signers.insert(pubkey, subaccount);
+        self.track(pubkey, label, keypair_path)?;
+        info!(
+            "SubaccountManager: created {} under {}",
+            pubkey, self.registry.main
+        );
+        Ok(pubkey)
+    }
+
+    /// Records a subaccount created elsewhere.
+    pub fn track(
+        &mut self,
+        pubkey: Pubkey,
+        label: Option<String>,
+        keypair_path: Option<PathBuf>,
+    ) -> Result<(), ExchangeError> {
+        if pubkey == self.registry.main {
+            return Err(ExchangeError::Validation(
+                "the main account cannot be its own subaccount".into(),
+            ));
+        }
+        self.registry.subaccounts.retain(|s| s.pubkey != pubkey);
+        self.registry.subaccounts.push(SubaccountEntry {
+            pubkey,
+            label,
+            keypair_path,
+            created_at: get_timestamp_ms(),
+        });
+        self.registry.save(&self.registry_path)
+    }
+
+    pub fn untrack(&mut self, pubkey: &Pubkey) -> Result<(), ExchangeError> {
+        self.registry.subaccounts.retain(|s| s.pubkey != *pubkey);
+        self.signers.remove(pubkey);
+        self.registry.save(&self.registry_path)
+    }
+
+    /// Fetches `account`, `positions` and `opened_orders` for every account in
+    /// the hierarchy concurrently.
+    pub async fn snapshot(&self) -> Result<HierarchySnapshot, ExchangeError> {
+        let main = self.account_view(self.registry.main, None, None);
+        let subaccounts = try_join_all(
+            self.registry
+                .subaccounts
+                .iter()
+                .map(|s| self.account_view(s.pubkey, Some(self.registry.main), s.label.clone())),
+        );
+        let (main, subaccounts) = futures_util::try_join!(main, subaccounts)?;
+        Ok(HierarchySnapshot { main, subaccounts })
+    }
+
+    pub async fn equity_report(&self) -> Result<EquityReport, ExchangeError> {
+        Ok(self.snapshot().await?.equity_report())
+    }
+
+    async fn account_view(
+        &self,
+        account: Pubkey,
+        parent: Option<Pubkey>,
+        label: Option<String>,
+    ) -> Result<AccountView, ExchangeError> {
+        let info_client = &self.main_client.info_client;
+        let (info, positions, orders) = futures_util::try_join!(
+            info_client.account(account),
+            info_client.positions(account),
+            info_client.opened_orders(account),
+        )?;
+        let data = |name: &str, error: Option<String>| {
+            ExchangeError::Custom(format!(
+                "{} for {}: {}",
+                name,
+                account,
+                error.unwrap_or_else(|| "no data".into())
+            ))
+        };
+        Ok(AccountView {
+            account,
+            parent,
+            label,
+            info: info.data.ok_or_else(|| data("account", info.error))?,
+            positions: positions.data.unwrap_or_default(),
+            orders: orders.data.unwrap_or_default(),
+        })
+    }
+
+    /// Moves `amount` from `from` to `to`. Transfers out of the main account are
+    /// signed by the main client, transfers out of a subaccount by its key.
+    pub async fn transfer(
+        &mut self,
+        from: Pubkey,
+        to: Pubkey,
+        amount: Decimal,
+    ) -> Result<DefaultResponse<SubaccountTransferResponse>, ExchangeError> {
+        let payload = SubaccountTransferPayload {
+            to_account: to,
+            amount,
+        };
+        let expiry_window = self.expiry_window;
+        let response = if from == self.registry.main {
+            self.main_client
+                .subaccount_transfer(payload, expiry_window)
+                .await?
+        } else {
+            let signer = self.signer(&from)?;
+            let operation = Operation::SubaccountTransfer;
+            let body = prepare_final_request(
+                &operation.name()?,
+                payload,
+                expiry_window,
+                signer,
+                &from,
+                &None,
+            )
+            .await?;
+            self.main_client
+                .submit_prebuilt(&operation.endpoint(), &body)
+                .await?
+        };
+        ensure_success("transfer_funds", &response)?;
+        if let Some(data) = &response.data
+            && !data.success
+        {
+            return Err(ExchangeError::Custom(format!(
+                "transfer_funds rejected: {}",
+                data.error.as_deref().unwrap_or("unknown error")
+            )));
+        }
+        Ok(response)
+    }
+
+    fn signer(&mut self, pubkey: &Pubkey) -> Result<&Keypair, ExchangeError> {
+        if !self.signers.contains_key(pubkey) {
+            let path = self
+                .registry
+                .get(pubkey)
+                .and_then(|s| s.keypair_path.as_ref())
+                .ok_or_else(|| {
+                    ExchangeError::NotInitialized(format!("no key for subaccount {}", pubkey))
+                })?;
+            let keypair = read_keypair_file(path)?;
+            self.signers.insert(*pubkey, keypair);
+        }
+        Ok(&self.signers[pubkey])
+    }
+
+    /// Plans transfers with `plan_rebalance` on a fresh snapshot and executes
+    /// them in order, stopping at the first failure.
+    pub async fn rebalance(
+        &mut self,
+        targets: &HashMap<Pubkey, Decimal>,
+        min_transfer: Decimal,
+    ) -> Result<Vec<PlannedTransfer>, ExchangeError> {
+        let snapshot = self.snapshot().await?;
+        let transfers = plan_rebalance(&snapshot, targets, min_transfer)?;
+        for (i, transfer) in transfers.iter().enumerate() {
+            if let Err(e) = self
+                .transfer(transfer.from, transfer.to, transfer.amount)
+                .await
+            {
+                warn!(
+                    "SubaccountManager: rebalance stopped after {} of {} transfers: {:?}",
+                    i,
+                    transfers.len(),
+                    e
+                );
+                return Err(e);
+            }
+        }
+        Ok(transfers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Mutex};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::{HttpRequest, HttpResponse, spawn_http_server};
+
+    // Manager over a mock exchange that records every request and rejects
+    // creates and transfers when `reject` is set
+    async fn manager(reject: bool) -> (SubaccountManager, Arc<Mutex<Vec<HttpRequest>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let url = spawn_http_server(move |req| {
+            seen.lock().unwrap().push(req.clone());
+            match req.path().rsplit('/').next().unwrap_or_default() {
+                "create" if reject => HttpResponse::rejected("invalid signature", 400),
+                "create" => HttpResponse::ok(json!(null)),
+                _ if reject => HttpResponse::ok(json!({
+                    "success": false,
+                    "error": "insufficient balance"
+                })),
+                _ => HttpResponse::ok(json!({ "success": true, "error": null })),
+            }
+        })
+        .await;
+        let keypair = Keypair::new();
+        let main = keypair.pubkey();
+        let client = ExchangeClient::from_markets(&url, Vec::new(), keypair, main, None).unwrap();
+        let registry = std::env::temp_dir().join(format!("{}.json", uuid::Uuid::new_v4()));
+        let manager = SubaccountManager::open(Arc::new(client), registry).unwrap();
+        (manager, requests)
+    }
+
+    #[tokio::test]
+    async fn test_create_and_transfer() {
+        let (mut manager, requests) = manager(false).await;
+        let main = manager.registry().main;
+        let subaccount = Keypair::new();
+        let sub = subaccount.pubkey();
+
+        manager.create(subaccount, Some("mm".into())).await.unwrap();
+        let create = requests.lock().unwrap().pop().unwrap();
+        assert!(create.path().ends_with("/account/subaccount/create"));
+        let body = create.json();
+        assert_eq!(body["main_account"], main.to_string());
+        assert_eq!(body["subaccount"], sub.to_string());
+        // Persisted with its label
+        let registry = SubaccountRegistry::load(&manager.registry_path).unwrap();
+        assert_eq!(registry.get(&sub).unwrap().label.as_deref(), Some("mm"));
+        assert!(manager.create(Keypair::new(), None).await.is_ok());
+
+        // Into the subaccount, signed by the main account, and back, signed by
+        // the subaccount key kept since `create`
+        for (from, to) in [(main, sub), (sub, main)] {
+            manager.transfer(from, to, Decimal::TEN).await.unwrap();
+            let transfer = requests.lock().unwrap().pop().unwrap();
+            assert!(transfer.path().ends_with("/account/subaccount/transfer"));
+            let body = transfer.json();
+            assert_eq!(body["account"], from.to_string());
+            assert_eq!(body["to_account"], to.to_string());
+            assert_eq!(body["amount"], "10");
+        }
+        // No key for an account outside the registry
+        let stranger = Keypair::new().pubkey();
+        assert!(
+            manager
+                .transfer(stranger, main, Decimal::ONE)
+                .await
+                .is_err()
+        );
+        std::fs::remove_file(&manager.registry_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rejections_are_errors() {
+        let (mut manager, _) = manager(true).await;
+        let main = manager.registry().main;
+        assert!(manager.create(Keypair::new(), None).await.is_err());
+        assert!(manager.registry().subaccounts.is_empty());
+
+        let sub = Keypair::new().pubkey();
+        manager.track(sub, None, None).unwrap();
+        let err = manager
+            .transfer(main, sub, Decimal::ONE)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("insufficient balance"), "{}", err);
+        std::fs::remove_file(&manager.registry_path).unwrap();
+    }
+
+    fn view(account: Pubkey, equity: &str, withdrawable: &str) -> AccountView {
+        let d = |v: &str| Decimal::from_str(v).unwrap();
+        AccountView {
+            account,
+            parent: None,
+            label: None,
+            info: AccountResponse {
+                balance: d(equity),
+                fee_level: 0,
+                account_equity: d(equity),
+                available_to_spend: d(withdrawable),
+                available_to_withdraw: d(withdrawable),
+                pending_balance: Decimal::ZERO,
+                total_margin_used: d(equity) - d(withdrawable),
+                positions_count: 0,
+                orders_count: 0,
+                stop_orders_count: 0,
+                updated_at: 0,
+            },
+            positions: Vec::new(),
+            orders: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_plan_rebalance_and_report() {
+        let (main, a, b) = (
+            Keypair::new().pubkey(),
+            Keypair::new().pubkey(),
+            Keypair::new().pubkey(),
+        );
+        let snapshot = HierarchySnapshot {
+            main: view(main, "1000", "1000"),
+            subaccounts: vec![view(a, "0", "0"), view(b, "500", "100")],
+        };
+        let report = snapshot.equity_report();
+        assert_eq!(report.total_equity, Decimal::from(1500));
+        assert_eq!(
+            report.accounts[0].share,
+            Decimal::from(1000) / Decimal::from(1500)
+        );
+
+        // Equal thirds: main sends 500 to `a`, `b` is already on target
+        let targets = HashMap::from([(main, Decimal::ONE), (a, Decimal::ONE), (b, Decimal::ONE)]);
+        let plan = plan_rebalance(&snapshot, &targets, Decimal::ONE).unwrap();
+        assert_eq!(
+            plan,
+            vec![PlannedTransfer {
+                from: main,
+                to: a,
+                amount: Decimal::from(500)
+            }]
+        );
+
+        // `b` should give 500 to `a` but can only withdraw 100
+        let targets = HashMap::from([(a, Decimal::ONE), (b, Decimal::ONE)]);
+        let plan = plan_rebalance(&snapshot, &targets, Decimal::ONE).unwrap();
+        assert_eq!(plan.len(), 1);
+        assert_eq!((plan[0].from, plan[0].amount), (b, Decimal::from(100)));
+
+        assert!(
+            plan_rebalance(
+                &snapshot,
+                &HashMap::from([(a, Decimal::ZERO)]),
+                Decimal::ONE
+            )
+            .is_err()
+        );
+        let stranger = Keypair::new().pubkey();
+        assert!(
+            plan_rebalance(
+                &snapshot,
+                &HashMap::from([(stranger, Decimal::ONE)]),
+                Decimal::ONE
+            )
+            .is_err()
+        );
+    }
+}
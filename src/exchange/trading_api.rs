@@ -0,0 +1,689 @@
+use std::future::Future;
+
+use solana_sdk::{pubkey::Pubkey, signature::Keypair};
+
+use crate::{
+    common::{errors::ExchangeError, types::DefaultResponse},
+    exchange::{exchange_client::ExchangeClient, paper_exchange::PaperExchange},
+    models::exchange::{
+        payload::{
+            account::WithdrawPayload,
+            agent_wallet::BindAgentWalletPayload,
+            api_key::{CreateApiKeyPayload, ListApiKeysPayload, RevokeApiKeyPayload},
+            batch_order::BatchOrderActionPayload,
+            market_settings::{UpdateLeveragePayload, UpdateMarginModePayload},
+            order::{
+                CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+                CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+                SetPositionTpslPayload,
+            },
+            subaccount::SubaccountTransferPayload,
+        },
+        response::{
+            account::WithdrawResponse,
+            agent_wallet::BindAgentWalletResponse,
+            api_key::{CreateApiKeyResponse, ListApiKeysResponse, RevokeApiKeyResponse},
+            batch_order::BatchOrderResponse,
+            market_settings::{UpdateLeverageResponse, UpdateMarginModeResponse},
+            order::{
+                CancelAllOrdersResponse, CancelOrderResponse, CancelStopOrderResponse,
+                CreateMarketOrderResponse, CreateOrderResponse, CreateStopOrderResponse,
+                SetPositionTPSLResponse,
+            },
+            subaccount::{SubaccountCreateResponse, SubaccountTransferResponse},
+        },
+    },
+    risk::guard::RiskGuard,
+};
+
+type ApiResult<T> = Result<DefaultResponse<T>, ExchangeError>;
+
+/// Signed exchange operations, with the signatures of `ExchangeClient`. Write
+/// strategies against `T: TradingApi` to run them on `ExchangeClient`,
+/// `PaperExchange` or a test double.
+///
+/// The methods return `impl Future`, so the trait is not object safe: use it as a
+/// generic bound, `dyn TradingApi` does not compile. Every method is required;
+/// a backend without account management returns an error from those methods,
+/// as `PaperExchange` does.
+pub trait TradingApi: Send + Sync {
+    /// Account whose orders and positions are traded.
+    fn main_pubkey(&self) -> Pubkey;
+
+    fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CreateOrderResponse>> + Send;
+
+    fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<BatchOrderResponse>> + Send;
+
+    fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CreateMarketOrderResponse>> + Send;
+
+    fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CreateStopOrderResponse>> + Send;
+
+    fn set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<SetPositionTPSLResponse>> + Send;
+
+    fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CancelOrderResponse>> + Send;
+
+    fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CancelAllOrdersResponse>> + Send;
+
+    fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CancelStopOrderResponse>> + Send;
+
+    // Account settings and management
+    fn update_margin_mode(
+        &self,
+        sign_payload: UpdateMarginModePayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<UpdateMarginModeResponse>> + Send;
+
+    fn update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<UpdateLeverageResponse>> + Send;
+
+    fn withdraw(
+        &self,
+        sign_payload: WithdrawPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<WithdrawResponse>> + Send;
+
+    fn subaccount_create(
+        &self,
+        subaccount: &Keypair,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<SubaccountCreateResponse>> + Send;
+
+    fn subaccount_transfer(
+        &self,
+        sign_payload: SubaccountTransferPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<SubaccountTransferResponse>> + Send;
+
+    fn bind_agent_wallet(
+        &self,
+        sign_payload: BindAgentWalletPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<BindAgentWalletResponse>> + Send;
+
+    fn create_api_key(
+        &self,
+        sign_payload: CreateApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<CreateApiKeyResponse>> + Send;
+
+    fn revoke_api_key(
+        &self,
+        sign_payload: RevokeApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<RevokeApiKeyResponse>> + Send;
+
+    fn get_list_api_keys(
+        &self,
+        sign_payload: ListApiKeysPayload,
+        expiry_window: Option<u32>,
+    ) -> impl Future<Output = ApiResult<ListApiKeysResponse>> + Send;
+}
+
+// Inherent methods take precedence, so these calls don't recurse
+impl TradingApi for ExchangeClient {
+    fn main_pubkey(&self) -> Pubkey {
+        ExchangeClient::main_pubkey(self)
+    }
+
+    async fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateOrderResponse> {
+        ExchangeClient::order(self, sign_payload, expiry_window).await
+    }
+
+    async fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<BatchOrderResponse> {
+        ExchangeClient::batch_order(self, orders, expiry_window).await
+    }
+
+    async fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateMarketOrderResponse> {
+        ExchangeClient::market_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateStopOrderResponse> {
+        ExchangeClient::stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SetPositionTPSLResponse> {
+        ExchangeClient::set_position_tpsl(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelOrderResponse> {
+        ExchangeClient::cancel_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelAllOrdersResponse> {
+        ExchangeClient::cancel_all_orders(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelStopOrderResponse> {
+        ExchangeClient::cancel_stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn update_margin_mode(
+        &self,
+        sign_payload: UpdateMarginModePayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateMarginModeResponse> {
+        ExchangeClient::update_margin_mode(self, sign_payload, expiry_window).await
+    }
+
+    async fn update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateLeverageResponse> {
+        ExchangeClient::update_leverage(self, sign_payload, expiry_window).await
+    }
+
+    async fn withdraw(
+        &self,
+        sign_payload: WithdrawPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<WithdrawResponse> {
+        ExchangeClient::withdraw(self, sign_payload, expiry_window).await
+    }
+
+    async fn subaccount_create(
+        &self,
+        subaccount: &Keypair,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountCreateResponse> {
+        ExchangeClient::subaccount_create(self, subaccount, expiry_window).await
+    }
+
+    async fn subaccount_transfer(
+        &self,
+        sign_payload: SubaccountTransferPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountTransferResponse> {
+        ExchangeClient::subaccount_transfer(self, sign_payload, expiry_window).await
+    }
+
+    async fn bind_agent_wallet(
+        &self,
+        sign_payload: BindAgentWalletPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<BindAgentWalletResponse> {
+        ExchangeClient::bind_agent_wallet(self, sign_payload, expiry_window).await
+    }
+
+    async fn create_api_key(
+        &self,
+        sign_payload: CreateApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateApiKeyResponse> {
+        ExchangeClient::create_api_key(self, sign_payload, expiry_window).await
+    }
+
+    async fn revoke_api_key(
+        &self,
+        sign_payload: RevokeApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<RevokeApiKeyResponse> {
+        ExchangeClient::revoke_api_key(self, sign_payload, expiry_window).await
+    }
+
+    async fn get_list_api_keys(
+        &self,
+        sign_payload: ListApiKeysPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<ListApiKeysResponse> {
+        ExchangeClient::get_list_api_keys(self, sign_payload, expiry_window).await
+    }
+}
+
+// Orders go through the guard's checks, account management straight to the
+// wrapped client
+impl<T: TradingApi> TradingApi for RiskGuard<T> {
+    fn main_pubkey(&self) -> Pubkey {
+        self.client().main_pubkey()
+    }
+
+    async fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateOrderResponse> {
+        RiskGuard::order(self, sign_payload, expiry_window).await
+    }
+
+    async fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<BatchOrderResponse> {
+        RiskGuard::batch_order(self, orders, expiry_window).await
+    }
+
+    async fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateMarketOrderResponse> {
+        RiskGuard::market_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateStopOrderResponse> {
+        RiskGuard::stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SetPositionTPSLResponse> {
+        self.client()
+            .set_position_tpsl(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelOrderResponse> {
+        RiskGuard::cancel_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelAllOrdersResponse> {
+        RiskGuard::cancel_all_orders(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelStopOrderResponse> {
+        RiskGuard::cancel_stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn update_margin_mode(
+        &self,
+        sign_payload: UpdateMarginModePayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateMarginModeResponse> {
+        self.client()
+            .update_margin_mode(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateLeverageResponse> {
+        self.client()
+            .update_leverage(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn withdraw(
+        &self,
+        sign_payload: WithdrawPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<WithdrawResponse> {
+        self.client().withdraw(sign_payload, expiry_window).await
+    }
+
+    async fn subaccount_create(
+        &self,
+        subaccount: &Keypair,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountCreateResponse> {
+        self.client()
+            .subaccount_create(subaccount, expiry_window)
+            .await
+    }
+
+    async fn subaccount_transfer(
+        &self,
+        sign_payload: SubaccountTransferPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountTransferResponse> {
+        self.client()
+            .subaccount_transfer(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn bind_agent_wallet(
+        &self,
+        sign_payload: BindAgentWalletPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<BindAgentWalletResponse> {
+        self.client()
+            .bind_agent_wallet(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn create_api_key(
+        &self,
+        sign_payload: CreateApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateApiKeyResponse> {
+        self.client()
+            .create_api_key(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn revoke_api_key(
+        &self,
+        sign_payload: RevokeApiKeyPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<RevokeApiKeyResponse> {
+        self.client()
+            .revoke_api_key(sign_payload, expiry_window)
+            .await
+    }
+
+    async fn get_list_api_keys(
+        &self,
+        sign_payload: ListApiKeysPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<ListApiKeysResponse> {
+        self.client()
+            .get_list_api_keys(sign_payload, expiry_window)
+            .await
+    }
+}
+
+fn unsupported<S, T>(operation: &str) -> ApiResult<T> {
+    Err(ExchangeError::Custom(format!(
+        "{} is not supported by {}",
+        operation,
+        std::any::type_name::<S>()
+    )))
+}
+
+// Account management has no simulated counterpart and is rejected
+impl TradingApi for PaperExchange {
+    fn main_pubkey(&self) -> Pubkey {
+        PaperExchange::main_pubkey(self)
+    }
+
+    async fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateOrderResponse> {
+        PaperExchange::order(self, sign_payload, expiry_window).await
+    }
+
+    async fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<BatchOrderResponse> {
+        PaperExchange::batch_order(self, orders, expiry_window).await
+    }
+
+    async fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateMarketOrderResponse> {
+        PaperExchange::market_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CreateStopOrderResponse> {
+        PaperExchange::stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn set_position_tpsl(
+        &self,
+        sign_payload: SetPositionTpslPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<SetPositionTPSLResponse> {
+        PaperExchange::set_position_tpsl(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelOrderResponse> {
+        PaperExchange::cancel_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelAllOrdersResponse> {
+        PaperExchange::cancel_all_orders(self, sign_payload, expiry_window).await
+    }
+
+    async fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<CancelStopOrderResponse> {
+        PaperExchange::cancel_stop_order(self, sign_payload, expiry_window).await
+    }
+
+    async fn update_margin_mode(
+        &self,
+        _sign_payload: UpdateMarginModePayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateMarginModeResponse> {
+        unsupported::<Self, _>("update_margin_mode")
+    }
+
+    async fn update_leverage(
+        &self,
+        sign_payload: UpdateLeveragePayload,
+        expiry_window: Option<u32>,
+    ) -> ApiResult<UpdateLeverageResponse> {
+        PaperExchange::update_leverage(self, sign_payload, expiry_window).await
+    }
+
+    async fn withdraw(
+        &self,
+        _sign_payload: WithdrawPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<WithdrawResponse> {
+        unsupported::<Self, _>("withdraw")
+    }
+
+    async fn subaccount_create(
+        &self,
+        _subaccount: &Keypair,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountCreateResponse> {
+        unsupported::<Self, _>("subaccount_create")
+    }
+
+    async fn subaccount_transfer(
+        &self,
+        _sign_payload: SubaccountTransferPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<SubaccountTransferResponse> {
+        unsupported::<Self, _>("subaccount_transfer")
+    }
+
+    async fn bind_agent_wallet(
+        &self,
+        _sign_payload: BindAgentWalletPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<BindAgentWalletResponse> {
+        unsupported::<Self, _>("bind_agent_wallet")
+    }
+
+    async fn create_api_key(
+        &self,
+        _sign_payload: CreateApiKeyPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<CreateApiKeyResponse> {
+        unsupported::<Self, _>("create_api_key")
+    }
+
+    async fn revoke_api_key(
+        &self,
+        _sign_payload: RevokeApiKeyPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<RevokeApiKeyResponse> {
+        unsupported::<Self, _>("revoke_api_key")
+    }
+
+    async fn get_list_api_keys(
+        &self,
+        _sign_payload: ListApiKeysPayload,
+        _expiry_window: Option<u32>,
+    ) -> ApiResult<ListApiKeysResponse> {
+        unsupported::<Self, _>("get_list_api_keys")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use crate::{
+        common::types::{OrderSide, Tif},
+        exchange::paper_exchange::PaperExchangeConfig,
+        risk::guard::RiskLimits,
+        test_util::book_event,
+    };
+
+    use super::*;
+
+    // Strategy code only knows the trait
+    async fn quote<T: TradingApi>(api: &T, price: i64) -> Option<u64> {
+        let response = api
+            .order(
+                CreateOrderPayload {
+                    symbol: "BTC".into(),
+                    price: Decimal::from(price),
+                    amount: Decimal::ONE,
+                    side: OrderSide::Bid,
+                    tif: Tif::ALO,
+                    reduce_only: false,
+                    client_order_id: None,
+                    take_profit: None,
+                    stop_loss: None,
+                },
+                None,
+            )
+            .await
+            .ok()?;
+        response.data.map(|data| data.order_id)
+    }
+
+    #[tokio::test]
+    async fn test_paper_exchange_as_trading_api() {
+        let paper = PaperExchange::new(Pubkey::new_unique(), PaperExchangeConfig::default());
+        paper.apply(&book_event("BTC", 1, ("99", "1"), ("101", "1")));
+        // Spawnable: the futures are `Send`
+        let handle = tokio::spawn(async move {
+            let resting = quote(&paper, 100).await;
+            let crossing = quote(&paper, 102).await;
+            let withdraw = TradingApi::withdraw(
+                &paper,
+                WithdrawPayload {
+                    amount: Decimal::ONE,
+                },
+                None,
+            )
+            .await;
+            let unsupported = withdraw.unwrap_err().to_string();
+            (
+                resting,
+                crossing,
+                unsupported.contains("withdraw is not supported by"),
+            )
+        });
+        assert_eq!(handle.await.unwrap(), (Some(1), None, true));
+    }
+
+    #[tokio::test]
+    async fn test_risk_guard_as_trading_api() {
+        let paper = PaperExchange::new(Pubkey::new_unique(), PaperExchangeConfig::default());
+        let guard = RiskGuard::new(
+            paper,
+            RiskLimits {
+                max_open_orders: Some(1),
+                ..Default::default()
+            },
+        );
+        assert_eq!(quote(&guard, 100).await, Some(1));
+        // Rejected by the guard before it reaches the exchange
+        assert_eq!(quote(&guard, 99).await, None);
+        assert_eq!(guard.open_orders().await, 1);
+    }
+}
@@ -0,0 +1,546 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    common::{errors::ExchangeError, types::OrderSide},
+    info::info_client::InfoClient,
+    models::{
+        info::response::{
+            account::AccountFundingHistoryModel,
+            market::{FundingRateHistoryModel, PriceModel},
+        },
+        ws::responses::{PriceModel as WsPriceModel, PricesResponse as WsPricesResponse},
+    },
+    risk::position::PositionSnapshot,
+};
+
+// Funding settles every hour: longs pay shorts `amount * oracle * rate` when
+// the rate is positive, shorts pay longs when it is negative.
+
+/// Hourly funding periods in a 365-day year.
+pub const HOURLY_PERIODS_PER_YEAR: u32 = 24 * 365;
+
+/// `rate` per period scaled to a year, without compounding.
+pub fn annualize(rate: Decimal, periods_per_year: u32) -> Decimal {
+    rate * Decimal::from(periods_per_year)
+}
+
+// REST and WS prices carry the same funding fields
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundingQuote {
+    pub symbol: String,
+    // Rate of the last settlement
+    pub funding: Decimal,
+    // Rate the next settlement is expected to use
+    pub next_funding: Decimal,
+    pub mark: Decimal,
+    pub oracle: Decimal,
+    pub open_interest: Decimal,
+    pub timestamp: u64,
+}
+
+impl From<&PriceModel> for FundingQuote {
+    fn from(p: &PriceModel) -> Self {
+        Self {
+            symbol: p.symbol.clone(),
+            funding: p.funding,
+            next_funding: p.next_funding,
+            mark: p.mark,
+            oracle: p.oracle,
+            open_interest: p.open_interest,
+            timestamp: p.timestamp,
+        }
+    }
+}
+
+impl From<&WsPriceModel> for FundingQuote {
+    fn from(p: &WsPriceModel) -> Self {
+        Self {
+            symbol: p.symbol.clone(),
+            funding: p.funding,
+            next_funding: p.next_funding,
+            mark: p.mark,
+            oracle: p.oracle,
+            open_interest: p.open_interest,
+            timestamp: p.timestamp,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarketFunding {
+    pub symbol: String,
+    pub funding: Decimal,
+    pub next_funding: Decimal,
+    pub annualized: Decimal,
+    pub next_annualized: Decimal,
+    pub open_interest: Decimal,
+    pub timestamp: u64,
+}
+
+/// Order of `FundingAnalytics::ranked`, always by the next funding rate.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FundingRanking {
+    // Most expensive for longs first
+    Highest,
+    // Most expensive for shorts first
+    Lowest,
+    // Largest absolute rate first
+    Extreme,
+}
+
+/// Summary of `funding_rate_history` rows for one symbol.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundingRateStats {
+    pub samples: usize,
+    pub latest: Decimal,
+    pub mean: Decimal,
+    pub min: Decimal,
+    pub max: Decimal,
+    pub annualized_mean: Decimal,
+    // Sum of the rates, what a long of one unit of notional paid over the range
+    pub cumulative: Decimal,
+    pub from: u64,
+    pub to: u64,
+}
+
+/// `None` for an empty history.
+pub fn rate_stats(
+    history: &[FundingRateHistoryModel],
+    periods_per_year: u32,
+) -> Option<FundingRateStats> {
+    let latest = history.iter().max_by_key(|r| r.created_at)?;
+    let cumulative: Decimal = history.iter().map(|r| r.funding_rate).sum();
+    let mean = cumulative / Decimal::from(history.len());
+    Some(FundingRateStats {
+        samples: history.len(),
+        latest: latest.funding_rate,
+        mean,
+        min: history.iter().map(|r| r.funding_rate).min()?,
+        max: history.iter().map(|r| r.funding_rate).max()?,
+        annualized_mean: annualize(mean, periods_per_year),
+        cumulative,
+        from: history.iter().map(|r| r.created_at).min()?,
+        to: latest.created_at,
+    })
+}
+
+/// Funding payments of one symbol and side. Amounts follow `payout`:
+/// `paid` and `received` are both positive, `net` is negative when paying.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundingSummary {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub payments: usize,
+    pub paid: Decimal,
+    pub received: Decimal,
+    pub net: Decimal,
+    pub first_at: u64,
+    pub last_at: u64,
+}
+
+/// Groups `account_funding_history` rows by symbol and side, sorted by symbol.
+pub fn summarize_payments(history: &[AccountFundingHistoryModel]) -> Vec<FundingSummary> {
+    let mut summaries: HashMap<(String, bool), FundingSummary> = HashMap::new();
+    for row in history {
+        let is_long = matches!(row.side, OrderSide::Bid);
+        let summary = summaries
+            .entry((row.symbol.clone(), is_long))
+            .or_insert_with(|| FundingSummary {
+                symbol: row.symbol.clone(),
+                side: row.side.clone(),
+                payments: 0,
+                paid: Decimal::ZERO,
+                received: Decimal::ZERO,
+                net: Decimal::ZERO,
+                first_at: row.created_at,
+                last_at: row.created_at,
+            });
+        summary.payments += 1;
+        if row.payout.is_sign_negative() {
+            summary.paid -= row.payout;
+        } else {
+            summary.received += row.payout;
+        }
+        summary.net += row.payout;
+        summary.first_at = summary.first_at.min(row.created_at);
+        summary.last_at = summary.last_at.max(row.created_at);
+    }
+    let mut summaries: Vec<FundingSummary> = summaries.into_values().collect();
+    summaries.sort_by(|a, b| {
+        a.symbol
+            .cmp(&b.symbol)
+            .then_with(|| matches!(b.side, OrderSide::Bid).cmp(&matches!(a.side, OrderSide::Bid)))
+    });
+    summaries
+}
+
+/// Expected next payment of an open position, signed like `payout`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundingPrediction {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub amount: Decimal,
+    pub oracle: Decimal,
+    pub rate: Decimal,
+    pub notional: Decimal,
+    pub payout: Decimal,
+    pub annualized: Decimal,
+}
+
+/// `payout` of one settlement for a position: negative when the position pays.
+pub fn funding_payout(
+    side: &OrderSide,
+    amount: Decimal,
+    oracle: Decimal,
+    rate: Decimal,
+) -> Decimal {
+    let payment = amount * oracle * rate;
+    match side {
+        OrderSide::Bid => -payment,
+        OrderSide::Ask => payment,
+    }
+}
+
+/// Latest funding quotes per symbol, fed from `prices` over REST or WebSocket.
+#[derive(Clone, Debug)]
+pub struct FundingAnalytics {
+    quotes: HashMap<String, FundingQuote>,
+    periods_per_year: u32,
+}
+
+impl Default for FundingAnalytics {
+    fn default() -> Self {
+        Self::new(HOURLY_PERIODS_PER_YEAR)
+    }
+}
+
+impl FundingAnalytics {
+    pub fn new(periods_per_year: u32) -> Self {
+        Self {
+            quotes: HashMap::new(),
+            periods_per_year,
+        }
+    }
+
+    /// Fetches current prices over REST.
+    pub async fn from_info_client(info_client: &InfoClient) -> Result<Self, ExchangeError> {
+        let mut analytics = Self::default();
+        if let Some(prices) = info_client.prices().await?.data {
+            analytics.update_prices(&prices);
+        }
+        Ok(analytics)
+    }
+
+    pub fn periods_per_year(&self) -> u32 {
+        self.periods_per_year
+    }
+
+    pub fn update_prices(&mut self, prices: &[PriceModel]) {
+        for p in prices {
+            self.update_quote(FundingQuote::from(p));
+        }
+    }
+
+    pub fn update_ws_prices(&mut self, prices: &WsPricesResponse) {
+        for p in prices {
+            self.update_quote(FundingQuote::from(p));
+        }
+    }
+
+    pub fn update_quote(&mut self, quote: FundingQuote) {
+        self.quotes.insert(quote.symbol.clone(), quote);
+    }
+
+    pub fn quote(&self, symbol: &str) -> Option<&FundingQuote> {
+        self.quotes.get(symbol)
+    }
+
+    pub fn quotes(&self) -> impl Iterator<Item = &FundingQuote> {
+        self.quotes.values()
+    }
+
+    pub fn market(&self, symbol: &str) -> Option<MarketFunding> {
+        self.quote(symbol).map(|q| self.market_funding(q))
+    }
+
+    /// Every quoted market in `ranking` order, ties broken by symbol.
+    pub fn ranked(&self, ranking: FundingRanking) -> Vec<MarketFunding> {
+        let mut markets: Vec<MarketFunding> = self
+            .quotes
+            .values()
+            .map(|q| self.market_funding(q))
+            .collect();
+        markets.sort_by(|a, b| {
+            let order = match ranking {
+                FundingRanking::Highest => b.next_funding.cmp(&a.next_funding),
+                FundingRanking::Lowest => a.next_funding.cmp(&b.next_funding),
+                FundingRanking::Extreme => b.next_funding.abs().cmp(&a.next_funding.abs()),
+            };
+            order.then_with(|| a.symbol.cmp(&b.symbol))
+        });
+        markets
+    }
+
+    /// Next payment of each position at its symbol's next funding rate.
+    /// Positions without a quote are left out.
+    pub fn predict<'a, I>(&self, positions: I) -> Vec<FundingPrediction>
+    where
+        I: IntoIterator<Item = &'a PositionSnapshot>,
+    {
+        positions
+            .into_iter()
+            .filter(|p| !p.amount.is_zero())
+            .filter_map(|p| {
+                let quote = self.quote(&p.symbol)?;
+                let payout = funding_payout(&p.side, p.amount, quote.oracle, quote.next_funding);
+                Some(FundingPrediction {
+                    symbol: p.symbol.clone(),
+                    side: p.side.clone(),
+                    amount: p.amount,
+                    oracle: quote.oracle,
+                    rate: quote.next_funding,
+                    notional: p.amount * quote.oracle,
+                    payout,
+                    annualized: annualize(payout, self.periods_per_year),
+                })
+            })
+            .collect()
+    }
+
+    fn market_funding(&self, quote: &FundingQuote) -> MarketFunding {
+        MarketFunding {
+            symbol: quote.symbol.clone(),
+            funding: quote.funding,
+            next_funding: quote.next_funding,
+            annualized: annualize(quote.funding, self.periods_per_year),
+            next_annualized: annualize(quote.next_funding, self.periods_per_year),
+            open_interest: quote.open_interest,
+            timestamp: quote.timestamp,
+        }
+    }
+}
+
+/// Funding rate history of `symbol` over REST, summarized.
+pub async fn fetch_rate_stats(
+    info_client: &InfoClient,
+    symbol: &str,
+    periods_per_year: u32,
+) -> Result<Option<FundingRateStats>, ExchangeError> {
+    let history = info_client
+        .funding_rate_history(symbol.to_string())
+        .await?
+        .data
+        .unwrap_or_default();
+    Ok(rate_stats(&history, periods_per_year))
+}
+
+// Guard against a server that keeps answering with full pages
+const MAX_PAYMENT_PAGES: usize = 10_000;
+
+/// Every funding payment of `account`, read `page_size` rows at a time until an
+/// empty page. A page may hold fewer rows than asked when the server caps `limit`.
+pub async fn fetch_payments(
+    info_client: &InfoClient,
+    account: Pubkey,
+    page_size: u32,
+) -> Result<Vec<AccountFundingHistoryModel>, ExchangeError> {
+    if page_size == 0 {
+        return Err(ExchangeError::Validation(
+            "page_size must be greater than zero".to_string(),
+        ));
+    }
+    let mut payments: Vec<AccountFundingHistoryModel> = Vec::new();
+    let mut first_row = None;
+    for _ in 0..MAX_PAYMENT_PAGES {
+        let page = info_client
+            .account_funding_history(account, Some(page_size), Some(payments.len() as u32))
+            .await?
+            .data
+            .unwrap_or_default();
+        let Some(first) = page.first() else {
+            return Ok(payments);
+        };
+        // The same first row again means the server ignored `offset`
+        if first_row == Some(first.history_id) {
+            return Err(ExchangeError::Custom(format!(
+                "funding history repeated payment {} at offset {}, the server ignores offset",
+                first.history_id,
+                payments.len()
+            )));
+        }
+        first_row = Some(first.history_id);
+        payments.extend(page);
+    }
+    Err(ExchangeError::Custom(format!(
+        "funding history still not exhausted after {} pages",
+        MAX_PAYMENT_PAGES
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{
+        rest::rest_client::RestClientOptions,
+        test_util::{HttpResponse, spawn_http_server},
+    };
+
+    fn price(symbol: &str, funding: &str, next_funding: &str, oracle: &str) -> PriceModel {
+        serde_json::from_value(json!({
+            "funding": funding, "mark": oracle, "mid": oracle, "next_funding": next_funding,
+            "open_interest": "10", "oracle": oracle, "symbol": symbol, "timestamp": 1_000,
+            "volume_24h": "0", "yesterday_price": oracle
+        }))
+        .unwrap()
+    }
+
+    fn payment(
+        symbol: &str,
+        side: &str,
+        payout: &str,
+        created_at: u64,
+    ) -> AccountFundingHistoryModel {
+        serde_json::from_value(json!({
+            "history_id": created_at, "symbol": symbol, "side": side, "amount": "1",
+            "payout": payout, "rate": "0.0001", "created_at": created_at
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_rank_and_predict() {
+        let mut analytics = FundingAnalytics::default();
+        analytics.update_prices(&[
+            price("BTC", "0.0001", "0.0002", "100"),
+            price("ETH", "-0.0003", "-0.0004", "10"),
+            price("SOL", "0.00001", "0.00001", "1"),
+        ]);
+        assert_eq!(
+            annualize(Decimal::new(1, 4), HOURLY_PERIODS_PER_YEAR),
+            Decimal::new(876, 3)
+        );
+
+        let symbols = |ranking| {
+            analytics
+                .ranked(ranking)
+                .into_iter()
+                .map(|m| m.symbol)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(symbols(FundingRanking::Highest), ["BTC", "SOL", "ETH"]);
+        assert_eq!(symbols(FundingRanking::Lowest), ["ETH", "SOL", "BTC"]);
+        assert_eq!(symbols(FundingRanking::Extreme), ["ETH", "BTC", "SOL"]);
+
+        let position = |symbol: &str, side| PositionSnapshot {
+            symbol: symbol.into(),
+            side,
+            amount: Decimal::TWO,
+            entry_price: Decimal::ONE,
+            margin: None,
+            funding: Decimal::ZERO,
+            isolated: false,
+        };
+        let positions = [
+            position("BTC", OrderSide::Bid),
+            position("ETH", OrderSide::Ask),
+            position("DOGE", OrderSide::Bid),
+        ];
+        let predictions = analytics.predict(&positions);
+        assert_eq!(predictions.len(), 2);
+        // Long pays a positive rate, short pays a negative one
+        assert_eq!(predictions[0].payout, Decimal::new(-4, 2));
+        assert_eq!(predictions[1].payout, Decimal::new(-8, 3));
+        assert_eq!(predictions[0].notional, Decimal::from(200));
+    }
+
+    #[test]
+    fn test_summaries_and_rate_stats() {
+        let summaries = summarize_payments(&[
+            payment("ETH", "bid", "0.5", 3),
+            payment("BTC", "ask", "-1.5", 2),
+            payment("BTC", "ask", "0.25", 1),
+            payment("BTC", "bid", "-2", 4),
+        ]);
+        let keys: Vec<(&str, bool)> = summaries
+            .iter()
+            .map(|s| (s.symbol.as_str(), matches!(s.side, OrderSide::Bid)))
+            .collect();
+        assert_eq!(keys, [("BTC", true), ("BTC", false), ("ETH", true)]);
+        let short = &summaries[1];
+        assert_eq!(short.payments, 2);
+        assert_eq!(short.paid, Decimal::new(15, 1));
+        assert_eq!(short.received, Decimal::new(25, 2));
+        assert_eq!(short.net, Decimal::new(-125, 2));
+        assert_eq!((short.first_at, short.last_at), (1, 2));
+
+        let history: Vec<FundingRateHistoryModel> = ["0.0001", "-0.0002", "0.0004"]
+            .iter()
+            .enumerate()
+            .map(|(i, rate)| {
+                serde_json::from_value(json!({
+                    "oracle_price": "100", "bid_impact_price": "100", "ask_impact_price": "100",
+                    "funding_rate": rate, "next_funding_rate": rate, "created_at": i as u64
+                }))
+                .unwrap()
+            })
+            .collect();
+        let stats = rate_stats(&history, HOURLY_PERIODS_PER_YEAR).unwrap();
+        assert_eq!(stats.samples, 3);
+        assert_eq!(stats.latest, Decimal::new(4, 4));
+        assert_eq!(stats.cumulative, Decimal::new(3, 4));
+        assert_eq!(stats.mean, Decimal::new(1, 4));
+        assert_eq!(
+            (stats.min, stats.max),
+            (Decimal::new(-2, 4), Decimal::new(4, 4))
+        );
+        assert_eq!(stats.annualized_mean, Decimal::new(876, 3));
+        assert!(rate_stats(&[], HOURLY_PERIODS_PER_YEAR).is_none());
+    }
+
+    // Serves 5 payments, at most 2 per page whatever the limit, from `offset` unless
+    // `ignore_offset`
+    async fn history_client(ignore_offset: bool) -> InfoClient {
+        let url = spawn_http_server(move |request| {
+            let offset = request
+                .query("offset")
+                .and_then(|o| o.parse::<usize>().ok())
+                .filter(|_| !ignore_offset)
+                .unwrap_or(0);
+            let rows: Vec<_> = (offset..5)
+                .take(2)
+                .map(|i| {
+                    json!({
+                        "history_id": i, "symbol": "BTC", "side": "bid", "amount": "1",
+                        "payout": "0.1", "rate": "0.0001", "created_at": i
+                    })
+                })
+                .collect();
+            HttpResponse::ok(json!(rows))
+        })
+        .await;
+        InfoClient::from_markets(&url, None, RestClientOptions::default(), Vec::new()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_fetch_payments_pages() {
+        // Pages shorter than the limit do not end the history
+        let client = history_client(false).await;
+        let payments = fetch_payments(&client, Pubkey::new_unique(), 10)
+            .await
+            .unwrap();
+        let ids: Vec<u64> = payments.iter().map(|p| p.history_id).collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4]);
+
+        let client = history_client(true).await;
+        let err = fetch_payments(&client, Pubkey::new_unique(), 2)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("ignores offset"), "{err}");
+    }
+}
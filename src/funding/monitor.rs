@@ -0,0 +1,266 @@
+use std::{collections::HashMap, sync::Arc};
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    sync::{RwLock, broadcast},
+    task::JoinHandle,
+};
+use tracing::{debug, error};
+
+use crate::{
+    common::types::DefaultWebSocketMsg,
+    funding::analytics::{FundingAnalytics, FundingQuote, annualize},
+    models::{
+        info::response::market::PriceModel, ws::responses::PricesResponse as WsPricesResponse,
+    },
+    ws::ws_client::Subscription,
+};
+
+/// Bounds on a market's annualized next funding rate. `symbol: None` applies
+/// to every market.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FundingThreshold {
+    pub symbol: Option<String>,
+    pub above: Option<Decimal>,
+    pub below: Option<Decimal>,
+}
+
+impl FundingThreshold {
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    pub fn symbol(symbol: &str) -> Self {
+        Self {
+            symbol: Some(symbol.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn above(mut self, annualized: Decimal) -> Self {
+        self.above = Some(annualized);
+        self
+    }
+
+    pub fn below(mut self, annualized: Decimal) -> Self {
+        self.below = Some(annualized);
+        self
+    }
+
+    fn applies_to(&self, symbol: &str) -> bool {
+        self.symbol.as_deref().is_none_or(|s| s == symbol)
+    }
+
+    fn zone(&self, annualized: Decimal) -> FundingZone {
+        match (self.above, self.below) {
+            (Some(above), _) if annualized > above => FundingZone::Above,
+            (_, Some(below)) if annualized < below => FundingZone::Below,
+            _ => FundingZone::Within,
+        }
+    }
+}
+
+/// Where a rate sits relative to a threshold.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FundingZone {
+    Above,
+    Within,
+    Below,
+}
+
+/// A market's rate moved into `zone` of the threshold at `threshold` index.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FundingAlert {
+    pub symbol: String,
+    pub threshold: usize,
+    pub zone: FundingZone,
+    pub previous: FundingZone,
+    pub next_funding: Decimal,
+    pub annualized: Decimal,
+    pub timestamp: u64,
+}
+
+/// Checks every price update against the thresholds. An alert is raised when
+/// a rate first leaves the range and again when it returns; a market already
+/// outside the range on its first update alerts too.
+#[derive(Debug)]
+pub struct FundingMonitor {
+    thresholds: Vec<FundingThreshold>,
+    analytics: FundingAnalytics,
+    zones: HashMap<(String, usize), FundingZone>,
+    alerts: broadcast::Sender<FundingAlert>,
+}
+
+impl FundingMonitor {
+    pub fn new(thresholds: Vec<FundingThreshold>) -> Self {
+        Self::with_analytics(thresholds, FundingAnalytics::default())
+    }
+
+    /// Keeps quotes already loaded in `analytics`, without alerting on them.
+    pub fn with_analytics(thresholds: Vec<FundingThreshold>, analytics: FundingAnalytics) -> Self {
+        let mut monitor = Self {
+            thresholds,
+            analytics,
+            zones: HashMap::new(),
+            alerts: broadcast::channel(256).0,
+        };
+        let quotes: Vec<FundingQuote> = monitor.analytics.quotes().cloned().collect();
+        for quote in &quotes {
+            monitor.check(quote);
+        }
+        monitor
+    }
+
+    /// Alerts from every later update; lagging receivers miss the oldest.
+    pub fn alerts(&self) -> broadcast::Receiver<FundingAlert> {
+        self.alerts.subscribe()
+    }
+
+    /// Latest quotes, for ranking and payment prediction.
+    pub fn analytics(&self) -> &FundingAnalytics {
+        &self.analytics
+    }
+
+    pub fn thresholds(&self) -> &[FundingThreshold] {
+        &self.thresholds
+    }
+
+    pub fn update_prices(&mut self, prices: &[PriceModel]) -> Vec<FundingAlert> {
+        prices
+            .iter()
+            .flat_map(|p| self.update_quote(FundingQuote::from(p)))
+            .collect()
+    }
+
+    pub fn update_ws_prices(&mut self, prices: &WsPricesResponse) -> Vec<FundingAlert> {
+        prices
+            .iter()
+            .flat_map(|p| self.update_quote(FundingQuote::from(p)))
+            .collect()
+    }
+
+    /// Stores `quote` and returns the alerts it raised, also sent to `alerts()`.
+    pub fn update_quote(&mut self, quote: FundingQuote) -> Vec<FundingAlert> {
+        let alerts = self.check(&quote);
+        self.analytics.update_quote(quote);
+        for alert in &alerts {
+            // No receiver is not an error, alerts are also returned
+            self.alerts.send(alert.clone()).ok();
+        }
+        alerts
+    }
+
+    fn check(&mut self, quote: &FundingQuote) -> Vec<FundingAlert> {
+        let annualized = annualize(quote.next_funding, self.analytics.periods_per_year());
+        let mut alerts = Vec::new();
+        for (index, threshold) in self.thresholds.iter().enumerate() {
+            if !threshold.applies_to(&quote.symbol) {
+                continue;
+            }
+            let zone = threshold.zone(annualized);
+            let previous = self
+                .zones
+                .insert((quote.symbol.clone(), index), zone)
+                .unwrap_or(FundingZone::Within);
+            if zone != previous {
+                alerts.push(FundingAlert {
+                    symbol: quote.symbol.clone(),
+                    threshold: index,
+                    zone,
+                    previous,
+                    next_funding: quote.next_funding,
+                    annualized,
+                    timestamp: quote.timestamp,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+/// Feeds `monitor` from a `prices` subscription; alerts go to `alerts()`.
+pub fn track_prices(
+    monitor: Arc<RwLock<FundingMonitor>>,
+    mut subscription: Subscription,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(value) = subscription.recv().await {
+            match serde_json::from_value::<DefaultWebSocketMsg<WsPricesResponse>>(value) {
+                Ok(msg) => {
+                    monitor.write().await.update_ws_prices(&msg.data);
+                }
+                Err(e) => error!(
+                    "funding track_prices: failed to deserialize prices: {:?}",
+                    e
+                ),
+            }
+        }
+        debug!("funding track_prices: subscription closed");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(symbol: &str, next_funding: Decimal) -> FundingQuote {
+        FundingQuote {
+            symbol: symbol.into(),
+            funding: next_funding,
+            next_funding,
+            mark: Decimal::ONE,
+            oracle: Decimal::ONE,
+            open_interest: Decimal::ZERO,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_threshold_crossings() {
+        // 50% and -10% a year
+        let mut monitor = FundingMonitor::new(vec![
+            FundingThreshold::all()
+                .above(Decimal::new(5, 1))
+                .below(Decimal::new(-1, 1)),
+            FundingThreshold::symbol("ETH").above(Decimal::ZERO),
+        ]);
+        let mut alerts = monitor.alerts();
+        let high = Decimal::new(1, 4); // 87.6% a year
+        let low = Decimal::new(-2, 5); // -17.52% a year
+
+        assert!(
+            monitor
+                .update_quote(quote("BTC", Decimal::new(1, 6)))
+                .is_empty()
+        );
+        let raised = monitor.update_quote(quote("BTC", high));
+        assert_eq!(raised.len(), 1);
+        assert_eq!(
+            (raised[0].zone, raised[0].previous),
+            (FundingZone::Above, FundingZone::Within)
+        );
+        assert_eq!(raised[0].annualized, Decimal::new(876, 3));
+        // Staying above does not repeat the alert
+        assert!(monitor.update_quote(quote("BTC", high)).is_empty());
+        let raised = monitor.update_quote(quote("BTC", low));
+        assert_eq!(
+            (raised[0].zone, raised[0].previous),
+            (FundingZone::Below, FundingZone::Above)
+        );
+        assert_eq!(
+            monitor.update_quote(quote("BTC", Decimal::ZERO))[0].zone,
+            FundingZone::Within
+        );
+
+        // ETH is checked against both thresholds
+        let raised = monitor.update_quote(quote("ETH", high));
+        assert_eq!(
+            raised.iter().map(|a| a.threshold).collect::<Vec<_>>(),
+            [0, 1]
+        );
+        assert_eq!(std::iter::from_fn(|| alerts.try_recv().ok()).count(), 5);
+        assert_eq!(monitor.analytics().quote("ETH").unwrap().next_funding, high);
+    }
+}
@@ -38,18 +38,22 @@ use crate::{
             },
         },
     },
-    rest::rest_client::RestClient,
-    ws::ws_client::WebSocketClient,
+    rest::{
+        audit::AuditLog,
+        rest_client::{RestClient, RestClientOptions},
+    },
+    ws::ws_client::{WebSocketClient, WebSocketOptions},
 };
 
 pub struct InfoClient {
-    pub base_url: &'static str,
+    pub base_url: String,
     pub market_cache: HashMap<String, MarketModel>,
     pub tick_lot_utils: TickLot,
     pub web_socket_client: Option<WebSocketClient>,
-    api_key: Option<String>,
     default_headers: HeaderMap,
     http_client: RestClient,
+    // The WebSocket belongs to a `SessionPool` and is not re-keyed by `set_api_key`
+    shared_ws: bool,
 }
 
 impl InfoClient {
@@ -58,69 +62,148 @@ impl InfoClient {
         enable_ws: bool,
         api_key: Option<String>,
     ) -> Result<Self, ExchangeError> {
-        let base_url = if is_mainnet {
-            consts::REST_API_MAINNET_URL
+        let (base_url, ws_url) = if is_mainnet {
+            (consts::REST_API_MAINNET_URL, consts::WS_MAINNET_URL)
         } else {
-            consts::REST_API_TESTNET_URL
+            (consts::REST_API_TESTNET_URL, consts::WS_TESTNET_URL)
         };
+        Self::with_urls(base_url, enable_ws.then_some(ws_url), api_key).await
+    }
 
-        let web_socket_client: Option<WebSocketClient> = if enable_ws {
-            let ws_url = if is_mainnet {
-                consts::WS_MAINNET_URL
-            } else {
-                consts::WS_TESTNET_URL
-            };
-            Some(WebSocketClient::new(ws_url, api_key.clone()).await?)
-        } else {
-            None
-        };
+    /// Same as `new` but against explicit endpoints, e.g. a proxy or a local mock.
+    /// The WebSocket client is only created when `ws_url` is set.
+    pub async fn with_urls(
+        base_url: &str,
+        ws_url: Option<&str>,
+        api_key: Option<String>,
+    ) -> Result<Self, ExchangeError> {
+        Self::with_options(
+            base_url,
+            ws_url,
+            api_key,
+            RestClientOptions::default(),
+            WebSocketOptions::default(),
+        )
+        .await
+    }
 
-        let mut default_headers = HeaderMap::new();
-        default_headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
-        if let Some(ref key) = api_key {
-            default_headers.insert("PF-API-KEY", HeaderValue::from_str(key)?);
-        }
+    pub async fn with_options(
+        base_url: &str,
+        ws_url: Option<&str>,
+        api_key: Option<String>,
+        rest_options: RestClientOptions,
+        ws_options: WebSocketOptions,
+    ) -> Result<Self, ExchangeError> {
+        let web_socket_client: Option<WebSocketClient> = match ws_url {
+            Some(ws_url) => {
+                Some(WebSocketClient::with_options(ws_url, api_key.clone(), ws_options).await?)
+            }
+            None => None,
+        };
 
-        let http_client = RestClient::new(base_url);
+        let http_client = RestClient::with_options(base_url, rest_options);
+        http_client.set_api_key(api_key.as_deref())?;
 
         let response = http_client
             .get::<DefaultResponse<MarketsInfoResponse>, MarketsInfoParams>(
                 Some(&InfoEndpoint::MarketsInfo.get()),
                 Some(&MarketsInfoParams {}),
-                Some(&default_headers),
+                Some(&Self::headers()),
             )
             .await?;
-        let market_cache: HashMap<String, MarketModel> = response
-            .data
-            .unwrap()
-            .into_iter()
-            .map(|m| (m.symbol.clone(), m))
-            .collect();
-        let tick_lot_utils = TickLot::new(market_cache.clone());
+        let mut client = Self::with_markets(base_url, http_client, response.data.unwrap());
+        client.web_socket_client = web_socket_client;
+        Ok(client)
+    }
 
-        Ok(Self {
-            base_url,
+    /// Builds a client without any request, from market metadata fetched
+    /// earlier with `get_markets_info`, e.g. to sign on a machine without network
+    /// access. There is no WebSocket client.
+    pub fn from_markets(
+        base_url: &str,
+        api_key: Option<String>,
+        rest_options: RestClientOptions,
+        markets: MarketsInfoResponse,
+    ) -> Result<Self, ExchangeError> {
+        let http_client = RestClient::with_options(base_url, rest_options);
+        http_client.set_api_key(api_key.as_deref())?;
+        Ok(Self::with_markets(base_url, http_client, markets))
+    }
+
+    fn headers() -> HeaderMap {
+        let mut default_headers = HeaderMap::new();
+        default_headers.insert(ACCEPT, HeaderValue::from_static("*/*"));
+        default_headers
+    }
+
+    fn with_markets(base_url: &str, http_client: RestClient, markets: MarketsInfoResponse) -> Self {
+        let market_cache: HashMap<String, MarketModel> =
+            markets.into_iter().map(|m| (m.symbol.clone(), m)).collect();
+        let tick_lot_utils = TickLot::new(market_cache.clone());
+        Self {
+            base_url: base_url.to_string(),
             market_cache,
             tick_lot_utils,
-            web_socket_client,
-            api_key,
-            default_headers,
+            web_socket_client: None,
+            default_headers: Self::headers(),
             http_client,
+            shared_ws: false,
+        }
+    }
+
+    /// Client for another account on the same endpoints, built without any
+    /// request: market metadata is copied and the HTTP connection pool and rate
+    /// limiter are shared, while `api_key` only applies to the new client.
+    /// `web_socket_client` is treated as shared and keeps its own key.
+    pub fn share(
+        &self,
+        api_key: Option<String>,
+        web_socket_client: Option<WebSocketClient>,
+    ) -> Result<Self, ExchangeError> {
+        Ok(Self {
+            base_url: self.base_url.clone(),
+            market_cache: self.market_cache.clone(),
+            tick_lot_utils: self.tick_lot_utils.clone(),
+            web_socket_client,
+            default_headers: self.default_headers.clone(),
+            http_client: self.http_client.with_api_key(api_key.as_deref())?,
+            shared_ws: true,
         })
     }
 
     pub async fn set_default_api_key(&mut self, api_key: String) -> Result<(), ExchangeError> {
-        self.api_key = Some(api_key);
-        if let Some(ref key) = self.api_key {
-            let header_value = HeaderValue::from_str(key)?;
-            self.default_headers.insert("PF-API-KEY", header_value);
-            if let Some(ws_client) = &mut self.web_socket_client {
-                ws_client.set_api_key(key.clone()).await?;
-            }
+        self.set_api_key(Some(api_key)).await
+    }
+
+    /// Switches the key for REST calls immediately (including clients sharing this
+    /// one's `RestClient`) and reconnects the WebSocket with it, keeping subscriptions.
+    /// A WebSocket passed to `share` is left alone.
+    pub async fn set_api_key(&self, api_key: Option<String>) -> Result<(), ExchangeError> {
+        self.http_client.set_api_key(api_key.as_deref())?;
+        if !self.shared_ws
+            && let Some(ws_client) = &self.web_socket_client
+        {
+            ws_client.set_api_key(api_key).await?;
         }
         Ok(())
     }
 
+    pub fn api_key(&self) -> Option<String> {
+        self.http_client.api_key()
+    }
+
+    /// See `RestClient::set_audit_log`, applies to clients sharing this one's
+    /// `RestClient` too.
+    pub fn set_audit_log(&self, audit_log: Option<AuditLog>) {
+        self.http_client.set_audit_log(audit_log);
+    }
+
+    /// The underlying REST client, for `get_with` calls that need per-call
+    /// options or response metadata.
+    pub fn http_client(&self) -> &RestClient {
+        &self.http_client
+    }
+
     async fn request_info_fn<T, P>(
         &self,
         endpoint: InfoEndpoint,
@@ -133,7 +216,11 @@ impl InfoClient {
         // debug!("{}", serde_json::to_string(params)?);
         let response = self
             .http_client
-            .get::<T, P>(Some(&endpoint.get()), Some(params), None)
+            .get::<T, P>(
+                Some(&endpoint.get()),
+                Some(params),
+                Some(&self.default_headers),
+            )
             .await?;
         Ok(response)
     }
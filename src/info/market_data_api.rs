@@ -0,0 +1,220 @@
+use std::future::Future;
+
+use solana_sdk::pubkey::Pubkey;
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{AggLevel, DefaultResponse, Interval},
+    },
+    info::info_client::InfoClient,
+    models::info::{
+        params::account::EquityHistoryParams,
+        response::{
+            account::{
+                AccountFundingHistoryResponse, AccountResponse, AccountSettingsResponse,
+                BalanceHistoryResponse, EquityHistoryResponse, OpenedOrdersResponse,
+                OrderHistoryByIdResponse, OrderHistoryResponse, PositionsResponse,
+                TradesHistoryResponse,
+            },
+            market::{
+                FundingRateHistoryResponse, KlineResponse, MarketsInfoResponse, OrderBookResponse,
+                PricesResponse, RecentTradesResponse,
+            },
+        },
+    },
+};
+
+type ApiResult<T> = Result<DefaultResponse<T>, ExchangeError>;
+
+/// Market and account queries, with the signatures of `InfoClient`.
+pub trait MarketDataApi: Send + Sync {
+    fn get_markets_info(&self) -> impl Future<Output = ApiResult<MarketsInfoResponse>> + Send;
+
+    fn prices(&self) -> impl Future<Output = ApiResult<PricesResponse>> + Send;
+
+    fn kline(
+        &self,
+        symbol: String,
+        interval: Interval,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> impl Future<Output = ApiResult<KlineResponse>> + Send;
+
+    fn recent_trades(
+        &self,
+        symbol: String,
+    ) -> impl Future<Output = ApiResult<RecentTradesResponse>> + Send;
+
+    fn order_book(
+        &self,
+        symbol: String,
+        agg_level: Option<AggLevel>,
+    ) -> impl Future<Output = ApiResult<OrderBookResponse>> + Send;
+
+    fn funding_rate_history(
+        &self,
+        symbol: String,
+    ) -> impl Future<Output = ApiResult<FundingRateHistoryResponse>> + Send;
+
+    fn account(&self, account: Pubkey) -> impl Future<Output = ApiResult<AccountResponse>> + Send;
+
+    fn account_funding_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> impl Future<Output = ApiResult<AccountFundingHistoryResponse>> + Send;
+
+    fn account_settings(
+        &self,
+        account: Pubkey,
+    ) -> impl Future<Output = ApiResult<AccountSettingsResponse>> + Send;
+
+    fn positions(
+        &self,
+        account: Pubkey,
+    ) -> impl Future<Output = ApiResult<PositionsResponse>> + Send;
+
+    fn trade_history(
+        &self,
+        account: Pubkey,
+        symbol: Option<String>,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> impl Future<Output = ApiResult<TradesHistoryResponse>> + Send;
+
+    fn balance_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> impl Future<Output = ApiResult<BalanceHistoryResponse>> + Send;
+
+    fn equity_history(
+        &self,
+        equity_history_params: EquityHistoryParams,
+    ) -> impl Future<Output = ApiResult<EquityHistoryResponse>> + Send;
+
+    fn opened_orders(
+        &self,
+        account: Pubkey,
+    ) -> impl Future<Output = ApiResult<OpenedOrdersResponse>> + Send;
+
+    fn order_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> impl Future<Output = ApiResult<OrderHistoryResponse>> + Send;
+
+    fn order_history_by_id(
+        &self,
+        order_id: u64,
+    ) -> impl Future<Output = ApiResult<OrderHistoryByIdResponse>> + Send;
+}
+
+// Inherent methods take precedence, so these calls don't recurse
+impl MarketDataApi for InfoClient {
+    async fn get_markets_info(&self) -> ApiResult<MarketsInfoResponse> {
+        InfoClient::get_markets_info(self).await
+    }
+
+    async fn prices(&self) -> ApiResult<PricesResponse> {
+        InfoClient::prices(self).await
+    }
+
+    async fn kline(
+        &self,
+        symbol: String,
+        interval: Interval,
+        start_time: u64,
+        end_time: Option<u64>,
+    ) -> ApiResult<KlineResponse> {
+        InfoClient::kline(self, symbol, interval, start_time, end_time).await
+    }
+
+    async fn recent_trades(&self, symbol: String) -> ApiResult<RecentTradesResponse> {
+        InfoClient::recent_trades(self, symbol).await
+    }
+
+    async fn order_book(
+        &self,
+        symbol: String,
+        agg_level: Option<AggLevel>,
+    ) -> ApiResult<OrderBookResponse> {
+        InfoClient::order_book(self, symbol, agg_level).await
+    }
+
+    async fn funding_rate_history(&self, symbol: String) -> ApiResult<FundingRateHistoryResponse> {
+        InfoClient::funding_rate_history(self, symbol).await
+    }
+
+    async fn account(&self, account: Pubkey) -> ApiResult<AccountResponse> {
+        InfoClient::account(self, account).await
+    }
+
+    async fn account_funding_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> ApiResult<AccountFundingHistoryResponse> {
+        InfoClient::account_funding_history(self, account, limit, offset).await
+    }
+
+    async fn account_settings(&self, account: Pubkey) -> ApiResult<AccountSettingsResponse> {
+        InfoClient::account_settings(self, account).await
+    }
+
+    async fn positions(&self, account: Pubkey) -> ApiResult<PositionsResponse> {
+        InfoClient::positions(self, account).await
+    }
+
+    async fn trade_history(
+        &self,
+        account: Pubkey,
+        symbol: Option<String>,
+        start_time: Option<u64>,
+        end_time: Option<u64>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> ApiResult<TradesHistoryResponse> {
+        InfoClient::trade_history(self, account, symbol, start_time, end_time, limit, offset).await
+    }
+
+    async fn balance_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> ApiResult<BalanceHistoryResponse> {
+        InfoClient::balance_history(self, account, limit, offset).await
+    }
+
+    async fn equity_history(
+        &self,
+        equity_history_params: EquityHistoryParams,
+    ) -> ApiResult<EquityHistoryResponse> {
+        InfoClient::equity_history(self, equity_history_params).await
+    }
+
+    async fn opened_orders(&self, account: Pubkey) -> ApiResult<OpenedOrdersResponse> {
+        InfoClient::opened_orders(self, account).await
+    }
+
+    async fn order_history(
+        &self,
+        account: Pubkey,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> ApiResult<OrderHistoryResponse> {
+        InfoClient::order_history(self, account, limit, offset).await
+    }
+
+    async fn order_history_by_id(&self, order_id: u64) -> ApiResult<OrderHistoryByIdResponse> {
+        InfoClient::order_history_by_id(self, order_id).await
+    }
+}
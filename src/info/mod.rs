@@ -1,2 +1,3 @@
 pub mod info_client;
 mod info_endpoint;
+pub mod market_data_api;
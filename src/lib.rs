@@ -1,8 +1,15 @@
+pub mod backtest;
 pub mod common;
+pub mod config;
 pub mod exchange;
+pub mod funding;
 pub mod info;
 pub mod logging;
+pub mod metrics;
 pub mod models;
 pub mod prelude;
 pub mod rest;
+pub mod risk;
+#[cfg(test)]
+mod test_util;
 pub mod ws;
@@ -1,9 +1,226 @@
-use tracing_subscriber::{EnvFilter, FmtSubscriber};
+use std::{str::FromStr, time::Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::{Span, Subscriber, field::Empty};
+use tracing_subscriber::{
+    EnvFilter, Layer, fmt::format::FmtSpan, layer::SubscriberExt, registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+use crate::{
+    common::errors::ExchangeError,
+    rest::audit::{REDACTED, SignatureRedaction, redact_signatures},
+};
+
+// The SDK only emits `tracing` events and spans, installing a subscriber is
+// left to the application: these helpers are for binaries and quick setups.
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    // One JSON object per line, span fields included
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = ExchangeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(ExchangeError::Validation(format!(
+                "unknown log format `{}`, expected text or json",
+                other
+            ))),
+        }
+    }
+}
+
+/// `fmt` layer printing closed operation spans with their fields, for
+/// applications composing their own subscriber, e.g. next to an OpenTelemetry layer.
+pub fn fmt_layer<S>(format: LogFormat) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let layer = tracing_subscriber::fmt::layer().with_span_events(FmtSpan::CLOSE);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+/// Installs a global subscriber filtered by `level` (an `EnvFilter` directive).
+/// Fails if the application already installed one.
+pub fn try_init_logging(level: &str, format: LogFormat) -> Result<(), ExchangeError> {
+    tracing_subscriber::registry()
+        .with(EnvFilter::new(level))
+        .with(fmt_layer(format))
+        .try_init()
+        .map_err(|e| ExchangeError::Custom(format!("logging already initialized: {}", e)))
+}
+
 static INIT: std::sync::Once = std::sync::Once::new();
 
+/// Text logs at `level`, once per process; a subscriber installed elsewhere is kept.
 pub fn init_logging_once(level: &str) {
     INIT.call_once(|| {
-        let filter = EnvFilter::new(level);
-        FmtSubscriber::builder().with_env_filter(filter).init();
+        try_init_logging(level, LogFormat::Text).ok();
     });
 }
+
+/// JSON of `value` for logs, with signatures, API keys and key material replaced.
+pub(crate) fn redacted_json<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(mut value) => {
+            redact_secrets(&mut value);
+            value.to_string()
+        }
+        Err(_) => "<failed to serialize>".to_string(),
+    }
+}
+
+/// `redacted_json` for a body that is already text; non-JSON is returned as is.
+pub(crate) fn redacted_text(text: &str) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(mut value) => {
+            redact_secrets(&mut value);
+            value.to_string()
+        }
+        Err(_) => text.to_string(),
+    }
+}
+
+// Fields whose whole value is secret, e.g. `active_api_keys` of list_api_keys
+const SECRET_FIELDS: &[&str] = &["api_key", "active_api_keys", "private_key", "mnemonic"];
+
+fn redact_secrets(value: &mut Value) {
+    redact_signatures(value, SignatureRedaction::Remove);
+    redact_secret_fields(value);
+}
+
+/// Replaces API keys and key material at any depth, leaving signatures alone.
+pub(crate) fn redact_secret_fields(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    redact_field(field);
+                } else {
+                    redact_secret_fields(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_secret_fields),
+        _ => {}
+    }
+}
+
+// Strings are replaced, a list keeps its length
+fn redact_field(field: &mut Value) {
+    match field {
+        Value::String(_) => *field = Value::String(REDACTED.to_string()),
+        Value::Array(items) => items.iter_mut().for_each(redact_field),
+        Value::Object(map) => map.values_mut().for_each(redact_field),
+        _ => {}
+    }
+}
+
+/// Span of one exchange operation. `symbol` and `client_order_id` are taken
+/// from the payload when it has them; `request_id`, `status`, `latency_ms`
+/// and `outcome` are filled in by `OperationTimer`.
+pub(crate) fn operation_span<P: Serialize>(operation: &str, payload: Option<&P>) -> Span {
+    let span = tracing::info_span!(
+        "pacifica.operation",
+        operation,
+        symbol = Empty,
+        client_order_id = Empty,
+        request_id = Empty,
+        status = Empty,
+        latency_ms = Empty,
+        outcome = Empty,
+    );
+    if !span.is_disabled()
+        && let Some(Ok(Value::Object(fields))) = payload.map(serde_json::to_value)
+    {
+        for name in ["symbol", "client_order_id"] {
+            if let Some(value) = fields.get(name).and_then(Value::as_str) {
+                span.record(name, value);
+            }
+        }
+    }
+    span
+}
+
+/// Records latency and outcome on a span once the call it covers is done.
+pub(crate) struct OperationTimer {
+    span: Span,
+    started: Instant,
+}
+
+impl OperationTimer {
+    pub(crate) fn start(span: &Span) -> Self {
+        Self {
+            span: span.clone(),
+            started: Instant::now(),
+        }
+    }
+
+    /// `ok` below 400, `rejected` for an answer decoded from a 4xx, `error` otherwise.
+    pub(crate) fn finish<T>(self, result: &Result<T, ExchangeError>, status: Option<u16>) {
+        let outcome = match (result, status) {
+            (Err(_), _) => "error",
+            (Ok(_), Some(status)) if status >= 400 => "rejected",
+            (Ok(_), _) => "ok",
+        };
+        self.span
+            .record("latency_ms", self.started.elapsed().as_millis() as u64);
+        self.span.record("outcome", outcome);
+        if let Some(status) = status.or_else(|| result.as_ref().err().and_then(|e| e.status())) {
+            self.span.record("status", status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_redacted_json() {
+        let body = json!({
+            "account": "acc",
+            "signature": "5xyz",
+            "api_key": "key",
+            "data": {"actions": [{"signature": {"type": "hardware", "signature": "abc"}}]},
+        });
+        let redacted: Value = serde_json::from_str(&redacted_json(&body)).unwrap();
+        assert_eq!(redacted["account"], "acc");
+        assert_eq!(redacted["signature"], REDACTED);
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(
+            redacted["data"]["actions"][0]["signature"]["signature"],
+            REDACTED
+        );
+        assert_eq!(
+            redacted["data"]["actions"][0]["signature"]["type"],
+            "hardware"
+        );
+        assert_eq!(redacted_text("<html>"), "<html>");
+        let keys = json!({
+            "success": true,
+            "data": {"active_api_keys": ["key1", "key2"], "api_key": null},
+        });
+        let redacted: Value = serde_json::from_str(&redacted_json(&keys)).unwrap();
+        assert_eq!(
+            redacted["data"]["active_api_keys"],
+            json!([REDACTED, REDACTED])
+        );
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+    }
+}
@@ -0,0 +1,181 @@
+use std::time::Duration;
+
+use ::metrics::{Unit, counter, describe_counter, describe_histogram, histogram};
+
+// Recorded through the `metrics` facade: nothing is collected until a recorder
+// is installed, any `metrics` exporter or `install_prometheus`. Histograms are
+// in seconds.
+
+/// Signing a request, `operation`.
+pub const SIGN_DURATION: &str = "pacifica_sign_duration_seconds";
+/// REST round trip until the whole body is read, `method`, `endpoint`, `status`.
+pub const REST_DURATION: &str = "pacifica_rest_request_duration_seconds";
+/// REST calls, `method`, `endpoint`, `status` (`error` when no response arrived).
+pub const REST_REQUESTS: &str = "pacifica_rest_requests_total";
+/// WebSocket exchange request until its answer, `method`.
+pub const WS_ACK_DURATION: &str = "pacifica_ws_ack_duration_seconds";
+/// WebSocket messages received, `channel`.
+pub const WS_MESSAGES: &str = "pacifica_ws_messages_total";
+/// WebSocket connections established after the first one.
+pub const WS_RECONNECTS: &str = "pacifica_ws_reconnects_total";
+
+/// Registers units and help texts with the installed recorder.
+pub fn describe() {
+    describe_histogram!(SIGN_DURATION, Unit::Seconds, "Time to sign a request");
+    describe_histogram!(
+        REST_DURATION,
+        Unit::Seconds,
+        "REST round trip including reading the body"
+    );
+    describe_counter!(REST_REQUESTS, "REST requests by endpoint and status");
+    describe_histogram!(
+        WS_ACK_DURATION,
+        Unit::Seconds,
+        "WebSocket exchange request until its answer"
+    );
+    describe_counter!(WS_MESSAGES, "WebSocket messages received by channel");
+    describe_counter!(WS_RECONNECTS, "WebSocket reconnections");
+}
+
+pub(crate) fn record_sign(operation: &str, elapsed: Duration) {
+    histogram!(SIGN_DURATION, "operation" => operation.to_string()).record(elapsed);
+}
+
+pub(crate) fn record_rest(method: &str, endpoint: &str, status: Option<u16>, elapsed: Duration) {
+    let status = status.map_or_else(|| "error".to_string(), |s| s.to_string());
+    let labels = [
+        ("method", method.to_string()),
+        ("endpoint", endpoint.to_string()),
+        ("status", status),
+    ];
+    histogram!(REST_DURATION, &labels).record(elapsed);
+    counter!(REST_REQUESTS, &labels).increment(1);
+}
+
+pub(crate) fn record_ws_ack(method: &str, elapsed: Duration) {
+    histogram!(WS_ACK_DURATION, "method" => method.to_string()).record(elapsed);
+}
+
+pub(crate) fn record_ws_message(channel: &str) {
+    counter!(WS_MESSAGES, "channel" => channel.to_string()).increment(1);
+}
+
+pub(crate) fn record_ws_reconnect() {
+    counter!(WS_RECONNECTS).increment(1);
+}
+
+/// Installs a global Prometheus recorder serving the text format over HTTP on
+/// `addr`, e.g. `127.0.0.1:9000`. Fails if a recorder is already installed.
+#[cfg(feature = "prometheus")]
+pub fn install_prometheus(
+    addr: std::net::SocketAddr,
+) -> Result<(), crate::common::errors::ExchangeError> {
+    use metrics_exporter_prometheus::{Matcher, PrometheusBuilder};
+
+    // Signing takes microseconds, REST and WebSocket round trips milliseconds to seconds
+    const BUCKETS: [f64; 14] = [
+        0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5,
+    ];
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), &BUCKETS)
+        .and_then(|builder| builder.install())
+        .map_err(|e| crate::common::errors::ExchangeError::Custom(e.to_string()))?;
+    describe();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ::metrics::{
+        Counter, CounterFn, Gauge, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+        SharedString, with_local_recorder,
+    };
+
+    use super::*;
+
+    // Keeps "name{label=value,..} value" for every update
+    #[derive(Default)]
+    struct ListRecorder(Arc<Mutex<Vec<String>>>);
+
+    struct Handle(Key, Arc<Mutex<Vec<String>>>);
+
+    impl Handle {
+        fn push(&self, value: f64) {
+            let labels: Vec<String> = self
+                .0
+                .labels()
+                .map(|l| format!("{}={}", l.key(), l.value()))
+                .collect();
+            self.1.lock().unwrap().push(format!(
+                "{}{{{}}} {}",
+                self.0.name(),
+                labels.join(","),
+                value
+            ));
+        }
+    }
+
+    impl CounterFn for Handle {
+        fn increment(&self, value: u64) {
+            self.push(value as f64);
+        }
+
+        fn absolute(&self, value: u64) {
+            self.push(value as f64);
+        }
+    }
+
+    impl HistogramFn for Handle {
+        fn record(&self, value: f64) {
+            self.push(value);
+        }
+    }
+
+    impl Recorder for ListRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(Arc::new(Handle(key.clone(), self.0.clone())))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::from_arc(Arc::new(Handle(key.clone(), self.0.clone())))
+        }
+    }
+
+    #[test]
+    fn test_recorded_names_and_labels() {
+        let recorder = ListRecorder::default();
+        with_local_recorder(&recorder, || {
+            record_rest(
+                "POST",
+                "/orders/create",
+                Some(400),
+                Duration::from_millis(250),
+            );
+            record_rest("GET", "/info", None, Duration::from_secs(1));
+            record_ws_message("book");
+            record_ws_reconnect();
+        });
+        assert_eq!(
+            *recorder.0.lock().unwrap(),
+            vec![
+                "pacifica_rest_request_duration_seconds{method=POST,endpoint=/orders/create,status=400} 0.25",
+                "pacifica_rest_requests_total{method=POST,endpoint=/orders/create,status=400} 1",
+                "pacifica_rest_request_duration_seconds{method=GET,endpoint=/info,status=error} 1",
+                "pacifica_rest_requests_total{method=GET,endpoint=/info,status=error} 1",
+                "pacifica_ws_messages_total{channel=book} 1",
+                "pacifica_ws_reconnects_total{} 1",
+            ]
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{
     common::{types::FinalRequest, utils::Validatable},
@@ -24,6 +25,24 @@ pub enum BatchOrderActionPayload {
     CreateMarketOrder(CreateMarketOrderPayload),
 }
 
+impl BatchOrderActionPayload {
+    pub fn symbol(&self) -> &str {
+        match self {
+            BatchOrderActionPayload::CreateOrder(o) => &o.symbol,
+            BatchOrderActionPayload::CancelOrder(o) => &o.symbol,
+            BatchOrderActionPayload::CreateMarketOrder(o) => &o.symbol,
+        }
+    }
+
+    pub fn client_order_id(&self) -> Option<Uuid> {
+        match self {
+            BatchOrderActionPayload::CreateOrder(o) => o.client_order_id,
+            BatchOrderActionPayload::CancelOrder(o) => o.client_order_id,
+            BatchOrderActionPayload::CreateMarketOrder(o) => o.client_order_id,
+        }
+    }
+}
+
 make_validatable!(BatchOrderActionsFinalHeaders);
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct BatchOrderActionsFinalHeaders {
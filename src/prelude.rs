@@ -12,5 +12,8 @@ pub use tracing::{debug, error, info};
 pub use uuid::Uuid;
 
 pub use crate::exchange::exchange_client::ExchangeClient;
+pub use crate::exchange::trading_api::TradingApi;
 pub use crate::info::info_client::InfoClient;
+pub use crate::info::market_data_api::MarketDataApi;
 pub use crate::models;
+pub use crate::ws::market_stream_api::MarketStreamApi;
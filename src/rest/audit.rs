@@ -0,0 +1,544 @@
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    sync::{Arc, mpsc},
+    thread::JoinHandle,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{common::errors::ExchangeError, logging::redact_secret_fields};
+
+/// How signatures in recorded request bodies are written. Only `Keep` leaves
+/// signed requests replayable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignatureRedaction {
+    Keep,
+    // First 8 characters, enough to match against exchange-side logs
+    #[default]
+    Truncate,
+    Remove,
+}
+
+pub const REDACTED: &str = "<redacted>";
+
+/// One line of the audit log. A request and its response share `correlation_id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditEntry {
+    Request {
+        correlation_id: Uuid,
+        timestamp: u64,
+        method: String,
+        endpoint: String,
+        // Query parameters for GET, body for POST
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        payload: Option<Value>,
+    },
+    Response {
+        correlation_id: Uuid,
+        timestamp: u64,
+        duration_ms: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        status: Option<u16>,
+        // Body as received, with secret fields of a JSON body redacted
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        body: Option<String>,
+        // Transport error when no body was received
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+impl AuditEntry {
+    pub fn correlation_id(&self) -> Uuid {
+        match self {
+            AuditEntry::Request { correlation_id, .. }
+            | AuditEntry::Response { correlation_id, .. } => *correlation_id,
+        }
+    }
+}
+
+/// Destination of audit entries. Called inline on the request path, so it
+/// should not block for long.
+pub trait AuditSink: Send + Sync {
+    fn record(&self, entry: &AuditEntry);
+}
+
+/// Appends one JSON object per line. Entries are written by a background
+/// thread, flushed whenever it has caught up so the log survives a crash, and
+/// drained when the sink is dropped. The file is readable by its owner only.
+pub struct JsonlAuditSink {
+    lines: Option<mpsc::Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl JsonlAuditSink {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        let mut options = OpenOptions::new();
+        options.create(true).append(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(path)?;
+        // `mode` only applies to a new file
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        let (lines, rx) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("pacifica-audit".to_string())
+            .spawn(move || write_lines(BufWriter::new(file), rx))?;
+        Ok(Self {
+            lines: Some(lines),
+            writer: Some(writer),
+        })
+    }
+}
+
+// Runs until the sink is dropped, flushing each time the queue is empty
+fn write_lines(mut writer: BufWriter<File>, lines: mpsc::Receiver<String>) {
+    while let Ok(line) = lines.recv() {
+        let result = std::iter::once(line)
+            .chain(lines.try_iter())
+            .try_for_each(|line| writer.write_all(line.as_bytes()))
+            .and_then(|_| writer.flush());
+        if let Err(e) = result {
+            warn!("Audit log write failed: {}", e);
+        }
+    }
+}
+
+impl AuditSink for JsonlAuditSink {
+    fn record(&self, entry: &AuditEntry) {
+        match serde_json::to_string(entry) {
+            Ok(mut line) => {
+                line.push('\n');
+                if let Some(lines) = &self.lines {
+                    lines.send(line).ok();
+                }
+            }
+            Err(e) => warn!("Audit log write failed: {}", e),
+        }
+    }
+}
+
+impl Drop for JsonlAuditSink {
+    fn drop(&mut self) {
+        // Closing the channel lets the writer finish the queue and exit
+        self.lines.take();
+        if let Some(writer) = self.writer.take() {
+            writer.join().ok();
+        }
+    }
+}
+
+/// Audit settings attached to a `RestClient`, cheap to clone.
+#[derive(Clone)]
+pub struct AuditLog {
+    sink: Arc<dyn AuditSink>,
+    pub signatures: SignatureRedaction,
+    // Set to false to keep the log to signed traffic
+    pub include_get: bool,
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("signatures", &self.signatures)
+            .field("include_get", &self.include_get)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AuditLog {
+    pub fn new(sink: impl AuditSink + 'static) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            signatures: SignatureRedaction::default(),
+            include_get: true,
+        }
+    }
+
+    /// Audit log appending to a JSONL file.
+    pub fn jsonl(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        Ok(Self::new(JsonlAuditSink::open(path)?))
+    }
+
+    pub fn with_signatures(mut self, signatures: SignatureRedaction) -> Self {
+        self.signatures = signatures;
+        self
+    }
+
+    pub fn with_get(mut self, include_get: bool) -> Self {
+        self.include_get = include_get;
+        self
+    }
+
+    pub(crate) fn records(&self, method: &str) -> bool {
+        self.include_get || method != "GET"
+    }
+
+    pub(crate) fn request(
+        &self,
+        correlation_id: Uuid,
+        method: &str,
+        endpoint: &str,
+        payload: Option<Value>,
+    ) {
+        let payload = payload.map(|mut payload| {
+            redact_signatures(&mut payload, self.signatures);
+            redact_secret_fields(&mut payload);
+            payload
+        });
+        self.sink.record(&AuditEntry::Request {
+            correlation_id,
+            timestamp: crate::common::utils::get_timestamp_ms(),
+            method: method.to_string(),
+            endpoint: endpoint.to_string(),
+            payload,
+        });
+    }
+
+    pub(crate) fn response(
+        &self,
+        correlation_id: Uuid,
+        duration_ms: u64,
+        status: Option<u16>,
+        body: Option<String>,
+        error: Option<String>,
+    ) {
+        // E.g. the key returned by create_api_key
+        let body = body.map(|body| match serde_json::from_str::<Value>(&body) {
+            Ok(mut value) => {
+                redact_secret_fields(&mut value);
+                value.to_string()
+            }
+            Err(_) => body,
+        });
+        self.sink.record(&AuditEntry::Response {
+            correlation_id,
+            timestamp: crate::common::utils::get_timestamp_ms(),
+            duration_ms,
+            status,
+            body,
+            error,
+        });
+    }
+}
+
+/// Rewrites every `signature` or `*_signature` field, at any depth, so batch
+/// actions and subaccount requests are covered too.
+pub fn redact_signatures(value: &mut Value, mode: SignatureRedaction) {
+    if mode == SignatureRedaction::Keep {
+        return;
+    }
+    match value {
+        Value::Object(map) => {
+            for (key, field) in map.iter_mut() {
+                if key == "signature" || key.ends_with("_signature") {
+                    redact(field, mode);
+                } else {
+                    redact_signatures(field, mode);
+                }
+            }
+        }
+        Value::Array(items) => items
+            .iter_mut()
+            .for_each(|item| redact_signatures(item, mode)),
+        _ => {}
+    }
+}
+
+// Signatures are either a string or a `{type, signature}` object
+fn redact(value: &mut Value, mode: SignatureRedaction) {
+    match value {
+        Value::String(s) => {
+            *s = match mode {
+                SignatureRedaction::Truncate => {
+                    format!("{}...", s.chars().take(8).collect::<String>())
+                }
+                _ => REDACTED.to_string(),
+            }
+        }
+        other => redact_signatures(other, mode),
+    }
+}
+
+/// Whether any signature in `payload` was redacted, making it unfit to resend.
+pub fn is_redacted(payload: &Value) -> bool {
+    match payload {
+        Value::Object(map) => map.iter().any(|(key, field)| {
+            if key == "signature" || key.ends_with("_signature") {
+                has_redacted_string(field)
+            } else {
+                is_redacted(field)
+            }
+        }),
+        Value::Array(items) => items.iter().any(is_redacted),
+        _ => false,
+    }
+}
+
+fn has_redacted_string(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s == REDACTED || s.ends_with("..."),
+        other => is_redacted(other),
+    }
+}
+
+/// A recorded request paired with its response, if one was logged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRecord {
+    pub correlation_id: Uuid,
+    pub timestamp: u64,
+    pub method: String,
+    pub endpoint: String,
+    pub payload: Option<Value>,
+    pub status: Option<u16>,
+    pub duration_ms: Option<u64>,
+    pub response: Option<String>,
+    pub error: Option<String>,
+}
+
+impl AuditRecord {
+    /// The response body parsed as JSON, when it is JSON.
+    pub fn response_json(&self) -> Option<Value> {
+        serde_json::from_str(self.response.as_deref()?).ok()
+    }
+}
+
+/// Reads a JSONL audit log and pairs requests with their responses, in request
+/// order. Unparseable lines are skipped with a warning.
+pub fn read_audit_log(path: impl AsRef<Path>) -> Result<Vec<AuditRecord>, ExchangeError> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut records: Vec<AuditRecord> = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = match serde_json::from_str::<AuditEntry>(&line) {
+            Ok(entry) => entry,
+            Err(e) => {
+                warn!("Skipping audit log line {}: {}", number + 1, e);
+                continue;
+            }
+        };
+        match entry {
+            AuditEntry::Request {
+                correlation_id,
+                timestamp,
+                method,
+                endpoint,
+                payload,
+            } => records.push(AuditRecord {
+                correlation_id,
+                timestamp,
+                method,
+                endpoint,
+                payload,
+                status: None,
+                duration_ms: None,
+                response: None,
+                error: None,
+            }),
+            AuditEntry::Response {
+                correlation_id,
+                duration_ms,
+                status,
+                body,
+                error,
+                ..
+            } => {
+                // Responses follow their request, usually closely
+                if let Some(record) = records
+                    .iter_mut()
+                    .rev()
+                    .find(|r| r.correlation_id == correlation_id)
+                {
+                    record.status = status;
+                    record.duration_ms = Some(duration_ms);
+                    record.response = body;
+                    record.error = error;
+                }
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// A value that differs between two JSON documents; `None` means absent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonDiff {
+    // e.g. `data[0].price`, empty for the root
+    pub path: String,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+/// Leaf-level differences between `before` and `after`. Arrays are compared
+/// by index.
+pub fn diff_json(before: &Value, after: &Value) -> Vec<JsonDiff> {
+    let mut diffs = Vec::new();
+    diff_at(String::new(), Some(before), Some(after), &mut diffs);
+    diffs
+}
+
+fn diff_at(path: String, before: Option<&Value>, after: Option<&Value>, diffs: &mut Vec<JsonDiff>) {
+    match (before, after) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_at(child, a.get(key), b.get(key), diffs);
+            }
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) => {
+            for i in 0..a.len().max(b.len()) {
+                diff_at(format!("{}[{}]", path, i), a.get(i), b.get(i), diffs);
+            }
+        }
+        (a, b) if a != b => diffs.push(JsonDiff {
+            path,
+            before: a.cloned(),
+            after: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use std::sync::Mutex;
+
+    use super::*;
+
+    #[derive(Default)]
+    struct MemorySink(Mutex<Vec<AuditEntry>>);
+
+    impl AuditSink for Arc<MemorySink> {
+        fn record(&self, entry: &AuditEntry) {
+            self.0.lock().unwrap().push(entry.clone());
+        }
+    }
+
+    #[test]
+    fn test_redaction_and_log_roundtrip() {
+        let body = json!({
+            "account": "acc",
+            "signature": {"type": "hardware", "signature": "0123456789abcdef"},
+            "actions": [{"type": "Create", "signature": "abcdefghijkl"}],
+            "main_signature": "mainmainmain",
+        });
+
+        let sink = Arc::new(MemorySink::default());
+        let log = AuditLog::new(sink.clone());
+        let id = Uuid::new_v4();
+        log.request(id, "POST", "/orders/create", Some(body.clone()));
+        let AuditEntry::Request { payload, .. } = &sink.0.lock().unwrap()[0] else {
+            panic!("expected a request entry");
+        };
+        let payload = payload.clone().unwrap();
+        assert_eq!(payload["signature"]["signature"], "01234567...");
+        assert_eq!(payload["signature"]["type"], "hardware");
+        assert_eq!(payload["actions"][0]["signature"], "abcdefgh...");
+        assert_eq!(payload["main_signature"], "mainmain...");
+        assert_eq!(payload["account"], "acc");
+        assert!(is_redacted(&payload));
+
+        let mut removed = body.clone();
+        redact_signatures(&mut removed, SignatureRedaction::Remove);
+        assert_eq!(removed["actions"][0]["signature"], REDACTED);
+        assert!(!is_redacted(&body));
+
+        let path = std::env::temp_dir().join(format!("pacifica-audit-{}.jsonl", id));
+        let file_log = AuditLog::jsonl(&path)
+            .unwrap()
+            .with_signatures(SignatureRedaction::Keep);
+        file_log.request(id, "POST", "/orders/create", Some(body.clone()));
+        let other = Uuid::new_v4();
+        file_log.request(other, "GET", "/info", None);
+        file_log.response(other, 5, None, None, Some("timed out".into()));
+        file_log.response(id, 12, Some(400), Some(r#"{"success":false}"#.into()), None);
+        let keys = Uuid::new_v4();
+        file_log.request(
+            keys,
+            "POST",
+            "/account/api_keys/revoke",
+            Some(json!({"api_key": "old"})),
+        );
+        let listed = r#"{"success":true,"data":{"active_api_keys":["k1","k2"]}}"#;
+        file_log.response(keys, 3, Some(200), Some(listed.into()), None);
+        // Dropping the last handle drains the writer
+        drop(file_log);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let records = read_audit_log(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].correlation_id, id);
+        assert_eq!(records[0].payload, Some(body));
+        assert_eq!(records[0].status, Some(400));
+        assert_eq!(records[0].duration_ms, Some(12));
+        assert_eq!(records[0].response_json(), Some(json!({"success": false})));
+        assert_eq!(records[1].error.as_deref(), Some("timed out"));
+        assert_eq!(records[2].payload, Some(json!({"api_key": REDACTED})));
+        assert_eq!(
+            records[2].response_json().unwrap()["data"]["active_api_keys"],
+            json!([REDACTED, REDACTED])
+        );
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let before =
+            json!({"success": true, "data": [{"price": "1"}, {"price": "2"}], "code": null});
+        let after =
+            json!({"success": true, "data": [{"price": "1.5"}], "error": "x", "code": null});
+        assert_eq!(
+            diff_json(&before, &after),
+            vec![
+                JsonDiff {
+                    path: "data[0].price".into(),
+                    before: Some(json!("1")),
+                    after: Some(json!("1.5")),
+                },
+                JsonDiff {
+                    path: "data[1]".into(),
+                    before: Some(json!({"price": "2"})),
+                    after: None,
+                },
+                JsonDiff {
+                    path: "error".into(),
+                    before: None,
+                    after: Some(json!("x")),
+                },
+            ]
+        );
+        assert!(diff_json(&before, &before).is_empty());
+    }
+}
@@ -1 +1,3 @@
+pub mod audit;
+pub mod response;
 pub mod rest_client;
@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+
+/// Per-call settings for `RestClient::get_with` / `post_with`.
+///
+/// There is no per-call connect timeout: reqwest only sets it on the client,
+/// for its pooled connections. `timeout` also bounds the connect phase.
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    // Whole request, overrides `RestClientOptions::timeout`
+    pub timeout: Option<Duration>,
+}
+
+impl RequestOptions {
+    pub fn timeout(timeout: Duration) -> Self {
+        Self {
+            timeout: Some(timeout),
+        }
+    }
+}
+
+/// Rate-limit headers, `X-RateLimit-*` or the unprefixed `RateLimit-*` form.
+/// Fields the server did not send stay `None`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateLimit {
+    pub limit: Option<u64>,
+    pub remaining: Option<u64>,
+    // As sent: seconds until reset on most servers, an epoch time on some
+    pub reset: Option<u64>,
+    pub retry_after: Option<Duration>,
+}
+
+impl RateLimit {
+    /// `None` when no rate-limit header is present.
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let number = |names: &[&str]| {
+            names.iter().find_map(|name| {
+                headers
+                    .get(*name)?
+                    .to_str()
+                    .ok()?
+                    .split([',', ';'])
+                    .next()?
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+        };
+        let rate_limit = Self {
+            limit: number(&["x-ratelimit-limit", "ratelimit-limit"]),
+            remaining: number(&["x-ratelimit-remaining", "ratelimit-remaining"]),
+            reset: number(&["x-ratelimit-reset", "ratelimit-reset"]),
+            // Only the delay-seconds form, HTTP dates are ignored
+            retry_after: number(&["retry-after"]).map(Duration::from_secs),
+        };
+        (rate_limit != Self::default()).then_some(rate_limit)
+    }
+}
+
+/// What came back besides the body.
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    pub status: u16,
+    pub headers: HeaderMap,
+    // From sending the request to reading the whole body, rate limiter wait excluded
+    pub latency: Duration,
+    pub rate_limit: Option<RateLimit>,
+}
+
+/// A decoded response with its metadata.
+#[derive(Debug, Clone)]
+pub struct RestResponse<T> {
+    pub data: T,
+    pub meta: ResponseMeta,
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_headers() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(RateLimit::from_headers(&headers), None);
+
+        headers.insert("X-RateLimit-Limit", HeaderValue::from_static("120"));
+        headers.insert("ratelimit-remaining", HeaderValue::from_static("0"));
+        headers.insert("RateLimit-Reset", HeaderValue::from_static("30, 60"));
+        headers.insert("Retry-After", HeaderValue::from_static("2"));
+        assert_eq!(
+            RateLimit::from_headers(&headers),
+            Some(RateLimit {
+                limit: Some(120),
+                remaining: Some(0),
+                reset: Some(30),
+                retry_after: Some(Duration::from_secs(2)),
+            })
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "retry-after",
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(RateLimit::from_headers(&headers), None);
+    }
+}
@@ -1,21 +1,149 @@
-use reqwest::{Client, header::HeaderMap};
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use reqwest::{
+    Client, RequestBuilder,
+    header::{HeaderMap, HeaderValue},
+};
 use serde::{Serialize, de::DeserializeOwned};
-use tracing::debug;
+use serde_json::Value;
+use tokio::sync::Mutex;
+use tracing::{Instrument, Span, debug, field};
+use uuid::Uuid;
+
+use crate::{
+    common::errors::ExchangeError,
+    logging::{redacted_json, redacted_text},
+    metrics,
+    rest::{
+        audit::{AuditLog, AuditRecord, is_redacted},
+        response::{RateLimit, RequestOptions, ResponseMeta, RestResponse},
+    },
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct RestClientOptions {
+    pub timeout: Option<Duration>,
+    // Connection setup only, shared by every call of the client
+    pub connect_timeout: Option<Duration>,
+    // Requests are spaced evenly to stay under this rate
+    pub max_requests_per_second: Option<u32>,
+    // Records requests and raw responses, see `RestClient::set_audit_log`
+    pub audit_log: Option<AuditLog>,
+}
 
-use crate::common::errors::ExchangeError;
+pub const API_KEY_HEADER: &str = "PF-API-KEY";
 
+/// Cheap to clone, clones share the connection pool, the rate limiter, the
+/// API key and the audit log, so `set_api_key` on one clone applies to all of them.
+#[derive(Clone)]
 pub struct RestClient {
     client: Client,
     base_url: String,
+    min_interval: Option<Duration>,
+    next_slot: Arc<Mutex<Instant>>,
+    api_key: Arc<RwLock<Option<HeaderValue>>>,
+    audit_log: Arc<RwLock<Option<AuditLog>>>,
 }
 
 type Result<T> = std::result::Result<T, ExchangeError>;
 
 impl RestClient {
-    pub fn new(base_url: &'static str) -> Self {
+    pub fn new(base_url: &str) -> Self {
+        Self::with_options(base_url, RestClientOptions::default())
+    }
+
+    pub fn with_options(base_url: &str, options: RestClientOptions) -> Self {
+        let mut builder = Client::builder().connection_verbose(true);
+        if let Some(timeout) = options.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = options.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
         Self {
-            client: Client::builder().connection_verbose(true).build().unwrap(),
+            client: builder.build().unwrap(),
             base_url: base_url.to_string(),
+            min_interval: options
+                .max_requests_per_second
+                .filter(|rate| *rate > 0)
+                .map(|rate| Duration::from_secs(1) / rate),
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+            api_key: Arc::new(RwLock::new(None)),
+            audit_log: Arc::new(RwLock::new(options.audit_log)),
+        }
+    }
+
+    /// Clone sharing the connection pool and rate limiter but sending its own
+    /// API key, for another account on the same IP.
+    pub fn with_api_key(&self, api_key: Option<&str>) -> Result<Self> {
+        let client = Self {
+            api_key: Arc::new(RwLock::new(None)),
+            ..self.clone()
+        };
+        client.set_api_key(api_key)?;
+        Ok(client)
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Sent as `PF-API-KEY` on every request, replacing any key in per-call headers.
+    pub fn set_api_key(&self, api_key: Option<&str>) -> Result<()> {
+        let value = api_key.map(HeaderValue::from_str).transpose()?;
+        *self.api_key.write().unwrap() = value;
+        Ok(())
+    }
+
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key
+            .read()
+            .unwrap()
+            .as_ref()
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+    }
+
+    /// Starts or stops recording every request with its raw response, timing and
+    /// HTTP status. Signatures are redacted as configured in `audit_log`.
+    pub fn set_audit_log(&self, audit_log: Option<AuditLog>) {
+        *self.audit_log.write().unwrap() = audit_log;
+    }
+
+    pub fn audit_log(&self) -> Option<AuditLog> {
+        self.audit_log.read().unwrap().clone()
+    }
+
+    fn with_headers(&self, mut req: RequestBuilder, headers: Option<&HeaderMap>) -> RequestBuilder {
+        if let Some(h) = headers {
+            req = req.headers(h.clone());
+        }
+        if let Some(key) = self.api_key.read().unwrap().as_ref() {
+            let mut key_header = HeaderMap::new();
+            key_header.insert(API_KEY_HEADER, key.clone());
+            // `headers` replaces rather than appends
+            req = req.headers(key_header);
+        }
+        req
+    }
+
+    async fn throttle(&self) {
+        let Some(interval) = self.min_interval else {
+            return;
+        };
+        let wait = {
+            let mut next = self.next_slot.lock().await;
+            let now = Instant::now();
+            let slot = (*next).max(now);
+            *next = slot + interval;
+            slot - now
+        };
+        if !wait.is_zero() {
+            debug!("RestClient: rate limited, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
         }
     }
 
@@ -29,28 +157,44 @@ impl RestClient {
         T: DeserializeOwned,
         P: Serialize,
     {
+        self.get_with(endpoint, params, headers, &RequestOptions::default())
+            .await
+            .map(|response| response.data)
+    }
+
+    /// `get` with per-call options, returning the response metadata as well.
+    pub async fn get_with<T, P>(
+        &self,
+        endpoint: Option<&str>,
+        params: Option<&P>,
+        headers: Option<&HeaderMap>,
+        options: &RequestOptions,
+    ) -> Result<RestResponse<T>>
+    where
+        T: DeserializeOwned,
+        P: Serialize,
+    {
+        self.throttle().await;
         let endpoint = endpoint.unwrap_or("");
         let mut req = self.client.get(format!("{}{}", self.base_url, endpoint));
 
         if let Some(p) = params {
-            if cfg!(debug_assertions) {
-                match serde_json::to_string(p) {
-                    Ok(json) => debug!("GET {} params: {}", endpoint, json),
-                    Err(_) => debug!("GET {} params: <failed to serialize>", endpoint),
-                }
-            }
+            debug!("GET {} params: {}", endpoint, redacted_json(p));
             req = req.query(p);
         }
 
-        if let Some(h) = headers {
-            req = req.headers(h.clone());
-        }
-
-        let resp = req.send().await?;
-        debug!("GET {} response: {:?}", endpoint, &resp);
-        let resp_text = resp.text().await?;
-        let response: T = serde_json::from_str(&resp_text)?;
-        Ok(response)
+        let (meta, resp_text) = self
+            .execute("GET", endpoint, req, headers, options, || {
+                params.and_then(|p| serde_json::to_value(p).ok())
+            })
+            .await?;
+        debug!(
+            "GET {} response ({}): {}",
+            endpoint,
+            meta.status,
+            redacted_text(&resp_text)
+        );
+        decode(meta, &resp_text)
     }
 
     pub async fn post<T, P>(
@@ -63,28 +207,235 @@ impl RestClient {
         T: DeserializeOwned,
         P: Serialize,
     {
+        self.post_with(endpoint, body, headers, &RequestOptions::default())
+            .await
+            .map(|response| response.data)
+    }
+
+    /// `post` with per-call options, returning the response metadata as well.
+    pub async fn post_with<T, P>(
+        &self,
+        endpoint: Option<&str>,
+        body: Option<&P>,
+        headers: Option<&HeaderMap>,
+        options: &RequestOptions,
+    ) -> Result<RestResponse<T>>
+    where
+        T: DeserializeOwned,
+        P: Serialize,
+    {
+        self.throttle().await;
         let endpoint = endpoint.unwrap_or("");
         let mut req = self.client.post(format!("{}{}", self.base_url, endpoint));
 
         if let Some(b) = body {
-            if cfg!(debug_assertions) {
-                match serde_json::to_string(b) {
-                    Ok(json) => debug!("POST {} body: {}", endpoint, json),
-                    Err(_) => debug!("POST {} body: <failed to serialize>", endpoint),
-                }
+            debug!("POST {} body: {}", endpoint, redacted_json(b));
+            req = req.json(b);
+        }
+
+        let (meta, resp_text) = self
+            .execute("POST", endpoint, req, headers, options, || {
+                body.and_then(|b| serde_json::to_value(b).ok())
+            })
+            .await?;
+        debug!(
+            "POST {} response ({}): {}",
+            endpoint,
+            meta.status,
+            redacted_text(&resp_text)
+        );
+        decode(meta, &resp_text)
+    }
+
+    /// Sends a recorded request again, with this client's base URL and API key,
+    /// and returns it with the new response. Signed requests are only accepted
+    /// by the exchange within their expiry window, and cannot be resent at all
+    /// when their signature was redacted.
+    pub async fn replay(&self, record: &AuditRecord) -> Result<AuditRecord> {
+        if record.payload.as_ref().is_some_and(is_redacted) {
+            return Err(ExchangeError::Validation(
+                "the signature of this request was redacted".into(),
+            ));
+        }
+        self.throttle().await;
+        let url = format!("{}{}", self.base_url, record.endpoint);
+        let req = match (record.method.as_str(), &record.payload) {
+            ("GET", Some(params)) => self.client.get(url).query(params),
+            ("GET", None) => self.client.get(url),
+            ("POST", Some(body)) => self.client.post(url).json(body),
+            ("POST", None) => self.client.post(url),
+            (method, _) => {
+                return Err(ExchangeError::Validation(format!(
+                    "cannot replay {} requests",
+                    method
+                )));
             }
+        };
+        let timestamp = crate::common::utils::get_timestamp_ms();
+        let (meta, response) = self
+            .execute(
+                &record.method,
+                &record.endpoint,
+                req,
+                None,
+                &RequestOptions::default(),
+                || record.payload.clone(),
+            )
+            .await?;
+        Ok(AuditRecord {
+            correlation_id: Uuid::new_v4(),
+            timestamp,
+            status: Some(meta.status),
+            duration_ms: Some(meta.latency.as_millis() as u64),
+            response: Some(response),
+            error: None,
+            ..record.clone()
+        })
+    }
 
-            req = req.json(b);
+    // Sends the request and reads the body, recording both when auditing.
+    // `payload` is only serialized for the audit log.
+    async fn execute(
+        &self,
+        method: &str,
+        endpoint: &str,
+        req: RequestBuilder,
+        headers: Option<&HeaderMap>,
+        options: &RequestOptions,
+        payload: impl FnOnce() -> Option<Value>,
+    ) -> Result<(ResponseMeta, String)> {
+        let audit_log = self.audit_log().filter(|log| log.records(method));
+        let correlation_id = Uuid::new_v4();
+        // Also set on the enclosing operation span, if it has the field
+        Span::current().record("request_id", field::display(correlation_id));
+        let span = tracing::debug_span!(
+            "pacifica.rest",
+            method,
+            endpoint,
+            request_id = %correlation_id,
+            status = field::Empty,
+            latency_ms = field::Empty,
+        );
+        if let Some(log) = &audit_log {
+            log.request(correlation_id, method, endpoint, payload());
         }
 
-        if let Some(h) = headers {
-            req = req.headers(h.clone());
+        let mut req = self.with_headers(req, headers);
+        if let Some(timeout) = options.timeout {
+            req = req.timeout(timeout);
+        }
+        let started = Instant::now();
+        let mut response_headers = None;
+        let result = async {
+            let resp = req.send().await?;
+            response_headers = Some((resp.status().as_u16(), resp.headers().clone()));
+            resp.text().await
         }
+        .instrument(span.clone())
+        .await;
+        let latency = started.elapsed();
+        let status = response_headers.as_ref().map(|(status, _)| *status);
+        metrics::record_rest(method, endpoint, status, latency);
+        span.record("latency_ms", latency.as_millis() as u64);
+        if let Some(status) = status {
+            span.record("status", status);
+        }
+
+        if let Some(log) = &audit_log {
+            let duration_ms = latency.as_millis() as u64;
+            match &result {
+                Ok(text) => log.response(
+                    correlation_id,
+                    duration_ms,
+                    status,
+                    Some(text.clone()),
+                    None,
+                ),
+                Err(e) => log.response(
+                    correlation_id,
+                    duration_ms,
+                    status,
+                    None,
+                    Some(e.to_string()),
+                ),
+            }
+        }
+        let text = result?;
+        let (status, headers) = response_headers.unwrap_or_default();
+        let meta = ResponseMeta {
+            status,
+            rate_limit: RateLimit::from_headers(&headers),
+            headers,
+            latency,
+        };
+        Ok((meta, text))
+    }
+}
+
+// Exchange rejections are JSON with a 4xx status and decode into `T` as before.
+// Bodies that don't decode with a 4xx, and any 429 or 5xx, become `HttpStatus`;
+// other bodies that don't decode become `ResponseDecode`.
+fn decode<T: DeserializeOwned>(meta: ResponseMeta, text: &str) -> Result<RestResponse<T>> {
+    let status_error = |meta: ResponseMeta| ExchangeError::HttpStatus {
+        status: meta.status,
+        body: text.to_string(),
+        rate_limit: meta.rate_limit,
+    };
+    if meta.status == 429 || meta.status >= 500 {
+        return Err(status_error(meta));
+    }
+    match serde_json::from_str(text) {
+        Ok(data) => Ok(RestResponse { data, meta }),
+        Err(_) if meta.status >= 400 => Err(status_error(meta)),
+        Err(source) => Err(ExchangeError::ResponseDecode {
+            status: meta.status,
+            source,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::HeaderValue;
+
+    use super::*;
+    use crate::common::types::DefaultResponse;
+
+    fn meta(status: u16) -> ResponseMeta {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", HeaderValue::from_static("3"));
+        ResponseMeta {
+            status,
+            rate_limit: RateLimit::from_headers(&headers),
+            headers,
+            latency: Duration::from_millis(20),
+        }
+    }
+
+    #[test]
+    fn test_decode_by_status() {
+        let rejection =
+            r#"{"success":false,"data":null,"error":"Insufficient balance","code":400}"#;
+        let response = decode::<DefaultResponse<Value>>(meta(400), rejection).unwrap();
+        assert_eq!(response.data.code, Some(400));
+        assert_eq!(response.meta.status, 400);
+
+        let html = "<html><body>502 Bad Gateway</body></html>";
+        let err = decode::<DefaultResponse<Value>>(meta(502), html).unwrap_err();
+        assert_eq!(err.status(), Some(502));
+        assert!(matches!(&err, ExchangeError::HttpStatus { body, .. } if body == html));
+
+        let err = decode::<DefaultResponse<Value>>(meta(429), rejection).unwrap_err();
+        assert_eq!(
+            err.rate_limit().and_then(|r| r.retry_after),
+            Some(Duration::from_secs(3))
+        );
 
-        let resp = req.send().await?;
-        let resp_text = resp.text().await?;
-        debug!("POST {} response: {:?}", endpoint, &resp_text);
-        let response: T = serde_json::from_str(&resp_text)?;
-        Ok(response)
+        let err = decode::<DefaultResponse<Value>>(meta(404), "not found").unwrap_err();
+        assert_eq!(err.to_string(), "HTTP status 404: not found");
+        assert!(matches!(
+            decode::<DefaultResponse<Value>>(meta(200), "not json"),
+            Err(ExchangeError::ResponseDecode { status: 200, .. })
+        ));
     }
 }
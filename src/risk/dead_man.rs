@@ -0,0 +1,469 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    sync::{Mutex, broadcast},
+    task::JoinHandle,
+};
+use tracing::{debug, error, info};
+
+use crate::{
+    common::{errors::ExchangeError, types::DefaultResponse},
+    exchange::exchange_client::ExchangeClient,
+    models::exchange::{
+        payload::order::CancelAllOrdersPayload, response::order::CancelAllOrdersResponse,
+    },
+    ws::ws_client::ConnectionEvent,
+};
+
+#[derive(Debug, Clone)]
+pub struct DeadManSwitchConfig {
+    // Maximum time between two `heartbeat()` calls
+    pub timeout: Duration,
+    pub check_interval: Duration,
+    // Reconnects requested by the client, e.g. on an API key change, don't count
+    pub cancel_on_disconnect: bool,
+    pub exclude_reduce_only: bool,
+    pub max_retries: u32,
+    pub retry_delay: Duration,
+}
+
+impl Default for DeadManSwitchConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            check_interval: Duration::from_secs(1),
+            cancel_on_disconnect: true,
+            exclude_reduce_only: false,
+            max_retries: 5,
+            retry_delay: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerReason {
+    HeartbeatTimeout,
+    Disconnected,
+    Manual,
+}
+
+struct Shared {
+    primary: Arc<ExchangeClient>,
+    standby: Option<Arc<ExchangeClient>>,
+    config: DeadManSwitchConfig,
+    last_heartbeat: Mutex<Instant>,
+    triggered: AtomicBool,
+}
+
+/// Cancels all resting orders over REST when the application stops sending
+/// heartbeats or the primary client's WebSocket connection drops. Dropping the
+/// switch stops the watcher.
+pub struct DeadManSwitch {
+    shared: Arc<Shared>,
+    handle: JoinHandle<()>,
+}
+
+impl DeadManSwitch {
+    pub fn start(
+        primary: Arc<ExchangeClient>,
+        standby: Option<Arc<ExchangeClient>>,
+        config: DeadManSwitchConfig,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            primary,
+            standby,
+            config,
+            last_heartbeat: Mutex::new(Instant::now()),
+            triggered: AtomicBool::new(false),
+        });
+        let shared_clone = shared.clone();
+        let handle = tokio::spawn(async move {
+            shared_clone.watch().await;
+        });
+        Self { shared, handle }
+    }
+
+    pub async fn heartbeat(&self) {
+        *self.shared.last_heartbeat.lock().await = Instant::now();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.shared.triggered.load(Ordering::SeqCst)
+    }
+
+    /// Re-enables the switch after it fired and resets the heartbeat timer.
+    pub async fn rearm(&self) {
+        self.heartbeat().await;
+        self.shared.triggered.store(false, Ordering::SeqCst);
+    }
+
+    pub async fn trigger(&self) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        self.shared.triggered.store(true, Ordering::SeqCst);
+        self.shared.cancel_all(TriggerReason::Manual).await
+    }
+
+    pub fn stop(self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for DeadManSwitch {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+impl Shared {
+    async fn watch(&self) {
+        let ws = self
+            .primary
+            .info_client
+            .web_socket_client
+            .as_ref()
+            .filter(|_| self.config.cancel_on_disconnect);
+        // Events rather than `connection_status`, which can coalesce a drop and
+        // its reconnect into no change at all
+        let mut events = ws.map(|ws| ws.events());
+        let mut connected = ws.is_some_and(|ws| *ws.connection_status().borrow());
+        let mut restart_pending = false;
+        let mut interval = tokio::time::interval(self.config.check_interval);
+
+        loop {
+            let reason = tokio::select! {
+                _ = interval.tick() => {
+                    let elapsed = self.last_heartbeat.lock().await.elapsed();
+                    if elapsed > self.config.timeout {
+                        Some(TriggerReason::HeartbeatTimeout)
+                    } else {
+                        None
+                    }
+                }
+                event = async {
+                    match events.as_mut() {
+                        Some(rx) => rx.recv().await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    match event {
+                        Ok(ConnectionEvent::Connected) => {
+                            connected = true;
+                            restart_pending = false;
+                            None
+                        }
+                        Ok(ConnectionEvent::Restarting) => {
+                            restart_pending = true;
+                            None
+                        }
+                        // Still counted as connected, so a failed restart is a drop
+                        Ok(ConnectionEvent::Disconnected { .. }) if restart_pending => {
+                            debug!("DeadManSwitch: ignoring intentional reconnect");
+                            restart_pending = false;
+                            None
+                        }
+                        Ok(ConnectionEvent::Disconnected { .. }) => {
+                            std::mem::replace(&mut connected, false)
+                                .then_some(TriggerReason::Disconnected)
+                        }
+                        Ok(_) => None,
+                        Err(broadcast::error::RecvError::Lagged(missed)) => {
+                            // Fall back to the current state
+                            debug!("DeadManSwitch: missed {} connection events", missed);
+                            let ws = ws.expect("events come from the WebSocket client");
+                            let (now, restarting) = (*ws.connection_status().borrow(), ws.is_restarting());
+                            let dropped = connected && !now && !restarting;
+                            // A restart in progress counts as connected, as after `Restarting`
+                            connected = now || (connected && restarting);
+                            restart_pending = false;
+                            dropped.then_some(TriggerReason::Disconnected)
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            debug!("DeadManSwitch: connection events closed");
+                            events = None;
+                            None
+                        }
+                    }
+                }
+            };
+
+            if let Some(reason) = reason
+                && !self.triggered.swap(true, Ordering::SeqCst)
+                && let Err(e) = self.cancel_all(reason).await
+            {
+                error!("DeadManSwitch: failed to cancel orders: {:?}", e);
+            }
+        }
+    }
+
+    async fn cancel_all(
+        &self,
+        reason: TriggerReason,
+    ) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        error!("DeadManSwitch triggered: {:?}", reason);
+        match self.cancel_with_retries(&self.primary).await {
+            Ok(response) => Ok(response),
+            Err(e) => match &self.standby {
+                Some(standby) => {
+                    error!(
+                        "DeadManSwitch: primary cancel failed ({:?}), retrying from standby",
+                        e
+                    );
+                    self.cancel_with_retries(standby).await
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    async fn cancel_with_retries(
+        &self,
+        client: &ExchangeClient,
+    ) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        let mut last_err = ExchangeError::Custom("cancel_all_orders was not attempted".into());
+        for attempt in 0..=self.config.max_retries {
+            let payload = CancelAllOrdersPayload {
+                all_symbols: true,
+                exclude_reduce_only: self.config.exclude_reduce_only,
+                symbol: None,
+            };
+            match client.cancel_all_orders(payload, None).await {
+                Ok(response) if response.success != Some(false) => {
+                    info!(
+                        "DeadManSwitch: cancel_all_orders succeeded on attempt {}: {:?}",
+                        attempt + 1,
+                        response.data
+                    );
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    last_err = ExchangeError::Custom(format!(
+                        "cancel_all_orders rejected: {:?}",
+                        response.error
+                    ));
+                }
+                Err(e) => last_err = e,
+            }
+            debug!(
+                "DeadManSwitch: attempt {} failed: {:?}",
+                attempt + 1,
+                last_err
+            );
+            if attempt < self.config.max_retries {
+                tokio::time::sleep(self.config.retry_delay).await;
+            }
+        }
+        Err(last_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use solana_sdk::signature::{Keypair, Signer};
+    use tokio::{net::TcpListener, sync::watch};
+
+    use super::*;
+    use crate::{
+        info::info_client::InfoClient,
+        rest::rest_client::RestClientOptions,
+        test_util::{HttpResponse, spawn_http_server},
+        ws::ws_client::WebSocketOptions,
+    };
+
+    // Answers `/info` with no markets and counts `/orders/cancel_all` calls
+    async fn spawn_rest_server() -> (String, Arc<AtomicUsize>) {
+        let cancels = Arc::new(AtomicUsize::new(0));
+        let counter = cancels.clone();
+        let url = spawn_http_server(move |request| {
+            if request.method == "POST" && request.path().ends_with("/orders/cancel_all") {
+                counter.fetch_add(1, Ordering::SeqCst);
+                HttpResponse::ok(json!({"cancelled_count": 1}))
+            } else {
+                HttpResponse::ok(json!([]))
+            }
+        })
+        .await;
+        (url, cancels)
+    }
+
+    // Accepts WebSocket connections and closes all of them whenever `drop_tx` is bumped
+    async fn spawn_ws_server() -> (String, watch::Sender<u32>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (drop_tx, drop_rx) = watch::channel(0u32);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let mut drop_rx = drop_rx.clone();
+                drop_rx.borrow_and_update();
+                tokio::spawn(async move {
+                    let Ok(ws) = tokio_tungstenite::accept_async(stream).await else {
+                        return;
+                    };
+                    let (_write, mut read) = ws.split();
+                    loop {
+                        tokio::select! {
+                            _ = drop_rx.changed() => return,
+                            msg = read.next() => if !matches!(msg, Some(Ok(_))) { return },
+                        }
+                    }
+                });
+            }
+        });
+        (url, drop_tx)
+    }
+
+    async fn test_client(
+        ws: bool,
+    ) -> (
+        Arc<ExchangeClient>,
+        Arc<AtomicUsize>,
+        Option<watch::Sender<u32>>,
+    ) {
+        let (rest_url, cancels) = spawn_rest_server().await;
+        let (ws_url, drop_tx) = if ws {
+            let (url, tx) = spawn_ws_server().await;
+            (Some(url), Some(tx))
+        } else {
+            (None, None)
+        };
+        let ws_options = WebSocketOptions {
+            reconnect_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let info = InfoClient::with_options(
+            &rest_url,
+            ws_url.as_deref(),
+            Some("old".into()),
+            RestClientOptions::default(),
+            ws_options,
+        )
+        .await
+        .unwrap();
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client = ExchangeClient::from_info_client(info, keypair, pubkey, None).unwrap();
+        (Arc::new(client), cancels, drop_tx)
+    }
+
+    fn fast_config() -> DeadManSwitchConfig {
+        DeadManSwitchConfig {
+            timeout: Duration::from_millis(100),
+            check_interval: Duration::from_millis(10),
+            max_retries: 0,
+            ..Default::default()
+        }
+    }
+
+    async fn wait_for(cancels: &AtomicUsize, count: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while cancels.load(Ordering::SeqCst) < count {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("cancel_all_orders was not sent");
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_timeout_cancels_once() {
+        let (client, cancels, _) = test_client(false).await;
+        let switch = DeadManSwitch::start(client, None, fast_config());
+
+        // Heartbeats keep it quiet past the timeout
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(40)).await;
+            switch.heartbeat().await;
+        }
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+
+        wait_for(&cancels, 1).await;
+        assert!(switch.is_triggered());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(cancels.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_cancels() {
+        let (client, cancels, drop_tx) = test_client(true).await;
+        let config = DeadManSwitchConfig {
+            timeout: Duration::from_secs(60),
+            ..fast_config()
+        };
+        let switch = DeadManSwitch::start(client, None, config);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+
+        drop_tx.unwrap().send_modify(|n| *n += 1);
+        wait_for(&cancels, 1).await;
+        assert!(switch.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_api_key_change_does_not_cancel() {
+        let (client, cancels, drop_tx) = test_client(true).await;
+        let config = DeadManSwitchConfig {
+            timeout: Duration::from_secs(60),
+            ..fast_config()
+        };
+        let switch = DeadManSwitch::start(client.clone(), None, config);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // Rotation reconnects the WebSocket on purpose
+        client.set_api_key(Some("new".into())).await.unwrap();
+        client.set_api_key(Some("newer".into())).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+        assert!(!switch.is_triggered());
+
+        // Still armed for a real disconnect
+        drop_tx.unwrap().send_modify(|n| *n += 1);
+        wait_for(&cancels, 1).await;
+        assert!(switch.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_drop_recovered_before_watcher_runs_cancels() {
+        let (client, cancels, drop_tx) = test_client(true).await;
+        let config = DeadManSwitchConfig {
+            timeout: Duration::from_secs(60),
+            ..fast_config()
+        };
+        let switch = DeadManSwitch::start(client.clone(), None, config);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let mut events = client
+            .info_client
+            .web_socket_client
+            .as_ref()
+            .unwrap()
+            .events();
+
+        // The watcher blocks on the heartbeat lock until the client is back
+        let heartbeat = switch.shared.last_heartbeat.lock().await;
+        drop_tx.unwrap().send_modify(|n| *n += 1);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !matches!(events.recv().await, Ok(ConnectionEvent::Connected)) {}
+        })
+        .await
+        .unwrap();
+        drop(heartbeat);
+
+        wait_for(&cancels, 1).await;
+        assert!(switch.is_triggered());
+    }
+
+    #[tokio::test]
+    async fn test_drop_stops_watcher() {
+        let (client, cancels, _) = test_client(false).await;
+        drop(DeadManSwitch::start(client, None, fast_config()));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(cancels.load(Ordering::SeqCst), 0);
+    }
+}
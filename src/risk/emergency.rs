@@ -0,0 +1,331 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info};
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{DefaultResponse, FinalRequest, OperationFinalHeaders, PrebuiltRequest},
+        utils::get_timestamp_ms,
+    },
+    exchange::exchange_client::ExchangeClient,
+    models::exchange::{
+        payload::order::CancelAllOrdersPayload, response::order::CancelAllOrdersResponse,
+    },
+    rest::rest_client::RestClient,
+};
+
+// Exchange default when the header carries no expiry_window
+const DEFAULT_EXPIRY_WINDOW_MS: u64 = 30_000;
+
+/// A signed `cancel_all_orders` request. `symbol` is None for the all-symbols packet.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmergencyCancelPacket {
+    pub symbol: Option<String>,
+    pub endpoint: String,
+    pub body: FinalRequest<CancelAllOrdersPayload>,
+    // ms since epoch, the exchange rejects the packet from this point on
+    pub expires_at: u64,
+}
+
+impl EmergencyCancelPacket {
+    pub fn from_prebuilt(prebuilt: PrebuiltRequest<FinalRequest<CancelAllOrdersPayload>>) -> Self {
+        let (timestamp, expiry_window) = match &prebuilt.body.headers {
+            OperationFinalHeaders::Default(h) => (h.timestamp, h.expiry_window),
+            OperationFinalHeaders::SubAccountCreate(h) => (h.timestamp, h.expiry_window),
+        };
+        Self {
+            symbol: prebuilt.body.payload.symbol.clone(),
+            endpoint: prebuilt.endpoint,
+            expires_at: timestamp + expiry_window.map_or(DEFAULT_EXPIRY_WINDOW_MS, u64::from),
+            body: prebuilt.body,
+        }
+    }
+
+    pub fn is_valid_at(&self, now_ms: u64) -> bool {
+        now_ms < self.expires_at
+    }
+}
+
+/// Pre-signed cancel requests for one account, as persisted on disk.
+///
+/// The file holds no key material, only signatures that can cancel orders until
+/// they expire, so a watchdog process can fire them without access to the signer.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmergencyCancelSet {
+    pub base_url: String,
+    pub packets: Vec<EmergencyCancelPacket>,
+}
+
+impl EmergencyCancelSet {
+    /// Signs one all-symbols packet plus one packet per entry of `symbols`.
+    pub async fn sign(
+        client: &ExchangeClient,
+        symbols: &[String],
+        exclude_reduce_only: bool,
+        expiry_window: u32,
+    ) -> Result<Self, ExchangeError> {
+        let targets = std::iter::once(None).chain(symbols.iter().cloned().map(Some));
+        let mut packets = Vec::new();
+        for symbol in targets {
+            let payload = CancelAllOrdersPayload {
+                all_symbols: symbol.is_none(),
+                exclude_reduce_only,
+                symbol,
+            };
+            let prebuilt = client
+                .build_cancel_all_orders(payload, Some(expiry_window))
+                .await?;
+            packets.push(EmergencyCancelPacket::from_prebuilt(prebuilt));
+        }
+        Ok(Self {
+            base_url: client.base_url.clone(),
+            packets,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ExchangeError> {
+        let content = std::fs::read(path)?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Writes to a temporary file first and renames it, so a concurrent reader
+    /// never sees a partially written set.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ExchangeError> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(self)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Earliest expiry among the packets, None for an empty set.
+    pub fn expires_at(&self) -> Option<u64> {
+        self.packets.iter().map(|p| p.expires_at).min()
+    }
+
+    pub fn packet(&self, symbol: Option<&str>) -> Option<&EmergencyCancelPacket> {
+        self.packets.iter().find(|p| p.symbol.as_deref() == symbol)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmergencyCancelConfig {
+    pub path: PathBuf,
+    // Per-symbol packets signed in addition to the all-symbols one
+    pub symbols: Vec<String>,
+    pub exclude_reduce_only: bool,
+    // Milliseconds, as in the signature header
+    pub expiry_window: u32,
+    // How long before expiry the set is re-signed
+    pub refresh_margin: Duration,
+    pub retry_delay: Duration,
+}
+
+impl EmergencyCancelConfig {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            symbols: Vec::new(),
+            exclude_reduce_only: false,
+            expiry_window: 600_000,
+            refresh_margin: Duration::from_secs(120),
+            retry_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Keeps the packets on disk fresh from the process that owns the signing key.
+/// Stops refreshing when dropped.
+pub struct EmergencyCancelRefresher {
+    handle: JoinHandle<()>,
+}
+
+impl EmergencyCancelRefresher {
+    pub fn start(client: Arc<ExchangeClient>, config: EmergencyCancelConfig) -> Self {
+        let handle = tokio::spawn(async move {
+            loop {
+                let delay = match refresh(&client, &config).await {
+                    Ok(expires_at) => {
+                        let remaining = expires_at.saturating_sub(get_timestamp_ms());
+                        Duration::from_millis(remaining)
+                            .saturating_sub(config.refresh_margin)
+                            .max(config.retry_delay)
+                    }
+                    Err(e) => {
+                        error!("EmergencyCancelRefresher: refresh failed: {:?}", e);
+                        config.retry_delay
+                    }
+                };
+                tokio::time::sleep(delay).await;
+            }
+        });
+        Self { handle }
+    }
+
+    pub fn stop(self) {
+        self.handle.abort();
+    }
+}
+
+impl Drop for EmergencyCancelRefresher {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn refresh(
+    client: &ExchangeClient,
+    config: &EmergencyCancelConfig,
+) -> Result<u64, ExchangeError> {
+    let set = EmergencyCancelSet::sign(
+        client,
+        &config.symbols,
+        config.exclude_reduce_only,
+        config.expiry_window,
+    )
+    .await?;
+    set.save(&config.path)?;
+    let expires_at = set.expires_at().unwrap_or_default();
+    debug!(
+        "EmergencyCancelRefresher: saved {} packet(s) to {:?}, valid until {}",
+        set.packets.len(),
+        config.path,
+        expires_at
+    );
+    Ok(expires_at)
+}
+
+/// Fires packets written by `EmergencyCancelRefresher`. Holds no key.
+pub struct EmergencyCancelWatchdog {
+    path: PathBuf,
+}
+
+impl EmergencyCancelWatchdog {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Time left until the current packets expire. Zero when they already have.
+    pub fn remaining(&self) -> Result<Duration, ExchangeError> {
+        let set = EmergencyCancelSet::load(&self.path)?;
+        let expires_at = set.expires_at().unwrap_or_default();
+        Ok(Duration::from_millis(
+            expires_at.saturating_sub(get_timestamp_ms()),
+        ))
+    }
+
+    /// Sends the all-symbols cancel, or the one for `symbol`.
+    pub async fn fire(
+        &self,
+        symbol: Option<&str>,
+    ) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        let set = EmergencyCancelSet::load(&self.path)?;
+        let packet = set.packet(symbol).ok_or_else(|| {
+            ExchangeError::NotInitialized(format!("no emergency cancel packet for {:?}", symbol))
+        })?;
+        if !packet.is_valid_at(get_timestamp_ms()) {
+            return Err(ExchangeError::Validation(format!(
+                "emergency cancel packet for {:?} expired at {}",
+                symbol, packet.expires_at
+            )));
+        }
+
+        error!("EmergencyCancelWatchdog: firing cancel for {:?}", symbol);
+        let response = RestClient::new(&set.base_url)
+            .post::<DefaultResponse<CancelAllOrdersResponse>, _>(
+                Some(&packet.endpoint),
+                Some(&packet.body),
+                None,
+            )
+            .await?;
+        info!("EmergencyCancelWatchdog: response {:?}", response);
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::signature::{Keypair, Signer};
+
+    use super::*;
+    use crate::{common::utils::prepare_final_request, exchange::operations::Operation};
+
+    async fn packet(symbol: Option<&str>, expiry_window: u32) -> EmergencyCancelPacket {
+        let keypair = Keypair::new();
+        let body = prepare_final_request(
+            &Operation::CancelAllOrders.name().unwrap(),
+            CancelAllOrdersPayload {
+                all_symbols: symbol.is_none(),
+                exclude_reduce_only: false,
+                symbol: symbol.map(str::to_string),
+            },
+            Some(expiry_window),
+            &keypair,
+            &keypair.pubkey(),
+            &None,
+        )
+        .await
+        .unwrap();
+        EmergencyCancelPacket::from_prebuilt(PrebuiltRequest {
+            endpoint: Operation::CancelAllOrders.endpoint(),
+            body,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_set_roundtrip_and_expiry() {
+        let set = EmergencyCancelSet {
+            base_url: "http://localhost".to_string(),
+            packets: vec![packet(None, 60_000).await, packet(Some("BTC"), 1_000).await],
+        };
+        let path = std::env::temp_dir().join(format!("emergency-{}.json", uuid::Uuid::new_v4()));
+        set.save(&path).unwrap();
+        let loaded = EmergencyCancelSet::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let all = loaded.packet(None).unwrap();
+        let btc = loaded.packet(Some("BTC")).unwrap();
+        assert_eq!(loaded.expires_at(), Some(btc.expires_at));
+        assert!(all.expires_at > btc.expires_at);
+        assert!(btc.is_valid_at(btc.expires_at - 1));
+        assert!(!btc.is_valid_at(btc.expires_at));
+        assert_eq!(
+            serde_json::to_value(&all.body).unwrap(),
+            serde_json::to_value(&set.packets[0].body).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refresher_stops_on_drop() {
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client =
+            ExchangeClient::from_markets("http://localhost", Vec::new(), keypair, pubkey, None)
+                .unwrap();
+        let path = std::env::temp_dir().join(format!("emergency-{}.json", uuid::Uuid::new_v4()));
+        let mut config = EmergencyCancelConfig::new(&path);
+        config.expiry_window = 1_000;
+        config.retry_delay = Duration::from_millis(10);
+
+        let refresher = EmergencyCancelRefresher::start(Arc::new(client), config);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !path.exists() {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        drop(refresher);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        std::fs::remove_file(&path).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,1316 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use rust_decimal::Decimal;
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{debug, error};
+use uuid::Uuid;
+
+use crate::{
+    common::{
+        errors::{ExchangeError, RiskViolation},
+        types::{DefaultResponse, DefaultWebSocketMsg, OrderSide, OrderStatus},
+        utils::ensure_success,
+    },
+    exchange::{exchange_client::ExchangeClient, trading_api::TradingApi},
+    models::{
+        exchange::{
+            payload::{
+                batch_order::BatchOrderActionPayload,
+                order::{
+                    CancelAllOrdersPayload, CancelOrderPayload, CancelStopOrderPayload,
+                    CreateMarketOrderPayload, CreateOrderPayload, CreateStopOrderPayload,
+                },
+            },
+            response::{
+                batch_order::BatchOrderResponse,
+                order::{
+                    CancelAllOrdersResponse, CancelOrderResponse, CancelStopOrderResponse,
+                    CreateMarketOrderResponse, CreateOrderResponse, CreateStopOrderResponse,
+                },
+            },
+        },
+        info::response::{account::OpenedOrderModel, market::PriceModel},
+        ws::responses::{
+            OrderUpdateModel, OrderUpdatesResponse, PositionsResponse as WsPositionsResponse,
+            PricesResponse as WsPricesResponse,
+        },
+    },
+    ws::ws_client::Subscription,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ReferencePrice {
+    #[default]
+    Mark,
+    Mid,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    pub max_notional_per_order: Option<Decimal>,
+    // Absolute position size, in base units
+    pub max_position_per_symbol: Option<Decimal>,
+    pub max_position_overrides: HashMap<String, Decimal>,
+    pub max_open_orders: Option<u32>,
+    pub max_orders_per_second: Option<u32>,
+    pub allowed_symbols: Option<HashSet<String>>,
+    // Fraction of the reference price, e.g. 0.05 for 5%
+    pub price_band: Option<Decimal>,
+    pub reference_price: ReferencePrice,
+}
+
+impl RiskLimits {
+    fn max_position(&self, symbol: &str) -> Option<Decimal> {
+        self.max_position_overrides
+            .get(symbol)
+            .copied()
+            .or(self.max_position_per_symbol)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct OpenOrder {
+    symbol: String,
+    client_order_id: Option<Uuid>,
+    side: OrderSide,
+    amount: Decimal,
+    reduce_only: bool,
+    filled: Decimal,
+}
+
+// An order that passed the checks and has no answer yet
+#[derive(Debug, Clone)]
+struct PendingOrder {
+    symbol: String,
+    side: OrderSide,
+    amount: Decimal,
+    reduce_only: bool,
+    rests: bool,
+}
+
+#[derive(Debug)]
+struct PendingSend {
+    sent_at: Instant,
+    orders: Vec<PendingOrder>,
+}
+
+#[derive(Debug, Default)]
+struct GuardState {
+    marks: HashMap<String, Decimal>,
+    mids: HashMap<String, Decimal>,
+    // Signed: positive long, negative short
+    positions: HashMap<String, Decimal>,
+    // Resting orders by order id
+    open_orders: HashMap<u64, OpenOrder>,
+    // Sends between their check and their answer, by reservation id
+    pending: HashMap<u64, PendingSend>,
+    next_reservation: u64,
+    sent: VecDeque<Instant>,
+}
+
+struct OrderIntent<'a> {
+    symbol: &'a str,
+    side: &'a OrderSide,
+    amount: Decimal,
+    price: Option<Decimal>,
+    reduce_only: bool,
+    rests: bool,
+}
+
+impl GuardState {
+    fn order_opened(
+        &mut self,
+        order_id: u64,
+        symbol: &str,
+        client_order_id: Option<Uuid>,
+        side: &OrderSide,
+        amount: Decimal,
+        reduce_only: bool,
+    ) {
+        self.open_orders.insert(
+            order_id,
+            OpenOrder {
+                symbol: symbol.to_string(),
+                client_order_id,
+                side: side.clone(),
+                amount,
+                reduce_only,
+                filled: Decimal::ZERO,
+            },
+        );
+    }
+
+    fn order_closed(&mut self, order_id: Option<u64>, client_order_id: Option<Uuid>) {
+        self.open_orders.retain(|id, order| {
+            Some(*id) != order_id
+                && (client_order_id.is_none() || order.client_order_id != client_order_id)
+        });
+    }
+
+    fn orders_cancelled(&mut self, payload: &CancelAllOrdersPayload) {
+        self.open_orders.retain(|_, order| {
+            let symbol_matches =
+                payload.all_symbols || payload.symbol.as_deref() == Some(order.symbol.as_str());
+            !symbol_matches || (payload.exclude_reduce_only && order.reduce_only)
+        });
+    }
+
+    // Market orders fill on arrival; assumed filled in full until the next
+    // position snapshot.
+    fn apply_fill(&mut self, symbol: &str, side: &OrderSide, amount: Decimal, reduce_only: bool) {
+        let current = self.positions.get(symbol).copied().unwrap_or_default();
+        let mut next = current + signed(side, amount);
+        if reduce_only
+            && (next.is_sign_negative() != current.is_sign_negative() || current.is_zero())
+        {
+            next = Decimal::ZERO;
+        }
+        self.positions.insert(symbol.to_string(), next);
+    }
+
+    // Orders the guard did not place are only counted again after `sync`
+    fn apply_order_update(&mut self, update: &OrderUpdateModel) {
+        let Some(order) = self.open_orders.get_mut(&update.order_id) else {
+            return;
+        };
+        let delta = update.filled_amount - order.filled;
+        order.filled = update.filled_amount;
+        let (symbol, side, reduce_only) =
+            (order.symbol.clone(), order.side.clone(), order.reduce_only);
+        if delta > Decimal::ZERO {
+            self.apply_fill(&symbol, &side, delta, reduce_only);
+        }
+        if matches!(
+            update.order_status,
+            OrderStatus::Filled | OrderStatus::Cancelled | OrderStatus::Rejected
+        ) {
+            self.open_orders.remove(&update.order_id);
+        }
+    }
+
+    fn check(
+        &mut self,
+        limits: &RiskLimits,
+        killed: bool,
+        intents: &[OrderIntent<'_>],
+        now: Instant,
+    ) -> Result<(), ExchangeError> {
+        if killed {
+            return Err(RiskViolation::KillSwitchActive.into());
+        }
+        if let Some(limit) = limits.max_orders_per_second {
+            while self
+                .sent
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= Duration::from_secs(1))
+            {
+                self.sent.pop_front();
+            }
+            if self.sent.len() + intents.len() > limit as usize {
+                return Err(RiskViolation::RateLimitExceeded { limit }.into());
+            }
+        }
+
+        if let Some(limit) = limits.max_open_orders {
+            let pending = self.pending_orders().filter(|o| o.rests).count();
+            let open = (self.open_orders.len() + pending) as u32;
+            let resting = intents.iter().filter(|i| i.rests).count() as u32;
+            if resting > 0 && open + resting > limit {
+                return Err(RiskViolation::MaxOpenOrdersExceeded { open, limit }.into());
+            }
+        }
+
+        for (i, intent) in intents.iter().enumerate() {
+            self.check_intent(limits, intent, &intents[..i])?;
+        }
+        debug!("RiskGuard: {} order(s) passed checks", intents.len());
+        Ok(())
+    }
+
+    // Checks `intents` and holds their rate budget and exposure until `release`,
+    // so orders checked before an answer arrives count against the limits
+    fn reserve(
+        &mut self,
+        limits: &RiskLimits,
+        killed: bool,
+        intents: &[OrderIntent<'_>],
+        now: Instant,
+    ) -> Result<u64, ExchangeError> {
+        self.check(limits, killed, intents, now)?;
+        self.record_sent(limits, intents.len(), now);
+        let id = self.next_reservation;
+        self.next_reservation += 1;
+        let orders = intents
+            .iter()
+            .map(|i| PendingOrder {
+                symbol: i.symbol.to_string(),
+                side: i.side.clone(),
+                amount: i.amount,
+                reduce_only: i.reduce_only,
+                rests: i.rests,
+            })
+            .collect();
+        self.pending.insert(
+            id,
+            PendingSend {
+                sent_at: now,
+                orders,
+            },
+        );
+        Ok(id)
+    }
+
+    // Drops a reservation's exposure; a send that failed also gives back its
+    // rate budget
+    fn release(&mut self, id: u64, failed: bool) {
+        let Some(send) = self.pending.remove(&id) else {
+            return;
+        };
+        if failed {
+            for _ in &send.orders {
+                if let Some(i) = self.sent.iter().rposition(|t| *t == send.sent_at) {
+                    self.sent.remove(i);
+                }
+            }
+        }
+    }
+
+    // Counts orders against `max_orders_per_second`
+    fn record_sent(&mut self, limits: &RiskLimits, count: usize, now: Instant) {
+        if limits.max_orders_per_second.is_some() {
+            self.sent.extend(std::iter::repeat_n(now, count));
+        }
+    }
+
+    fn pending_orders(&self) -> impl Iterator<Item = &PendingOrder> {
+        self.pending.values().flat_map(|send| &send.orders)
+    }
+
+    // Position on `symbol` once every resting and pending order on `side` fills;
+    // reduce-only orders can't add to it
+    fn exposure(&self, symbol: &str, side: &OrderSide) -> Decimal {
+        let resting: Decimal = self
+            .open_orders
+            .values()
+            .filter(|o| o.symbol == symbol && o.side == *side && !o.reduce_only)
+            .map(|o| o.amount - o.filled)
+            .sum();
+        let pending: Decimal = self
+            .pending_orders()
+            .filter(|o| o.symbol == symbol && o.side == *side && !o.reduce_only)
+            .map(|o| o.amount)
+            .sum();
+        self.positions.get(symbol).copied().unwrap_or_default() + signed(side, resting + pending)
+    }
+
+    // `earlier` are the intents before this one in the same request
+    fn check_intent(
+        &self,
+        limits: &RiskLimits,
+        intent: &OrderIntent<'_>,
+        earlier: &[OrderIntent<'_>],
+    ) -> Result<(), RiskViolation> {
+        let symbol = intent.symbol;
+        if let Some(allowed) = &limits.allowed_symbols
+            && !allowed.contains(symbol)
+        {
+            return Err(RiskViolation::SymbolNotAllowed(symbol.to_string()));
+        }
+
+        // A zero price from the feed is no reference at all
+        let reference = match limits.reference_price {
+            ReferencePrice::Mark => self.marks.get(symbol),
+            ReferencePrice::Mid => self.mids.get(symbol),
+        }
+        .copied()
+        .filter(|p| *p > Decimal::ZERO);
+
+        if let (Some(band), Some(price)) = (limits.price_band, intent.price) {
+            let reference = reference
+                .ok_or_else(|| RiskViolation::MissingReferencePrice(symbol.to_string()))?;
+            let deviation = ((price - reference) / reference).abs();
+            if deviation > band {
+                return Err(RiskViolation::PriceOutOfBand {
+                    symbol: symbol.to_string(),
+                    price,
+                    reference,
+                    deviation,
+                });
+            }
+        }
+
+        if let Some(limit) = limits.max_notional_per_order {
+            let price = intent
+                .price
+                .or(reference)
+                .ok_or_else(|| RiskViolation::MissingReferencePrice(symbol.to_string()))?;
+            let notional = price * intent.amount;
+            if notional > limit {
+                return Err(RiskViolation::MaxNotionalExceeded {
+                    symbol: symbol.to_string(),
+                    notional,
+                    limit,
+                });
+            }
+        }
+
+        if intent.reduce_only {
+            return Ok(());
+        }
+        let batched: Decimal = earlier
+            .iter()
+            .filter(|i| i.symbol == symbol && i.side == intent.side && !i.reduce_only)
+            .map(|i| i.amount)
+            .sum();
+        let current = self.exposure(symbol, intent.side) + signed(intent.side, batched);
+        let next = current + signed(intent.side, intent.amount);
+        if let Some(limit) = limits.max_position(symbol)
+            && next.abs() > limit
+            && next.abs() > current.abs()
+        {
+            return Err(RiskViolation::MaxPositionExceeded {
+                symbol: symbol.to_string(),
+                position: next,
+                limit,
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Checks orders against `RiskLimits` before handing them to the wrapped client,
+/// an `ExchangeClient` by default or any other `TradingApi` such as
+/// `PaperExchange`. The guard implements `TradingApi` too, so strategy code
+/// takes a guarded client unchanged; account management and position TP/SL
+/// pass through unchecked.
+///
+/// Open orders and positions change with orders and cancels sent through the
+/// guard, fills reported by `apply_order_updates` and snapshots from
+/// `update_ws_positions` or `sync`. Feed it `order_updates` and `positions`
+/// (see `track_order_updates`, `track_positions`) or call `sync` regularly;
+/// orders placed or filled elsewhere are not seen otherwise.
+///
+/// Orders count against the limits from their check on, so concurrent callers
+/// can't all pass the same limit while their sends are in flight.
+pub struct RiskGuard<T = ExchangeClient> {
+    client: T,
+    limits: RiskLimits,
+    state: Mutex<GuardState>,
+    killed: AtomicBool,
+    // Notified when the last pending send got its answer
+    settled: Notify,
+}
+
+// Exposure and rate budget held by a send until its answer. Dropped without
+// `finish`, e.g. when the send is cancelled, it frees the exposure but keeps the
+// rate budget, as the orders may have reached the exchange.
+struct Reservation<'a> {
+    state: &'a Mutex<GuardState>,
+    settled: &'a Notify,
+    id: Option<u64>,
+}
+
+impl<'a> Reservation<'a> {
+    // `failed` gives the rate budget back too
+    fn finish(mut self, failed: bool) -> MutexGuard<'a, GuardState> {
+        self.end(failed)
+    }
+
+    fn end(&mut self, failed: bool) -> MutexGuard<'a, GuardState> {
+        let mut state = lock(self.state);
+        if let Some(id) = self.id.take() {
+            state.release(id, failed);
+            if state.pending.is_empty() {
+                self.settled.notify_waiters();
+            }
+        }
+        state
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        if self.id.is_some() {
+            drop(self.end(false));
+        }
+    }
+}
+
+fn lock(state: &Mutex<GuardState>) -> MutexGuard<'_, GuardState> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<T: TradingApi> RiskGuard<T> {
+    pub fn new(client: T, limits: RiskLimits) -> Self {
+        Self {
+            client,
+            limits,
+            state: Mutex::new(GuardState::default()),
+            killed: AtomicBool::new(false),
+            settled: Notify::new(),
+        }
+    }
+
+    pub fn client(&self) -> &T {
+        &self.client
+    }
+
+    pub fn limits(&self) -> &RiskLimits {
+        &self.limits
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    /// Blocks all new orders and cancels everything resting on the account, once
+    /// orders already past the checks got their answer.
+    pub async fn kill(&self) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        // Under the state lock, so no check passes after it
+        {
+            let _state = self.state();
+            self.killed.store(true, Ordering::SeqCst);
+        }
+        error!("RiskGuard kill switch triggered");
+        // Cancelling earlier would miss orders still on their way
+        loop {
+            let settled = self.settled.notified();
+            if self.state().pending.is_empty() {
+                break;
+            }
+            settled.await;
+        }
+        self.cancel_all_orders(
+            CancelAllOrdersPayload {
+                all_symbols: true,
+                exclude_reduce_only: false,
+                symbol: None,
+            },
+            None,
+        )
+        .await
+    }
+
+    pub fn reset_kill_switch(&self) {
+        self.killed.store(false, Ordering::SeqCst);
+    }
+
+    pub async fn update_prices(&self, prices: &[PriceModel]) {
+        let mut state = self.state();
+        for p in prices {
+            state.marks.insert(p.symbol.clone(), p.mark);
+            state.mids.insert(p.symbol.clone(), p.mid);
+        }
+    }
+
+    pub async fn update_ws_prices(&self, prices: &WsPricesResponse) {
+        let mut state = self.state();
+        for p in prices {
+            state.marks.insert(p.symbol.clone(), p.mark);
+            state.mids.insert(p.symbol.clone(), p.mid);
+        }
+    }
+
+    /// `amount` is signed: positive for long, negative for short.
+    pub async fn set_position(&self, symbol: &str, amount: Decimal) {
+        self.state().positions.insert(symbol.to_string(), amount);
+    }
+
+    /// Replaces every position with a `positions` snapshot.
+    pub async fn update_ws_positions(&self, positions: &WsPositionsResponse) {
+        self.state().positions = positions
+            .iter()
+            .map(|p| (p.symbol.clone(), signed(&p.side, p.amount)))
+            .collect();
+    }
+
+    /// Replaces the tracked open orders.
+    pub async fn set_open_orders(&self, orders: &[OpenedOrderModel]) {
+        let mut state = self.state();
+        state.open_orders.clear();
+        for o in orders {
+            state.order_opened(
+                o.order_id,
+                &o.symbol,
+                o.client_order_id,
+                &o.side,
+                o.initial_amount,
+                o.reduce_only,
+            );
+            if let Some(order) = state.open_orders.get_mut(&o.order_id) {
+                order.filled = o.filled_amount;
+            }
+        }
+    }
+
+    pub async fn open_orders(&self) -> u32 {
+        self.state().open_orders.len() as u32
+    }
+
+    pub async fn position(&self, symbol: &str) -> Decimal {
+        self.state()
+            .positions
+            .get(symbol)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Applies fills to positions and drops filled, cancelled and rejected orders.
+    pub async fn apply_order_updates(&self, updates: &OrderUpdatesResponse) {
+        let mut state = self.state();
+        for update in updates {
+            state.apply_order_update(update);
+        }
+    }
+
+    pub async fn order(
+        &self,
+        sign_payload: CreateOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateOrderResponse>, ExchangeError> {
+        let reservation = self.reserve(&[OrderIntent {
+            symbol: &sign_payload.symbol,
+            side: &sign_payload.side,
+            amount: sign_payload.amount,
+            price: Some(sign_payload.price),
+            reduce_only: sign_payload.reduce_only,
+            rests: true,
+        }])?;
+        let (symbol, side, client_order_id, amount, reduce_only) = (
+            sign_payload.symbol.clone(),
+            sign_payload.side.clone(),
+            sign_payload.client_order_id,
+            sign_payload.amount,
+            sign_payload.reduce_only,
+        );
+        let response = self.client.order(sign_payload, expiry_window).await;
+        let mut state = reservation.finish(response.is_err());
+        let response = response?;
+        if let Some(data) = &response.data {
+            state.order_opened(
+                data.order_id,
+                &symbol,
+                client_order_id,
+                &side,
+                amount,
+                reduce_only,
+            );
+        }
+        Ok(response)
+    }
+
+    pub async fn market_order(
+        &self,
+        sign_payload: CreateMarketOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateMarketOrderResponse>, ExchangeError> {
+        let reservation = self.reserve(&[OrderIntent {
+            symbol: &sign_payload.symbol,
+            side: &sign_payload.side,
+            amount: sign_payload.amount,
+            price: None,
+            reduce_only: sign_payload.reduce_only,
+            rests: false,
+        }])?;
+        let (symbol, side, amount, reduce_only) = (
+            sign_payload.symbol.clone(),
+            sign_payload.side.clone(),
+            sign_payload.amount,
+            sign_payload.reduce_only,
+        );
+        let response = self.client.market_order(sign_payload, expiry_window).await;
+        let mut state = reservation.finish(response.is_err());
+        let response = response?;
+        if response.data.is_some() {
+            state.apply_fill(&symbol, &side, amount, reduce_only);
+        }
+        Ok(response)
+    }
+
+    pub async fn stop_order(
+        &self,
+        sign_payload: CreateStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CreateStopOrderResponse>, ExchangeError> {
+        let stop = &sign_payload.stop_order;
+        let reservation = self.reserve(&[OrderIntent {
+            symbol: &sign_payload.symbol,
+            side: &sign_payload.side,
+            amount: stop.amount,
+            price: Some(stop.limit_price.unwrap_or(stop.stop_price)),
+            reduce_only: sign_payload.reduce_only,
+            rests: true,
+        }])?;
+        let (symbol, side, client_order_id, amount, reduce_only) = (
+            sign_payload.symbol.clone(),
+            sign_payload.side.clone(),
+            stop.client_order_id,
+            stop.amount,
+            sign_payload.reduce_only,
+        );
+        let response = self.client.stop_order(sign_payload, expiry_window).await;
+        let mut state = reservation.finish(response.is_err());
+        let response = response?;
+        if let Some(data) = &response.data {
+            state.order_opened(
+                data.order_id,
+                &symbol,
+                client_order_id,
+                &side,
+                amount,
+                reduce_only,
+            );
+        }
+        Ok(response)
+    }
+
+    pub async fn batch_order(
+        &self,
+        orders: Vec<BatchOrderActionPayload>,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<BatchOrderResponse>, ExchangeError> {
+        let intents: Vec<OrderIntent> = orders
+            .iter()
+            .filter_map(|action| match action {
+                BatchOrderActionPayload::CreateOrder(o) => Some(OrderIntent {
+                    symbol: &o.symbol,
+                    side: &o.side,
+                    amount: o.amount,
+                    price: Some(o.price),
+                    reduce_only: o.reduce_only,
+                    rests: true,
+                }),
+                BatchOrderActionPayload::CreateMarketOrder(o) => Some(OrderIntent {
+                    symbol: &o.symbol,
+                    side: &o.side,
+                    amount: o.amount,
+                    price: None,
+                    reduce_only: o.reduce_only,
+                    rests: false,
+                }),
+                BatchOrderActionPayload::CancelOrder(_) => None,
+            })
+            .collect();
+        // Cancels add no exposure, so a cancel-only batch passes the kill switch
+        let reservation = if intents.is_empty() {
+            self.unreserved()
+        } else {
+            self.reserve(&intents)?
+        };
+        let response = self.client.batch_order(orders.clone(), expiry_window).await;
+        let mut state = reservation.finish(response.is_err());
+        let response = response?;
+        // Results are aligned with the actions
+        if let Some(data) = &response.data {
+            for (action, result) in orders.iter().zip(&data.results) {
+                if !result.success {
+                    continue;
+                }
+                match action {
+                    BatchOrderActionPayload::CreateOrder(o) => {
+                        if let Some(order_id) = result.order_id {
+                            state.order_opened(
+                                order_id,
+                                &o.symbol,
+                                o.client_order_id,
+                                &o.side,
+                                o.amount,
+                                o.reduce_only,
+                            );
+                        }
+                    }
+                    BatchOrderActionPayload::CreateMarketOrder(o) => {
+                        state.apply_fill(&o.symbol, &o.side, o.amount, o.reduce_only)
+                    }
+                    BatchOrderActionPayload::CancelOrder(c) => {
+                        state.order_closed(c.order_id, c.client_order_id)
+                    }
+                }
+            }
+        }
+        Ok(response)
+    }
+
+    pub async fn cancel_order(
+        &self,
+        sign_payload: CancelOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelOrderResponse>, ExchangeError> {
+        let (order_id, client_order_id) = (sign_payload.order_id, sign_payload.client_order_id);
+        let response = self
+            .client
+            .cancel_order(sign_payload, expiry_window)
+            .await?;
+        if ensure_success("cancel", &response).is_ok() {
+            self.state().order_closed(order_id, client_order_id);
+        }
+        Ok(response)
+    }
+
+    pub async fn cancel_stop_order(
+        &self,
+        sign_payload: CancelStopOrderPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelStopOrderResponse>, ExchangeError> {
+        let (order_id, client_order_id) = (sign_payload.order_id, sign_payload.client_order_id);
+        let response = self
+            .client
+            .cancel_stop_order(sign_payload, expiry_window)
+            .await?;
+        if ensure_success("cancel", &response).is_ok() {
+            self.state().order_closed(order_id, client_order_id);
+        }
+        Ok(response)
+    }
+
+    /// Not checked against limits, so it works while the kill switch is active.
+    pub async fn cancel_all_orders(
+        &self,
+        sign_payload: CancelAllOrdersPayload,
+        expiry_window: Option<u32>,
+    ) -> Result<DefaultResponse<CancelAllOrdersResponse>, ExchangeError> {
+        let response = self
+            .client
+            .cancel_all_orders(sign_payload.clone(), expiry_window)
+            .await?;
+        if ensure_success("cancel_all_orders", &response).is_ok() {
+            self.state().orders_cancelled(&sign_payload);
+        }
+        Ok(response)
+    }
+
+    fn state(&self) -> MutexGuard<'_, GuardState> {
+        lock(&self.state)
+    }
+
+    // The kill switch is read under the state lock, see `kill`
+    fn reserve(&self, intents: &[OrderIntent<'_>]) -> Result<Reservation<'_>, ExchangeError> {
+        let id = self
+            .state()
+            .reserve(&self.limits, self.is_killed(), intents, Instant::now())?;
+        Ok(Reservation {
+            state: &self.state,
+            settled: &self.settled,
+            id: Some(id),
+        })
+    }
+
+    fn unreserved(&self) -> Reservation<'_> {
+        Reservation {
+            state: &self.state,
+            settled: &self.settled,
+            id: None,
+        }
+    }
+}
+
+impl RiskGuard<ExchangeClient> {
+    /// Refreshes positions, open orders and prices over REST.
+    pub async fn sync(&self) -> Result<(), ExchangeError> {
+        let info = &self.client.info_client;
+        let account = self.client.main_pubkey();
+        let positions = info.positions(account).await?.data.unwrap_or_default();
+        let orders = info.opened_orders(account).await?.data.unwrap_or_default();
+        let prices = info.prices().await?.data.unwrap_or_default();
+
+        self.update_prices(&prices).await;
+        self.set_open_orders(&orders).await;
+        self.state().positions = positions
+            .iter()
+            .map(|p| (p.symbol.clone(), signed(&p.side, p.amount)))
+            .collect();
+        Ok(())
+    }
+}
+
+/// Keeps `guard` open orders and positions in sync with an `order_updates` subscription.
+pub fn track_order_updates<T: TradingApi + 'static>(
+    guard: Arc<RiskGuard<T>>,
+    mut subscription: Subscription,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(value) = subscription.recv().await {
+            match serde_json::from_value::<DefaultWebSocketMsg<OrderUpdatesResponse>>(value) {
+                Ok(msg) => guard.apply_order_updates(&msg.data).await,
+                Err(e) => error!(
+                    "track_order_updates: failed to deserialize updates: {:?}",
+                    e
+                ),
+            }
+        }
+        debug!("track_order_updates: subscription closed");
+    })
+}
+
+/// Keeps `guard` positions in sync with a `positions` subscription.
+pub fn track_positions<T: TradingApi + 'static>(
+    guard: Arc<RiskGuard<T>>,
+    mut subscription: Subscription,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(value) = subscription.recv().await {
+            match serde_json::from_value::<DefaultWebSocketMsg<WsPositionsResponse>>(value) {
+                Ok(msg) => guard.update_ws_positions(&msg.data).await,
+                Err(e) => error!("track_positions: failed to deserialize positions: {:?}", e),
+            }
+        }
+        debug!("track_positions: subscription closed");
+    })
+}
+
+fn signed(side: &OrderSide, amount: Decimal) -> Decimal {
+    match side {
+        OrderSide::Bid => amount,
+        OrderSide::Ask => -amount,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use serde_json::json;
+    use solana_sdk::signer::{Signer, keypair::Keypair};
+
+    use super::*;
+    use crate::{
+        common::types::Tif,
+        test_util::{HttpResponse, spawn_http_server},
+    };
+
+    fn intent<'a>(
+        symbol: &'a str,
+        side: &'a OrderSide,
+        amount: i64,
+        price: Option<i64>,
+    ) -> OrderIntent<'a> {
+        OrderIntent {
+            symbol,
+            side,
+            amount: Decimal::from(amount),
+            price: price.map(Decimal::from),
+            reduce_only: false,
+            rests: price.is_some(),
+        }
+    }
+
+    fn violation(result: Result<(), ExchangeError>) -> RiskViolation {
+        match result {
+            Err(ExchangeError::RiskViolation(v)) => v,
+            other => panic!("expected a risk violation, got {:?}", other),
+        }
+    }
+
+    fn state_with_mark(symbol: &str, mark: i64) -> GuardState {
+        let mut state = GuardState::default();
+        state.marks.insert(symbol.to_string(), Decimal::from(mark));
+        state.mids.insert(symbol.to_string(), Decimal::from(mark));
+        state
+    }
+
+    fn update(order_id: u64, filled: i64, status: &str) -> OrderUpdateModel {
+        serde_json::from_value(json!({
+            "i": order_id, "I": null, "s": "BTC", "d": "bid", "ip": "100", "p": "100",
+            "a": "3", "f": filled.to_string(), "os": status, "ot": "limit", "sp": null,
+            "si": null, "r": false, "u": "11111111111111111111111111111111", "oe": "make",
+            "ut": 0, "ct": 0
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_violations() {
+        let bid = OrderSide::Bid;
+        let now = Instant::now();
+        let mut state = state_with_mark("BTC", 100);
+
+        assert!(matches!(
+            violation(state.check(
+                &RiskLimits::default(),
+                true,
+                &[intent("BTC", &bid, 1, Some(100))],
+                now
+            )),
+            RiskViolation::KillSwitchActive
+        ));
+
+        let limits = RiskLimits {
+            allowed_symbols: Some(HashSet::from(["BTC".to_string()])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("ETH", &bid, 1, Some(100))], now)),
+            RiskViolation::SymbolNotAllowed(s) if s == "ETH"
+        ));
+
+        let limits = RiskLimits {
+            max_notional_per_order: Some(Decimal::from(250)),
+            ..Default::default()
+        };
+        assert!(
+            state
+                .check(&limits, false, &[intent("BTC", &bid, 2, None)], now)
+                .is_ok()
+        );
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("BTC", &bid, 3, None)], now)),
+            RiskViolation::MaxNotionalExceeded { .. }
+        ));
+
+        let limits = RiskLimits {
+            max_position_per_symbol: Some(Decimal::from(5)),
+            ..Default::default()
+        };
+        state.positions.insert("BTC".to_string(), Decimal::from(4));
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("BTC", &bid, 2, None)], now)),
+            RiskViolation::MaxPositionExceeded { .. }
+        ));
+        // Reducing is always allowed
+        assert!(
+            state
+                .check(
+                    &limits,
+                    false,
+                    &[intent("BTC", &OrderSide::Ask, 6, None)],
+                    now
+                )
+                .is_ok()
+        );
+
+        let limits = RiskLimits {
+            max_open_orders: Some(1),
+            ..Default::default()
+        };
+        state.order_opened(1, "BTC", None, &bid, Decimal::ONE, false);
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("BTC", &bid, 1, Some(100))], now)),
+            RiskViolation::MaxOpenOrdersExceeded { open: 1, limit: 1 }
+        ));
+
+        // Position 4 plus the resting bid of 1 leaves no room for more bids
+        let limits = RiskLimits {
+            max_position_per_symbol: Some(Decimal::from(5)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("BTC", &bid, 1, None)], now)),
+            RiskViolation::MaxPositionExceeded { .. }
+        ));
+        assert!(
+            state
+                .check(
+                    &limits,
+                    false,
+                    &[intent("BTC", &OrderSide::Ask, 1, None)],
+                    now
+                )
+                .is_ok()
+        );
+
+        let limits = RiskLimits {
+            max_orders_per_second: Some(2),
+            ..Default::default()
+        };
+        let mut rate_state = GuardState::default();
+        let orders = [intent("BTC", &bid, 1, None), intent("BTC", &bid, 1, None)];
+        assert!(rate_state.check(&limits, false, &orders, now).is_ok());
+        // Checking alone doesn't use up the budget
+        assert!(rate_state.check(&limits, false, &orders, now).is_ok());
+        let id = rate_state.reserve(&limits, false, &orders, now).unwrap();
+        assert!(matches!(
+            violation(rate_state.check(&limits, false, &orders[..1], now)),
+            RiskViolation::RateLimitExceeded { limit: 2 }
+        ));
+        // A failed send gives its budget back, an answered one keeps it used
+        rate_state.release(id, true);
+        let id = rate_state.reserve(&limits, false, &orders, now).unwrap();
+        rate_state.release(id, false);
+        assert!(rate_state.pending.is_empty());
+        assert!(matches!(
+            violation(rate_state.check(&limits, false, &orders[..1], now)),
+            RiskViolation::RateLimitExceeded { limit: 2 }
+        ));
+        assert!(
+            rate_state
+                .check(&limits, false, &orders[..1], now + Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_price_band_and_reference() {
+        let bid = OrderSide::Bid;
+        let now = Instant::now();
+        let limits = RiskLimits {
+            price_band: Some(Decimal::new(5, 2)),
+            ..Default::default()
+        };
+        let mut state = state_with_mark("BTC", 100);
+        assert!(
+            state
+                .check(&limits, false, &[intent("BTC", &bid, 1, Some(104))], now)
+                .is_ok()
+        );
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("BTC", &bid, 1, Some(110))], now)),
+            RiskViolation::PriceOutOfBand { .. }
+        ));
+        assert!(matches!(
+            violation(state.check(&limits, false, &[intent("ETH", &bid, 1, Some(100))], now)),
+            RiskViolation::MissingReferencePrice(_)
+        ));
+
+        // A zero mark must not divide by zero
+        let mut zero = state_with_mark("BTC", 0);
+        assert!(matches!(
+            violation(zero.check(&limits, false, &[intent("BTC", &bid, 1, Some(100))], now)),
+            RiskViolation::MissingReferencePrice(_)
+        ));
+        let limits = RiskLimits {
+            max_notional_per_order: Some(Decimal::from(1_000)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            violation(zero.check(&limits, false, &[intent("BTC", &bid, 1, None)], now)),
+            RiskViolation::MissingReferencePrice(_)
+        ));
+    }
+
+    #[test]
+    fn test_state_follows_cancels_and_fills() {
+        let bid = OrderSide::Bid;
+        let mut state = GuardState::default();
+        let client_id = Uuid::new_v4();
+        state.order_opened(1, "BTC", None, &bid, Decimal::from(3), false);
+        state.order_opened(2, "BTC", Some(client_id), &bid, Decimal::from(3), false);
+        state.order_opened(3, "ETH", None, &bid, Decimal::from(3), true);
+        state.order_opened(4, "ETH", None, &bid, Decimal::from(3), false);
+
+        state.order_closed(None, Some(client_id));
+        assert!(!state.open_orders.contains_key(&2));
+        state.orders_cancelled(&CancelAllOrdersPayload {
+            all_symbols: false,
+            exclude_reduce_only: true,
+            symbol: Some("ETH".to_string()),
+        });
+        let mut open: Vec<u64> = state.open_orders.keys().copied().collect();
+        open.sort();
+        assert_eq!(open, [1, 3]);
+
+        // Partial then full fill of order 1: position grows by each delta
+        state.apply_order_update(&update(1, 1, "partially_filled"));
+        assert_eq!(state.positions["BTC"], Decimal::ONE);
+        assert!(state.open_orders.contains_key(&1));
+        state.apply_order_update(&update(1, 3, "filled"));
+        assert_eq!(state.positions["BTC"], Decimal::from(3));
+        assert!(!state.open_orders.contains_key(&1));
+        // Unknown orders are ignored
+        state.apply_order_update(&update(9, 3, "filled"));
+        assert_eq!(state.positions["BTC"], Decimal::from(3));
+
+        // Market orders count in full; reduce-only stops at flat
+        state.apply_fill("BTC", &OrderSide::Ask, Decimal::from(5), true);
+        assert_eq!(state.positions["BTC"], Decimal::ZERO);
+        state.apply_fill("BTC", &OrderSide::Ask, Decimal::from(2), false);
+        assert_eq!(state.positions["BTC"], Decimal::from(-2));
+    }
+
+    #[tokio::test]
+    async fn test_kill_switch_passes_cancels_and_failed_sends_keep_budget() {
+        // The first request fails with a 503, every later one succeeds
+        let requests = Arc::new(AtomicUsize::new(0));
+        let seen = requests.clone();
+        let url = spawn_http_server(move |request| {
+            if seen.fetch_add(1, Ordering::SeqCst) == 0 {
+                return HttpResponse::status(503);
+            }
+            if request.path().ends_with("/orders/batch") {
+                HttpResponse::ok(json!({"results": [{"success": true, "order_id": 1}]}))
+            } else {
+                HttpResponse::ok(json!({"order_id": 1}))
+            }
+        })
+        .await;
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client = ExchangeClient::from_markets(&url, Vec::new(), keypair, pubkey, None).unwrap();
+        let limits = RiskLimits {
+            max_orders_per_second: Some(1),
+            ..Default::default()
+        };
+        let guard = RiskGuard::new(client, limits);
+        let order = || CreateOrderPayload {
+            symbol: "BTC".to_string(),
+            price: Decimal::from(100),
+            amount: Decimal::ONE,
+            side: OrderSide::Bid,
+            tif: Tif::GTC,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        };
+
+        assert!(guard.order(order(), None).await.is_err());
+        // The failed send left the one order per second available
+        assert!(guard.order(order(), None).await.is_ok());
+        assert!(matches!(
+            violation(guard.order(order(), None).await.map(|_| ())),
+            RiskViolation::RateLimitExceeded { limit: 1 }
+        ));
+
+        guard.killed.store(true, Ordering::SeqCst);
+        let cancel = BatchOrderActionPayload::CancelOrder(CancelOrderPayload {
+            symbol: "BTC".to_string(),
+            order_id: Some(1),
+            client_order_id: None,
+        });
+        assert!(guard.batch_order(vec![cancel], None).await.is_ok());
+        assert!(matches!(
+            violation(
+                guard
+                    .batch_order(vec![BatchOrderActionPayload::CreateOrder(order())], None)
+                    .await
+                    .map(|_| ())
+            ),
+            RiskViolation::KillSwitchActive
+        ));
+    }
+
+    // Guard over a mock exchange that answers every order with a new order id,
+    // and the request paths it saw
+    async fn guard_over_mock(
+        limits: RiskLimits,
+    ) -> (RiskGuard, Arc<std::sync::Mutex<Vec<String>>>) {
+        let paths = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = paths.clone();
+        let url = spawn_http_server(move |request| {
+            let mut paths = seen.lock().unwrap();
+            paths.push(request.path().to_string());
+            HttpResponse::ok(json!({"order_id": paths.len(), "cancelled_count": 0}))
+        })
+        .await;
+        let keypair = Keypair::new();
+        let pubkey = keypair.pubkey();
+        let client = ExchangeClient::from_markets(&url, Vec::new(), keypair, pubkey, None).unwrap();
+        (RiskGuard::new(client, limits), paths)
+    }
+
+    fn bid() -> CreateOrderPayload {
+        CreateOrderPayload {
+            symbol: "BTC".to_string(),
+            price: Decimal::from(100),
+            amount: Decimal::ONE,
+            side: OrderSide::Bid,
+            tif: Tif::GTC,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        }
+    }
+
+    fn market_bid() -> CreateMarketOrderPayload {
+        CreateMarketOrderPayload {
+            symbol: "BTC".to_string(),
+            amount: Decimal::ONE,
+            side: OrderSide::Bid,
+            slippage_percent: Decimal::ONE,
+            reduce_only: false,
+            client_order_id: None,
+            take_profit: None,
+            stop_loss: None,
+        }
+    }
+
+    // Exactly one of three concurrent results is a violation
+    fn one_rejected<R>(results: [Result<R, ExchangeError>; 3]) -> RiskViolation {
+        let mut violations: Vec<RiskViolation> = results
+            .into_iter()
+            .filter_map(|r| r.err())
+            .map(|e| violation(Err(e)))
+            .collect();
+        assert_eq!(violations.len(), 1);
+        violations.remove(0)
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_orders_cannot_exceed_limits() {
+        let (guard, paths) = guard_over_mock(RiskLimits {
+            max_orders_per_second: Some(2),
+            ..Default::default()
+        })
+        .await;
+        let (a, b, c) = tokio::join!(
+            guard.order(bid(), None),
+            guard.order(bid(), None),
+            guard.order(bid(), None)
+        );
+        assert!(matches!(
+            one_rejected([a, b, c]),
+            RiskViolation::RateLimitExceeded { limit: 2 }
+        ));
+        assert_eq!(paths.lock().unwrap().len(), 2);
+
+        let (guard, _) = guard_over_mock(RiskLimits {
+            max_open_orders: Some(2),
+            ..Default::default()
+        })
+        .await;
+        let (a, b, c) = tokio::join!(
+            guard.order(bid(), None),
+            guard.order(bid(), None),
+            guard.order(bid(), None)
+        );
+        assert!(matches!(
+            one_rejected([a, b, c]),
+            RiskViolation::MaxOpenOrdersExceeded { open: 2, limit: 2 }
+        ));
+        assert_eq!(guard.open_orders().await, 2);
+
+        let (guard, _) = guard_over_mock(RiskLimits {
+            max_position_per_symbol: Some(Decimal::from(2)),
+            ..Default::default()
+        })
+        .await;
+        let (a, b, c) = tokio::join!(
+            guard.market_order(market_bid(), None),
+            guard.market_order(market_bid(), None),
+            guard.market_order(market_bid(), None)
+        );
+        assert!(matches!(
+            one_rejected([a, b, c]),
+            RiskViolation::MaxPositionExceeded { .. }
+        ));
+        assert_eq!(guard.position("BTC").await, Decimal::from(2));
+    }
+
+    #[tokio::test]
+    async fn test_kill_cancels_after_orders_in_flight() {
+        let (guard, paths) = guard_over_mock(RiskLimits::default()).await;
+        // The order passes the checks on its first poll, before the kill
+        let (order, killed) = tokio::join!(guard.order(bid(), None), guard.kill());
+        assert!(order.is_ok());
+        assert!(killed.is_ok());
+        let paths = paths.lock().unwrap();
+        assert!(paths[0].ends_with("/orders/create"));
+        assert!(paths[1].ends_with("/orders/cancel_all"));
+        assert!(matches!(
+            violation(guard.state().check(
+                guard.limits(),
+                guard.is_killed(),
+                &[intent("BTC", &OrderSide::Bid, 1, None)],
+                Instant::now()
+            )),
+            RiskViolation::KillSwitchActive
+        ));
+    }
+}
@@ -0,0 +1,4 @@
+pub mod dead_man;
+pub mod emergency;
+pub mod guard;
+pub mod position;
@@ -0,0 +1,561 @@
+use std::collections::HashMap;
+
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use tokio::{sync::RwLock, task::JoinHandle};
+use tracing::{debug, error};
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{DefaultWebSocketMsg, OrderSide},
+    },
+    info::info_client::InfoClient,
+    models::{
+        info::response::{
+            account::{AccountResponse, AccountSettingsModel, PositionModel},
+            market::{MarketModel, PriceModel},
+        },
+        ws::responses::{
+            AccountInfoResponse, PositionModel as WsPositionModel,
+            PositionsResponse as WsPositionsResponse, PricesResponse as WsPricesResponse,
+        },
+    },
+    ws::ws_client::Subscription,
+};
+
+// REST and WS positions carry the same data under different field names
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionSnapshot {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub amount: Decimal,
+    pub entry_price: Decimal,
+    pub margin: Option<Decimal>,
+    pub funding: Decimal,
+    pub isolated: bool,
+}
+
+impl From<&PositionModel> for PositionSnapshot {
+    fn from(p: &PositionModel) -> Self {
+        Self {
+            symbol: p.symbol.clone(),
+            side: p.side.clone(),
+            amount: p.amount,
+            entry_price: p.entry_price,
+            margin: p.margin,
+            funding: p.funding,
+            isolated: p.isolated,
+        }
+    }
+}
+
+impl From<&WsPositionModel> for PositionSnapshot {
+    fn from(p: &WsPositionModel) -> Self {
+        Self {
+            symbol: p.symbol.clone(),
+            side: p.side.clone(),
+            amount: p.amount,
+            entry_price: p.entry_price,
+            margin: p.margin,
+            funding: p.funding,
+            isolated: p.isolated,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionRisk {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub amount: Decimal,
+    pub entry_price: Decimal,
+    pub mark_price: Decimal,
+    pub leverage: Decimal,
+    pub isolated: bool,
+    pub notional: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub initial_margin: Decimal,
+    pub maintenance_margin: Decimal,
+    pub roe: Decimal,
+    pub margin_ratio: Option<Decimal>,
+    pub liquidation_price: Option<Decimal>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AccountExposure {
+    pub balance: Decimal,
+    pub equity: Decimal,
+    pub long_notional: Decimal,
+    pub short_notional: Decimal,
+    pub gross_notional: Decimal,
+    pub net_notional: Decimal,
+    pub unrealized_pnl: Decimal,
+    pub initial_margin: Decimal,
+    pub maintenance_margin: Decimal,
+    pub margin_ratio: Option<Decimal>,
+    pub effective_leverage: Option<Decimal>,
+    // Sorted by symbol
+    pub positions: Vec<PositionRisk>,
+    // Open positions left out of the totals because their symbol has no mark
+    pub unpriced: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RiskCalculator {
+    market_cache: HashMap<String, MarketModel>,
+    leverages: HashMap<String, Decimal>,
+    marks: HashMap<String, Decimal>,
+    positions: HashMap<String, PositionSnapshot>,
+    balance: Decimal,
+}
+
+impl RiskCalculator {
+    pub fn new(market_cache: HashMap<String, MarketModel>) -> Self {
+        Self {
+            market_cache,
+            leverages: HashMap::new(),
+            marks: HashMap::new(),
+            positions: HashMap::new(),
+            balance: Decimal::ZERO,
+        }
+    }
+
+    /// Fetches positions, settings, balance and marks for `account` over REST.
+    pub async fn from_info_client(
+        info_client: &InfoClient,
+        account: Pubkey,
+    ) -> Result<Self, ExchangeError> {
+        let mut calculator = Self::new(info_client.market_cache.clone());
+
+        if let Some(settings) = info_client.account_settings(account).await?.data {
+            calculator.update_settings(&settings);
+        }
+        if let Some(positions) = info_client.positions(account).await?.data {
+            calculator.set_positions(positions.iter().map(PositionSnapshot::from));
+        }
+        if let Some(account_data) = info_client.account(account).await?.data {
+            calculator.update_account(&account_data);
+        }
+        if let Some(prices) = info_client.prices().await?.data {
+            calculator.update_prices(&prices);
+        }
+
+        Ok(calculator)
+    }
+
+    pub fn update_settings(&mut self, settings: &[AccountSettingsModel]) {
+        for s in settings {
+            self.leverages.insert(s.symbol.clone(), s.leverage);
+        }
+    }
+
+    pub fn update_leverage(&mut self, symbol: &str, leverage: Decimal) {
+        self.leverages.insert(symbol.to_string(), leverage);
+    }
+
+    /// Replaces every tracked position, dropping symbols absent from `positions`.
+    pub fn set_positions<I: IntoIterator<Item = PositionSnapshot>>(&mut self, positions: I) {
+        self.positions = positions
+            .into_iter()
+            .filter(|p| !p.amount.is_zero())
+            .map(|p| (p.symbol.clone(), p))
+            .collect();
+    }
+
+    pub fn update_account(&mut self, account: &AccountResponse) {
+        self.balance = account.balance;
+    }
+
+    pub fn update_account_info(&mut self, account: &AccountInfoResponse) {
+        self.balance = account.balance;
+    }
+
+    pub fn update_prices(&mut self, prices: &[PriceModel]) {
+        for p in prices {
+            self.marks.insert(p.symbol.clone(), p.mark);
+        }
+    }
+
+    pub fn update_ws_prices(&mut self, prices: &WsPricesResponse) {
+        for p in prices {
+            self.marks.insert(p.symbol.clone(), p.mark);
+        }
+    }
+
+    pub fn set_mark(&mut self, symbol: &str, mark: Decimal) {
+        self.marks.insert(symbol.to_string(), mark);
+    }
+
+    pub fn mark(&self, symbol: &str) -> Option<Decimal> {
+        self.marks.get(symbol).copied()
+    }
+
+    pub fn positions(&self) -> impl Iterator<Item = &PositionSnapshot> {
+        self.positions.values()
+    }
+
+    /// Leverage from account settings, falling back to the market maximum.
+    pub fn leverage(&self, symbol: &str) -> Result<Decimal, ExchangeError> {
+        if let Some(leverage) = self.leverages.get(symbol) {
+            return Ok(*leverage);
+        }
+        self.market_cache
+            .get(symbol)
+            .map(|m| m.max_leverage)
+            .ok_or_else(|| ExchangeError::SymbolNotFound(symbol.to_string()))
+    }
+
+    /// Estimated maintenance margin rate: half of the initial margin at max leverage.
+    pub fn maintenance_margin_rate(&self, symbol: &str) -> Result<Decimal, ExchangeError> {
+        let market = self
+            .market_cache
+            .get(symbol)
+            .ok_or_else(|| ExchangeError::SymbolNotFound(symbol.to_string()))?;
+        if market.max_leverage.is_zero() {
+            return Err(ExchangeError::Custom(format!(
+                "max_leverage for {} is zero",
+                symbol
+            )));
+        }
+        Ok(Decimal::ONE / (Decimal::TWO * market.max_leverage))
+    }
+
+    /// Fails with `MarkPriceNotFound` only when `symbol` itself has no mark.
+    pub fn position_risk(&self, symbol: &str) -> Result<PositionRisk, ExchangeError> {
+        let position = self
+            .positions
+            .get(symbol)
+            .ok_or_else(|| ExchangeError::Custom(format!("No open position for {}", symbol)))?;
+        if self.mark(symbol).is_none() {
+            return Err(ExchangeError::MarkPriceNotFound(symbol.to_string()));
+        }
+        let exposure = self.exposure()?;
+        exposure
+            .positions
+            .into_iter()
+            .find(|p| p.symbol == position.symbol)
+            .ok_or_else(|| ExchangeError::Custom(format!("No open position for {}", symbol)))
+    }
+
+    /// Positions without a mark are listed in `unpriced` and left out of the
+    /// totals, so cross liquidation prices ignore their margin and PnL.
+    pub fn exposure(&self) -> Result<AccountExposure, ExchangeError> {
+        let mut exposure = AccountExposure {
+            balance: self.balance,
+            ..Default::default()
+        };
+
+        for position in self.positions.values() {
+            if self.mark(&position.symbol).is_none() {
+                exposure.unpriced.push(position.symbol.clone());
+                continue;
+            }
+            let risk = self.base_risk(position)?;
+            match position.side {
+                OrderSide::Bid => exposure.long_notional += risk.notional,
+                OrderSide::Ask => exposure.short_notional += risk.notional,
+            }
+            exposure.unrealized_pnl += risk.unrealized_pnl;
+            exposure.initial_margin += risk.initial_margin;
+            exposure.maintenance_margin += risk.maintenance_margin;
+            exposure.positions.push(risk);
+        }
+
+        exposure.unpriced.sort();
+        exposure.positions.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        exposure.gross_notional = exposure.long_notional + exposure.short_notional;
+        exposure.net_notional = exposure.long_notional - exposure.short_notional;
+        exposure.equity = self.balance + exposure.unrealized_pnl;
+        if exposure.equity > Decimal::ZERO {
+            exposure.margin_ratio = Some(exposure.maintenance_margin / exposure.equity);
+            exposure.effective_leverage = Some(exposure.gross_notional / exposure.equity);
+        }
+
+        // Cross positions share the account collateral, so their liquidation
+        // price depends on every other position's maintenance requirement.
+        let cross_maintenance: Decimal = exposure
+            .positions
+            .iter()
+            .filter(|p| !p.isolated)
+            .map(|p| p.maintenance_margin)
+            .sum();
+        let isolated_margin: Decimal = exposure
+            .positions
+            .iter()
+            .filter(|p| p.isolated)
+            .map(|p| p.initial_margin)
+            .sum();
+        let cross_pnl: Decimal = exposure
+            .positions
+            .iter()
+            .filter(|p| !p.isolated)
+            .map(|p| p.unrealized_pnl)
+            .sum();
+
+        for risk in exposure.positions.iter_mut() {
+            let mmr = self.maintenance_margin_rate(&risk.symbol)?;
+            let collateral = if risk.isolated {
+                risk.initial_margin
+            } else {
+                self.balance - isolated_margin + cross_pnl
+                    - risk.unrealized_pnl
+                    - (cross_maintenance - risk.maintenance_margin)
+            };
+            if collateral > Decimal::ZERO && !risk.isolated {
+                risk.margin_ratio = Some(risk.maintenance_margin / collateral);
+            } else if risk.isolated {
+                let position_equity = risk.initial_margin + risk.unrealized_pnl;
+                if position_equity > Decimal::ZERO {
+                    risk.margin_ratio = Some(risk.maintenance_margin / position_equity);
+                }
+            }
+            risk.liquidation_price =
+                liquidation_price(&risk.side, risk.entry_price, risk.amount, collateral, mmr);
+        }
+
+        Ok(exposure)
+    }
+
+    fn base_risk(&self, position: &PositionSnapshot) -> Result<PositionRisk, ExchangeError> {
+        let mark = self
+            .mark(&position.symbol)
+            .ok_or_else(|| ExchangeError::MarkPriceNotFound(position.symbol.clone()))?;
+        let leverage = self.leverage(&position.symbol)?;
+        let mmr = self.maintenance_margin_rate(&position.symbol)?;
+
+        let notional = position.amount * mark;
+        let unrealized_pnl =
+            unrealized_pnl(&position.side, position.entry_price, mark, position.amount);
+        let initial_margin = match (position.isolated, position.margin) {
+            (true, Some(margin)) => margin,
+            _ if leverage.is_zero() => Decimal::ZERO,
+            _ => position.amount * position.entry_price / leverage,
+        };
+        let roe = if initial_margin.is_zero() {
+            Decimal::ZERO
+        } else {
+            unrealized_pnl / initial_margin
+        };
+
+        Ok(PositionRisk {
+            symbol: position.symbol.clone(),
+            side: position.side.clone(),
+            amount: position.amount,
+            entry_price: position.entry_price,
+            mark_price: mark,
+            leverage,
+            isolated: position.isolated,
+            notional,
+            unrealized_pnl,
+            initial_margin,
+            maintenance_margin: notional * mmr,
+            roe,
+            margin_ratio: None,
+            liquidation_price: None,
+        })
+    }
+}
+
+pub fn unrealized_pnl(
+    side: &OrderSide,
+    entry_price: Decimal,
+    mark: Decimal,
+    amount: Decimal,
+) -> Decimal {
+    match side {
+        OrderSide::Bid => (mark - entry_price) * amount,
+        OrderSide::Ask => (entry_price - mark) * amount,
+    }
+}
+
+/// Price at which `collateral + pnl` falls to `mmr * notional`.
+/// Returns `None` when the position cannot be liquidated by price alone.
+pub fn liquidation_price(
+    side: &OrderSide,
+    entry_price: Decimal,
+    amount: Decimal,
+    collateral: Decimal,
+    mmr: Decimal,
+) -> Option<Decimal> {
+    if amount.is_zero() {
+        return None;
+    }
+    let price = match side {
+        OrderSide::Bid => {
+            let denom = amount * (Decimal::ONE - mmr);
+            if denom <= Decimal::ZERO {
+                return None;
+            }
+            (entry_price * amount - collateral) / denom
+        }
+        OrderSide::Ask => (entry_price * amount + collateral) / (amount * (Decimal::ONE + mmr)),
+    };
+    if price > Decimal::ZERO {
+        Some(price)
+    } else {
+        None
+    }
+}
+
+/// Keeps `calculator` marks in sync with a `prices` subscription.
+pub fn track_prices(
+    calculator: std::sync::Arc<RwLock<RiskCalculator>>,
+    mut subscription: Subscription,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(value) = subscription.recv().await {
+            match serde_json::from_value::<DefaultWebSocketMsg<WsPricesResponse>>(value) {
+                Ok(msg) => calculator.write().await.update_ws_prices(&msg.data),
+                Err(e) => error!("track_prices: failed to deserialize prices: {:?}", e),
+            }
+        }
+        debug!("track_prices: subscription closed");
+    })
+}
+
+/// Keeps `calculator` positions in sync with a `positions` subscription.
+pub fn track_positions(
+    calculator: std::sync::Arc<RwLock<RiskCalculator>>,
+    mut subscription: Subscription,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        while let Some(value) = subscription.recv().await {
+            match serde_json::from_value::<DefaultWebSocketMsg<WsPositionsResponse>>(value) {
+                Ok(msg) => calculator
+                    .write()
+                    .await
+                    .set_positions(msg.data.iter().map(PositionSnapshot::from)),
+                Err(e) => error!("track_positions: failed to deserialize positions: {:?}", e),
+            }
+        }
+        debug!("track_positions: subscription closed");
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn market(symbol: &str, max_leverage: i64) -> MarketModel {
+        MarketModel {
+            symbol: symbol.to_string(),
+            tick_size: Decimal::ONE,
+            min_tick: Decimal::ZERO,
+            max_tick: Decimal::from(1_000_000),
+            lot_size: Decimal::from_str("0.00001").unwrap(),
+            max_leverage: Decimal::from(max_leverage),
+            isolated_only: false,
+            min_order_size: Decimal::from(10),
+            max_order_size: Decimal::from(1_000_000),
+            funding_rate: Decimal::ZERO,
+            next_funding_rate: Decimal::ZERO,
+        }
+    }
+
+    fn position(symbol: &str, side: OrderSide, amount: i64, entry: i64) -> PositionSnapshot {
+        PositionSnapshot {
+            symbol: symbol.to_string(),
+            side,
+            amount: Decimal::from(amount),
+            entry_price: Decimal::from(entry),
+            margin: None,
+            funding: Decimal::ZERO,
+            isolated: false,
+        }
+    }
+
+    #[test]
+    fn test_unrealized_pnl_and_roe() {
+        let mut calc = RiskCalculator::new(HashMap::from([("BTC".to_string(), market("BTC", 50))]));
+        calc.balance = Decimal::from(10_000);
+        calc.set_positions([position("BTC", OrderSide::Bid, 1, 100_000)]);
+        calc.set_mark("BTC", Decimal::from(101_000));
+
+        let risk = calc.position_risk("BTC").unwrap();
+        assert_eq!(risk.notional, Decimal::from(101_000));
+        assert_eq!(risk.unrealized_pnl, Decimal::from(1_000));
+        // 100_000 / 50x = 2_000 initial margin
+        assert_eq!(risk.initial_margin, Decimal::from(2_000));
+        assert_eq!(risk.roe, Decimal::from_str("0.5").unwrap());
+
+        let exposure = calc.exposure().unwrap();
+        assert_eq!(exposure.equity, Decimal::from(11_000));
+        assert_eq!(exposure.net_notional, Decimal::from(101_000));
+    }
+
+    #[test]
+    fn test_liquidation_price_isolated() {
+        // long 1 @ 100, 10 margin, mmr 1% => (100 - 10) / 0.99
+        let mmr = Decimal::from_str("0.01").unwrap();
+        let long = liquidation_price(
+            &OrderSide::Bid,
+            Decimal::from(100),
+            Decimal::ONE,
+            Decimal::from(10),
+            mmr,
+        )
+        .unwrap();
+        assert_eq!(long.round_dp(4), Decimal::from_str("90.9091").unwrap());
+
+        let short = liquidation_price(
+            &OrderSide::Ask,
+            Decimal::from(100),
+            Decimal::ONE,
+            Decimal::from(10),
+            mmr,
+        )
+        .unwrap();
+        assert_eq!(short.round_dp(4), Decimal::from_str("108.9109").unwrap());
+
+        // collateral exceeds notional: long cannot be liquidated
+        assert!(
+            liquidation_price(
+                &OrderSide::Bid,
+                Decimal::from(100),
+                Decimal::ONE,
+                Decimal::from(200),
+                mmr
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_missing_mark() {
+        let mut calc = RiskCalculator::new(HashMap::from([
+            ("ETH".to_string(), market("ETH", 20)),
+            ("BTC".to_string(), market("BTC", 50)),
+        ]));
+        calc.balance = Decimal::from(10_000);
+        calc.set_positions([
+            position("ETH", OrderSide::Ask, 2, 4_000),
+            position("BTC", OrderSide::Bid, 1, 100_000),
+        ]);
+        calc.set_mark("BTC", Decimal::from(100_000));
+
+        let exposure = calc.exposure().unwrap();
+        assert_eq!(exposure.unpriced, vec!["ETH".to_string()]);
+        assert_eq!(exposure.positions.len(), 1);
+        assert_eq!(exposure.gross_notional, Decimal::from(100_000));
+        // BTC is still priced although ETH has no mark
+        assert!(calc.position_risk("BTC").is_ok());
+        assert!(matches!(
+            calc.position_risk("ETH"),
+            Err(ExchangeError::MarkPriceNotFound(_))
+        ));
+
+        calc.set_mark("ETH", Decimal::from(4_000));
+        let symbols: Vec<String> = calc
+            .exposure()
+            .unwrap()
+            .positions
+            .into_iter()
+            .map(|risk| risk.symbol)
+            .collect();
+        assert_eq!(symbols, ["BTC", "ETH"]);
+    }
+}
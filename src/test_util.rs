@@ -0,0 +1,173 @@
+// Local HTTP and WebSocket servers shared by the tests of REST and WebSocket
+// based components, and market data fixtures
+use std::sync::Arc;
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Value, json};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+use crate::backtest::events::MarketEvent;
+
+/// `book` message data with one level per side, each `(price, amount)`.
+pub fn book_data(symbol: &str, timestamp: u64, bid: (&str, &str), ask: (&str, &str)) -> Value {
+    json!({
+        "s": symbol,
+        "l": [[{"p": bid.0, "a": bid.1, "n": 1}], [{"p": ask.0, "a": ask.1, "n": 1}]],
+        "t": timestamp
+    })
+}
+
+/// `book_data` as a backtest event.
+pub fn book_event(
+    symbol: &str,
+    timestamp: u64,
+    bid: (&str, &str),
+    ask: (&str, &str),
+) -> MarketEvent {
+    MarketEvent::Book(serde_json::from_value(book_data(symbol, timestamp, bid, ask)).unwrap())
+}
+
+/// Request received by `spawn_http_server`.
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: String,
+    // Path and query, e.g. `/api/v1/orders/create`
+    pub target: String,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    pub fn path(&self) -> &str {
+        self.target.split('?').next().unwrap_or_default()
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        let (_, query) = self.target.split_once('?')?;
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == name).then_some(value)
+        })
+    }
+
+    pub fn json(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap()
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl HttpResponse {
+    /// Successful `DefaultResponse` carrying `data`.
+    pub fn ok(data: Value) -> Self {
+        Self {
+            status: 200,
+            body: json!({"success": true, "data": data, "error": null, "code": null}),
+        }
+    }
+
+    /// `DefaultResponse` of a request the exchange rejected.
+    pub fn rejected(error: &str, code: u32) -> Self {
+        Self {
+            status: 400,
+            body: json!({"success": false, "data": null, "error": error, "code": code}),
+        }
+    }
+
+    pub fn status(status: u16) -> Self {
+        Self {
+            status,
+            body: json!({"error": "unavailable"}),
+        }
+    }
+}
+
+/// Serves keep-alive HTTP/1.1 on a local port, answering every request with
+/// `handler`. Returns the base URL.
+pub async fn spawn_http_server<F>(handler: F) -> String
+where
+    F: Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve(stream, handler.clone()));
+        }
+    });
+    url
+}
+
+/// Accepts one WebSocket connection on a local port and sends it every text
+/// pushed into the returned sender. Returns the `ws://` URL.
+pub async fn spawn_ws_server() -> (String, mpsc::UnboundedSender<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("ws://{}", listener.local_addr().unwrap());
+    let (push, mut pushed) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let (mut write, _read) = tokio_tungstenite::accept_async(stream)
+            .await
+            .unwrap()
+            .split();
+        while let Some(text) = pushed.recv().await {
+            write.send(text.into()).await.unwrap();
+        }
+    });
+    (url, push)
+}
+
+async fn serve<F>(mut stream: TcpStream, handler: Arc<F>)
+where
+    F: Fn(&HttpRequest) -> HttpResponse,
+{
+    let mut buf = Vec::new();
+    loop {
+        let mut chunk = [0u8; 4096];
+        match stream.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+        // Answer every complete request in the buffer
+        while let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = String::from_utf8_lossy(&buf[..end]).to_string();
+            let length = head
+                .lines()
+                .find_map(|l| {
+                    let (name, value) = l.split_once(':')?;
+                    name.eq_ignore_ascii_case("content-length")
+                        .then(|| value.trim().parse::<usize>().ok())?
+                })
+                .unwrap_or(0);
+            if buf.len() < end + 4 + length {
+                break;
+            }
+            let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+            let request = HttpRequest {
+                method: request_line.next().unwrap_or_default().to_string(),
+                target: request_line.next().unwrap_or_default().to_string(),
+                body: buf[end + 4..end + 4 + length].to_vec(),
+            };
+            buf.drain(..end + 4 + length);
+            let response = handler(&request);
+            let body = response.body.to_string();
+            let response = format!(
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.status,
+                body.len(),
+                body
+            );
+            if stream.write_all(response.as_bytes()).await.is_err() {
+                return;
+            }
+        }
+    }
+}
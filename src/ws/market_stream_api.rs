@@ -0,0 +1,179 @@
+use std::future::Future;
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{AggLevel, Interval},
+    },
+    ws::{
+        replay::ReplayWebSocketClient,
+        ws_client::{Subscription, WebSocketClient},
+    },
+};
+
+/// Market data streams, with the signatures of `WebSocketClient`. Write
+/// strategies against `S: MarketStreamApi` to run them on live data or on a
+/// `ReplayWebSocketClient` without changes.
+pub trait MarketStreamApi: Send + Sync {
+    fn subscribe_to_prices(
+        &self,
+    ) -> impl Future<Output = Result<Subscription, ExchangeError>> + Send;
+
+    fn subscribe_to_orderbook(
+        &self,
+        symbol: &str,
+        agg_level: Option<AggLevel>,
+    ) -> impl Future<Output = Result<Subscription, ExchangeError>> + Send;
+
+    fn subscribe_to_trades(
+        &self,
+        symbol: &str,
+    ) -> impl Future<Output = Result<Subscription, ExchangeError>> + Send;
+
+    fn subscribe_to_candle(
+        &self,
+        symbol: &str,
+        interval: Interval,
+    ) -> impl Future<Output = Result<Subscription, ExchangeError>> + Send;
+
+    fn is_connected(&self) -> bool;
+}
+
+impl MarketStreamApi for WebSocketClient {
+    async fn subscribe_to_prices(&self) -> Result<Subscription, ExchangeError> {
+        WebSocketClient::subscribe_to_prices(self).await
+    }
+
+    async fn subscribe_to_orderbook(
+        &self,
+        symbol: &str,
+        agg_level: Option<AggLevel>,
+    ) -> Result<Subscription, ExchangeError> {
+        WebSocketClient::subscribe_to_orderbook(self, symbol, agg_level).await
+    }
+
+    async fn subscribe_to_trades(&self, symbol: &str) -> Result<Subscription, ExchangeError> {
+        WebSocketClient::subscribe_to_trades(self, symbol).await
+    }
+
+    async fn subscribe_to_candle(
+        &self,
+        symbol: &str,
+        interval: Interval,
+    ) -> Result<Subscription, ExchangeError> {
+        WebSocketClient::subscribe_to_candle(self, symbol, interval).await
+    }
+
+    fn is_connected(&self) -> bool {
+        WebSocketClient::is_connected(self)
+    }
+}
+
+impl MarketStreamApi for ReplayWebSocketClient {
+    async fn subscribe_to_prices(&self) -> Result<Subscription, ExchangeError> {
+        ReplayWebSocketClient::subscribe_to_prices(self).await
+    }
+
+    async fn subscribe_to_orderbook(
+        &self,
+        symbol: &str,
+        agg_level: Option<AggLevel>,
+    ) -> Result<Subscription, ExchangeError> {
+        ReplayWebSocketClient::subscribe_to_orderbook(self, symbol, agg_level).await
+    }
+
+    async fn subscribe_to_trades(&self, symbol: &str) -> Result<Subscription, ExchangeError> {
+        ReplayWebSocketClient::subscribe_to_trades(self, symbol).await
+    }
+
+    async fn subscribe_to_candle(
+        &self,
+        symbol: &str,
+        interval: Interval,
+    ) -> Result<Subscription, ExchangeError> {
+        ReplayWebSocketClient::subscribe_to_candle(self, symbol, interval).await
+    }
+
+    fn is_connected(&self) -> bool {
+        ReplayWebSocketClient::is_connected(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::{
+        models::ws::responses::TradesResponse,
+        test_util::spawn_ws_server,
+        ws::{
+            recorder::RecordedMessage,
+            replay::{ReplaySpeed, ReplayWebSocketClient},
+        },
+    };
+
+    fn trade(symbol: &str, timestamp: u64) -> Value {
+        json!({
+            "channel": "trades",
+            "data": [{
+                "a": "1", "d": "open_long", "p": "100", "s": symbol, "t": timestamp,
+                "tc": "normal", "u": "11111111111111111111111111111111"
+            }]
+        })
+    }
+
+    // Strategy code, written once for any stream source
+    async fn btc_trade_times<S: MarketStreamApi>(client: &S, count: usize) -> Vec<u64> {
+        let mut trades = client.subscribe_to_trades("BTC").await.unwrap();
+        let mut times = Vec::new();
+        while times.len() < count {
+            let msg = trades
+                .recv_typed::<TradesResponse>()
+                .await
+                .unwrap()
+                .unwrap();
+            times.extend(msg.data.iter().map(|t| t.timestamp));
+        }
+        times
+    }
+
+    #[tokio::test]
+    async fn test_one_strategy_on_live_and_replay() {
+        let messages = [trade("BTC", 1), trade("ETH", 2), trade("BTC", 3)];
+        let timeout = Duration::from_secs(5);
+
+        let records = messages
+            .iter()
+            .enumerate()
+            .map(|(i, message)| RecordedMessage {
+                local_ts: i as u64,
+                message: message.clone(),
+            })
+            .collect();
+        let replay = ReplayWebSocketClient::from_records(records, ReplaySpeed::AsFastAsPossible);
+        let (times, started) = tokio::join!(btc_trade_times(&replay, 2), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            replay.start()
+        });
+        started.unwrap();
+        assert_eq!(times, [1, 3]);
+
+        let (url, push) = spawn_ws_server().await;
+        let live = WebSocketClient::new(&url, None).await.unwrap();
+        let times = tokio::time::timeout(timeout, async {
+            let (times, _) = tokio::join!(btc_trade_times(&live, 2), async {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                for message in &messages {
+                    push.send(message.to_string()).unwrap();
+                }
+            });
+            times
+        })
+        .await
+        .unwrap();
+        assert_eq!(times, [1, 3]);
+    }
+}
@@ -1 +1,4 @@
+pub mod market_stream_api;
+pub mod recorder;
+pub mod replay;
 pub mod ws_client;
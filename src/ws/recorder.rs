@@ -0,0 +1,529 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, watch},
+    task::JoinHandle,
+};
+use tracing::{debug, error, warn};
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{AggLevel, Interval, WebSocketParams},
+        utils::get_timestamp_ms,
+    },
+    models::ws::subscriptions::{Candle, OrderBook, Prices, SubscriptionMethod, Trades},
+    ws::ws_client::{WebSocketClient, subscription_key},
+};
+
+const EXTENSION: &str = "ndjson.gz";
+
+#[derive(Debug, Clone)]
+pub enum MarketStream {
+    Prices,
+    Book {
+        symbol: String,
+        agg_level: Option<AggLevel>,
+    },
+    Trades {
+        symbol: String,
+    },
+    Candle {
+        symbol: String,
+        interval: Interval,
+    },
+}
+
+impl MarketStream {
+    fn params(&self) -> Result<Value, ExchangeError> {
+        let params = match self {
+            MarketStream::Prices => serde_json::to_value(WebSocketParams {
+                source: SubscriptionMethod::Prices.to_string(),
+                params: Prices {},
+            }),
+            MarketStream::Book { symbol, agg_level } => serde_json::to_value(WebSocketParams {
+                source: SubscriptionMethod::Book.to_string(),
+                params: OrderBook {
+                    symbol: symbol.clone(),
+                    agg_level: *agg_level,
+                },
+            }),
+            MarketStream::Trades { symbol } => serde_json::to_value(WebSocketParams {
+                source: SubscriptionMethod::Trades.to_string(),
+                params: Trades {
+                    symbol: symbol.clone(),
+                },
+            }),
+            MarketStream::Candle { symbol, interval } => serde_json::to_value(WebSocketParams {
+                source: SubscriptionMethod::Candle.to_string(),
+                params: Candle {
+                    symbol: symbol.clone(),
+                    interval: interval.clone(),
+                },
+            }),
+        };
+        Ok(params?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Hourly,
+    Daily,
+}
+
+/// One line of a recording: the message as received and the local receive time.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecordedMessage {
+    // Milliseconds since the epoch
+    pub local_ts: u64,
+    pub message: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecorderConfig {
+    pub dir: PathBuf,
+    // Files are named `<prefix>-<period>.<seq>.ndjson.gz`
+    pub prefix: String,
+    pub streams: Vec<MarketStream>,
+    pub rotation: Rotation,
+    // Starts a new file of the same period once this many compressed bytes are written
+    pub max_file_bytes: Option<u64>,
+    // Buffered messages are written at least this often... Each flush is a gzip
+    // member of its own, so short intervals compress worse
+    pub flush_interval: Duration,
+    // ...or as soon as this many are buffered
+    pub flush_messages: usize,
+    // fsync after every flush
+    pub sync: bool,
+}
+
+impl RecorderConfig {
+    pub fn new(dir: impl Into<PathBuf>, streams: Vec<MarketStream>) -> Self {
+        Self {
+            dir: dir.into(),
+            prefix: "market".to_string(),
+            streams,
+            rotation: Rotation::Hourly,
+            max_file_bytes: Some(256 * 1024 * 1024),
+            flush_interval: Duration::from_secs(1),
+            flush_messages: 1000,
+            sync: true,
+        }
+    }
+}
+
+struct OpenFile {
+    period: String,
+    file: File,
+    bytes: u64,
+}
+
+/// Rotating NDJSON writer behind `MarketDataRecorder`. Every flush is appended as
+/// a complete gzip member, so a crash loses at most the unflushed messages and
+/// the file stays readable up to the last flush.
+pub struct RecordingWriter {
+    dir: PathBuf,
+    prefix: String,
+    rotation: Rotation,
+    max_file_bytes: Option<u64>,
+    sync: bool,
+    buffer: Vec<u8>,
+    buffered: usize,
+    // Period of the buffered messages
+    buffer_period: Option<String>,
+    current: Option<OpenFile>,
+}
+
+impl RecordingWriter {
+    pub fn new(config: &RecorderConfig) -> Result<Self, ExchangeError> {
+        std::fs::create_dir_all(&config.dir)?;
+        Ok(Self {
+            dir: config.dir.clone(),
+            prefix: config.prefix.clone(),
+            rotation: config.rotation,
+            max_file_bytes: config.max_file_bytes,
+            sync: config.sync,
+            buffer: Vec::new(),
+            buffered: 0,
+            buffer_period: None,
+            current: None,
+        })
+    }
+
+    /// Buffers `record`, flushing first when it belongs to the next period.
+    pub fn write(&mut self, record: &RecordedMessage) -> Result<(), ExchangeError> {
+        let period = period(record.local_ts, self.rotation);
+        if self.buffer_period.as_ref().is_some_and(|p| *p != period) {
+            self.flush()?;
+        }
+        serde_json::to_writer(&mut self.buffer, record)?;
+        self.buffer.push(b'\n');
+        self.buffered += 1;
+        self.buffer_period = Some(period);
+        Ok(())
+    }
+
+    pub fn buffered(&self) -> usize {
+        self.buffered
+    }
+
+    /// Appends the buffered messages as one gzip member.
+    pub fn flush(&mut self) -> Result<(), ExchangeError> {
+        let Some(period) = self.buffer_period.take() else {
+            return Ok(());
+        };
+        let rotate = match &self.current {
+            Some(current) => {
+                current.period != period
+                    || self.max_file_bytes.is_some_and(|max| current.bytes >= max)
+            }
+            None => true,
+        };
+        if rotate {
+            self.current = Some(self.open(period)?);
+        }
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.buffer)?;
+        let member = encoder.finish()?;
+        let current = self.current.as_mut().unwrap();
+        current.file.write_all(&member)?;
+        if self.sync {
+            current.file.sync_data()?;
+        }
+        current.bytes += member.len() as u64;
+        debug!(
+            "RecordingWriter: flushed {} message(s), {} bytes",
+            self.buffered,
+            member.len()
+        );
+        self.buffer.clear();
+        self.buffered = 0;
+        Ok(())
+    }
+
+    // Always a new file: appending after a member cut short by a crash would
+    // make everything behind it unreadable
+    fn open(&self, period: String) -> Result<OpenFile, ExchangeError> {
+        let mut seq = 0;
+        let path = loop {
+            let path = self.dir.join(format!(
+                "{}-{}.{:04}.{}",
+                self.prefix, period, seq, EXTENSION
+            ));
+            if !path.exists() {
+                break path;
+            }
+            seq += 1;
+        };
+        debug!("RecordingWriter: writing {}", path.display());
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(path)?;
+        Ok(OpenFile {
+            period,
+            file,
+            bytes: 0,
+        })
+    }
+}
+
+/// Files written with `prefix` in `dir`, oldest first.
+pub fn recording_files(dir: impl AsRef<Path>, prefix: &str) -> Result<Vec<PathBuf>, ExchangeError> {
+    let start = format!("{}-", prefix);
+    let end = format!(".{}", EXTENSION);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(&start) && n.ends_with(&end))
+        })
+        .collect();
+    // Periods and sequence numbers are fixed width, so names sort by time
+    files.sort();
+    Ok(files)
+}
+
+/// Reads a recording. A truncated last member (crash during a write) ends the
+/// file early with a warning instead of failing.
+pub fn read_recording(path: impl AsRef<Path>) -> Result<Vec<RecordedMessage>, ExchangeError> {
+    let path = path.as_ref();
+    let reader = BufReader::new(MultiGzDecoder::new(File::open(path)?));
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                warn!("{}: stopped at a damaged block: {}", path.display(), e);
+                break;
+            }
+        };
+        match serde_json::from_str(&line) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                warn!("{}: stopped at a damaged line: {}", path.display(), e);
+                break;
+            }
+        }
+    }
+    Ok(records)
+}
+
+/// Subscribes to market data streams and writes every message to disk, see
+/// `RecordingWriter`. Files are written on the blocking thread pool. Recording
+/// a stream the client is already subscribed to shares that subscription.
+pub struct MarketDataRecorder {
+    feeds: Vec<JoinHandle<()>>,
+    shutdown: watch::Sender<bool>,
+    handle: JoinHandle<Result<(), ExchangeError>>,
+}
+
+impl MarketDataRecorder {
+    pub async fn start(
+        client: &WebSocketClient,
+        config: RecorderConfig,
+    ) -> Result<Self, ExchangeError> {
+        let writer = Arc::new(Mutex::new(RecordingWriter::new(&config)?));
+        let (tx, mut rx) = mpsc::channel::<RecordedMessage>(config.flush_messages.max(1) * 4);
+        let mut feeds = Vec::new();
+        for stream in &config.streams {
+            let params = stream.params()?;
+            let key = subscription_key(&params);
+            let params: WebSocketParams<Value> = serde_json::from_value(params)?;
+            let mut messages = client.subscribe(params, &key).await?;
+            let tx = tx.clone();
+            feeds.push(tokio::spawn(async move {
+                while let Some(message) = messages.recv().await {
+                    let record = RecordedMessage {
+                        local_ts: get_timestamp_ms(),
+                        message,
+                    };
+                    if tx.send(record).await.is_err() {
+                        break;
+                    }
+                }
+            }));
+        }
+        drop(tx);
+
+        let (shutdown, mut shutdown_rx) = watch::channel(false);
+        let handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(config.flush_interval);
+            let mut batch = Vec::new();
+            let result = loop {
+                let flush = tokio::select! {
+                    record = rx.recv() => match record {
+                        Some(record) => {
+                            batch.push(record);
+                            batch.len() >= config.flush_messages
+                        }
+                        None => break Ok(()),
+                    },
+                    _ = ticker.tick() => !batch.is_empty(),
+                    _ = shutdown_rx.changed() => break Ok(()),
+                };
+                if flush && let Err(e) = write_batch(&writer, std::mem::take(&mut batch)).await {
+                    break Err(e);
+                }
+            };
+            let result = match result {
+                Ok(()) => write_batch(&writer, batch).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &result {
+                error!("MarketDataRecorder: stopped: {:?}", e);
+            }
+            result
+        });
+        Ok(Self {
+            feeds,
+            shutdown,
+            handle,
+        })
+    }
+
+    /// Releases the subscriptions and flushes what is buffered.
+    pub async fn stop(mut self) -> Result<(), ExchangeError> {
+        self.stop_feeds();
+        self.shutdown.send_replace(true);
+        (&mut self.handle)
+            .await
+            .map_err(|e| ExchangeError::Custom(format!("recorder task failed: {}", e)))?
+    }
+
+    // Dropping a feed's `Subscription` releases it on the client
+    fn stop_feeds(&mut self) {
+        for feed in self.feeds.drain(..) {
+            feed.abort();
+        }
+    }
+}
+
+impl Drop for MarketDataRecorder {
+    fn drop(&mut self) {
+        self.stop_feeds();
+        self.shutdown.send_replace(true);
+    }
+}
+
+// Compression and file IO block, so they run off the async workers
+async fn write_batch(
+    writer: &Arc<Mutex<RecordingWriter>>,
+    batch: Vec<RecordedMessage>,
+) -> Result<(), ExchangeError> {
+    let writer = writer.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut writer = writer.lock().unwrap();
+        for record in &batch {
+            writer.write(record)?;
+        }
+        writer.flush()
+    })
+    .await
+    .map_err(|e| ExchangeError::Custom(format!("recorder write failed: {}", e)))?
+}
+
+// UTC `YYYYMMDD` or `YYYYMMDDTHH`
+fn period(ts_ms: u64, rotation: Rotation) -> String {
+    let secs = ts_ms / 1000;
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    match rotation {
+        Rotation::Daily => format!("{:04}{:02}{:02}", year, month, day),
+        Rotation::Hourly => format!(
+            "{:04}{:02}{:02}T{:02}",
+            year,
+            month,
+            day,
+            secs % 86_400 / 3600
+        ),
+    }
+}
+
+// Days since 1970-01-01 to a proleptic Gregorian date (H. Hinnant's algorithm)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_util::spawn_ws_server;
+
+    #[test]
+    fn test_period() {
+        // 2024-02-29 23:59:59 UTC
+        assert_eq!(period(1_709_251_199_000, Rotation::Hourly), "20240229T23");
+        assert_eq!(period(1_709_251_200_000, Rotation::Daily), "20240301");
+        assert_eq!(period(0, Rotation::Hourly), "19700101T00");
+    }
+
+    #[test]
+    fn test_writer_rotates_and_survives_truncation() {
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let mut config = RecorderConfig::new(&dir, Vec::new());
+        config.max_file_bytes = Some(1);
+        let mut writer = RecordingWriter::new(&config).unwrap();
+        let hour = 3_600_000;
+        let record = |local_ts: u64, n: u64| RecordedMessage {
+            local_ts,
+            message: json!({ "channel": "prices", "data": [{ "n": n }] }),
+        };
+
+        writer.write(&record(0, 0)).unwrap();
+        writer.write(&record(1, 1)).unwrap();
+        // Next hour: the first two are flushed to their own file
+        writer.write(&record(hour, 2)).unwrap();
+        writer.flush().unwrap();
+        // Over the size limit: same hour, next file
+        writer.write(&record(hour + 1, 3)).unwrap();
+        writer.flush().unwrap();
+
+        let files = recording_files(&dir, "market").unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "market-19700101T00.0000.ndjson.gz",
+                "market-19700101T01.0000.ndjson.gz",
+                "market-19700101T01.0001.ndjson.gz",
+            ]
+        );
+        let counts: Vec<_> = files
+            .iter()
+            .map(|f| read_recording(f).unwrap().len())
+            .collect();
+        assert_eq!(counts, vec![2, 1, 1]);
+
+        // A crash in the middle of a write leaves a partial member behind
+        let mut file = OpenOptions::new().append(true).open(&files[0]).unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&serde_json::to_vec(&record(2, 9)).unwrap())
+            .unwrap();
+        let member = encoder.finish().unwrap();
+        file.write_all(&member[..member.len() / 2]).unwrap();
+        let records = read_recording(&files[0]).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].message["data"][0]["n"], 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recorder_shares_the_strategy_client() {
+        let (url, push) = spawn_ws_server().await;
+        let client = WebSocketClient::new(&url, None).await.unwrap();
+        let mut strategy_prices = client.subscribe_to_prices().await.unwrap();
+        let dir = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let recorder = MarketDataRecorder::start(
+            &client,
+            RecorderConfig::new(&dir, vec![MarketStream::Prices]),
+        )
+        .await
+        .unwrap();
+        let timeout = Duration::from_secs(5);
+
+        let msg = json!({ "channel": "prices", "data": [] }).to_string();
+        push.send(msg.clone()).unwrap();
+        let received = tokio::time::timeout(timeout, strategy_prices.recv()).await;
+        assert_eq!(received.unwrap().unwrap()["channel"], "prices");
+        // Both see the message before the recorder stops
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        recorder.stop().await.unwrap();
+
+        // The strategy keeps its subscription
+        push.send(msg).unwrap();
+        let received = tokio::time::timeout(timeout, strategy_prices.recv()).await;
+        assert_eq!(received.unwrap().unwrap()["channel"], "prices");
+
+        let files = recording_files(&dir, "market").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(read_recording(&files[0]).unwrap().len(), 1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::{
+    sync::{mpsc, watch},
+    time::Instant,
+};
+use tracing::{debug, error};
+
+use crate::{
+    common::{
+        errors::ExchangeError,
+        types::{AggLevel, Interval, WebSocketParams},
+    },
+    models::ws::subscriptions::{Candle, OrderBook, Prices, SubscriptionMethod, Trades},
+    ws::{
+        recorder::{RecordedMessage, read_recording, recording_files},
+        ws_client::{Subscription, route_key, subscription_key},
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    // Gaps between messages as recorded
+    RealTime,
+    // Gaps divided by the factor, e.g. 10.0 for ten times faster
+    Accelerated(f64),
+    // No gaps, only bounded by how fast subscribers consume
+    AsFastAsPossible,
+}
+
+/// Replay time: the `local_ts` of the last message delivered, in milliseconds.
+/// Strategies should read it instead of the system clock.
+#[derive(Clone, Debug, Default)]
+pub struct VirtualClock(Arc<AtomicU64>);
+
+impl VirtualClock {
+    pub fn now_ms(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+
+    fn set(&self, ts_ms: u64) {
+        self.0.store(ts_ms, Ordering::SeqCst);
+    }
+}
+
+enum Source {
+    Files(Vec<PathBuf>),
+    Records(Vec<RecordedMessage>),
+}
+
+// Senders per subscription key, each tagged with the id of its `Subscription`
+type Subscribers = Arc<Mutex<HashMap<String, Vec<(u64, mpsc::Sender<Value>)>>>>;
+
+/// Plays back recordings from `MarketDataRecorder` through the same
+/// `Subscription` API as `WebSocketClient`, so strategy code runs unchanged.
+/// Subscribe first, then call `start()`; messages are delivered in recorded
+/// order, waiting for slow subscribers rather than dropping messages.
+pub struct ReplayWebSocketClient {
+    source: Mutex<Option<Source>>,
+    speed: ReplaySpeed,
+    clock: VirtualClock,
+    subscribers: Subscribers,
+    next_subscriber: AtomicU64,
+    done: watch::Sender<bool>,
+}
+
+impl ReplayWebSocketClient {
+    pub fn from_files(files: Vec<PathBuf>, speed: ReplaySpeed) -> Self {
+        Self::with_source(Source::Files(files), speed)
+    }
+
+    /// Every file written with `prefix` in `dir`, oldest first.
+    pub fn from_dir(
+        dir: impl AsRef<Path>,
+        prefix: &str,
+        speed: ReplaySpeed,
+    ) -> Result<Self, ExchangeError> {
+        Ok(Self::from_files(recording_files(dir, prefix)?, speed))
+    }
+
+    pub fn from_records(records: Vec<RecordedMessage>, speed: ReplaySpeed) -> Self {
+        Self::with_source(Source::Records(records), speed)
+    }
+
+    fn with_source(source: Source, speed: ReplaySpeed) -> Self {
+        Self {
+            source: Mutex::new(Some(source)),
+            speed,
+            clock: VirtualClock::default(),
+            subscribers: Arc::new(Mutex::new(HashMap::new())),
+            next_subscriber: AtomicU64::new(0),
+            done: watch::channel(false).0,
+        }
+    }
+
+    pub fn clock(&self) -> VirtualClock {
+        self.clock.clone()
+    }
+
+    /// Starts playback. Subscriptions made afterwards only see later messages.
+    pub fn start(&self) -> Result<(), ExchangeError> {
+        let source =
+            self.source.lock().unwrap().take().ok_or_else(|| {
+                ExchangeError::Validation("replay has already been started".into())
+            })?;
+        let speed = self.speed;
+        let clock = self.clock.clone();
+        let subscribers = self.subscribers.clone();
+        let done = self.done.clone();
+        tokio::spawn(async move {
+            let mut player = Player {
+                speed,
+                clock,
+                subscribers,
+                origin: None,
+            };
+            let result = match source {
+                Source::Records(records) => player.play(records).await,
+                Source::Files(files) => player.play_files(files).await,
+            };
+            if let Err(e) = result {
+                error!("ReplayWebSocketClient: playback failed: {:?}", e);
+            }
+            // Closes every subscription
+            player.subscribers.lock().unwrap().clear();
+            done.send_replace(true);
+        });
+        Ok(())
+    }
+
+    /// Resolves once every message has been delivered.
+    pub async fn finished(&self) {
+        self.done.subscribe().wait_for(|done| *done).await.ok();
+    }
+
+    /// True until playback has finished.
+    pub fn is_connected(&self) -> bool {
+        !*self.done.borrow()
+    }
+
+    pub async fn subscribe_to_prices(&self) -> Result<Subscription, ExchangeError> {
+        self.subscribe_to(SubscriptionMethod::Prices, Prices {})
+    }
+
+    pub async fn subscribe_to_orderbook(
+        &self,
+        symbol: &str,
+        agg_level: Option<AggLevel>,
+    ) -> Result<Subscription, ExchangeError> {
+        self.subscribe_to(
+            SubscriptionMethod::Book,
+            OrderBook {
+                symbol: symbol.to_string(),
+                agg_level,
+            },
+        )
+    }
+
+    pub async fn subscribe_to_trades(&self, symbol: &str) -> Result<Subscription, ExchangeError> {
+        self.subscribe_to(
+            SubscriptionMethod::Trades,
+            Trades {
+                symbol: symbol.to_string(),
+            },
+        )
+    }
+
+    pub async fn subscribe_to_candle(
+        &self,
+        symbol: &str,
+        interval: Interval,
+    ) -> Result<Subscription, ExchangeError> {
+        self.subscribe_to(
+            SubscriptionMethod::Candle,
+            Candle {
+                symbol: symbol.to_string(),
+                interval,
+            },
+        )
+    }
+
+    /// Like `WebSocketClient::subscribe`, every `Subscription` of a key gets
+    /// each of its messages.
+    pub async fn subscribe<P: Serialize>(
+        &self,
+        _params: WebSocketParams<P>,
+        channel: &str,
+    ) -> Result<Subscription, ExchangeError> {
+        Ok(self.add_subscriber(channel.to_string()))
+    }
+
+    /// Ends every `Subscription` on `channel`.
+    pub async fn unsubscribe(&self, channel: &str) -> Result<(), ExchangeError> {
+        self.subscribers.lock().unwrap().remove(channel);
+        Ok(())
+    }
+
+    fn subscribe_to<P: Serialize>(
+        &self,
+        method: SubscriptionMethod,
+        params: P,
+    ) -> Result<Subscription, ExchangeError> {
+        let params = serde_json::to_value(WebSocketParams {
+            source: method.to_string(),
+            params,
+        })?;
+        Ok(self.add_subscriber(subscription_key(&params)))
+    }
+
+    fn add_subscriber(&self, channel: String) -> Subscription {
+        let (tx, rx) = mpsc::channel(32);
+        let id = self.next_subscriber.fetch_add(1, Ordering::Relaxed);
+        self.subscribers
+            .lock()
+            .unwrap()
+            .entry(channel.clone())
+            .or_default()
+            .push((id, tx));
+        let subscribers = self.subscribers.clone();
+        Subscription::new(channel, rx, move |channel| {
+            let mut subscribers = subscribers.lock().unwrap();
+            if let Some(senders) = subscribers.get_mut(&channel) {
+                senders.retain(|(sender, _)| *sender != id);
+                if senders.is_empty() {
+                    subscribers.remove(&channel);
+                }
+            }
+        })
+    }
+}
+
+struct Player {
+    speed: ReplaySpeed,
+    clock: VirtualClock,
+    subscribers: Subscribers,
+    // First recorded timestamp and when it was replayed
+    origin: Option<(u64, Instant)>,
+}
+
+impl Player {
+    async fn play_files(&mut self, files: Vec<PathBuf>) -> Result<(), ExchangeError> {
+        for file in files {
+            debug!("ReplayWebSocketClient: playing {}", file.display());
+            let records = tokio::task::spawn_blocking(move || read_recording(file))
+                .await
+                .map_err(|e| ExchangeError::Custom(format!("reading recording failed: {}", e)))??;
+            self.play(records).await?;
+        }
+        Ok(())
+    }
+
+    async fn play(&mut self, records: Vec<RecordedMessage>) -> Result<(), ExchangeError> {
+        for record in records {
+            self.wait_for(record.local_ts).await;
+            self.clock.set(record.local_ts);
+            for tx in self.subscribers_of(&record.message) {
+                // A dropped subscription is not an error
+                tx.send(record.message.clone()).await.ok();
+            }
+        }
+        Ok(())
+    }
+
+    async fn wait_for(&mut self, ts_ms: u64) {
+        let factor = match self.speed {
+            ReplaySpeed::RealTime => 1.0,
+            ReplaySpeed::Accelerated(factor) if factor > 0.0 => factor,
+            _ => return,
+        };
+        let (first_ts, started) = *self.origin.get_or_insert((ts_ms, Instant::now()));
+        let offset = Duration::from_millis(ts_ms.saturating_sub(first_ts)).div_f64(factor);
+        tokio::time::sleep_until(started + offset).await;
+    }
+
+    // Same routing as `WebSocketClient`
+    fn subscribers_of(&self, message: &Value) -> Vec<mpsc::Sender<Value>> {
+        let Some(channel) = message.get("channel").and_then(Value::as_str) else {
+            return Vec::new();
+        };
+        let subscribers = self.subscribers.lock().unwrap();
+        message
+            .get("data")
+            .and_then(|data| route_key(channel, data))
+            .and_then(|key| subscribers.get(&key))
+            .or_else(|| subscribers.get(channel))
+            .map(|senders| senders.iter().map(|(_, tx)| tx.clone()).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::models::ws::responses::TradesResponse;
+
+    use super::*;
+
+    fn trade(local_ts: u64, symbol: &str) -> RecordedMessage {
+        RecordedMessage {
+            local_ts,
+            message: json!({
+                "channel": "trades",
+                "data": [{
+                    "a": "1", "d": "open_long", "p": "100", "s": symbol, "t": local_ts,
+                    "tc": "normal", "u": "11111111111111111111111111111111"
+                }]
+            }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_routes_and_paces() {
+        let records = vec![
+            trade(1_000, "BTC"),
+            trade(2_000, "ETH"),
+            trade(61_000, "BTC"),
+        ];
+        let client = ReplayWebSocketClient::from_records(records, ReplaySpeed::Accelerated(1000.0));
+        let mut btc = client.subscribe_to_trades("BTC").await.unwrap();
+        let clock = client.clock();
+        let started = Instant::now();
+        client.start().unwrap();
+
+        let first = btc.recv_typed::<TradesResponse>().await.unwrap().unwrap();
+        assert_eq!(first.data[0].timestamp, 1_000);
+        let second = btc.recv_typed::<TradesResponse>().await.unwrap().unwrap();
+        assert_eq!(second.data[0].timestamp, 61_000);
+        assert_eq!(clock.now_ms(), 61_000);
+        // 60 recorded seconds at 1000x
+        assert!(started.elapsed() >= Duration::from_millis(60));
+
+        client.finished().await;
+        assert!(btc.recv().await.is_none());
+        assert!(!client.is_connected());
+        assert!(client.start().is_err());
+    }
+}
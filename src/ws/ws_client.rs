@@ -1,19 +1,19 @@
 use std::{
     collections::HashMap,
     sync::{
-        Arc,
-        atomic::{AtomicBool, Ordering},
+        Arc, RwLock,
+        atomic::{AtomicBool, AtomicU64, Ordering},
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use futures_util::{SinkExt, StreamExt, stream::SplitSink};
-use serde::Serialize;
+use serde::{Serialize, de::DeserializeOwned};
 use serde_json::{Value, json};
 use solana_sdk::pubkey::Pubkey;
 use tokio::{
     net::TcpStream,
-    sync::{Mutex, mpsc},
+    sync::{Mutex, broadcast, mpsc, watch},
 };
 use tokio_tungstenite::{
     MaybeTlsStream, WebSocketStream, connect_async,
@@ -27,29 +27,197 @@ use crate::{
     common::{
         errors::ExchangeError,
         types::{
-            AggLevel, FinalRequest, Interval, WebSocketParams, WebSocketRequest,
-            WebSocketSubscription, WsMethod,
+            AggLevel, DefaultWebSocketMsg, FinalRequest, Interval, WebSocketParams,
+            WebSocketRequest, WebSocketSubscription, WsMethod,
         },
     },
+    logging::{redacted_json, redacted_text},
+    metrics,
     models::ws::subscriptions::{
         AccountInfo, AccountTrades, Balance, Candle, Leverage, Margin, OrderBook, OrderUpdates,
         Orders, Positions, Prices, SubscriptionMethod, Trades,
     },
 };
 
+#[derive(Debug, Clone)]
+pub struct WebSocketOptions {
+    pub ping_interval: Duration,
+    // Delay before the first reconnect, doubled after each failed attempt
+    pub reconnect_delay: Duration,
+    pub max_reconnect_delay: Duration,
+    // Consecutive failed attempts before giving up, None retries forever
+    pub max_reconnect_attempts: Option<u32>,
+    // How long a send waits for a (re)connection before failing
+    pub send_timeout: Duration,
+}
+
+impl Default for WebSocketOptions {
+    fn default() -> Self {
+        Self {
+            ping_interval: Duration::from_secs(50),
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+            max_reconnect_attempts: None,
+            send_timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+impl WebSocketOptions {
+    // Exponential backoff with jitter, in [delay / 2, delay]
+    fn reconnect_backoff(&self, failed_attempts: u32) -> Duration {
+        let delay = self
+            .reconnect_delay
+            .saturating_mul(2u32.saturating_pow(failed_attempts))
+            .min(self.max_reconnect_delay);
+        delay.mul_f64(rand::random_range(0.5..=1.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connecting,
+    Connected,
+    Disconnected,
+    // After `close()` or once `max_reconnect_attempts` is exhausted
+    Closed,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectionEvent {
+    // `attempt` counts from 1 since the last established connection
+    Connecting { attempt: u32 },
+    Connected,
+    // Reconnect requested by the client, e.g. on an API key change. The next
+    // `Disconnected` belongs to it
+    Restarting,
+    Disconnected { reason: String },
+    Resubscribed { channels: usize },
+    GaveUp { attempts: u32 },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionStats {
+    // Round trip of the last ping answered with a pong
+    pub latency: Option<Duration>,
+    // Connections established after the first one
+    pub reconnects: u64,
+    // Messages received per channel since the client was created
+    pub messages: HashMap<String, u64>,
+}
+
+// Channels whose messages name the subscription they belong to: (channel,
+// [(subscription param, message field)]). Other channels fit once per connection.
+const ROUTED_CHANNELS: &[(&str, &[(&str, &str)])] = &[
+    ("book", &[("symbol", "s")]),
+    ("trades", &[("symbol", "s")]),
+    ("candle", &[("symbol", "s"), ("interval", "i")]),
+    ("margin", &[("account", "u")]),
+    ("leverage", &[("account", "u")]),
+    ("account_order_updates", &[("account", "u")]),
+];
+
+fn routing_fields(channel: &str) -> Option<&'static [(&'static str, &'static str)]> {
+    ROUTED_CHANNELS
+        .iter()
+        .find(|(name, _)| *name == channel)
+        .map(|(_, fields)| *fields)
+}
+
+fn join_key<'a>(channel: &str, parts: impl Iterator<Item = Option<&'a Value>>) -> Option<String> {
+    let mut key = channel.to_string();
+    for part in parts {
+        match part? {
+            Value::String(s) => key.push_str(&format!(":{}", s)),
+            other => key.push_str(&format!(":{}", other)),
+        }
+    }
+    Some(key)
+}
+
+/// Key under which serialized `WebSocketParams` are registered so
+/// several subscriptions of one channel (e.g. `book` for two symbols, or
+/// `margin` for two accounts) can share a connection. Channels whose messages
+/// carry nothing to tell subscriptions apart use the bare channel name.
+pub fn subscription_key(params: &Value) -> String {
+    let source = params
+        .get("source")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    routing_fields(source)
+        .and_then(|fields| join_key(source, fields.iter().map(|(param, _)| params.get(*param))))
+        .unwrap_or_else(|| source.to_string())
+}
+
+// Counterpart of `subscription_key` for an incoming message
+pub(crate) fn route_key(channel: &str, data: &Value) -> Option<String> {
+    let fields = routing_fields(channel)?;
+    let item = match data {
+        Value::Array(items) => items.first()?,
+        other => other,
+    };
+    join_key(channel, fields.iter().map(|(_, field)| item.get(*field)))
+}
+
+// Senders per subscription key, each tagged with the id of its `Subscription`,
+// so one server subscription can feed several consumers
+type Subscribers = HashMap<String, Vec<(u64, mpsc::Sender<Value>)>>;
+
+// Drops subscriber `id` of `channel`, and the key with the last one; `true`
+// when the key was removed
+fn remove_subscriber(subscribers: &mut Subscribers, channel: &str, id: u64) -> bool {
+    let Some(senders) = subscribers.get_mut(channel) else {
+        return false;
+    };
+    senders.retain(|(sender, _)| *sender != id);
+    if !senders.is_empty() {
+        return false;
+    }
+    subscribers.remove(channel);
+    true
+}
+
+// An exchange request waiting for the answer with its id
+struct PendingRequest {
+    tx: mpsc::Sender<Value>,
+    method: String,
+    sent_at: Instant,
+}
+
+impl PendingRequest {
+    async fn answer(self, value: Value) {
+        metrics::record_ws_ack(&self.method, self.sent_at.elapsed());
+        self.tx.send(value).await.ok();
+    }
+}
+
 #[derive(Clone)]
 pub struct WebSocketClient(Arc<Inner>);
 
 #[derive(Clone)]
 struct Inner {
     url: String,
-    api_key: Option<String>,
+    api_key: Arc<RwLock<Option<String>>>,
+    options: WebSocketOptions,
     #[allow(clippy::type_complexity)]
     write: Arc<Mutex<Option<SplitSink<WebSocketStream<MaybeTlsStream<TcpStream>>, Message>>>>,
-    subscribers: Arc<Mutex<HashMap<String, mpsc::Sender<Value>>>>,
+    subscribers: Arc<Mutex<Subscribers>>,
+    next_subscriber: Arc<AtomicU64>,
     active_subscriptions: Arc<Mutex<HashMap<String, Value>>>,
-    pending_requests: Arc<Mutex<HashMap<String, mpsc::Sender<Value>>>>,
+    // Held across the send of a subscribe or unsubscribe that adds or removes a
+    // key, so the server sees them in the order the keys changed
+    subscription_sends: Arc<Mutex<()>>,
+    pending_requests: Arc<Mutex<HashMap<String, PendingRequest>>>,
     closed: Arc<AtomicBool>,
+    connected: Arc<watch::Sender<bool>>,
+    // Bumped to make the current connection drop so the run loop reconnects
+    restart: Arc<watch::Sender<u64>>,
+    // Set while a requested restart is in progress
+    restarting: Arc<AtomicBool>,
+    state: Arc<RwLock<ConnectionState>>,
+    events: broadcast::Sender<ConnectionEvent>,
+    stats: Arc<RwLock<ConnectionStats>>,
+    last_ping: Arc<RwLock<Option<Instant>>>,
 }
 
 impl std::ops::Deref for Inner {
@@ -66,14 +234,32 @@ impl std::ops::Deref for Inner {
 
 impl WebSocketClient {
     pub async fn new(url: &str, api_key: Option<String>) -> Result<Self, ExchangeError> {
+        Self::with_options(url, api_key, WebSocketOptions::default()).await
+    }
+
+    pub async fn with_options(
+        url: &str,
+        api_key: Option<String>,
+        options: WebSocketOptions,
+    ) -> Result<Self, ExchangeError> {
         let inner = Arc::new(Inner {
             url: url.to_string(),
-            api_key,
+            api_key: Arc::new(RwLock::new(api_key)),
+            options,
             write: Arc::new(Mutex::new(None)),
             subscribers: Arc::new(Mutex::new(HashMap::new())),
+            next_subscriber: Arc::new(AtomicU64::new(0)),
             active_subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            subscription_sends: Arc::new(Mutex::new(())),
             pending_requests: Arc::new(Mutex::new(HashMap::new())),
             closed: Arc::new(AtomicBool::new(false)),
+            connected: Arc::new(watch::channel(false).0),
+            restart: Arc::new(watch::channel(0).0),
+            restarting: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(RwLock::new(ConnectionState::Connecting)),
+            events: broadcast::channel(64).0,
+            stats: Arc::new(RwLock::new(ConnectionStats::default())),
+            last_ping: Arc::new(RwLock::new(None)),
         });
         let inner_clone = inner.clone();
         tokio::spawn(async move {
@@ -84,28 +270,7 @@ impl WebSocketClient {
 
     pub async fn subscribe_to_prices(&self) -> Result<Subscription, ExchangeError> {
         let p = Prices {};
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Prices.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Prices.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Prices, p).await
     }
 
     pub async fn subscribe_to_orderbook(
@@ -117,56 +282,14 @@ impl WebSocketClient {
             symbol: symbol.to_string(),
             agg_level,
         };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Book.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Book.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Book, p).await
     }
 
     pub async fn subscribe_to_trades(&self, symbol: &str) -> Result<Subscription, ExchangeError> {
         let p = Trades {
             symbol: symbol.to_string(),
         };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Trades.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Trades.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Trades, p).await
     }
 
     pub async fn subscribe_to_candle(
@@ -178,28 +301,7 @@ impl WebSocketClient {
             symbol: symbol.to_string(),
             interval,
         };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Candle.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Candle.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Candle, p).await
     }
 
     pub async fn subscribe_to_balance(
@@ -207,28 +309,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = Balance { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Balance.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Balance.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Balance, p).await
     }
 
     pub async fn subscribe_to_margin(
@@ -236,28 +317,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = Margin { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Margin.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Margin.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Margin, p).await
     }
 
     pub async fn subscribe_to_leverage(
@@ -265,28 +325,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = Leverage { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Leverage.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Leverage.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Leverage, p).await
     }
 
     pub async fn subscribe_to_account_info(
@@ -294,28 +333,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = AccountInfo { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::AccountInfo.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::AccountInfo.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::AccountInfo, p).await
     }
 
     pub async fn subscribe_to_positions(
@@ -323,28 +341,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = Positions { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Positions.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Positions.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Positions, p).await
     }
 
     pub async fn subscribe_to_orders(
@@ -352,28 +349,7 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = Orders { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::Orders.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::Orders.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
-            .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
+        self.subscribe_to(SubscriptionMethod::Orders, p).await
     }
 
     pub async fn subscribe_to_order_updates(
@@ -381,28 +357,8 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = OrderUpdates { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::AccountOrderUpdates.to_string(),
-            params: p,
-        };
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params,
-        };
-        let channel = SubscriptionMethod::AccountOrderUpdates.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
-        self.0
-            .active_subscriptions
-            .lock()
+        self.subscribe_to(SubscriptionMethod::AccountOrderUpdates, p)
             .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
-        })
     }
 
     pub async fn subscribe_to_account_trades(
@@ -410,68 +366,195 @@ impl WebSocketClient {
         account: Pubkey,
     ) -> Result<Subscription, ExchangeError> {
         let p = AccountTrades { account };
-        let params = WebSocketParams {
-            source: SubscriptionMethod::AccountTrades.to_string(),
-            params: p,
+        self.subscribe_to(SubscriptionMethod::AccountTrades, p)
+            .await
+    }
+
+    async fn subscribe_to<P: Serialize>(
+        &self,
+        source: SubscriptionMethod,
+        p: P,
+    ) -> Result<Subscription, ExchangeError> {
+        let sub = WebSocketSubscription {
+            method: WsMethod::Subscribe,
+            params: WebSocketParams {
+                source: source.to_string(),
+                params: p,
+            },
         };
+        let channel = subscription_key(&serde_json::to_value(&sub.params)?);
+        self.subscribe(sub.params, &channel).await
+    }
+
+    /// Subscribes `params` under the routing key `channel`. Subscribing to a
+    /// key that already has subscribers shares its server subscription; each
+    /// `Subscription` receives every message, and the server subscription ends
+    /// when the last one is dropped.
+    ///
+    /// Sharing needs the same `params`: messages don't say e.g. which `agg_level`
+    /// a `book` was subscribed with, so a different one on the same key is a
+    /// validation error.
+    pub async fn subscribe<P: Serialize>(
+        &self,
+        params: WebSocketParams<P>,
+        channel: &str,
+    ) -> Result<Subscription, ExchangeError> {
         let sub = WebSocketSubscription {
             method: WsMethod::Subscribe,
             params,
         };
-        let channel = SubscriptionMethod::AccountTrades.to_string();
-        self.0.send(serde_json::to_string(&sub)?).await?;
+        let params = serde_json::to_value(&sub.params)?;
+        let text = serde_json::to_string(&sub)?;
         let (tx, rx) = mpsc::channel(32);
-        self.0.subscribers.lock().await.insert(channel.clone(), tx);
+        let id = self.0.next_subscriber.fetch_add(1, Ordering::Relaxed);
+        if self.join(channel, &params, id, &tx).await? {
+            return Ok(self.subscription(channel.to_string(), id, rx));
+        }
+
+        // The key is reserved before the send, so subscribers arriving meanwhile
+        // join it, and `subscribers` isn't held while the send waits. The run
+        // loop takes `subscription_sends` to connect, so wait for it first and
+        // don't wait for the connection under the lock.
+        let _ = self.0.wait_for_write(self.0.options.send_timeout).await;
+        let _sends = self.0.subscription_sends.lock().await;
+        if self.join(channel, &params, id, &tx).await? {
+            return Ok(self.subscription(channel.to_string(), id, rx));
+        }
+        self.0
+            .subscribers
+            .lock()
+            .await
+            .insert(channel.to_string(), vec![(id, tx)]);
         self.0
             .active_subscriptions
             .lock()
             .await
-            .insert(channel.clone(), serde_json::to_value(&sub.params)?);
-        Ok(Subscription {
-            client: self.clone(),
-            channel,
-            rx,
+            .insert(channel.to_string(), params);
+        // Created before the send, so a cancelled subscribe releases the key
+        let subscription = self.subscription(channel.to_string(), id, rx);
+        if let Err(e) = self.0.send_message(Message::Text(text.into())).await {
+            // Subscribers that joined meanwhile keep the key, to be
+            // resubscribed with the next connection
+            let mut subscribers = self.0.subscribers.lock().await;
+            if remove_subscriber(&mut subscribers, channel, id) {
+                self.0.active_subscriptions.lock().await.remove(channel);
+            }
+            return Err(e);
+        }
+        Ok(subscription)
+    }
+
+    // Adds `tx` to `channel` if it is subscribed already; `false` if it isn't
+    async fn join(
+        &self,
+        channel: &str,
+        params: &Value,
+        id: u64,
+        tx: &mpsc::Sender<Value>,
+    ) -> Result<bool, ExchangeError> {
+        let mut subscribers = self.0.subscribers.lock().await;
+        let Some(senders) = subscribers.get_mut(channel) else {
+            return Ok(false);
+        };
+        if let Some(active) = self.0.active_subscriptions.lock().await.get(channel)
+            && active != params
+        {
+            return Err(ExchangeError::Validation(format!(
+                "{} is already subscribed with {}, not {}",
+                channel, active, params
+            )));
+        }
+        senders.push((id, tx.clone()));
+        Ok(true)
+    }
+
+    // Releases its own sender when dropped
+    fn subscription(&self, channel: String, id: u64, rx: mpsc::Receiver<Value>) -> Subscription {
+        let client = self.clone();
+        Subscription::new(channel, rx, move |channel| {
+            tokio::spawn(async move {
+                client.release(&channel, id).await.ok();
+            });
         })
     }
 
+    async fn release(&self, channel: &str, id: u64) -> Result<(), ExchangeError> {
+        {
+            let mut subscribers = self.0.subscribers.lock().await;
+            let Some(senders) = subscribers.get_mut(channel) else {
+                return Ok(());
+            };
+            if senders.iter().any(|(sender, _)| *sender != id) {
+                senders.retain(|(sender, _)| *sender != id);
+                return Ok(());
+            }
+        }
+        // The last subscriber removes the key, see `subscribe`
+        let _sends = self.0.subscription_sends.lock().await;
+        if remove_subscriber(&mut *self.0.subscribers.lock().await, channel, id) {
+            self.send_unsubscribe(channel).await?;
+        }
+        Ok(())
+    }
+
+    /// Ends the server subscription for `channel` and every `Subscription` on it.
+    /// Dropping a `Subscription` only releases that one.
     pub async fn unsubscribe(&self, channel: &str) -> Result<(), ExchangeError> {
-        let mut active = self.0.active_subscriptions.lock().await;
-        if let Some(params_value) = active.remove(channel) {
+        let _sends = self.0.subscription_sends.lock().await;
+        self.0.subscribers.lock().await.remove(channel);
+        self.send_unsubscribe(channel).await
+    }
+
+    async fn send_unsubscribe(&self, channel: &str) -> Result<(), ExchangeError> {
+        let params_value = self.0.active_subscriptions.lock().await.remove(channel);
+        if let Some(params_value) = params_value {
             let params: WebSocketParams<Value> = serde_json::from_value(params_value)?;
             let unsub = WebSocketSubscription {
                 method: WsMethod::Unsubscribe,
                 params,
             };
-            self.0.send(serde_json::to_string(&unsub)?).await?;
+            // Without a connection there is nothing to end: the key is no
+            // longer resubscribed
+            let text = serde_json::to_string(&unsub)?;
+            self.0.send_message(Message::Text(text.into())).await?;
         }
-        self.0.subscribers.lock().await.remove(channel);
         Ok(())
     }
 
-    pub async fn subscribe<P: Serialize + Clone>(
-        &self,
-        params: WebSocketParams<P>,
-        channel: &str,
-    ) -> Result<mpsc::Receiver<Value>, ExchangeError> {
-        let mut active = self.0.active_subscriptions.lock().await;
-        active.insert(channel.to_string(), json!(params));
-        drop(active);
-        let sub = WebSocketSubscription {
-            method: WsMethod::Subscribe,
-            params: params.clone(),
-        };
-        self.0.send(serde_json::to_string(&sub)?).await?;
-        let (tx, rx) = mpsc::channel(32);
-        self.0
-            .subscribers
-            .lock()
-            .await
-            .insert(channel.to_string(), tx);
-        Ok(rx)
+    pub fn is_connected(&self) -> bool {
+        *self.0.connected.borrow()
+    }
+
+    /// Yields `true` once a connection is established and `false` when it drops.
+    pub fn connection_status(&self) -> watch::Receiver<bool> {
+        self.0.connected.subscribe()
+    }
+
+    pub fn state(&self) -> ConnectionState {
+        *self.0.state.read().unwrap()
+    }
+
+    /// `true` between the drop of a connection closed on purpose, e.g. by
+    /// `set_api_key`, and the next connection. Such a close is announced by
+    /// `ConnectionEvent::Restarting`; if the reconnect fails the flag clears and
+    /// the next `Disconnected` is an unintended drop.
+    pub fn is_restarting(&self) -> bool {
+        self.0.restarting.load(Ordering::SeqCst)
+    }
+
+    /// Connection lifecycle events from now on. Events are dropped for receivers
+    /// lagging more than 64 behind.
+    pub fn events(&self) -> broadcast::Receiver<ConnectionEvent> {
+        self.0.events.subscribe()
+    }
+
+    pub fn stats(&self) -> ConnectionStats {
+        self.0.stats.read().unwrap().clone()
     }
 
     pub async fn close(&self) -> Result<(), ExchangeError> {
         self.0.closed.store(true, Ordering::Relaxed);
+        self.0.set_state(ConnectionState::Closed);
         let mut guard = self.0.write.lock().await;
         if let Some(sink) = guard.as_mut() {
             sink.send(Message::Close(None)).await?;
@@ -486,6 +569,8 @@ impl WebSocketClient {
         request: FinalRequest<P>,
     ) -> Result<mpsc::Receiver<Value>, ExchangeError> {
         let request_id = request_id.unwrap_or(Uuid::new_v4());
+        // Also set on the enclosing operation span, if it has the field
+        tracing::Span::current().record("request_id", tracing::field::display(request_id));
         let mut params = HashMap::new();
         params.insert(request_method.to_string(), request);
         let web_socket_request: WebSocketRequest<P> = WebSocketRequest {
@@ -493,34 +578,97 @@ impl WebSocketClient {
             params,
         };
         let msg = json!(web_socket_request).to_string();
-        self.0.send(msg.clone()).await?;
         let (tx, rx) = mpsc::channel(1);
-        self.0
-            .pending_requests
-            .lock()
-            .await
-            .insert(request_id.to_string(), tx);
+        // Registered first so a fast answer can't arrive before it
+        self.0.pending_requests.lock().await.insert(
+            request_id.to_string(),
+            PendingRequest {
+                tx,
+                method: request_method.to_string(),
+                sent_at: Instant::now(),
+            },
+        );
+        if let Err(e) = self.0.send(msg).await {
+            self.0
+                .pending_requests
+                .lock()
+                .await
+                .remove(&request_id.to_string());
+            return Err(e);
+        }
 
         Ok(rx)
     }
 
-    // For future: Type () is required for T
-    pub(crate) async fn set_api_key(&self, api_key: String) -> Result<String, ExchangeError> {
-        Ok(api_key)
+    pub fn api_key(&self) -> Option<String> {
+        self.0.api_key.read().unwrap().clone()
+    }
+
+    /// Reconnects with the new `PF-API-KEY` header and waits for the new connection.
+    /// Subscriptions are restored on it; messages sent by the server during the
+    /// switch are lost. `is_restarting()` is set while reconnecting.
+    pub async fn set_api_key(&self, api_key: Option<String>) -> Result<(), ExchangeError> {
+        if let Some(key) = &api_key {
+            key.parse::<tokio_tungstenite::tungstenite::http::HeaderValue>()?;
+        }
+        *self.0.api_key.write().unwrap() = api_key;
+        if !self.is_connected() {
+            // The next connection attempt picks the key up
+            return Ok(());
+        }
+        let mut status = self.0.connected.subscribe();
+        self.0.restart.send_modify(|generation| *generation += 1);
+        let timeout = self.0.options.reconnect_delay + self.0.options.send_timeout;
+        tokio::time::timeout(timeout, async {
+            status.wait_for(|connected| !*connected).await?;
+            status.wait_for(|connected| *connected).await?;
+            Ok::<_, watch::error::RecvError>(())
+        })
+        .await
+        .map_err(|_| ExchangeError::WebSocketLostConnection)?
+        .map_err(|_| ExchangeError::WebSocketLostConnection)
     }
 }
 
+/// Messages of one channel, from `WebSocketClient` or `ReplayWebSocketClient`.
+/// Dropping it unsubscribes.
 pub struct Subscription {
-    client: WebSocketClient,
     channel: String,
     rx: mpsc::Receiver<Value>,
+    on_drop: Option<Box<dyn FnOnce(String) + Send>>,
 }
 
 impl Subscription {
+    pub(crate) fn new(
+        channel: String,
+        rx: mpsc::Receiver<Value>,
+        on_drop: impl FnOnce(String) + Send + 'static,
+    ) -> Self {
+        Self {
+            channel,
+            rx,
+            on_drop: Some(Box::new(on_drop)),
+        }
+    }
+
+    /// Key the messages are routed by, e.g. `book:BTC`; see `subscription_key`.
+    pub fn channel(&self) -> &str {
+        &self.channel
+    }
+
     pub async fn recv(&mut self) -> Option<Value> {
         self.rx.recv().await
     }
 
+    /// `recv` deserialized into the channel's response type, e.g.
+    /// `recv_typed::<PricesResponse>()`.
+    pub async fn recv_typed<R: DeserializeOwned>(
+        &mut self,
+    ) -> Option<Result<DefaultWebSocketMsg<R>, ExchangeError>> {
+        let value = self.rx.recv().await?;
+        Some(serde_json::from_value(value).map_err(ExchangeError::from))
+    }
+
     pub fn attach_callback<F>(mut self, callback: F)
     where
         F: FnMut(Value) + Send + 'static,
@@ -536,45 +684,106 @@ impl Subscription {
 
 impl Drop for Subscription {
     fn drop(&mut self) {
-        let client = self.client.clone();
-        let channel = self.channel.clone();
-        tokio::spawn(async move {
-            client.unsubscribe(&channel).await.ok();
-        });
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop(std::mem::take(&mut self.channel));
+        }
     }
 }
 
 impl Inner {
+    fn set_state(&self, state: ConnectionState) {
+        *self.state.write().unwrap() = state;
+    }
+
+    fn emit(&self, event: ConnectionEvent) {
+        // No receivers is fine
+        self.events.send(event).ok();
+    }
+
     async fn run(&self) {
         debug!("WebSocketClient run loop started");
+        // Failed attempts since the last established connection
+        let mut attempt = 0;
+        let mut ever_connected = false;
         loop {
             if self.closed.load(Ordering::Relaxed) {
                 debug!("WebSocketClient closed, exiting run loop");
                 break;
             }
-            match self.connect_and_loop().await {
-                Ok(_) => debug!("WebSocketClient connection loop exited normally"),
-                Err(e) => tracing::error!("WebSocketClient connection error: {:?}", e),
+            self.set_state(ConnectionState::Connecting);
+            self.emit(ConnectionEvent::Connecting {
+                attempt: attempt + 1,
+            });
+            let mut connected = false;
+            let reason = match self.connect_and_loop(ever_connected, &mut connected).await {
+                Ok(_) => {
+                    debug!("WebSocketClient connection loop exited normally");
+                    "connection closed".to_string()
+                }
+                Err(e) => {
+                    tracing::error!("WebSocketClient connection error: {:?}", e);
+                    e.to_string()
+                }
+            };
+            if connected {
+                attempt = 0;
+                ever_connected = true;
+            } else {
+                attempt += 1;
+                // A failed attempt ends a restart
+                self.restarting.store(false, Ordering::SeqCst);
             }
-            tokio::time::sleep(Duration::from_secs(1)).await;
+            if !self.closed.load(Ordering::Relaxed) {
+                self.set_state(ConnectionState::Disconnected);
+            }
+            self.emit(ConnectionEvent::Disconnected { reason });
+            if let Some(max) = self.options.max_reconnect_attempts
+                && attempt >= max
+            {
+                error!("WebSocketClient giving up after {} attempts", attempt);
+                self.closed.store(true, Ordering::Relaxed);
+                self.emit(ConnectionEvent::GaveUp { attempts: attempt });
+                break;
+            }
+            tokio::time::sleep(self.options.reconnect_backoff(attempt)).await;
         }
+        self.set_state(ConnectionState::Closed);
         debug!("WebSocketClient run loop finished");
     }
 
-    async fn connect_and_loop(&self) -> Result<(), ExchangeError> {
+    async fn connect_and_loop(
+        &self,
+        reconnect: bool,
+        connected: &mut bool,
+    ) -> Result<(), ExchangeError> {
         debug!("Connecting to WebSocket: {}", self.url);
         let ws_url = &self.url;
         let mut req = ws_url.into_client_request()?;
-        if let Some(ref api_key) = self.api_key {
+        // Subscribed before reading the key, so a key change from here on restarts
+        let mut restart = self.restart.subscribe();
+        let api_key = self.api_key.read().unwrap().clone();
+        if let Some(api_key) = api_key {
             req.headers_mut().insert("PF-API-KEY", api_key.parse()?);
         }
         let (ws_stream, _) = connect_async(req).await?;
         debug!("WebSocket connection established");
         let (sink, stream) = ws_stream.split();
+        // Until the resubscribe is done, so a subscribe racing the connection
+        // isn't sent on it twice
+        let sends = self.subscription_sends.lock().await;
         {
             let mut write_guard = self.write.lock().await;
             *write_guard = Some(sink);
         }
+        *connected = true;
+        if reconnect {
+            self.stats.write().unwrap().reconnects += 1;
+            metrics::record_ws_reconnect();
+        }
+        self.set_state(ConnectionState::Connected);
+        self.connected.send_replace(true);
+        self.restarting.store(false, Ordering::SeqCst);
+        self.emit(ConnectionEvent::Connected);
         let self_clone = self.clone();
         let ping_handle = tokio::spawn(async move {
             loop {
@@ -582,8 +791,9 @@ impl Inner {
                     debug!("Ping task noticed closed flag, exiting");
                     break;
                 }
-                tokio::time::sleep(Duration::from_secs(50)).await;
+                tokio::time::sleep(self_clone.options.ping_interval).await;
                 debug!("Sending ping to WebSocket");
+                *self_clone.last_ping.write().unwrap() = Some(Instant::now());
                 if let Err(e) = self_clone.send(json!({"method":"ping"}).to_string()).await {
                     debug!("Ping send error (will continue): {:?}", e);
                 }
@@ -592,6 +802,7 @@ impl Inner {
 
         let active = self.active_subscriptions.lock().await.clone();
         debug!("Resubscribing to {} active subscriptions", active.len());
+        let mut resubscribed = 0;
         for (chan, params) in active.iter() {
             debug!("Resubscribing to {} with params: {:?}", chan, params);
             match serde_json::from_value::<WebSocketParams<Value>>(params.clone()) {
@@ -600,8 +811,9 @@ impl Inner {
                         method: WsMethod::Subscribe,
                         params: deser_params,
                     };
-                    if let Err(e) = self.send(json!(sub).to_string()).await {
-                        debug!("Failed to resubscribe to {}: {:?}", chan, e);
+                    match self.send(json!(sub).to_string()).await {
+                        Ok(_) => resubscribed += 1,
+                        Err(e) => debug!("Failed to resubscribe to {}: {:?}", chan, e),
                     }
                 }
                 Err(e) => {
@@ -613,7 +825,25 @@ impl Inner {
             }
         }
 
-        let res = self.receive_loop(stream).await;
+        drop(sends);
+        if !active.is_empty() {
+            self.emit(ConnectionEvent::Resubscribed {
+                channels: resubscribed,
+            });
+        }
+
+        let res = tokio::select! {
+            res = self.receive_loop(stream) => res,
+            _ = restart.changed() => {
+                debug!("WebSocket restart requested");
+                self.restarting.store(true, Ordering::SeqCst);
+                self.emit(ConnectionEvent::Restarting);
+                if let Some(sink) = self.write.lock().await.as_mut() {
+                    sink.send(Message::Close(None)).await.ok();
+                }
+                Ok(())
+            }
+        };
 
         debug!("WebSocket receive loop exited, aborting ping task");
 
@@ -621,6 +851,7 @@ impl Inner {
             let mut write_guard = self.write.lock().await;
             *write_guard = None;
         }
+        self.connected.send_replace(false);
 
         ping_handle.abort();
 
@@ -645,7 +876,7 @@ impl Inner {
     }
 
     async fn send(&self, text: String) -> Result<(), ExchangeError> {
-        let _ = self.wait_for_write(Duration::from_secs(5)).await;
+        let _ = self.wait_for_write(self.options.send_timeout).await;
 
         let msg = Message::Text(Utf8Bytes::from(text));
 
@@ -662,7 +893,10 @@ impl Inner {
     }
 
     async fn send_message(&self, msg: Message) -> Result<(), ExchangeError> {
-        debug!("Sending WS message: {:?}", msg);
+        match &msg {
+            Message::Text(text) => debug!("Sending WS message: {}", redacted_text(text)),
+            other => debug!("Sending WS message: {:?}", other),
+        }
         let mut guard = self.write.lock().await;
         if let Some(sink) = guard.as_mut() {
             match sink.send(msg).await {
@@ -690,29 +924,32 @@ impl Inner {
                     return Err(ExchangeError::WebSocket(Box::new(e)));
                 }
             };
-            debug!("Received WS message: {:?}", msg);
             match msg {
                 Message::Text(text) => {
-                    debug!("Received WS text: {}", text);
                     let value_res = serde_json::from_str::<Value>(&text);
                     let value = match value_res {
                         Ok(v) => v,
                         Err(e) => {
-                            debug!("Failed to parse WS JSON: {} | raw: {}", e, text);
+                            // Not logged: it can't be redacted without parsing
+                            debug!("Failed to parse WS JSON: {} ({} bytes)", e, text.len());
                             continue;
                         }
                     };
+                    debug!("Received WS text: {}", redacted_json(&value));
 
                     if value.get("channel") == Some(&json!("pong")) {
                         debug!("Received pong message");
+                        if let Some(sent) = self.last_ping.write().unwrap().take() {
+                            self.stats.write().unwrap().latency = Some(sent.elapsed());
+                        }
                         continue;
                     }
 
                     if let Some(id) = value.get("id").and_then(|v| v.as_str()) {
                         debug!("Received response for request id: {}", id);
                         let mut pending = self.pending_requests.lock().await;
-                        if let Some(tx) = pending.remove(id) {
-                            tx.send(value.clone()).await.ok();
+                        if let Some(request) = pending.remove(id) {
+                            request.answer(value.clone()).await;
                         } else {
                             debug!("No pending requester for id {}", id);
                         }
@@ -721,40 +958,63 @@ impl Inner {
 
                     if value.get("code").is_some() && value.get("err").is_some() {
                         if let Some(id) = value.get("id").and_then(|v| v.as_str()) {
-                            debug!("Received error response for id {}: {}", id, value);
+                            debug!(
+                                "Received error response for id {}: {}",
+                                id,
+                                redacted_json(&value)
+                            );
                             let mut pending = self.pending_requests.lock().await;
-                            if let Some(tx) = pending.remove(id) {
-                                tx.send(value.clone()).await.ok();
+                            if let Some(request) = pending.remove(id) {
+                                request.answer(value.clone()).await;
                                 continue;
                             }
                         }
 
                         let subs = self.subscribers.lock().await;
-                        if let Some(err_tx) = subs.get("__errors__") {
+                        if let Some(err_txs) = subs.get("__errors__") {
                             debug!(
                                 "Forwarding server error to __errors__ subscriber: {}",
-                                value
+                                redacted_json(&value)
                             );
-                            err_tx.send(value.clone()).await.ok();
+                            for (_, err_tx) in err_txs {
+                                err_tx.send(value.clone()).await.ok();
+                            }
                             continue;
                         }
 
-                        debug!("Server error (no id, no __errors__ subscriber): {}", value);
+                        debug!(
+                            "Server error (no id, no __errors__ subscriber): {}",
+                            redacted_json(&value)
+                        );
                         continue;
                     }
 
                     if let Some(channel) = value.get("channel").and_then(|v| v.as_str()) {
                         debug!("Received message for channel: {}", channel);
+                        metrics::record_ws_message(channel);
+                        *self
+                            .stats
+                            .write()
+                            .unwrap()
+                            .messages
+                            .entry(channel.to_string())
+                            .or_default() += 1;
                         let subs = self.subscribers.lock().await;
-                        if let Some(tx) = subs.get(channel) {
-                            tx.send(value.clone()).await.ok();
+                        let routed = value
+                            .get("data")
+                            .and_then(|data| route_key(channel, data))
+                            .and_then(|key| subs.get(&key));
+                        if let Some(txs) = routed.or_else(|| subs.get(channel)) {
+                            for (_, tx) in txs {
+                                tx.send(value.clone()).await.ok();
+                            }
                         } else {
                             debug!("No subscriber for channel {}", channel);
                         }
                         continue;
                     }
 
-                    debug!("Received unrecognized WS JSON: {}", value);
+                    debug!("Received unrecognized WS JSON: {}", redacted_json(&value));
                 }
                 Message::Ping(data) => {
                     debug!("Received WS ping");
@@ -772,3 +1032,243 @@ impl Inner {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::{
+        accept_hdr_async,
+        tungstenite::handshake::server::{Request, Response},
+    };
+
+    use super::*;
+    use crate::test_util::spawn_ws_server;
+
+    // Accepts connections forever, reporting each one's API key and text messages
+    #[allow(clippy::result_large_err)] // tungstenite's handshake callback signature
+    async fn spawn_server() -> (String, mpsc::UnboundedReceiver<(Option<String>, String)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let mut api_key = None;
+                    let ws = accept_hdr_async(stream, |req: &Request, resp: Response| {
+                        api_key = req
+                            .headers()
+                            .get("PF-API-KEY")
+                            .map(|v| v.to_str().unwrap().to_string());
+                        Ok(resp)
+                    })
+                    .await
+                    .unwrap();
+                    let (_, mut read) = ws.split();
+                    while let Some(Ok(msg)) = read.next().await {
+                        if let Message::Text(text) = msg {
+                            tx.send((api_key.clone(), text.to_string())).ok();
+                        }
+                    }
+                });
+            }
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn test_set_api_key_reconnects_and_resubscribes() {
+        let (url, mut messages) = spawn_server().await;
+        let options = WebSocketOptions {
+            reconnect_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let client = WebSocketClient::with_options(&url, Some("old".into()), options)
+            .await
+            .unwrap();
+        let _prices = client.subscribe_to_prices().await.unwrap();
+        let (key, text) = messages.recv().await.unwrap();
+        assert_eq!(key.as_deref(), Some("old"));
+        assert!(text.contains("prices"));
+
+        client.set_api_key(Some("new".into())).await.unwrap();
+        assert!(client.is_connected());
+        assert_eq!(client.api_key().as_deref(), Some("new"));
+        let (key, text) = messages.recv().await.unwrap();
+        assert_eq!(key.as_deref(), Some("new"));
+        assert!(text.contains("subscribe") && text.contains("prices"));
+
+        assert!(client.set_api_key(Some("bad\nkey".into())).await.is_err());
+    }
+
+    #[test]
+    fn test_subscription_key_matches_messages() {
+        let account = Pubkey::new_unique();
+        let params = json!({ "source": "margin", "account": account.to_string() });
+        let data = json!({ "u": account.to_string(), "s": "BTC", "i": true, "t": 0 });
+        assert_eq!(
+            route_key("margin", &data).unwrap(),
+            subscription_key(&params)
+        );
+
+        let params = json!({ "source": "candle", "symbol": "BTC", "interval": "1m" });
+        let data = json!({ "s": "BTC", "i": "1m", "t": 0 });
+        assert_eq!(subscription_key(&params), "candle:BTC:1m");
+        assert_eq!(route_key("candle", &data).unwrap(), "candle:BTC:1m");
+
+        let params = json!({ "source": "positions", "account": account.to_string() });
+        assert_eq!(subscription_key(&params), "positions");
+        assert_eq!(route_key("positions", &json!([])), None);
+    }
+
+    #[tokio::test]
+    async fn test_routes_subscriptions_of_one_channel_by_key() {
+        let (url, push) = spawn_ws_server().await;
+        let client = WebSocketClient::new(&url, None).await.unwrap();
+        let mut btc = client.subscribe_to_orderbook("BTC", None).await.unwrap();
+        let mut eth = client.subscribe_to_orderbook("ETH", None).await.unwrap();
+        assert_eq!(btc.channel(), "book:BTC");
+        assert_eq!(client.0.active_subscriptions.lock().await.len(), 2);
+        // Messages don't tell aggregation levels apart, so they can't share a key
+        assert!(matches!(
+            client
+                .subscribe_to_orderbook("BTC", Some(AggLevel::L10))
+                .await,
+            Err(ExchangeError::Validation(_))
+        ));
+        let _shared = client.subscribe_to_orderbook("BTC", None).await.unwrap();
+        assert_eq!(client.0.subscribers.lock().await["book:BTC"].len(), 2);
+
+        for symbol in ["ETH", "BTC"] {
+            let msg = json!({ "channel": "book", "data": { "s": symbol, "l": [[], []], "t": 0 } });
+            push.send(msg.to_string()).unwrap();
+        }
+        assert_eq!(btc.recv().await.unwrap()["data"]["s"], "BTC");
+        assert_eq!(eth.recv().await.unwrap()["data"]["s"], "ETH");
+    }
+
+    #[tokio::test]
+    async fn test_connection_events_and_stats() {
+        let (url, mut messages) = spawn_server().await;
+        let options = WebSocketOptions {
+            reconnect_delay: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let client = WebSocketClient::with_options(&url, None, options)
+            .await
+            .unwrap();
+        let _prices = client.subscribe_to_prices().await.unwrap();
+        messages.recv().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Connected);
+
+        let mut events = client.events();
+        client.set_api_key(Some("key".into())).await.unwrap();
+        let mut received = Vec::new();
+        while received.last() != Some(&ConnectionEvent::Resubscribed { channels: 1 }) {
+            received.push(events.recv().await.unwrap());
+        }
+        assert_eq!(
+            received,
+            vec![
+                ConnectionEvent::Restarting,
+                ConnectionEvent::Disconnected {
+                    reason: "connection closed".into()
+                },
+                ConnectionEvent::Connecting { attempt: 1 },
+                ConnectionEvent::Connected,
+                ConnectionEvent::Resubscribed { channels: 1 },
+            ]
+        );
+        assert_eq!(client.stats().reconnects, 1);
+
+        client.close().await.unwrap();
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        // Nothing listens on the port anymore
+        drop(listener);
+        let options = WebSocketOptions {
+            reconnect_delay: Duration::from_millis(1),
+            max_reconnect_attempts: Some(3),
+            ..Default::default()
+        };
+        let client = WebSocketClient::with_options(&url, None, options)
+            .await
+            .unwrap();
+        let mut events = client.events();
+        loop {
+            match events.recv().await.unwrap() {
+                ConnectionEvent::GaveUp { attempts } => {
+                    assert_eq!(attempts, 3);
+                    break;
+                }
+                ConnectionEvent::Connected => panic!("unexpected connection"),
+                _ => {}
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert_eq!(client.state(), ConnectionState::Closed);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_sends_without_holding_subscribers() {
+        let (url, push) = spawn_ws_server().await;
+        let client = WebSocketClient::new(&url, None).await.unwrap();
+        let mut btc = client.subscribe_to_orderbook("BTC", None).await.unwrap();
+
+        // The server doesn't read, so a subscribe this large stalls in the send
+        let params = WebSocketParams {
+            source: "large".to_string(),
+            params: json!({ "padding": "x".repeat(16 << 20) }),
+        };
+        let stalled = tokio::spawn({
+            let (client, params) = (client.clone(), params.clone());
+            async move { client.subscribe(params, "large").await.map(|_| ()) }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!stalled.is_finished());
+
+        // Messages are still fanned out, and the reserved key can be joined
+        let msg = json!({ "channel": "book", "data": { "s": "BTC", "l": [[], []], "t": 0 } });
+        push.send(msg.to_string()).unwrap();
+        let received = tokio::time::timeout(Duration::from_secs(1), btc.recv()).await;
+        assert_eq!(received.unwrap().unwrap()["data"]["s"], "BTC");
+        let joined =
+            tokio::time::timeout(Duration::from_secs(1), client.subscribe(params, "large"))
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(joined.channel(), "large");
+        assert_eq!(client.0.subscribers.lock().await["large"].len(), 2);
+        stalled.abort();
+    }
+
+    #[tokio::test]
+    async fn test_failed_subscribe_is_rolled_back() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let options = WebSocketOptions {
+            reconnect_delay: Duration::from_secs(60),
+            send_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let client = WebSocketClient::with_options(&url, None, options)
+            .await
+            .unwrap();
+        assert!(client.subscribe_to_orderbook("ETH", None).await.is_err());
+        assert!(!client.0.subscribers.lock().await.contains_key("book:ETH"));
+        assert!(
+            !client
+                .0
+                .active_subscriptions
+                .lock()
+                .await
+                .contains_key("book:ETH")
+        );
+    }
+}